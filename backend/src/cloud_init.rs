@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+use tracing::debug;
+
+/// Guest configuration delivered to cloud-init through a NoCloud seed.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloudInitConfig {
+    /// Raw user-data passed to the guest unchanged (usually `#cloud-config`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_data: Option<String>,
+    /// Hostname for the guest. Defaults to the VM name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// SSH public keys installed for the guest's default user.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ssh_authorized_keys: Vec<String>,
+}
+
+impl CloudInitConfig {
+    /// True when nothing was requested, in which case no seed is attached.
+    pub fn is_empty(&self) -> bool {
+        self.user_data.is_none() && self.hostname.is_none() && self.ssh_authorized_keys.is_empty()
+    }
+}
+
+/// Path of the seed ISO attached to a VM.
+pub fn seed_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.seed.iso"))
+}
+
+/// Build the NoCloud seed ISO for a VM, replacing any previous one. The ISO is
+/// labelled `cidata` so cloud-init finds it on whichever drive it is attached.
+pub async fn write_seed(
+    metadata_dir: &Path,
+    id: &str,
+    vm_name: &str,
+    config: &CloudInitConfig,
+) -> std::io::Result<PathBuf> {
+    let staging = metadata_dir.join(format!("{id}.cidata"));
+    let seed = seed_path(metadata_dir, id);
+
+    let _ = fs::remove_dir_all(&staging).await;
+    fs::create_dir_all(&staging).await?;
+    fs::write(
+        staging.join("meta-data"),
+        render_meta_data(id, vm_name, config),
+    )
+    .await?;
+    fs::write(staging.join("user-data"), render_user_data(config)).await?;
+
+    debug!("Writing cloud-init seed to {seed:?}");
+    let _ = fs::remove_file(&seed).await;
+    let result = build_seed_command(&staging, &seed).status().await;
+    let _ = fs::remove_dir_all(&staging).await;
+
+    let status = result?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!(
+            "genisoimage exited with status {status}"
+        )));
+    }
+    Ok(seed)
+}
+
+/// Remove a VM's seed ISO. A missing file is not an error.
+pub async fn remove_seed(metadata_dir: &Path, id: &str) -> std::io::Result<()> {
+    match fs::remove_file(seed_path(metadata_dir, id)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn build_seed_command(staging: &Path, seed: &Path) -> Command {
+    let mut cmd = Command::new("genisoimage");
+    cmd.arg("-output")
+        .arg(seed)
+        .args(["-volid", "cidata", "-joliet", "-rock", "-quiet"])
+        .arg(staging.join("user-data"))
+        .arg(staging.join("meta-data"));
+    cmd
+}
+
+/// NoCloud meta-data. SSH keys go here as `public-keys` rather than into
+/// user-data so caller-supplied user-data is passed through untouched.
+fn render_meta_data(id: &str, vm_name: &str, config: &CloudInitConfig) -> String {
+    let hostname = config.hostname.as_deref().unwrap_or(vm_name);
+    let mut out = format!(
+        "instance-id: {}\nlocal-hostname: {}\n",
+        yaml_string(id),
+        yaml_string(hostname)
+    );
+    if !config.ssh_authorized_keys.is_empty() {
+        out.push_str("public-keys:\n");
+        for key in &config.ssh_authorized_keys {
+            out.push_str(&format!("  - {}\n", yaml_string(key.trim())));
+        }
+    }
+    out
+}
+
+/// cloud-init requires a user-data file even when there is nothing to say.
+fn render_user_data(config: &CloudInitConfig) -> String {
+    config
+        .user_data
+        .clone()
+        .unwrap_or_else(|| "#cloud-config\n".to_string())
+}
+
+/// Quote a value as a YAML double-quoted scalar. JSON strings are valid YAML,
+/// which covers escaping of quotes, backslashes and control characters.
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).expect("string serialization cannot fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_args(cmd: &Command) -> Vec<String> {
+        cmd.as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(CloudInitConfig::default().is_empty());
+        let config = CloudInitConfig {
+            hostname: Some("web".to_string()),
+            ..Default::default()
+        };
+        assert!(!config.is_empty());
+    }
+
+    #[test]
+    fn test_render_meta_data_defaults_hostname_to_vm_name() {
+        let meta = render_meta_data("abc-1", "my-vm", &CloudInitConfig::default());
+        assert_eq!(meta, "instance-id: \"abc-1\"\nlocal-hostname: \"my-vm\"\n");
+    }
+
+    #[test]
+    fn test_render_meta_data_includes_hostname_and_keys() {
+        let config = CloudInitConfig {
+            hostname: Some("web-1".to_string()),
+            ssh_authorized_keys: vec!["ssh-ed25519 AAAA user@host\n".to_string()],
+            ..Default::default()
+        };
+        let meta = render_meta_data("abc-1", "my-vm", &config);
+        assert!(meta.contains("local-hostname: \"web-1\"\n"));
+        assert!(meta.contains("public-keys:\n  - \"ssh-ed25519 AAAA user@host\"\n"));
+    }
+
+    #[test]
+    fn test_render_user_data_passes_through_or_defaults() {
+        assert_eq!(
+            render_user_data(&CloudInitConfig::default()),
+            "#cloud-config\n"
+        );
+        let config = CloudInitConfig {
+            user_data: Some("#!/bin/sh\necho hi\n".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(render_user_data(&config), "#!/bin/sh\necho hi\n");
+    }
+
+    #[test]
+    fn test_build_seed_command_labels_volume_cidata() {
+        let cmd = build_seed_command(Path::new("/meta/x.cidata"), Path::new("/meta/x.seed.iso"));
+        let args = command_args(&cmd);
+
+        assert_eq!(cmd.as_std().get_program(), "genisoimage");
+        let volid = args.iter().position(|a| a == "-volid").unwrap();
+        assert_eq!(args[volid + 1], "cidata");
+        let output = args.iter().position(|a| a == "-output").unwrap();
+        assert_eq!(args[output + 1], "/meta/x.seed.iso");
+        assert!(args.contains(&"/meta/x.cidata/user-data".to_string()));
+        assert!(args.contains(&"/meta/x.cidata/meta-data".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_remove_seed_missing_file_is_ok() {
+        let dir = tempfile::TempDir::new().unwrap();
+        remove_seed(dir.path(), "nope").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remove_seed_deletes_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(seed_path(dir.path(), "vm-1"), "iso").unwrap();
+        remove_seed(dir.path(), "vm-1").await.unwrap();
+        assert!(!seed_path(dir.path(), "vm-1").exists());
+    }
+}
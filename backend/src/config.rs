@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 #[derive(Debug, Deserialize)]
@@ -6,6 +6,28 @@ pub struct StorageConfig {
     pub qcow2_dir: PathBuf,
     pub metadata_dir: PathBuf,
     pub volume_data_dir: PathBuf,
+    /// Directory holding registered base images and their metadata.
+    /// Defaults to `<qcow2_dir>/images` when not set.
+    #[serde(default)]
+    pub images_dir: Option<PathBuf>,
+    /// Directory that images registered with `source_path` must be in.
+    /// Defaults to `<qcow2_dir>/import` when not set.
+    #[serde(default)]
+    pub import_dir: Option<PathBuf>,
+}
+
+impl StorageConfig {
+    pub fn images_dir(&self) -> PathBuf {
+        self.images_dir
+            .clone()
+            .unwrap_or_else(|| self.qcow2_dir.join("images"))
+    }
+
+    pub fn import_dir(&self) -> PathBuf {
+        self.import_dir
+            .clone()
+            .unwrap_or_else(|| self.qcow2_dir.join("import"))
+    }
 }
 
 #[derive(Debug, Default, Deserialize, PartialEq, Clone)]
@@ -16,6 +38,39 @@ pub enum NetworkMode {
     Bridge,
 }
 
+/// A named VM shape from the instance-type catalog.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct InstanceType {
+    pub name: String,
+    pub vcpus: u32,
+    pub memory_mb: u32,
+    /// Default size of the root disk in gigabytes.
+    pub disk_gb: u64,
+}
+
+impl InstanceType {
+    fn new(name: &str, vcpus: u32, memory_mb: u32, disk_gb: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            vcpus,
+            memory_mb,
+            disk_gb,
+        }
+    }
+}
+
+/// Catalog used when the config file does not define `[[instance_types]]`.
+/// Mirrors the options offered by the frontend's launch dialog.
+fn default_instance_types() -> Vec<InstanceType> {
+    vec![
+        InstanceType::new("t2.micro", 1, 1024, 10),
+        InstanceType::new("t2.small", 1, 2048, 20),
+        InstanceType::new("t2.medium", 2, 4096, 20),
+        InstanceType::new("t2.large", 2, 8192, 40),
+        InstanceType::new("t2.xlarge", 4, 16384, 80),
+    ]
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub listen_ip: String,
@@ -24,6 +79,11 @@ pub struct Config {
     pub storage: StorageConfig,
     #[serde(default)]
     pub network_mode: NetworkMode,
+    #[serde(default = "default_instance_types")]
+    pub instance_types: Vec<InstanceType>,
+    /// Where the metadata service listens for bridged guests. The address
+    /// has to be assigned to the bridge for guests to reach it.
+    pub metadata_listen: String,
 }
 
 impl Config {
@@ -42,9 +102,91 @@ impl Config {
         let config = config::Config::builder()
             .set_default("listen_ip", "127.0.0.1")?
             .set_default("listen_port", 8081)?
+            .set_default("metadata_listen", "169.254.169.254:80")?
             .add_source(config::File::with_name(&config_file))
             .build()?;
 
         config.try_deserialize()
     }
+
+    /// Look up an instance type in the catalog by name.
+    pub fn instance_type(&self, name: &str) -> Option<&InstanceType> {
+        self.instance_types.iter().find(|t| t.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Config {
+        config::Config::builder()
+            .set_default("listen_ip", "127.0.0.1")
+            .unwrap()
+            .set_default("listen_port", 8081)
+            .unwrap()
+            .set_default("metadata_listen", "169.254.169.254:80")
+            .unwrap()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    const BASE: &str = r#"
+proxy_url = "http://127.0.0.1:8080"
+
+[storage]
+qcow2_dir = "/tmp/vm-data"
+metadata_dir = "/tmp/vm-data"
+volume_data_dir = "/tmp/volume-data"
+"#;
+
+    #[test]
+    fn test_default_instance_types_when_not_configured() {
+        let config = parse(BASE);
+        let micro = config.instance_type("t2.micro").unwrap();
+        assert_eq!(micro.vcpus, 1);
+        assert_eq!(micro.memory_mb, 1024);
+    }
+
+    #[test]
+    fn test_instance_types_from_config_replace_defaults() {
+        let config = parse(&format!(
+            r#"{BASE}
+[[instance_types]]
+name = "tiny"
+vcpus = 1
+memory_mb = 256
+disk_gb = 2
+"#
+        ));
+        assert_eq!(
+            config.instance_type("tiny"),
+            Some(&InstanceType::new("tiny", 1, 256, 2))
+        );
+        assert!(config.instance_type("t2.micro").is_none());
+    }
+
+    #[test]
+    fn test_images_dir_defaults_under_qcow2_dir() {
+        let config = parse(BASE);
+        assert_eq!(
+            config.storage.images_dir(),
+            PathBuf::from("/tmp/vm-data/images")
+        );
+    }
+
+    #[test]
+    fn test_images_dir_from_config() {
+        let config = parse(&BASE.replace("[storage]", "[storage]\nimages_dir = \"/srv/images\""));
+        assert_eq!(config.storage.images_dir(), PathBuf::from("/srv/images"));
+    }
+
+    #[test]
+    fn test_unknown_instance_type_returns_none() {
+        let config = parse(BASE);
+        assert!(config.instance_type("m5.huge").is_none());
+    }
 }
@@ -0,0 +1,305 @@
+use nix::errno::Errno;
+use nix::fcntl::{fallocate, FallocateFlags};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Size at which the console log is rotated. One rotated file is kept, so a
+/// VM retains between this and twice this much of its most recent output.
+pub const MAX_LOG_BYTES: u64 = 1024 * 1024;
+
+/// Unix socket QEMU serves the VM's serial console on.
+pub fn console_socket_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.console"))
+}
+
+/// File QEMU appends everything written to the serial console to.
+pub fn console_log_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.console.log"))
+}
+
+fn rotated_log_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.console.log.1"))
+}
+
+/// Records how many bytes of console output have been dropped by rotation, so
+/// offsets handed to clients stay valid across rotations and restarts.
+fn base_offset_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.console.log.base"))
+}
+
+/// A slice of a VM's console output. Offsets count bytes since the VM's
+/// first boot, so a client can poll with `offset = next_offset` to follow it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConsoleLogChunk {
+    /// Offset of the first byte of `content`.
+    pub offset: u64,
+    /// Offset to request next to continue after `content`.
+    pub next_offset: u64,
+    /// Console output, with invalid UTF-8 replaced.
+    pub content: String,
+}
+
+/// Rotate the console log once it exceeds `max_bytes`: its contents move to
+/// `.console.log.1` (replacing the previous one) and are removed from the
+/// front of the live log. QEMU keeps its append-mode descriptor, so output
+/// continues in the same file. Returns whether a rotation happened.
+///
+/// The moved bytes are cut out with `FALLOC_FL_COLLAPSE_RANGE`, which shifts
+/// whatever QEMU has appended since down in place, so nothing written during
+/// the rotation is lost. It only moves whole filesystem blocks and not every
+/// filesystem supports it; otherwise the rest is copied too and the log is
+/// truncated, losing only what QEMU writes between that copy and the truncate.
+pub fn rotate_if_needed(metadata_dir: &Path, id: &str, max_bytes: u64) -> std::io::Result<bool> {
+    let log = console_log_path(metadata_dir, id);
+    let mut file = match OpenOptions::new().read(true).write(true).open(&log) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let metadata = file.metadata()?;
+    let size = metadata.len();
+    if size <= max_bytes {
+        return Ok(false);
+    }
+
+    let rotated_path = rotated_log_path(metadata_dir, id);
+    let dropped = file_len(&rotated_path)?;
+    write_base_offset(
+        metadata_dir,
+        id,
+        read_base_offset(metadata_dir, id)? + dropped,
+    )?;
+
+    // The collapsed range must end before the end of the file.
+    let block = metadata.blksize();
+    let moved = (size - 1) / block * block;
+    let mut rotated = File::create(&rotated_path)?;
+    std::io::copy(&mut (&mut file).take(moved), &mut rotated)?;
+    let collapsed = moved > 0
+        && match fallocate(
+            file.as_raw_fd(),
+            FallocateFlags::FALLOC_FL_COLLAPSE_RANGE,
+            0,
+            moved as i64,
+        ) {
+            Ok(()) => true,
+            Err(Errno::EOPNOTSUPP | Errno::EINVAL) => false,
+            Err(e) => return Err(e.into()),
+        };
+    if !collapsed {
+        std::io::copy(&mut file, &mut rotated)?;
+        file.set_len(0)?;
+    }
+    debug!("Rotated console log of VM {id} at {size} bytes");
+    Ok(true)
+}
+
+/// Read retained console output from `offset` (default: the oldest retained
+/// byte), keeping only the last `tail` lines if given.
+pub fn read_console_log(
+    metadata_dir: &Path,
+    id: &str,
+    offset: Option<u64>,
+    tail: Option<usize>,
+) -> std::io::Result<ConsoleLogChunk> {
+    let base = read_base_offset(metadata_dir, id)?;
+    let mut retained = read_or_empty(&rotated_log_path(metadata_dir, id))?;
+    retained.extend(read_or_empty(&console_log_path(metadata_dir, id))?);
+    let end = base + retained.len() as u64;
+
+    // Offsets before the retained window were rotated away; past the end
+    // there is nothing yet.
+    let start = offset.unwrap_or(base).clamp(base, end);
+    let mut content = &retained[(start - base) as usize..];
+    if let Some(lines) = tail {
+        content = last_lines(content, lines);
+    }
+
+    Ok(ConsoleLogChunk {
+        offset: end - content.len() as u64,
+        next_offset: end,
+        content: String::from_utf8_lossy(content).into_owned(),
+    })
+}
+
+/// Remove a VM's console log, its rotated copy and offset record.
+pub fn remove_console_log(metadata_dir: &Path, id: &str) -> std::io::Result<()> {
+    for path in [
+        console_log_path(metadata_dir, id),
+        rotated_log_path(metadata_dir, id),
+        base_offset_path(metadata_dir, id),
+    ] {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The suffix of `content` holding its last `lines` lines. A trailing newline
+/// ends the last line rather than starting an empty one.
+fn last_lines(content: &[u8], lines: usize) -> &[u8] {
+    if lines == 0 {
+        return &content[content.len()..];
+    }
+    let body = content.strip_suffix(b"\n").unwrap_or(content);
+    let start = body
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, b)| **b == b'\n')
+        .nth(lines - 1)
+        .map_or(0, |(i, _)| i + 1);
+    &content[start..]
+}
+
+fn read_or_empty(path: &Path) -> std::io::Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        other => other,
+    }
+}
+
+fn file_len(path: &Path) -> std::io::Result<u64> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_base_offset(metadata_dir: &Path, id: &str) -> std::io::Result<u64> {
+    let raw = read_or_empty(&base_offset_path(metadata_dir, id))?;
+    Ok(String::from_utf8_lossy(&raw).trim().parse().unwrap_or(0))
+}
+
+fn write_base_offset(metadata_dir: &Path, id: &str, base: u64) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(base_offset_path(metadata_dir, id))?;
+    writeln!(file, "{base}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn append(dir: &Path, id: &str, data: &str) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(console_log_path(dir, id))
+            .unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_read_missing_log_is_empty() {
+        let dir = TempDir::new().unwrap();
+        let chunk = read_console_log(dir.path(), "vm-1", None, None).unwrap();
+        assert_eq!(chunk.content, "");
+        assert_eq!(chunk.offset, 0);
+        assert_eq!(chunk.next_offset, 0);
+    }
+
+    #[test]
+    fn test_read_from_offset() {
+        let dir = TempDir::new().unwrap();
+        append(dir.path(), "vm-1", "SeaBIOS\nBooting\n");
+
+        let chunk = read_console_log(dir.path(), "vm-1", Some(8), None).unwrap();
+        assert_eq!(chunk.content, "Booting\n");
+        assert_eq!(chunk.offset, 8);
+        assert_eq!(chunk.next_offset, 16);
+
+        let chunk = read_console_log(dir.path(), "vm-1", Some(100), None).unwrap();
+        assert_eq!(chunk.content, "");
+        assert_eq!(chunk.next_offset, 16);
+    }
+
+    #[test]
+    fn test_read_tail_lines() {
+        let dir = TempDir::new().unwrap();
+        append(dir.path(), "vm-1", "one\ntwo\nthree\nlogin: ");
+
+        let chunk = read_console_log(dir.path(), "vm-1", None, Some(2)).unwrap();
+        assert_eq!(chunk.content, "three\nlogin: ");
+        assert_eq!(chunk.offset, 8);
+
+        let chunk = read_console_log(dir.path(), "vm-1", None, Some(10)).unwrap();
+        assert_eq!(chunk.content, "one\ntwo\nthree\nlogin: ");
+    }
+
+    #[test]
+    fn test_last_lines_ignores_trailing_newline() {
+        assert_eq!(last_lines(b"a\nb\nc\n", 1), b"c\n");
+        assert_eq!(last_lines(b"a\nb\nc\n", 2), b"b\nc\n");
+        assert_eq!(last_lines(b"a\nb\n", 0), b"");
+    }
+
+    #[test]
+    fn test_rotate_only_above_limit() {
+        let dir = TempDir::new().unwrap();
+        append(dir.path(), "vm-1", "12345");
+        assert!(!rotate_if_needed(dir.path(), "vm-1", 10).unwrap());
+        assert!(!rotate_if_needed(dir.path(), "missing", 10).unwrap());
+    }
+
+    #[test]
+    fn test_offsets_stay_valid_across_rotations() {
+        let dir = TempDir::new().unwrap();
+        append(dir.path(), "vm-1", "first\n");
+        assert!(rotate_if_needed(dir.path(), "vm-1", 4).unwrap());
+        append(dir.path(), "vm-1", "second\n");
+
+        // Both files are retained: the window still starts at 0.
+        let chunk = read_console_log(dir.path(), "vm-1", None, None).unwrap();
+        assert_eq!(chunk.content, "first\nsecond\n");
+        assert_eq!(chunk.offset, 0);
+
+        assert!(rotate_if_needed(dir.path(), "vm-1", 4).unwrap());
+        append(dir.path(), "vm-1", "third\n");
+
+        // "first\n" has been dropped; offsets continue where they left off.
+        let chunk = read_console_log(dir.path(), "vm-1", Some(0), None).unwrap();
+        assert_eq!(chunk.content, "second\nthird\n");
+        assert_eq!(chunk.offset, 6);
+        assert_eq!(chunk.next_offset, 19);
+
+        let chunk = read_console_log(dir.path(), "vm-1", Some(13), None).unwrap();
+        assert_eq!(chunk.content, "third\n");
+    }
+
+    #[test]
+    fn test_rotation_keeps_output_past_whole_blocks() {
+        let dir = TempDir::new().unwrap();
+        let block = std::fs::metadata(dir.path()).unwrap().blksize() as usize;
+        let output: String = (0..3 * block + 10)
+            .map(|i| char::from(b'a' + (i % 26) as u8))
+            .collect();
+        append(dir.path(), "vm-1", &output);
+
+        assert!(rotate_if_needed(dir.path(), "vm-1", block as u64).unwrap());
+        append(dir.path(), "vm-1", "after\n");
+
+        let chunk = read_console_log(dir.path(), "vm-1", None, None).unwrap();
+        assert_eq!(chunk.content, format!("{output}after\n"));
+        assert_eq!(chunk.offset, 0);
+    }
+
+    #[test]
+    fn test_remove_console_log() {
+        let dir = TempDir::new().unwrap();
+        append(dir.path(), "vm-1", "first\n");
+        rotate_if_needed(dir.path(), "vm-1", 1).unwrap();
+
+        remove_console_log(dir.path(), "vm-1").unwrap();
+        assert!(std::fs::read_dir(dir.path()).unwrap().next().is_none());
+        remove_console_log(dir.path(), "vm-1").unwrap();
+    }
+}
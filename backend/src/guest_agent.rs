@@ -0,0 +1,606 @@
+use crate::qmp::{parse_reply, QmpError};
+use crate::vm_state::update_vm;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use tokio::task::JoinHandle;
+use tracing::{debug, error};
+
+/// How long a single guest agent query may take. A guest without the agent
+/// never answers, so this bounds every poll of such a VM.
+const AGENT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long freezing the guest's filesystems may take: each is flushed to
+/// disk first, which can be slow for a guest with much unwritten data.
+const FREEZE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a running VM's addresses are refreshed from its guest agent.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Largest block moved per `guest-file-read` or `guest-file-write`. The
+/// agent caps a single read at 48 MiB; smaller blocks keep each reply well
+/// inside `AGENT_TIMEOUT`.
+pub const FILE_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Sync IDs only have to differ between requests on the same channel.
+static NEXT_SYNC_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Unix socket QEMU exposes the VM's qemu-guest-agent channel on.
+pub fn guest_agent_socket_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.qga"))
+}
+
+/// A guest network interface as reported by qemu-guest-agent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuestInterface {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac_address: Option<String>,
+    #[serde(default)]
+    pub ipv4: Vec<String>,
+    #[serde(default)]
+    pub ipv6: Vec<String>,
+}
+
+/// The address to reach a VM on: the first IPv4 address of an interface
+/// other than loopback, preferring the one with the VM's own MAC.
+pub fn primary_ipv4(interfaces: &[GuestInterface], mac: Option<&str>) -> Option<String> {
+    let candidates = || {
+        interfaces
+            .iter()
+            .filter(|i| i.name != "lo" && !i.ipv4.is_empty())
+    };
+    let has_mac = |i: &&GuestInterface| match (&i.mac_address, mac) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        _ => false,
+    };
+    candidates()
+        .find(has_mac)
+        .or_else(|| candidates().next())
+        .map(|i| i.ipv4[0].clone())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct AgentInterface {
+    name: String,
+    #[serde(default)]
+    hardware_address: Option<String>,
+    #[serde(default)]
+    ip_addresses: Vec<AgentAddress>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct AgentAddress {
+    ip_address_type: String,
+    ip_address: String,
+}
+
+fn parse_interfaces(value: Value) -> Result<Vec<GuestInterface>, QmpError> {
+    let interfaces: Vec<AgentInterface> = serde_json::from_value(value)?;
+    Ok(interfaces
+        .into_iter()
+        .map(|interface| {
+            let addresses = |family: &str| {
+                interface
+                    .ip_addresses
+                    .iter()
+                    .filter(|a| a.ip_address_type == family)
+                    .map(|a| a.ip_address.clone())
+                    .collect()
+            };
+            GuestInterface {
+                ipv4: addresses("ipv4"),
+                ipv6: addresses("ipv6"),
+                name: interface.name,
+                mac_address: interface.hardware_address,
+            }
+        })
+        .collect())
+}
+
+/// Ask the guest agent for the guest's network interfaces and addresses.
+pub async fn network_interfaces(socket: &Path) -> Result<Vec<GuestInterface>, QmpError> {
+    let mut agent = GuestAgent::connect(socket).await?;
+    parse_interfaces(
+        agent
+            .execute("guest-network-get-interfaces", Value::Null)
+            .await?,
+    )
+}
+
+/// Output of a command run with `guest-exec`, once it has exited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecOutput {
+    /// Exit code; `None` when the process was killed by a signal.
+    pub exit_code: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i64>,
+    /// Output decoded as UTF-8, with invalid sequences replaced.
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether the agent dropped output beyond its capture limit (16 MiB).
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct AgentExecStatus {
+    exited: bool,
+    #[serde(default)]
+    exitcode: Option<i64>,
+    #[serde(default)]
+    signal: Option<i64>,
+    #[serde(default)]
+    out_data: Option<String>,
+    #[serde(default)]
+    err_data: Option<String>,
+    #[serde(default)]
+    out_truncated: bool,
+    #[serde(default)]
+    err_truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct AgentFileRead {
+    #[serde(rename = "buf-b64")]
+    buf: String,
+    eof: bool,
+}
+
+fn decode(data: &str) -> Result<Vec<u8>, QmpError> {
+    BASE64
+        .decode(data)
+        .map_err(|e| QmpError::Protocol(format!("invalid base64 from guest agent: {e}")))
+}
+
+/// A connection to a VM's guest agent, synchronised and ready for commands.
+///
+/// QEMU serves one client per channel at a time, so other clients queue
+/// until this one is dropped. After any error the connection may be out of
+/// step with the agent and should be dropped too.
+pub struct GuestAgent {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl GuestAgent {
+    /// Connect to the agent on `socket`. Fails with `QmpError::Timeout` when
+    /// the guest runs no agent, since QEMU accepts the connection anyway.
+    pub async fn connect(socket: &Path) -> Result<Self, QmpError> {
+        tokio::time::timeout(AGENT_TIMEOUT, Self::sync(socket))
+            .await
+            .unwrap_or(Err(QmpError::Timeout))
+    }
+
+    async fn sync(socket: &Path) -> Result<Self, QmpError> {
+        let stream = UnixStream::connect(socket).await?;
+        let (read_half, writer) = stream.into_split();
+        let mut agent = GuestAgent {
+            reader: BufReader::new(read_half),
+            writer,
+        };
+
+        // The channel may still hold a reply meant for an earlier client that
+        // gave up. `guest-sync-delimited` makes the agent emit a 0xFF byte
+        // before its reply, so everything up to the reply carrying our ID is
+        // skipped.
+        let sync_id = NEXT_SYNC_ID.fetch_add(1, Ordering::Relaxed);
+        agent
+            .send(json!({ "execute": "guest-sync-delimited", "arguments": { "id": sync_id } }))
+            .await?;
+        loop {
+            let line = agent.read_line().await?;
+            let line = line.rsplit(|b| *b == 0xFF).next().unwrap_or_default();
+            if let Ok(reply) = serde_json::from_slice::<Value>(line) {
+                if reply.get("return") == Some(&json!(sync_id)) {
+                    return Ok(agent);
+                }
+            }
+        }
+    }
+
+    /// Run an agent command and return its `return` value.
+    pub async fn execute(&mut self, command: &str, arguments: Value) -> Result<Value, QmpError> {
+        self.execute_within(AGENT_TIMEOUT, command, arguments).await
+    }
+
+    async fn execute_within(
+        &mut self,
+        timeout: Duration,
+        command: &str,
+        arguments: Value,
+    ) -> Result<Value, QmpError> {
+        let mut message = json!({ "execute": command });
+        if !arguments.is_null() {
+            message["arguments"] = arguments;
+        }
+        tokio::time::timeout(timeout, async {
+            self.send(message).await?;
+            let line = self.read_line().await?;
+            parse_reply(serde_json::from_slice(&line)?)
+        })
+        .await
+        .unwrap_or(Err(QmpError::Timeout))
+    }
+
+    /// Start `command` (a program and its arguments) in the guest, feeding
+    /// it `input` on stdin, and return its guest PID.
+    pub async fn exec(
+        &mut self,
+        command: &[String],
+        input: Option<&[u8]>,
+    ) -> Result<i64, QmpError> {
+        let (path, args) = command
+            .split_first()
+            .ok_or_else(|| QmpError::Protocol("empty command".to_string()))?;
+        let mut arguments = json!({ "path": path, "arg": args, "capture-output": true });
+        if let Some(input) = input {
+            arguments["input-data"] = json!(BASE64.encode(input));
+        }
+        let reply = self.execute("guest-exec", arguments).await?;
+        reply["pid"]
+            .as_i64()
+            .ok_or_else(|| QmpError::Protocol(format!("guest-exec returned no pid: {reply}")))
+    }
+
+    /// The output of the command started as `pid`, or `None` while it runs.
+    pub async fn exec_status(&mut self, pid: i64) -> Result<Option<ExecOutput>, QmpError> {
+        let reply = self
+            .execute("guest-exec-status", json!({ "pid": pid }))
+            .await?;
+        let status: AgentExecStatus = serde_json::from_value(reply)?;
+        if !status.exited {
+            return Ok(None);
+        }
+        let text = |data: Option<String>| -> Result<String, QmpError> {
+            let bytes = decode(data.as_deref().unwrap_or_default())?;
+            Ok(String::from_utf8_lossy(&bytes).into_owned())
+        };
+        Ok(Some(ExecOutput {
+            exit_code: status.exitcode,
+            signal: status.signal,
+            stdout: text(status.out_data)?,
+            stderr: text(status.err_data)?,
+            truncated: status.out_truncated || status.err_truncated,
+        }))
+    }
+
+    /// Open `path` in the guest with an `fopen` mode and return its handle.
+    pub async fn open_file(&mut self, path: &str, mode: &str) -> Result<i64, QmpError> {
+        let reply = self
+            .execute("guest-file-open", json!({ "path": path, "mode": mode }))
+            .await?;
+        reply
+            .as_i64()
+            .ok_or_else(|| QmpError::Protocol(format!("guest-file-open returned {reply}")))
+    }
+
+    /// Read up to `FILE_CHUNK_BYTES` from an open file; the flag is set at
+    /// end of file.
+    pub async fn read_file(&mut self, handle: i64) -> Result<(Vec<u8>, bool), QmpError> {
+        let reply = self
+            .execute(
+                "guest-file-read",
+                json!({ "handle": handle, "count": FILE_CHUNK_BYTES }),
+            )
+            .await?;
+        let read: AgentFileRead = serde_json::from_value(reply)?;
+        Ok((decode(&read.buf)?, read.eof))
+    }
+
+    /// Write all of `data` to an open file, in chunks the agent accepts.
+    pub async fn write_file(&mut self, handle: i64, data: &[u8]) -> Result<(), QmpError> {
+        for chunk in data.chunks(FILE_CHUNK_BYTES) {
+            self.execute(
+                "guest-file-write",
+                json!({ "handle": handle, "buf-b64": BASE64.encode(chunk) }),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn close_file(&mut self, handle: i64) -> Result<(), QmpError> {
+        self.execute("guest-file-close", json!({ "handle": handle }))
+            .await
+            .map(drop)
+    }
+
+    /// Flush and freeze the guest's filesystems, returning how many were
+    /// frozen. Writes in the guest block until `fs_thaw`.
+    pub async fn fs_freeze(&mut self) -> Result<i64, QmpError> {
+        let reply = self
+            .execute_within(FREEZE_TIMEOUT, "guest-fsfreeze-freeze", Value::Null)
+            .await?;
+        reply
+            .as_i64()
+            .ok_or_else(|| QmpError::Protocol(format!("guest-fsfreeze-freeze returned {reply}")))
+    }
+
+    pub async fn fs_thaw(&mut self) -> Result<i64, QmpError> {
+        let reply = self.execute("guest-fsfreeze-thaw", Value::Null).await?;
+        reply
+            .as_i64()
+            .ok_or_else(|| QmpError::Protocol(format!("guest-fsfreeze-thaw returned {reply}")))
+    }
+
+    async fn send(&mut self, message: Value) -> Result<(), QmpError> {
+        let mut line = serde_json::to_vec(&message)?;
+        line.push(b'\n');
+        self.writer.write_all(&line).await?;
+        Ok(())
+    }
+
+    /// Read one line as raw bytes: the sync delimiter is not valid UTF-8.
+    async fn read_line(&mut self) -> Result<Vec<u8>, QmpError> {
+        let mut line = Vec::new();
+        if self.reader.read_until(b'\n', &mut line).await? == 0 {
+            return Err(QmpError::Disconnected);
+        }
+        Ok(line)
+    }
+}
+
+/// Run `work` while the guest's filesystems are frozen, so whatever it reads
+/// from the guest's disks is consistent. The guest is thawed however `work`
+/// ends.
+pub async fn while_frozen<T>(
+    socket: &Path,
+    work: impl std::future::Future<Output = T>,
+) -> Result<T, QmpError> {
+    let mut agent = GuestAgent::connect(socket).await?;
+    if let Err(e) = agent.fs_freeze().await {
+        // A freeze that timed out may still complete in the guest; thaw on a
+        // fresh channel, since a late reply would confuse this one.
+        if let Ok(mut agent) = GuestAgent::connect(socket).await {
+            let _ = agent.fs_thaw().await;
+        }
+        return Err(e);
+    }
+
+    let result = work.await;
+
+    if let Err(e) = agent.fs_thaw().await {
+        error!(
+            "Failed to thaw the filesystems behind {socket:?}; the guest may hang on writes: {e}"
+        );
+    }
+    Ok(result)
+}
+
+/// Polls a VM's guest agent and records its interfaces; stops when dropped.
+pub struct InterfacePoller(JoinHandle<()>);
+
+impl Drop for InterfacePoller {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Keep the VM's stored `guest_interfaces` up to date while it runs. Guests
+/// without the agent simply never report any; the last report is kept if the
+/// agent stops answering.
+pub fn poll_interfaces(metadata_dir: &Path, id: &str) -> InterfacePoller {
+    let metadata_dir = metadata_dir.to_path_buf();
+    let id = id.to_string();
+    InterfacePoller(tokio::spawn(async move {
+        let socket = guest_agent_socket_path(&metadata_dir, &id);
+        let mut known: Option<Vec<GuestInterface>> = None;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            match network_interfaces(&socket).await {
+                Ok(interfaces) if known.as_ref() != Some(&interfaces) => {
+                    let stored = interfaces.clone();
+                    if update_vm(&metadata_dir, &id, |vm| vm.guest_interfaces = stored).is_ok() {
+                        known = Some(interfaces);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Guest agent of VM {id} did not report interfaces: {e}"),
+            }
+        }
+    }))
+}
+
+/// A fake guest agent on a unix socket for tests. It answers
+/// `guest-sync-delimited` itself, after a stale reply and the 0xFF delimiter
+/// like a real agent that was left a reply by an earlier client, and answers
+/// every other command with whatever `reply` returns for its name and
+/// `arguments`.
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::net::UnixListener;
+
+    pub fn spawn<F>(socket: &Path, reply: F)
+    where
+        F: Fn(&str, &Value) -> Value + Send + Sync + 'static,
+    {
+        let listener = UnixListener::bind(socket).unwrap();
+        let reply = Arc::new(reply);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let reply = reply.clone();
+                tokio::spawn(async move {
+                    let (read_half, mut write_half) = stream.into_split();
+                    let mut lines = BufReader::new(read_half).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let request: Value = serde_json::from_str(&line).unwrap();
+                        let command = request["execute"].as_str().unwrap_or_default();
+                        let out = if command == "guest-sync-delimited" {
+                            let id = &request["arguments"]["id"];
+                            let mut out = b"{\"return\": 0}\n\xff".to_vec();
+                            out.extend(format!("{{\"return\": {id}}}\n").as_bytes());
+                            out
+                        } else {
+                            format!("{}\n", reply(command, &request["arguments"])).into_bytes()
+                        };
+                        if write_half.write_all(&out).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use tokio::net::UnixListener;
+
+    const INTERFACES: &str = r#"[
+        {"name": "lo", "hardware-address": "00:00:00:00:00:00",
+         "ip-addresses": [{"ip-address-type": "ipv4", "ip-address": "127.0.0.1", "prefix": 8},
+                          {"ip-address-type": "ipv6", "ip-address": "::1", "prefix": 128}]},
+        {"name": "eth0", "hardware-address": "52:54:00:ab:cd:ef",
+         "ip-addresses": [{"ip-address-type": "ipv4", "ip-address": "192.168.100.23", "prefix": 24},
+                          {"ip-address-type": "ipv6", "ip-address": "fe80::5054:ff:feab:cdef", "prefix": 64},
+                          {"ip-address-type": "ipv6", "ip-address": "2001:db8::23", "prefix": 64}]}
+    ]"#;
+
+    #[tokio::test]
+    async fn test_network_interfaces_reports_all_addresses() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qga");
+        fake::spawn(&socket, |command, _| {
+            assert_eq!(command, "guest-network-get-interfaces");
+            json!({ "return": serde_json::from_str::<Value>(INTERFACES).unwrap() })
+        });
+
+        let interfaces = network_interfaces(&socket).await.unwrap();
+
+        assert_eq!(interfaces.len(), 2);
+        assert_eq!(interfaces[1].name, "eth0");
+        assert_eq!(
+            interfaces[1].mac_address.as_deref(),
+            Some("52:54:00:ab:cd:ef")
+        );
+        assert_eq!(interfaces[1].ipv4, ["192.168.100.23"]);
+        assert_eq!(
+            interfaces[1].ipv6,
+            ["fe80::5054:ff:feab:cdef", "2001:db8::23"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_network_interfaces_times_out_without_agent() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qga");
+        // QEMU accepts the connection whether or not the guest runs an agent.
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(async move {
+            let _stream = listener.accept().await;
+            std::future::pending::<()>().await;
+        });
+
+        let result = network_interfaces(&socket).await;
+        assert!(matches!(result, Err(QmpError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn test_exec_runs_command_and_decodes_output() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qga");
+        fake::spawn(&socket, |command, arguments| match command {
+            "guest-exec" => {
+                assert_eq!(arguments["path"], "sh");
+                assert_eq!(arguments["arg"], json!(["-c", "cat; echo oops >&2"]));
+                assert_eq!(arguments["capture-output"], true);
+                assert_eq!(arguments["input-data"], BASE64.encode("hi\n"));
+                json!({ "return": { "pid": 812 } })
+            }
+            _ => {
+                assert_eq!(arguments["pid"], 812);
+                json!({ "return": {
+                    "exited": true,
+                    "exitcode": 3,
+                    "out-data": BASE64.encode("hi\n"),
+                    "err-data": BASE64.encode("oops\n"),
+                } })
+            }
+        });
+
+        let mut agent = GuestAgent::connect(&socket).await.unwrap();
+        let command = ["sh", "-c", "cat; echo oops >&2"].map(String::from);
+        let pid = agent.exec(&command, Some(b"hi\n")).await.unwrap();
+        let output = agent.exec_status(pid).await.unwrap().unwrap();
+
+        assert_eq!(
+            output,
+            ExecOutput {
+                exit_code: Some(3),
+                signal: None,
+                stdout: "hi\n".to_string(),
+                stderr: "oops\n".to_string(),
+                truncated: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exec_status_is_none_while_running() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qga");
+        fake::spawn(&socket, |_, _| json!({ "return": { "exited": false } }));
+
+        let mut agent = GuestAgent::connect(&socket).await.unwrap();
+        assert_eq!(agent.exec_status(1).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_file_commands_surface_agent_errors() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qga");
+        fake::spawn(
+            &socket,
+            |_, _| json!({ "error": { "class": "GenericError", "desc": "failed to open file '/nope': No such file or directory" } }),
+        );
+
+        let mut agent = GuestAgent::connect(&socket).await.unwrap();
+        match agent.open_file("/nope", "r").await {
+            Err(QmpError::Command { desc, .. }) => assert!(desc.contains("No such file")),
+            other => panic!("expected a command error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_primary_ipv4_prefers_the_vms_own_interface() {
+        let interfaces = parse_interfaces(serde_json::from_str(INTERFACES).unwrap()).unwrap();
+        assert_eq!(
+            primary_ipv4(&interfaces, Some("52:54:00:AB:CD:EF")).as_deref(),
+            Some("192.168.100.23")
+        );
+
+        let mut docker = interfaces.clone();
+        docker.insert(
+            1,
+            GuestInterface {
+                name: "docker0".to_string(),
+                mac_address: Some("02:42:00:00:00:01".to_string()),
+                ipv4: vec!["172.17.0.1".to_string()],
+                ipv6: Vec::new(),
+            },
+        );
+        assert_eq!(
+            primary_ipv4(&docker, Some("52:54:00:ab:cd:ef")).as_deref(),
+            Some("192.168.100.23")
+        );
+        assert_eq!(primary_ipv4(&docker, None).as_deref(), Some("172.17.0.1"));
+        assert!(primary_ipv4(&interfaces[..1], None).is_none());
+    }
+}
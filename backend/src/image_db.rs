@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub id: String,
+    pub name: String,
+    pub os: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// Hex-encoded SHA-256 of the qcow2 file.
+    pub checksum: String,
+    pub size_bytes: u64,
+    /// Registration time as seconds since the Unix epoch.
+    pub created_secs: u64,
+}
+
+/// Path of the qcow2 file backing an image.
+pub fn image_file_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.qcow2"))
+}
+
+pub fn store_image_info(dir: &Path, image_info: &ImageInfo) -> std::io::Result<()> {
+    debug!("Storing image info: {image_info:?}");
+    let file_path = create_file_path(dir, &image_info.id);
+    let json = serde_json::to_string_pretty(&image_info)?;
+    debug!("Writing image info to: {file_path:?}");
+    fs::write(file_path, json)
+}
+
+pub fn list_images(dir: &Path) -> std::io::Result<Vec<ImageInfo>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("json") {
+            if let Ok(contents) = fs::read_to_string(&path) {
+                if let Ok(image_info) = serde_json::from_str(&contents) {
+                    images.push(image_info);
+                }
+            }
+        }
+    }
+    Ok(images)
+}
+
+pub fn get_image_by_id(dir: &Path, id: &str) -> std::io::Result<Option<ImageInfo>> {
+    let file_path = create_file_path(dir, id);
+
+    if !file_path.exists() {
+        return Ok(None);
+    }
+
+    match fs::read_to_string(file_path) {
+        Ok(contents) => match serde_json::from_str(&contents) {
+            Ok(image_info) => Ok(Some(image_info)),
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+pub fn delete_image_by_id(dir: &Path, id: &str) -> std::io::Result<()> {
+    let file_path = create_file_path(dir, id);
+
+    if file_path.exists() {
+        fs::remove_file(file_path)?;
+    }
+    Ok(())
+}
+
+fn create_file_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{id}.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_test_image(id: &str, name: &str) -> ImageInfo {
+        ImageInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            os: "alpine".to_string(),
+            version: "3.19".to_string(),
+            description: String::new(),
+            checksum: "abc123".to_string(),
+            size_bytes: 1024,
+            created_secs: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_store_and_get_image() {
+        let dir = TempDir::new().unwrap();
+        let image = create_test_image("img-1", "Alpine");
+
+        store_image_info(dir.path(), &image).unwrap();
+
+        let retrieved = get_image_by_id(dir.path(), "img-1").unwrap().unwrap();
+        assert_eq!(retrieved.id, image.id);
+        assert_eq!(retrieved.os, "alpine");
+        assert_eq!(retrieved.version, "3.19");
+        assert_eq!(retrieved.checksum, "abc123");
+    }
+
+    #[test]
+    fn test_get_nonexistent_image() {
+        let dir = TempDir::new().unwrap();
+        assert!(get_image_by_id(dir.path(), "nope").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_list_images_skips_qcow2_files() {
+        let dir = TempDir::new().unwrap();
+        let image = create_test_image("img-2", "Alpine");
+        store_image_info(dir.path(), &image).unwrap();
+        std::fs::write(image_file_path(dir.path(), "img-2"), "disk").unwrap();
+
+        let images = list_images(dir.path()).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].id, "img-2");
+    }
+
+    #[test]
+    fn test_list_images_nonexistent_directory() {
+        let dir = TempDir::new().unwrap();
+        let images = list_images(&dir.path().join("missing")).unwrap();
+        assert!(images.is_empty());
+    }
+
+    #[test]
+    fn test_delete_image() {
+        let dir = TempDir::new().unwrap();
+        store_image_info(dir.path(), &create_test_image("img-3", "Alpine")).unwrap();
+
+        delete_image_by_id(dir.path(), "img-3").unwrap();
+
+        assert!(get_image_by_id(dir.path(), "img-3").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_image_corrupted_json() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("bad.json"), "not json").unwrap();
+
+        let result = get_image_by_id(dir.path(), "bad");
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
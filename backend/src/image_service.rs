@@ -0,0 +1,765 @@
+use crate::config::Config;
+use crate::image_db::{
+    delete_image_by_id, get_image_by_id, image_file_path, list_images, store_image_info, ImageInfo,
+};
+use crate::qemu::disk_info;
+use crate::vm_db::list_vms;
+use axum::{
+    body::Body,
+    extract::Path as AxumPath,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Image launched when a request does not name one. Kept for compatibility
+/// with deployments that predate the image catalog.
+const LEGACY_IMAGE_FILE: &str = "alpine.qcow2";
+
+/// Size of the reads that stream a file to a client.
+pub(crate) const DOWNLOAD_CHUNK_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterImageRequest {
+    pub name: String,
+    pub os: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// Path of a qcow2 file on the worker node to copy into the catalog. It
+    /// must be inside the configured import directory.
+    #[serde(default)]
+    pub source_path: Option<String>,
+    /// URL to download the qcow2 file from.
+    #[serde(default)]
+    pub source_url: Option<String>,
+    /// Expected SHA-256 of the file; registration fails on mismatch.
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterImageResponse {
+    pub success: bool,
+    pub message: String,
+    pub id: Option<String>,
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteImageRequest {
+    pub id: String,
+}
+
+pub async fn register_image_handler(
+    Json(payload): Json<RegisterImageRequest>,
+) -> (StatusCode, Json<RegisterImageResponse>) {
+    info!(
+        "Registering image: {} {} {}",
+        payload.name, payload.os, payload.version
+    );
+    let config = Config::load().expect("Failed to load configuration");
+    register_image_response(
+        &config.storage.images_dir(),
+        &config.storage.import_dir(),
+        payload,
+    )
+    .await
+}
+
+/// Copy or download a qcow2 file into the catalog. Only standalone qcow2
+/// images are accepted, so nothing but a disk image is ever served back
+/// from `/image-file`.
+async fn register_image_response(
+    images_dir: &Path,
+    import_dir: &Path,
+    payload: RegisterImageRequest,
+) -> (StatusCode, Json<RegisterImageResponse>) {
+    let id = Uuid::new_v4().to_string();
+    let target = image_file_path(images_dir, &id);
+
+    if let Err(e) = fs::create_dir_all(images_dir).await {
+        error!("Failed to create images directory {images_dir:?}: {e}");
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create images directory: {e}"),
+        );
+    }
+
+    let fetched = match (&payload.source_path, &payload.source_url) {
+        (Some(path), None) => copy_source(import_dir, Path::new(path), &target).await,
+        (None, Some(url)) => download_source(url, &target).await,
+        _ => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "Exactly one of source_path or source_url must be given".to_string(),
+            );
+        }
+    };
+    if let Err((status, message)) = fetched {
+        error!("Failed to fetch image {}: {message}", payload.name);
+        let _ = fs::remove_file(&target).await;
+        return error_response(status, message);
+    }
+    if let Err(message) = check_qcow2(&target).await {
+        warn!("Refusing to register image {}: {message}", payload.name);
+        let _ = fs::remove_file(&target).await;
+        return error_response(StatusCode::UNPROCESSABLE_ENTITY, message);
+    }
+
+    let image_info = ImageInfo {
+        id,
+        name: payload.name,
+        os: payload.os,
+        version: payload.version,
+        description: payload.description,
+        checksum: String::new(),
+        size_bytes: 0,
+        created_secs: 0,
+    };
+    add_to_catalog(images_dir, image_info, payload.checksum.as_deref()).await
+}
+
+/// Record the qcow2 file already at the catalog path of `image_info.id` in
+/// the catalog, filling in its checksum, size and creation time. The file is
+/// removed if that fails, including when its checksum is not `expected`.
+pub(crate) async fn add_to_catalog(
+    images_dir: &Path,
+    mut image_info: ImageInfo,
+    expected: Option<&str>,
+) -> (StatusCode, Json<RegisterImageResponse>) {
+    let id = image_info.id.clone();
+    let target = image_file_path(images_dir, &id);
+
+    let checksum = match sha256_file(&target).await {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Failed to checksum image {target:?}: {e}");
+            let _ = fs::remove_file(&target).await;
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to checksum image: {e}"),
+            );
+        }
+    };
+
+    if let Some(expected) = expected {
+        if !expected.eq_ignore_ascii_case(&checksum) {
+            warn!(
+                "Checksum mismatch for image {}: expected {expected}, got {checksum}",
+                image_info.name
+            );
+            let _ = fs::remove_file(&target).await;
+            return error_response(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Checksum mismatch: expected {expected}, got {checksum}"),
+            );
+        }
+    }
+
+    image_info.checksum = checksum.clone();
+    image_info.size_bytes = fs::metadata(&target).await.map(|m| m.len()).unwrap_or(0);
+    image_info.created_secs = now_secs();
+
+    if let Err(e) = store_image_info(images_dir, &image_info) {
+        error!("Failed to store image metadata for {id}: {e}");
+        let _ = fs::remove_file(&target).await;
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to store image metadata: {e}"),
+        );
+    }
+
+    info!("Image {} ({id}) registered", image_info.name);
+    (
+        StatusCode::OK,
+        Json(RegisterImageResponse {
+            success: true,
+            message: format!("Image {} registered", image_info.name),
+            id: Some(id),
+            checksum: Some(checksum),
+        }),
+    )
+}
+
+pub async fn list_images_handler() -> impl IntoResponse {
+    let config = Config::load().expect("Failed to load configuration");
+
+    match list_images(&config.storage.images_dir()) {
+        Ok(images) => (StatusCode::OK, Json(images)).into_response(),
+        Err(e) => {
+            error!("Failed to list images: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+pub async fn image_file_handler(AxumPath(id): AxumPath<String>) -> Response {
+    let config = Config::load().expect("Failed to load configuration");
+    image_file_response(&config.storage.images_dir(), &id).await
+}
+
+/// Stream an image's qcow2 file, so other backends can register a copy of it
+/// with `source_url`.
+async fn image_file_response(images_dir: &Path, id: &str) -> Response {
+    match get_image_by_id(images_dir, id) {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "Image not found").into_response(),
+        Err(e) => {
+            error!("Error retrieving image info: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving image info: {e}"),
+            )
+                .into_response();
+        }
+    }
+    let path = image_file_path(images_dir, id);
+    let file = match fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Failed to open image file {path:?}: {e}");
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Image file is unavailable: {e}"),
+            )
+                .into_response();
+        }
+    };
+    file_response(file).await
+}
+
+/// Stream an open file as an `application/octet-stream` response body.
+pub(crate) async fn file_response(file: fs::File) -> Response {
+    let size = file.metadata().await.map(|m| m.len()).unwrap_or(0);
+
+    // After a read error the stream ends; the client sees a short body.
+    let chunks = futures_util::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buf = vec![0; DOWNLOAD_CHUNK_BYTES];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), Some(file)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    (
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, size.to_string()),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+pub async fn delete_image_handler(Json(payload): Json<DeleteImageRequest>) -> impl IntoResponse {
+    info!("Deleting image: {}", payload.id);
+    let config = Config::load().expect("Failed to load configuration");
+    delete_image_response(
+        &config.storage.images_dir(),
+        &config.storage.metadata_dir,
+        &payload.id,
+    )
+    .await
+}
+
+async fn delete_image_response(
+    images_dir: &Path,
+    metadata_dir: &Path,
+    id: &str,
+) -> axum::response::Response {
+    match get_image_by_id(images_dir, id) {
+        Ok(Some(image_info)) => {
+            let file = image_file_path(images_dir, &image_info.id);
+
+            // VM disks are overlays that read unwritten blocks from the base
+            // image, so removing it would corrupt every VM built on it.
+            let backing = std::fs::canonicalize(&file).unwrap_or_else(|_| file.clone());
+            let dependents: Vec<String> = match list_vms(metadata_dir) {
+                Ok(vms) => vms
+                    .into_iter()
+                    .filter(|vm| vm.is_backed_by(&backing))
+                    .map(|vm| vm.id)
+                    .collect(),
+                Err(e) => {
+                    error!("Failed to list VMs while deleting image {id}: {e}");
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to list VMs: {e}"),
+                    )
+                        .into_response();
+                }
+            };
+            if !dependents.is_empty() {
+                warn!("Refusing to delete image {id}: still backing {dependents:?}");
+                return (
+                    StatusCode::CONFLICT,
+                    format!("Image is still in use by VMs: {}", dependents.join(", ")),
+                )
+                    .into_response();
+            }
+
+            if let Err(e) = fs::remove_file(&file).await {
+                warn!("Could not delete image file {file:?}: {e}");
+            }
+
+            if let Err(e) = delete_image_by_id(images_dir, &image_info.id) {
+                error!("Failed to delete image metadata for {}: {e}", image_info.id);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to delete image metadata: {e}"),
+                )
+                    .into_response();
+            }
+
+            (StatusCode::OK, "Image successfully removed").into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Image not found").into_response(),
+        Err(e) => {
+            error!("Error retrieving image info: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving image info: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Resolve the qcow2 file a VM should be created from. An explicit `image_id`
+/// must exist in the catalog (404 otherwise); without one the legacy
+/// `alpine.qcow2` in `fallback_dir` is used. A catalog entry or fallback whose
+/// file is missing yields 422 so callers get a clear error instead of a 500.
+pub fn resolve_base_image(
+    images_dir: &Path,
+    image_id: Option<&str>,
+    fallback_dir: &Path,
+) -> Result<PathBuf, (StatusCode, String)> {
+    let path = match image_id {
+        Some(id) => match get_image_by_id(images_dir, id) {
+            Ok(Some(image)) => image_file_path(images_dir, &image.id),
+            Ok(None) => return Err((StatusCode::NOT_FOUND, format!("Image {id} not found"))),
+            Err(e) => {
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Error retrieving image info: {e}"),
+                ))
+            }
+        },
+        None => fallback_dir.join(LEGACY_IMAGE_FILE),
+    };
+
+    if !path.exists() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Base image file {} does not exist", path.display()),
+        ));
+    }
+    Ok(path)
+}
+
+/// Copy `source` to `target`. The source, with symlinks resolved, has to be
+/// inside `import_dir`, so only files put there for import can be copied.
+async fn copy_source(
+    import_dir: &Path,
+    source: &Path,
+    target: &Path,
+) -> Result<(), (StatusCode, String)> {
+    let resolved = match fs::canonicalize(source).await {
+        Ok(resolved) => resolved,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Source file {} does not exist", source.display()),
+            ))
+        }
+        Err(e) => {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to resolve {}: {e}", source.display()),
+            ))
+        }
+    };
+    let inside = match fs::canonicalize(import_dir).await {
+        Ok(import_dir) => resolved.starts_with(import_dir),
+        Err(_) => false,
+    };
+    if !inside {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "source_path must be inside the import directory {}",
+                import_dir.display()
+            ),
+        ));
+    }
+    match fs::copy(&resolved, target).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Source file {} does not exist", source.display()),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to copy image: {e}"),
+        )),
+    }
+}
+
+async fn download_source(url: &str, target: &Path) -> Result<(), (StatusCode, String)> {
+    let bad_gateway = |msg: String| (StatusCode::BAD_GATEWAY, msg);
+
+    let mut resp = reqwest::get(url)
+        .await
+        .map_err(|e| bad_gateway(format!("Failed to download image: {e}")))?;
+    if !resp.status().is_success() {
+        return Err(bad_gateway(format!(
+            "Image download returned status {}",
+            resp.status()
+        )));
+    }
+
+    let write_err = |e: std::io::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to write image: {e}"),
+        )
+    };
+    let mut file = fs::File::create(target).await.map_err(write_err)?;
+    while let Some(chunk) = resp
+        .chunk()
+        .await
+        .map_err(|e| bad_gateway(format!("Failed to download image: {e}")))?
+    {
+        file.write_all(&chunk).await.map_err(write_err)?;
+    }
+    file.flush().await.map_err(write_err)
+}
+
+/// Magic number every qcow2 file starts with.
+const QCOW2_MAGIC: &[u8; 4] = b"QFI\xfb";
+
+/// Check that `path` is a qcow2 image that stands alone: no backing file and
+/// no external data file, either of which could point at any file on the
+/// node. The magic number is checked first so that files which are plainly
+/// not images are turned away without running qemu-img.
+async fn check_qcow2(path: &Path) -> Result<(), String> {
+    let mut magic = [0u8; 4];
+    let read = async {
+        let mut file = fs::File::open(path).await?;
+        file.read_exact(&mut magic).await
+    };
+    if read.await.is_err() || &magic != QCOW2_MAGIC {
+        return Err("Image is not a qcow2 file".to_string());
+    }
+    let info = disk_info(path)
+        .await
+        .map_err(|e| format!("Failed to inspect image: {e}"))?;
+    if info.format != "qcow2" {
+        return Err(format!("Image is {}, not qcow2", info.format));
+    }
+    if let Some(backing_file) = info.backing_file {
+        return Err(format!("Image has a backing file ({backing_file})"));
+    }
+    if let Some(data_file) = info.data_file {
+        return Err(format!("Image has an external data file ({data_file})"));
+    }
+    Ok(())
+}
+
+async fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let output = Command::new("sha256sum").arg(path).output().await?;
+
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "sha256sum exited with status {}",
+            output.status
+        )));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(str::to_string)
+        .ok_or_else(|| std::io::Error::other("sha256sum produced no output"))
+}
+
+pub(crate) fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub(crate) fn error_response(
+    status: StatusCode,
+    message: String,
+) -> (StatusCode, Json<RegisterImageResponse>) {
+    (
+        status,
+        Json(RegisterImageResponse {
+            success: false,
+            message,
+            id: None,
+            checksum: None,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm_db::{store_vm_info, VmInfo};
+    use axum::body::to_bytes;
+    use tempfile::TempDir;
+
+    // sha256("hello")
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn request_from_path(path: &Path, checksum: Option<&str>) -> RegisterImageRequest {
+        RegisterImageRequest {
+            name: "alpine".to_string(),
+            os: "alpine".to_string(),
+            version: "3.19".to_string(),
+            description: String::new(),
+            source_path: Some(path.to_string_lossy().into_owned()),
+            source_url: None,
+            checksum: checksum.map(str::to_string),
+        }
+    }
+
+    fn image_info(id: &str) -> ImageInfo {
+        ImageInfo {
+            id: id.to_string(),
+            name: "alpine".to_string(),
+            os: "alpine".to_string(),
+            version: "3.19".to_string(),
+            description: String::new(),
+            checksum: String::new(),
+            size_bytes: 0,
+            created_secs: 0,
+        }
+    }
+
+    /// Put a file holding `content` in the catalog, as registering it would.
+    async fn catalog_fixture(images_dir: &Path, content: &str) -> String {
+        let id = Uuid::new_v4().to_string();
+        std::fs::write(image_file_path(images_dir, &id), content).unwrap();
+        let (status, _) = add_to_catalog(images_dir, image_info(&id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        id
+    }
+
+    // ── register_image_response ──────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_add_to_catalog_stores_metadata() {
+        let images = TempDir::new().unwrap();
+        let id = catalog_fixture(images.path(), "hello").await;
+
+        let info = get_image_by_id(images.path(), &id).unwrap().unwrap();
+        assert_eq!(info.checksum, HELLO_SHA256);
+        assert_eq!(info.size_bytes, 5);
+        assert!(image_file_path(images.path(), &id).exists());
+    }
+
+    #[tokio::test]
+    async fn test_add_to_catalog_checksum_mismatch_returns_422_and_cleans_up() {
+        let images = TempDir::new().unwrap();
+        std::fs::write(image_file_path(images.path(), "img-1"), "hello").unwrap();
+
+        let (status, _) =
+            add_to_catalog(images.path(), image_info("img-1"), Some("deadbeef")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let leftovers: Vec<_> = std::fs::read_dir(images.path()).unwrap().collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_image_missing_source_returns_422() {
+        let import = TempDir::new().unwrap();
+        let images = TempDir::new().unwrap();
+        let (status, _) = register_image_response(
+            images.path(),
+            import.path(),
+            request_from_path(&import.path().join("disk.qcow2"), None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(list_images(images.path()).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_image_outside_import_dir_returns_400() {
+        let import = TempDir::new().unwrap();
+        let elsewhere = TempDir::new().unwrap();
+        let images = TempDir::new().unwrap();
+        let source = elsewhere.path().join("disk.qcow2");
+        std::fs::write(&source, "hello").unwrap();
+        let link = import.path().join("link.qcow2");
+        std::os::unix::fs::symlink(&source, &link).unwrap();
+
+        for path in [&source, &link] {
+            let (status, _) = register_image_response(
+                images.path(),
+                import.path(),
+                request_from_path(path, None),
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        let leftovers: Vec<_> = std::fs::read_dir(images.path()).unwrap().collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_image_rejects_non_qcow2_and_cleans_up() {
+        let import = TempDir::new().unwrap();
+        let images = TempDir::new().unwrap();
+        let source = import.path().join("disk.qcow2");
+        std::fs::write(&source, "hello").unwrap();
+
+        let (status, Json(resp)) = register_image_response(
+            images.path(),
+            import.path(),
+            request_from_path(&source, None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(resp.message.contains("not a qcow2"));
+
+        let leftovers: Vec<_> = std::fs::read_dir(images.path()).unwrap().collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_image_requires_exactly_one_source() {
+        let images = TempDir::new().unwrap();
+        let mut req = request_from_path(Path::new("/tmp/disk.qcow2"), None);
+        req.source_url = Some("http://example.invalid/disk.qcow2".to_string());
+
+        let (status, _) = register_image_response(images.path(), images.path(), req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    // ── delete_image_response ────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_delete_image_not_found() {
+        let images = TempDir::new().unwrap();
+        let resp = delete_image_response(images.path(), images.path(), "nope").await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_image_removes_file_and_metadata() {
+        let images = TempDir::new().unwrap();
+        let id = catalog_fixture(images.path(), "hello").await;
+
+        let metadata = TempDir::new().unwrap();
+        let resp = delete_image_response(images.path(), metadata.path(), &id).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let _ = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+
+        assert!(get_image_by_id(images.path(), &id).unwrap().is_none());
+        assert!(!image_file_path(images.path(), &id).exists());
+    }
+
+    #[tokio::test]
+    async fn test_delete_image_still_backing_a_vm_returns_409() {
+        let images = TempDir::new().unwrap();
+        let metadata = TempDir::new().unwrap();
+        let id = catalog_fixture(images.path(), "hello").await;
+
+        let base = std::fs::canonicalize(image_file_path(images.path(), &id)).unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "vm".to_string(),
+            backing_chain: vec![base],
+            ..Default::default()
+        };
+        store_vm_info(metadata.path(), &vm).unwrap();
+
+        let resp = delete_image_response(images.path(), metadata.path(), &id).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("vm-1"));
+
+        assert!(get_image_by_id(images.path(), &id).unwrap().is_some());
+        assert!(image_file_path(images.path(), &id).exists());
+    }
+
+    // ── image_file_response ──────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_image_file_streams_registered_file() {
+        let images = TempDir::new().unwrap();
+        let id = catalog_fixture(images.path(), "hello").await;
+
+        let resp = image_file_response(images.path(), &id).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_LENGTH], "5");
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_image_file_unknown_id_returns_404() {
+        let images = TempDir::new().unwrap();
+        let resp = image_file_response(images.path(), "nope").await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    // ── resolve_base_image ───────────────────────────────────────────────────
+
+    #[test]
+    fn test_resolve_base_image_unknown_id_returns_404() {
+        let images = TempDir::new().unwrap();
+        let err = resolve_base_image(images.path(), Some("nope"), images.path()).unwrap_err();
+        assert_eq!(err.0, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_resolve_base_image_missing_file_returns_422() {
+        let images = TempDir::new().unwrap();
+        let info = ImageInfo {
+            id: "img-1".to_string(),
+            name: "alpine".to_string(),
+            os: "alpine".to_string(),
+            version: "3.19".to_string(),
+            description: String::new(),
+            checksum: String::new(),
+            size_bytes: 0,
+            created_secs: 0,
+        };
+        store_image_info(images.path(), &info).unwrap();
+
+        let err = resolve_base_image(images.path(), Some("img-1"), images.path()).unwrap_err();
+        assert_eq!(err.0, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn test_resolve_base_image_without_id_uses_legacy_file() {
+        let images = TempDir::new().unwrap();
+        let cwd = TempDir::new().unwrap();
+
+        let err = resolve_base_image(images.path(), None, cwd.path()).unwrap_err();
+        assert_eq!(err.0, StatusCode::UNPROCESSABLE_ENTITY);
+
+        std::fs::write(cwd.path().join("alpine.qcow2"), "disk").unwrap();
+        let path = resolve_base_image(images.path(), None, cwd.path()).unwrap();
+        assert_eq!(path, cwd.path().join("alpine.qcow2"));
+    }
+}
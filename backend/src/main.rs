@@ -5,19 +5,61 @@ use axum::{
 use tower_http::cors::{Any, CorsLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod cloud_init;
 mod config;
+mod console;
+mod guest_agent;
+mod image_db;
+mod image_service;
+mod metadata;
 mod qemu;
+mod qmp;
 mod register;
 mod vm_db;
+mod vm_exec;
+mod vm_image;
+mod vm_reboot;
+mod vm_resize;
 mod vm_service;
+mod vm_snapshot;
+mod vm_state;
+mod vm_stop;
+mod volume_archive;
+mod volume_attach;
 mod volume_db;
+mod volume_files;
 mod volume_service;
+mod volume_snapshot;
+mod ws_bridge;
+use image_service::{
+    delete_image_handler, image_file_handler, list_images_handler, register_image_handler,
+};
+use vm_exec::{exec_handler, read_file_handler, write_file_handler};
+use vm_image::create_image_handler;
+use vm_resize::resize_disk_handler;
 use vm_service::{
-    delete_vm_handler, launch_vm, list_vms_handler, start_all_vms, start_vm_handler,
-    stop_vm_handler,
+    console_handler, console_log_handler, delete_vm_handler, launch_vm,
+    list_instance_types_handler, list_vms_handler, pause_vm_handler, reboot_vm_handler,
+    reconcile_vms, resume_vm_handler, screenshot_handler, start_vm_handler, stop_vm_handler,
+    vnc_handler,
+};
+use vm_snapshot::{
+    create_snapshot_handler, delete_snapshot_handler, list_snapshots_handler,
+    revert_snapshot_handler,
+};
+use volume_archive::{export_volume_handler, import_volume_handler};
+use volume_attach::{attach_volume_handler, detach_volume_handler};
+use volume_files::{
+    delete_volume_file_handler, list_volume_files_handler, make_volume_dir_handler,
+    read_volume_file_handler, rename_volume_file_handler, write_volume_file_handler,
 };
 use volume_service::{
-    delete_volume_handler, launch_volume, list_volume_files_handler, list_volumes_handler,
+    delete_volume_handler, launch_volume, list_volumes_handler, mount_volume_handler,
+    resize_volume_handler, unmount_volume_handler,
+};
+use volume_snapshot::{
+    clone_volume_handler, create_volume_snapshot_handler, delete_volume_snapshot_handler,
+    list_volume_snapshots_handler,
 };
 
 #[tokio::main]
@@ -44,13 +86,72 @@ async fn main() {
         .route("/delete-vm", delete(delete_vm_handler))
         .route("/stop-vm", post(stop_vm_handler))
         .route("/start-vm", post(start_vm_handler))
+        .route("/reboot-vm", post(reboot_vm_handler))
+        .route("/pause-vm", post(pause_vm_handler))
+        .route("/resume-vm", post(resume_vm_handler))
+        .route("/vm-console/:id", get(console_handler))
+        .route("/vm-console-log/:id", get(console_log_handler))
+        .route("/vm-vnc/:id", get(vnc_handler))
+        .route("/vm-screenshot/:id", get(screenshot_handler))
+        .route("/vm-exec/:id", post(exec_handler))
+        .route(
+            "/vm-file/:id",
+            get(read_file_handler).put(write_file_handler),
+        )
+        .route("/resize-vm-disk", post(resize_disk_handler))
+        .route("/create-snapshot", post(create_snapshot_handler))
+        .route("/list-snapshots/:id", get(list_snapshots_handler))
+        .route("/revert-snapshot", post(revert_snapshot_handler))
+        .route("/delete-snapshot", delete(delete_snapshot_handler))
+        .route("/instance-types", get(list_instance_types_handler))
+        .route("/register-image", post(register_image_handler))
+        .route("/list-images", get(list_images_handler))
+        .route("/delete-image", delete(delete_image_handler))
+        .route("/image-file/:id", get(image_file_handler))
+        .route("/create-image", post(create_image_handler))
         .route("/launch-volume", post(launch_volume))
         .route("/list-volumes", get(list_volumes_handler))
         .route("/delete-volume", delete(delete_volume_handler))
         .route("/volume-files/:id", get(list_volume_files_handler))
+        .route(
+            "/volume-file/:id",
+            get(read_volume_file_handler)
+                .put(write_volume_file_handler)
+                .delete(delete_volume_file_handler),
+        )
+        .route("/volume-mkdir/:id", post(make_volume_dir_handler))
+        .route("/volume-rename/:id", post(rename_volume_file_handler))
+        .route("/mount-volume", post(mount_volume_handler))
+        .route("/unmount-volume", post(unmount_volume_handler))
+        .route("/resize-volume", post(resize_volume_handler))
+        .route(
+            "/create-volume-snapshot",
+            post(create_volume_snapshot_handler),
+        )
+        .route(
+            "/list-volume-snapshots/:id",
+            get(list_volume_snapshots_handler),
+        )
+        .route(
+            "/delete-volume-snapshot",
+            delete(delete_volume_snapshot_handler),
+        )
+        .route("/clone-volume", post(clone_volume_handler))
+        .route("/export-volume/:id", get(export_volume_handler))
+        .route("/import-volume", post(import_volume_handler))
+        .route("/attach-volume", post(attach_volume_handler))
+        .route("/detach-volume", post(detach_volume_handler))
         .layer(cors);
 
-    start_all_vms().await;
+    reconcile_vms().await;
+
+    // User-mode guests reach their metadata through QEMU instead.
+    if config.network_mode == config::NetworkMode::Bridge {
+        tokio::spawn(metadata::serve_bridge(
+            config.storage.metadata_dir.clone(),
+            config.metadata_listen.clone(),
+        ));
+    }
 
     // Bind first so we know the actual port before registering
     let listener =
@@ -0,0 +1,350 @@
+use crate::vm_db::{get_vm_by_id, list_vms, VmInfo};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Address guests reach the metadata service on, in both network modes.
+pub const METADATA_ADDRESS: &str = "169.254.169.254";
+
+/// Network QEMU puts user-mode guests on. QEMU only forwards guest
+/// connections (`guestfwd`) to addresses inside it, so it is the link-local
+/// /24 holding `METADATA_ADDRESS` rather than QEMU's default 10.0.2.0/24.
+/// Guests get 169.254.169.15 from its DHCP server, with the gateway on .2
+/// and DNS on .3.
+pub const USER_MODE_NETWORK: &str = "169.254.169.0/24";
+
+/// Unix socket a VM's metadata is served on. In user mode QEMU connects each
+/// guest connection to it, so the socket a request arrives on identifies the
+/// VM.
+pub fn metadata_socket_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.metadata"))
+}
+
+/// How the VM making a request is identified.
+#[derive(Clone)]
+enum Caller {
+    /// Requests arrive on the VM's own socket.
+    Vm(String),
+    /// Requests arrive over the bridge; the source IP is resolved to a MAC
+    /// through the host's ARP table.
+    ByAddress,
+}
+
+#[derive(Clone)]
+struct MetadataState {
+    metadata_dir: PathBuf,
+    caller: Caller,
+}
+
+fn router(metadata_dir: &Path, caller: Caller) -> Router {
+    Router::new()
+        .fallback(metadata_handler)
+        .with_state(MetadataState {
+            metadata_dir: metadata_dir.to_path_buf(),
+            caller,
+        })
+}
+
+async fn metadata_handler(
+    State(state): State<MetadataState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    uri: Uri,
+) -> Response {
+    let vm_info = match &state.caller {
+        Caller::Vm(id) => get_vm_by_id(&state.metadata_dir, id).ok().flatten(),
+        Caller::ByAddress => peer.and_then(|ConnectInfo(addr)| {
+            let arp = std::fs::read_to_string("/proc/net/arp").ok()?;
+            let mac = mac_for_ip(&arp, addr.ip())?;
+            vm_by_mac(&state.metadata_dir, &mac)
+        }),
+    };
+    let Some(vm_info) = vm_info else {
+        return (StatusCode::FORBIDDEN, "Caller is not a known VM").into_response();
+    };
+
+    match render(&vm_info, uri.path()) {
+        Some(body) => body.into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// The metadata document at `path`, EC2-style: directories list their
+/// entries one per line, with a trailing `/` on subdirectories.
+pub fn render(vm: &VmInfo, path: &str) -> Option<String> {
+    let hostname = || {
+        vm.cloud_init
+            .hostname
+            .clone()
+            .unwrap_or_else(|| vm.name.clone())
+    };
+    let keys = &vm.cloud_init.ssh_authorized_keys;
+
+    let path = path.trim_end_matches('/');
+    let rest = match path {
+        "" => return Some("latest/".to_string()),
+        "/latest" => return Some("meta-data/\nuser-data".to_string()),
+        "/latest/user-data" => return vm.cloud_init.user_data.clone(),
+        _ => path.strip_prefix("/latest/meta-data")?,
+    };
+    match rest {
+        "" => Some(
+            [
+                "hostname",
+                "instance-id",
+                "instance-type",
+                "local-hostname",
+                "public-keys/",
+                "tags/",
+            ]
+            .join("\n"),
+        ),
+        "/instance-id" => Some(vm.id.clone()),
+        "/instance-type" => Some(vm.instance_type.clone()),
+        "/hostname" | "/local-hostname" => Some(hostname()),
+        "/public-keys" => Some(
+            (0..keys.len())
+                .map(|i| format!("{i}=key-{i}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        "/tags" => Some("instance/".to_string()),
+        "/tags/instance" => Some(vm.tags.keys().cloned().collect::<Vec<_>>().join("\n")),
+        _ => {
+            if let Some(key) = rest.strip_prefix("/tags/instance/") {
+                return vm.tags.get(key).cloned();
+            }
+            let key = rest.strip_prefix("/public-keys/")?;
+            let (index, field) = key.split_once('/').unwrap_or((key, ""));
+            let key = keys.get(index.parse::<usize>().ok()?)?;
+            match field {
+                "" => Some("openssh-key".to_string()),
+                "openssh-key" => Some(key.clone()),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// MAC address the ARP table `/proc/net/arp` records for `ip`.
+fn mac_for_ip(arp_table: &str, ip: IpAddr) -> Option<String> {
+    let ip = ip.to_string();
+    arp_table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Flags 0x0 marks an incomplete entry without a usable address.
+        match fields.as_slice() {
+            [addr, _, flags, mac, ..] if *addr == ip && *flags != "0x0" => Some(mac.to_string()),
+            _ => None,
+        }
+    })
+}
+
+fn vm_by_mac(metadata_dir: &Path, mac: &str) -> Option<VmInfo> {
+    list_vms(metadata_dir).ok()?.into_iter().find(|vm| {
+        vm.mac_address
+            .as_deref()
+            .is_some_and(|m| m.eq_ignore_ascii_case(mac))
+    })
+}
+
+/// Serves a VM's metadata socket; stops serving and removes the socket when
+/// dropped.
+pub struct VmMetadataServer {
+    task: JoinHandle<()>,
+    path: PathBuf,
+}
+
+impl Drop for VmMetadataServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Start serving a VM's metadata on its socket, replacing any stale socket
+/// left by a previous run.
+pub fn serve_vm_socket(metadata_dir: &Path, id: &str) -> std::io::Result<VmMetadataServer> {
+    let path = metadata_socket_path(metadata_dir, id);
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    let app = router(metadata_dir, Caller::Vm(id.to_string()));
+
+    let task = tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let service = TowerToHyperService::new(app.clone());
+            tokio::spawn(async move {
+                if let Err(e) = hyper::server::conn::http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    debug!("Metadata connection failed: {e}");
+                }
+            });
+        }
+    });
+    Ok(VmMetadataServer { task, path })
+}
+
+/// Serve metadata to bridged guests on `listen`, normally 169.254.169.254:80,
+/// which has to be assigned to the bridge. Runs until the backend exits.
+pub async fn serve_bridge(metadata_dir: PathBuf, listen: String) {
+    let listener = match TcpListener::bind(&listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Metadata service disabled: cannot listen on {listen}: {e}");
+            return;
+        }
+    };
+    info!("Metadata service listening on {listen}");
+    let app = router(&metadata_dir, Caller::ByAddress);
+    if let Err(e) = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    {
+        warn!("Metadata service stopped: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cloud_init::CloudInitConfig;
+    use crate::vm_db::store_vm_info;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+
+    fn test_vm() -> VmInfo {
+        VmInfo {
+            id: "vm-1".to_string(),
+            name: "web".to_string(),
+            instance_type: "t2.micro".to_string(),
+            mac_address: Some("52:54:00:AB:CD:EF".to_string()),
+            cloud_init: CloudInitConfig {
+                user_data: Some("#cloud-config\n".to_string()),
+                hostname: None,
+                ssh_authorized_keys: vec!["ssh-ed25519 AAAA one".to_string()],
+            },
+            tags: [("env".to_string(), "prod".to_string())].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_instance_fields() {
+        let vm = test_vm();
+        assert_eq!(
+            render(&vm, "/latest/meta-data/instance-id").as_deref(),
+            Some("vm-1")
+        );
+        assert_eq!(
+            render(&vm, "/latest/meta-data/hostname").as_deref(),
+            Some("web")
+        );
+        assert_eq!(
+            render(&vm, "/latest/meta-data/instance-type").as_deref(),
+            Some("t2.micro")
+        );
+        assert_eq!(
+            render(&vm, "/latest/user-data").as_deref(),
+            Some("#cloud-config\n")
+        );
+        assert!(render(&vm, "/latest/meta-data/")
+            .unwrap()
+            .contains("public-keys/"));
+        assert!(render(&vm, "/latest/meta-data/nope").is_none());
+        assert!(render(&vm, "/other").is_none());
+    }
+
+    #[test]
+    fn test_render_public_keys() {
+        let vm = test_vm();
+        assert_eq!(
+            render(&vm, "/latest/meta-data/public-keys/").as_deref(),
+            Some("0=key-0")
+        );
+        assert_eq!(
+            render(&vm, "/latest/meta-data/public-keys/0/openssh-key").as_deref(),
+            Some("ssh-ed25519 AAAA one")
+        );
+        assert!(render(&vm, "/latest/meta-data/public-keys/1/openssh-key").is_none());
+    }
+
+    #[test]
+    fn test_render_tags() {
+        let vm = test_vm();
+        assert_eq!(
+            render(&vm, "/latest/meta-data/tags/instance").as_deref(),
+            Some("env")
+        );
+        assert_eq!(
+            render(&vm, "/latest/meta-data/tags/instance/env").as_deref(),
+            Some("prod")
+        );
+        assert!(render(&vm, "/latest/meta-data/tags/instance/missing").is_none());
+    }
+
+    #[test]
+    fn test_user_data_missing_is_not_found() {
+        let vm = VmInfo::default();
+        assert!(render(&vm, "/latest/user-data").is_none());
+    }
+
+    #[test]
+    fn test_mac_for_ip_reads_arp_table() {
+        let arp = "\
+IP address       HW type     Flags       HW address            Mask     Device
+192.168.100.23   0x1         0x2         52:54:00:ab:cd:ef     *        br0
+192.168.100.24   0x1         0x0         00:00:00:00:00:00     *        br0
+";
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            mac_for_ip(arp, ip("192.168.100.23")).as_deref(),
+            Some("52:54:00:ab:cd:ef")
+        );
+        assert!(mac_for_ip(arp, ip("192.168.100.24")).is_none());
+        assert!(mac_for_ip(arp, ip("10.0.0.1")).is_none());
+    }
+
+    #[test]
+    fn test_vm_by_mac_ignores_case() {
+        let dir = TempDir::new().unwrap();
+        store_vm_info(dir.path(), &test_vm()).unwrap();
+        let vm = vm_by_mac(dir.path(), "52:54:00:ab:cd:ef").unwrap();
+        assert_eq!(vm.id, "vm-1");
+        assert!(vm_by_mac(dir.path(), "52:54:00:00:00:00").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_vm_socket_serves_the_vms_metadata() {
+        let dir = TempDir::new().unwrap();
+        store_vm_info(dir.path(), &test_vm()).unwrap();
+        let server = serve_vm_socket(dir.path(), "vm-1").unwrap();
+
+        let mut stream = UnixStream::connect(metadata_socket_path(dir.path(), "vm-1"))
+            .await
+            .unwrap();
+        stream
+            .write_all(b"GET /latest/meta-data/instance-id HTTP/1.1\r\nHost: 169.254.169.254\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("vm-1"));
+
+        drop(server);
+        assert!(!metadata_socket_path(dir.path(), "vm-1").exists());
+    }
+}
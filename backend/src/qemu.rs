@@ -1,6 +1,10 @@
+use crate::metadata::{METADATA_ADDRESS, USER_MODE_NETWORK};
+use crate::vm_db::AttachedVolume;
 use nix::errno::Errno;
 use nix::sys::signal::kill;
 use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use tokio::process::{Child, Command};
 use uuid::Uuid;
 
@@ -9,6 +13,24 @@ pub enum NetworkConfig {
     Bridge { mac_address: String },
 }
 
+/// CPU and memory allocation passed to QEMU via `-smp` and `-m`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VmShape {
+    pub vcpus: u32,
+    pub memory_mb: u32,
+}
+
+impl Default for VmShape {
+    /// The fixed shape every VM used before instance types were honoured;
+    /// applied to metadata written by older versions.
+    fn default() -> Self {
+        Self {
+            vcpus: 6,
+            memory_mb: 8192,
+        }
+    }
+}
+
 pub fn mac_from_uuid(id: &str) -> String {
     let uuid = Uuid::parse_str(id).expect("valid uuid");
     let b = uuid.as_bytes();
@@ -24,65 +46,381 @@ pub fn is_process_running(pid: u32) -> bool {
     if pid > i32::MAX as u32 {
         return false;
     }
+    // PID 0 is what a VM that never ran stores, and kill(0, ...) would
+    // signal our own process group rather than fail.
+    if pid == 0 {
+        return false;
+    }
     matches!(
         kill(Pid::from_raw(pid as i32), None),
         Ok(()) | Err(Errno::EPERM)
     )
 }
 
-/// Send a single command to a running QEMU human-monitor socket.
-/// Drains the QEMU greeting banner before writing to avoid stalling the
-/// socket buffer.
-pub async fn send_monitor_command(socket_path: &str, command: &str) -> std::io::Result<()> {
-    use tokio::io::{AsyncReadExt, AsyncWriteExt};
-    use tokio::net::UnixStream;
+/// Whether `pid` is the QEMU started for the VM whose QMP socket is
+/// `qmp_socket`, judged from its command line. Guards against adopting or
+/// signalling an unrelated process that reused a stale PID.
+pub fn is_vm_process(pid: u32, qmp_socket: &Path) -> bool {
+    match std::fs::read(format!("/proc/{pid}/cmdline")) {
+        Ok(cmdline) => cmdline_matches(&cmdline, qmp_socket),
+        Err(_) => false,
+    }
+}
+
+/// Check a NUL-separated `/proc/<pid>/cmdline` for a `qemu-system-*` binary
+/// serving QMP on `qmp_socket`, or, if started by a backend that predates
+/// QMP, the human monitor on the `.monitor` socket next to it.
+fn cmdline_matches(cmdline: &[u8], qmp_socket: &Path) -> bool {
+    let mut args = cmdline
+        .split(|b| *b == 0)
+        .map(|arg| String::from_utf8_lossy(arg).into_owned());
+    let is_qemu = args.next().is_some_and(|program| {
+        Path::new(&program)
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("qemu-system"))
+    });
+    let qmp_arg = qmp_listen_arg(&qmp_socket.to_string_lossy());
+    let monitor_arg = qmp_listen_arg(&qmp_socket.with_extension("monitor").to_string_lossy());
+    is_qemu && args.any(|arg| arg == qmp_arg || arg == monitor_arg)
+}
 
-    let mut stream = UnixStream::connect(socket_path).await?;
+pub(crate) fn qmp_listen_arg(qmp_socket: &str) -> String {
+    format!("unix:{qmp_socket},server,nowait")
+}
 
-    // Drain the QEMU greeting ("QEMU … monitor …\r\n(qemu) ") with a short
-    // timeout so we don't block if the VM is slow to respond.
-    let mut banner = vec![0u8; 512];
-    let _ = tokio::time::timeout(
-        std::time::Duration::from_millis(200),
-        stream.read(&mut banner),
-    )
-    .await;
+/// Path of the QMP socket QEMU listens on for a VM.
+pub fn qmp_socket_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.qmp"))
+}
 
-    stream.write_all(format!("{command}\n").as_bytes()).await?;
-    stream.flush().await?;
-    Ok(())
+/// Path of the unix socket QEMU serves VNC on for a VM launched with graphics.
+pub fn vnc_socket_path(metadata_dir: &Path, id: &str) -> PathBuf {
+    metadata_dir.join(format!("{id}.vnc"))
+}
+
+/// Where QEMU serves a VM's serial console and logs everything written to it.
+pub struct ConsoleFiles<'a> {
+    pub socket: &'a str,
+    pub log: &'a str,
+}
+
+/// Optional additions to a VM's QEMU command line.
+#[derive(Default)]
+pub struct VmExtras<'a> {
+    /// Unix socket to serve VNC on; without one the VM runs `-nographic`.
+    pub vnc_socket: Option<&'a str>,
+    /// cloud-init seed ISO.
+    pub seed_file: Option<&'a str>,
+    /// Volumes to attach as virtio disks.
+    pub volumes: &'a [AttachedVolume],
+    /// Unix socket serving the VM's metadata. In user mode guest connections
+    /// to `metadata::METADATA_ADDRESS` port 80 are forwarded to it.
+    pub metadata_socket: Option<&'a str>,
+    /// Unix socket for the qemu-guest-agent channel.
+    pub guest_agent_socket: Option<&'a str>,
 }
 
 pub fn vm_start(
     qcow2_file: &str,
+    shape: &VmShape,
     network: &NetworkConfig,
-    monitor_socket: &str,
+    qmp_socket: &str,
+    console: &ConsoleFiles,
+    extras: &VmExtras,
 ) -> Result<Child, std::io::Error> {
+    build_vm_command(qcow2_file, shape, network, qmp_socket, console, extras).spawn()
+}
+
+/// Bytes in a gigabyte as disk sizes are counted here (GiB).
+pub const GIB: u64 = 1 << 30;
+
+/// Block device ID of the VM's disk, which QMP block commands refer to.
+pub const DISK_DEVICE: &str = "disk0";
+
+/// Create a qcow2 overlay at `overlay` whose backing file is `base`. The
+/// overlay starts empty and only stores blocks the guest writes, so creation
+/// takes constant time regardless of the base image size. `base` should be
+/// absolute: qemu-img records it verbatim and resolves relative paths against
+/// the overlay's directory. With `size_bytes` the overlay's disk is larger
+/// than `base`'s; without it the two are the same size.
+pub async fn create_overlay(
+    base: &Path,
+    overlay: &Path,
+    size_bytes: Option<u64>,
+) -> std::io::Result<()> {
+    let status = build_overlay_command(base, overlay, size_bytes)
+        .status()
+        .await?;
+
+    if !status.success() {
+        return Err(std::io::Error::other(format!(
+            "qemu-img create exited with status {status}"
+        )));
+    }
+    Ok(())
+}
+
+/// What `qemu-img snapshot` does with an internal snapshot.
+#[derive(Debug, Clone, Copy)]
+pub enum SnapshotOp {
+    Create,
+    /// Revert the image to the snapshot.
+    Apply,
+    Delete,
+}
+
+/// Create, apply or delete the internal snapshot `name` of a qcow2 file. The
+/// image must not be in use by a running QEMU.
+pub async fn internal_snapshot(op: SnapshotOp, name: &str, file: &Path) -> std::io::Result<()> {
+    run_qemu_img(build_snapshot_command(op, name, file), "snapshot").await
+}
+
+/// Point `overlay` at `backing` (or at no backing file), first copying into
+/// it every block in which the old and new chains differ, so the guest sees
+/// the same data.
+pub async fn rebase(overlay: &Path, backing: Option<&Path>) -> std::io::Result<()> {
+    run_qemu_img(build_rebase_command(overlay, backing), "rebase").await
+}
+
+/// Write the disk contents the guest sees through `source` and its backing
+/// chain into a standalone qcow2 file at `target`. `force_share` reads an
+/// image that a running QEMU has open, which is only consistent while the
+/// guest's writes are held back.
+pub async fn convert_image(source: &Path, target: &Path, force_share: bool) -> std::io::Result<()> {
+    run_qemu_img(
+        build_convert_command(source, target, force_share),
+        "convert",
+    )
+    .await
+}
+
+/// Size in bytes of the disk a qcow2 file presents to the guest. Works on
+/// files a running QEMU has open.
+pub async fn virtual_size(file: &Path) -> std::io::Result<u64> {
+    let mut cmd = Command::new("qemu-img");
+    cmd.args(["info", "-U", "--output=json"]).arg(file);
+    parse_virtual_size(&run_qemu_img_output(cmd, "info").await?)
+}
+
+/// What `qemu-img info` reports about an image file's format and the other
+/// files it refers to.
+#[derive(Debug, PartialEq)]
+pub struct DiskInfo {
+    pub format: String,
+    pub backing_file: Option<String>,
+    /// External data file of a qcow2 image, which holds its guest data.
+    pub data_file: Option<String>,
+}
+
+/// Inspect an image file with `qemu-img info`, letting it probe the format.
+pub async fn disk_info(file: &Path) -> std::io::Result<DiskInfo> {
+    let mut cmd = Command::new("qemu-img");
+    cmd.args(["info", "--output=json"]).arg(file);
+    parse_disk_info(&run_qemu_img_output(cmd, "info").await?)
+}
+
+/// Grow the disk a qcow2 file presents to the guest to `size_bytes`. The
+/// image must not be in use by a running QEMU.
+pub async fn resize_image(file: &Path, size_bytes: u64) -> std::io::Result<()> {
+    run_qemu_img(build_resize_command(file, size_bytes), "resize").await
+}
+
+async fn run_qemu_img(cmd: Command, what: &str) -> std::io::Result<()> {
+    run_qemu_img_output(cmd, what).await.map(drop)
+}
+
+async fn run_qemu_img_output(mut cmd: Command, what: &str) -> std::io::Result<Vec<u8>> {
+    let output = cmd.output().await?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "qemu-img {what} exited with status {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+fn parse_virtual_size(info: &[u8]) -> std::io::Result<u64> {
+    let info: serde_json::Value = serde_json::from_slice(info)?;
+    info["virtual-size"].as_u64().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "qemu-img info reported no virtual-size",
+        )
+    })
+}
+
+fn parse_disk_info(info: &[u8]) -> std::io::Result<DiskInfo> {
+    let info: serde_json::Value = serde_json::from_slice(info)?;
+    let format = info["format"].as_str().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "qemu-img info reported no format",
+        )
+    })?;
+    Ok(DiskInfo {
+        format: format.to_string(),
+        backing_file: info["backing-filename"].as_str().map(str::to_string),
+        data_file: info["format-specific"]["data"]["data-file"]
+            .as_str()
+            .map(str::to_string),
+    })
+}
+
+fn build_resize_command(file: &Path, size_bytes: u64) -> Command {
+    let mut cmd = Command::new("qemu-img");
+    cmd.args(["resize", "-f", "qcow2"])
+        .arg(file)
+        .arg(size_bytes.to_string());
+    cmd
+}
+
+fn build_snapshot_command(op: SnapshotOp, name: &str, file: &Path) -> Command {
+    let flag = match op {
+        SnapshotOp::Create => "-c",
+        SnapshotOp::Apply => "-a",
+        SnapshotOp::Delete => "-d",
+    };
+    let mut cmd = Command::new("qemu-img");
+    cmd.args(["snapshot", flag, name]).arg(file);
+    cmd
+}
+
+fn build_rebase_command(overlay: &Path, backing: Option<&Path>) -> Command {
+    let mut cmd = Command::new("qemu-img");
+    cmd.args(["rebase", "-f", "qcow2", "-b"]);
+    match backing {
+        Some(backing) => cmd.arg(backing).args(["-F", "qcow2"]),
+        None => cmd.arg(""),
+    };
+    cmd.arg(overlay);
+    cmd
+}
+
+fn build_convert_command(source: &Path, target: &Path, force_share: bool) -> Command {
+    let mut cmd = Command::new("qemu-img");
+    cmd.arg("convert");
+    if force_share {
+        cmd.arg("-U");
+    }
+    cmd.args(["-O", "qcow2"]).arg(source).arg(target);
+    cmd
+}
+
+fn build_overlay_command(base: &Path, overlay: &Path, size_bytes: Option<u64>) -> Command {
+    let mut cmd = Command::new("qemu-img");
+    cmd.args(["create", "-f", "qcow2", "-F", "qcow2", "-b"])
+        .arg(base)
+        .arg(overlay);
+    if let Some(size_bytes) = size_bytes {
+        cmd.arg(size_bytes.to_string());
+    }
+    cmd
+}
+
+fn build_vm_command(
+    qcow2_file: &str,
+    shape: &VmShape,
+    network: &NetworkConfig,
+    qmp_socket: &str,
+    console: &ConsoleFiles,
+    extras: &VmExtras,
+) -> Command {
     let mut cmd = Command::new("qemu-system-x86_64");
     cmd.args([
         "-m",
-        "8192",
+        &shape.memory_mb.to_string(),
         "-smp",
-        "6",
+        &shape.vcpus.to_string(),
         "-drive",
-        &format!("file={qcow2_file}"),
+        &format!("file={qcow2_file},id={DISK_DEVICE}"),
         "-boot",
         "d",
         "-vga",
         "virtio",
-        "-nographic",
-        "-monitor",
-        &format!("unix:{monitor_socket},server,nowait"),
+        "-qmp",
+        &qmp_listen_arg(qmp_socket),
     ]);
 
+    // The display is only exported when asked for, over a unix socket so it
+    // is reachable through the backend's WebSocket bridge but not the network.
+    match extras.vnc_socket {
+        Some(socket) => {
+            cmd.args(["-display", "none", "-vnc", &format!("unix:{socket}")]);
+        }
+        None => {
+            cmd.arg("-nographic");
+        }
+    }
+
+    // The serial console goes to a socket instead of stdio. QEMU itself
+    // appends all output to the log, so nothing is lost while no client is
+    // connected or the backend is down.
+    cmd.args([
+        "-chardev",
+        &format!(
+            "socket,id=console0,path={},server=on,wait=off,logfile={},logappend=on",
+            console.socket, console.log
+        ),
+        "-serial",
+        "chardev:console0",
+    ]);
+
+    // Attach the cloud-init seed as a read-only virtio disk rather than a
+    // CD-ROM so `-boot d` keeps booting from the VM's own disk.
+    if let Some(seed) = extras.seed_file {
+        cmd.args([
+            "-drive",
+            &format!("file={seed},format=raw,if=virtio,readonly=on"),
+        ]);
+    }
+
+    // Volumes get the same node names and device IDs as when hot-plugged, so
+    // they can be detached the same way whichever route attached them.
+    for volume in extras.volumes {
+        cmd.args([
+            "-blockdev",
+            &format!(
+                "driver=raw,node-name={},file.driver=file,file.filename={}",
+                volume.node_name(),
+                volume.path.display()
+            ),
+            "-device",
+            &format!(
+                "virtio-blk-pci,drive={},id={}",
+                volume.node_name(),
+                volume.device_id()
+            ),
+        ]);
+    }
+
+    // The guest agent talks over a virtio-serial port with the name
+    // qemu-guest-agent looks for. Guests without the agent just leave the
+    // port unused.
+    if let Some(socket) = extras.guest_agent_socket {
+        cmd.args([
+            "-chardev",
+            &format!("socket,id=qga0,path={socket},server=on,wait=off"),
+            "-device",
+            "virtio-serial",
+            "-device",
+            "virtserialport,chardev=qga0,name=org.qemu.guest_agent.0",
+        ]);
+    }
+
     match network {
         NetworkConfig::User { ssh_port } => {
-            cmd.args([
-                "-netdev",
-                &format!("user,id=net0,hostfwd=tcp::{ssh_port}-:22"),
-                "-device",
-                "e1000,netdev=net0",
-            ]);
+            let mut netdev =
+                format!("user,id=net0,net={USER_MODE_NETWORK},hostfwd=tcp::{ssh_port}-:22");
+            // QEMU runs the command once per guest connection, with the
+            // connection on its stdin and stdout.
+            if let Some(socket) = extras.metadata_socket {
+                netdev.push_str(&format!(
+                    ",guestfwd=tcp:{METADATA_ADDRESS}:80-cmd:socat STDIO UNIX-CONNECT:{socket}"
+                ));
+            }
+            cmd.args(["-netdev", &netdev, "-device", "e1000,netdev=net0"]);
         }
         NetworkConfig::Bridge { mac_address } => {
             cmd.args([
@@ -94,14 +432,12 @@ pub fn vm_start(
         }
     }
 
-    cmd.spawn()
+    cmd
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::Arc;
-    use tokio::sync::Mutex;
 
     #[test]
     fn test_is_process_running_current_process() {
@@ -114,47 +450,371 @@ mod tests {
         assert!(!is_process_running(u32::MAX));
     }
 
-    #[tokio::test]
-    async fn test_send_monitor_command_sends_command_to_socket() {
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
-        use tokio::net::UnixListener;
-
-        let tmp = tempfile::TempDir::new().unwrap();
-        let socket_path = tmp.path().join("test.monitor");
-
-        let listener = UnixListener::bind(&socket_path).unwrap();
-        let received = Arc::new(Mutex::new(Vec::new()));
-        let received_clone = received.clone();
-
-        tokio::spawn(async move {
-            let (mut stream, _) = listener.accept().await.unwrap();
-            // Simulate QEMU greeting
-            stream
-                .write_all(b"QEMU 7.2.0 monitor - type 'help'\r\n(qemu) ")
-                .await
-                .unwrap();
-            // Read the command sent by the client
-            let mut buf = vec![0u8; 64];
-            if let Ok(n) = stream.read(&mut buf).await {
-                *received_clone.lock().await = buf[..n].to_vec();
+    #[test]
+    fn test_is_process_running_pid_zero() {
+        assert!(!is_process_running(0));
+    }
+
+    #[test]
+    fn test_cmdline_matches_qemu_serving_the_socket() {
+        let cmdline =
+            b"/usr/bin/qemu-system-x86_64\0-m\08192\0-qmp\0unix:/meta/vm-1.qmp,server,nowait\0";
+        assert!(cmdline_matches(cmdline, Path::new("/meta/vm-1.qmp")));
+        assert!(!cmdline_matches(cmdline, Path::new("/meta/vm-2.qmp")));
+    }
+
+    #[test]
+    fn test_cmdline_matches_legacy_monitor_socket() {
+        let cmdline = b"qemu-system-x86_64\0-monitor\0unix:/meta/vm-1.monitor,server,nowait\0";
+        assert!(cmdline_matches(cmdline, Path::new("/meta/vm-1.qmp")));
+        assert!(!cmdline_matches(cmdline, Path::new("/meta/vm-2.qmp")));
+    }
+
+    #[test]
+    fn test_cmdline_matches_rejects_other_programs() {
+        let cmdline = b"/usr/bin/sleep\0unix:/meta/vm-1.qmp,server,nowait\0";
+        assert!(!cmdline_matches(cmdline, Path::new("/meta/vm-1.qmp")));
+        assert!(!cmdline_matches(b"", Path::new("/meta/vm-1.qmp")));
+    }
+
+    #[test]
+    fn test_is_vm_process_rejects_unrelated_process() {
+        assert!(!is_vm_process(
+            std::process::id(),
+            Path::new("/meta/vm-1.qmp")
+        ));
+        assert!(!is_vm_process(u32::MAX, Path::new("/meta/vm-1.qmp")));
+    }
+
+    fn command_args(cmd: &Command) -> Vec<String> {
+        cmd.as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_build_vm_command_applies_shape() {
+        let shape = VmShape {
+            vcpus: 2,
+            memory_mb: 4096,
+        };
+        let cmd = build_vm_command(
+            "/tmp/vm.qcow2",
+            &shape,
+            &NetworkConfig::User { ssh_port: 50000 },
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &VmExtras::default(),
+        );
+        let args = command_args(&cmd);
+
+        let m = args.iter().position(|a| a == "-m").unwrap();
+        assert_eq!(args[m + 1], "4096");
+        let smp = args.iter().position(|a| a == "-smp").unwrap();
+        assert_eq!(args[smp + 1], "2");
+    }
+
+    const TEST_CONSOLE: ConsoleFiles = ConsoleFiles {
+        socket: "/tmp/vm.console",
+        log: "/tmp/vm.console.log",
+    };
+
+    #[test]
+    fn test_build_vm_command_serves_console_and_logs_it() {
+        let cmd = build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &NetworkConfig::User { ssh_port: 50000 },
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &VmExtras::default(),
+        );
+        let args = command_args(&cmd);
+
+        let chardev = args.iter().position(|a| a == "-chardev").unwrap();
+        assert_eq!(
+            args[chardev + 1],
+            "socket,id=console0,path=/tmp/vm.console,server=on,wait=off,logfile=/tmp/vm.console.log,logappend=on"
+        );
+        let serial = args.iter().position(|a| a == "-serial").unwrap();
+        assert_eq!(args[serial + 1], "chardev:console0");
+    }
+
+    #[test]
+    fn test_build_vm_command_exports_vnc_only_when_asked() {
+        let headless = command_args(&build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &NetworkConfig::User { ssh_port: 50000 },
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &VmExtras::default(),
+        ));
+        assert!(headless.iter().any(|a| a == "-nographic"));
+        assert!(!headless.iter().any(|a| a == "-vnc"));
+
+        let graphical = command_args(&build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &NetworkConfig::User { ssh_port: 50000 },
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &VmExtras {
+                vnc_socket: Some("/tmp/vm.vnc"),
+                ..Default::default()
+            },
+        ));
+        assert!(!graphical.iter().any(|a| a == "-nographic"));
+        let vnc = graphical.iter().position(|a| a == "-vnc").unwrap();
+        assert_eq!(graphical[vnc + 1], "unix:/tmp/vm.vnc");
+        let display = graphical.iter().position(|a| a == "-display").unwrap();
+        assert_eq!(graphical[display + 1], "none");
+    }
+
+    #[test]
+    fn test_build_vm_command_listens_for_qmp() {
+        let cmd = build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &NetworkConfig::User { ssh_port: 50000 },
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &VmExtras::default(),
+        );
+        let args = command_args(&cmd);
+
+        let qmp = args.iter().position(|a| a == "-qmp").unwrap();
+        assert_eq!(args[qmp + 1], "unix:/tmp/vm.qmp,server,nowait");
+        assert!(!args.iter().any(|a| a == "-monitor"));
+    }
+
+    #[test]
+    fn test_build_vm_command_attaches_seed_drive() {
+        let network = NetworkConfig::User { ssh_port: 50000 };
+        let seed_drive = "file=/tmp/vm.seed.iso,format=raw,if=virtio,readonly=on";
+
+        let with_seed = command_args(&build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &network,
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &VmExtras {
+                seed_file: Some("/tmp/vm.seed.iso"),
+                ..Default::default()
+            },
+        ));
+        assert!(with_seed.iter().any(|a| a == seed_drive));
+
+        let without_seed = command_args(&build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &network,
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &VmExtras::default(),
+        ));
+        assert!(!without_seed.iter().any(|a| a.contains("seed")));
+    }
+
+    #[test]
+    fn test_build_vm_command_forwards_metadata_in_user_mode() {
+        let extras = VmExtras {
+            metadata_socket: Some("/tmp/vm.metadata"),
+            ..Default::default()
+        };
+        let user = command_args(&build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &NetworkConfig::User { ssh_port: 50000 },
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &extras,
+        ));
+        let netdev = user.iter().position(|a| a == "-netdev").unwrap();
+        assert_eq!(
+            user[netdev + 1],
+            "user,id=net0,net=169.254.169.0/24,hostfwd=tcp::50000-:22,\
+             guestfwd=tcp:169.254.169.254:80-cmd:socat STDIO UNIX-CONNECT:/tmp/vm.metadata"
+        );
+
+        // Bridged guests reach the backend's own listener instead.
+        let bridge = command_args(&build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &NetworkConfig::Bridge {
+                mac_address: "52:54:00:12:34:56".to_string(),
+            },
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &extras,
+        ));
+        assert!(!bridge.iter().any(|a| a.contains("guestfwd")));
+    }
+
+    #[test]
+    fn test_build_vm_command_adds_guest_agent_channel() {
+        let args = command_args(&build_vm_command(
+            "/tmp/vm.qcow2",
+            &VmShape::default(),
+            &NetworkConfig::User { ssh_port: 50000 },
+            "/tmp/vm.qmp",
+            &TEST_CONSOLE,
+            &VmExtras {
+                guest_agent_socket: Some("/tmp/vm.qga"),
+                ..Default::default()
+            },
+        ));
+        assert!(args
+            .iter()
+            .any(|a| a == "socket,id=qga0,path=/tmp/vm.qga,server=on,wait=off"));
+        assert!(args
+            .iter()
+            .any(|a| a == "virtserialport,chardev=qga0,name=org.qemu.guest_agent.0"));
+    }
+
+    #[test]
+    fn test_build_overlay_command_sets_backing_file() {
+        let cmd = build_overlay_command(
+            Path::new("/images/base.qcow2"),
+            Path::new("/vms/vm.qcow2"),
+            None,
+        );
+        let args = command_args(&cmd);
+
+        assert_eq!(cmd.as_std().get_program(), "qemu-img");
+        assert_eq!(
+            args,
+            [
+                "create",
+                "-f",
+                "qcow2",
+                "-F",
+                "qcow2",
+                "-b",
+                "/images/base.qcow2",
+                "/vms/vm.qcow2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_overlay_command_with_size_grows_disk() {
+        let cmd = build_overlay_command(
+            Path::new("/images/base.qcow2"),
+            Path::new("/vms/vm.qcow2"),
+            Some(8 << 30),
+        );
+        assert_eq!(command_args(&cmd).last().unwrap(), "8589934592");
+    }
+
+    #[test]
+    fn test_build_resize_command() {
+        let cmd = build_resize_command(Path::new("/vms/vm.qcow2"), 10 << 30);
+        assert_eq!(
+            command_args(&cmd),
+            ["resize", "-f", "qcow2", "/vms/vm.qcow2", "10737418240"]
+        );
+    }
+
+    #[test]
+    fn test_parse_virtual_size() {
+        let info = br#"{"virtual-size": 2147483648, "filename": "vm.qcow2", "format": "qcow2"}"#;
+        assert_eq!(parse_virtual_size(info).unwrap(), 2 << 30);
+        assert!(parse_virtual_size(br#"{"format": "qcow2"}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_disk_info() {
+        let info = br#"{"format": "qcow2", "backing-filename": "/etc/shadow",
+            "format-specific": {"type": "qcow2", "data": {"data-file": "/dev/sda"}}}"#;
+        assert_eq!(
+            parse_disk_info(info).unwrap(),
+            DiskInfo {
+                format: "qcow2".to_string(),
+                backing_file: Some("/etc/shadow".to_string()),
+                data_file: Some("/dev/sda".to_string()),
             }
-        });
+        );
+        let info = parse_disk_info(br#"{"format": "raw", "virtual-size": 5}"#).unwrap();
+        assert_eq!(info.format, "raw");
+        assert_eq!(info.backing_file, None);
+        assert!(parse_disk_info(b"{}").is_err());
+    }
 
-        tokio::task::yield_now().await;
+    #[test]
+    fn test_build_snapshot_command_per_op() {
+        let file = Path::new("/vms/vm.qcow2");
+        for (op, flag) in [
+            (SnapshotOp::Create, "-c"),
+            (SnapshotOp::Apply, "-a"),
+            (SnapshotOp::Delete, "-d"),
+        ] {
+            let cmd = build_snapshot_command(op, "before-upgrade", file);
+            assert_eq!(
+                command_args(&cmd),
+                ["snapshot", flag, "before-upgrade", "/vms/vm.qcow2"]
+            );
+        }
+    }
 
-        send_monitor_command(socket_path.to_str().unwrap(), "system_powerdown")
-            .await
-            .unwrap();
+    #[test]
+    fn test_build_rebase_command_with_and_without_backing() {
+        let overlay = Path::new("/vms/vm.1.qcow2");
+        let cmd = build_rebase_command(overlay, Some(Path::new("/images/base.qcow2")));
+        assert_eq!(
+            command_args(&cmd),
+            [
+                "rebase",
+                "-f",
+                "qcow2",
+                "-b",
+                "/images/base.qcow2",
+                "-F",
+                "qcow2",
+                "/vms/vm.1.qcow2"
+            ]
+        );
 
-        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let cmd = build_rebase_command(overlay, None);
+        assert_eq!(
+            command_args(&cmd),
+            ["rebase", "-f", "qcow2", "-b", "", "/vms/vm.1.qcow2"]
+        );
+    }
 
-        let data = received.lock().await;
-        assert_eq!(String::from_utf8_lossy(&data), "system_powerdown\n");
+    #[test]
+    fn test_build_convert_command_shares_lock_only_when_asked() {
+        let source = Path::new("/vms/vm.qcow2");
+        let target = Path::new("/images/new.qcow2");
+        assert_eq!(
+            command_args(&build_convert_command(source, target, false)),
+            [
+                "convert",
+                "-O",
+                "qcow2",
+                "/vms/vm.qcow2",
+                "/images/new.qcow2"
+            ]
+        );
+        assert_eq!(
+            command_args(&build_convert_command(source, target, true)),
+            [
+                "convert",
+                "-U",
+                "-O",
+                "qcow2",
+                "/vms/vm.qcow2",
+                "/images/new.qcow2"
+            ]
+        );
     }
 
-    #[tokio::test]
-    async fn test_send_monitor_command_returns_error_for_missing_socket() {
-        let result = send_monitor_command("/nonexistent/path.monitor", "stop").await;
-        assert!(result.is_err());
+    #[test]
+    fn test_vm_shape_default_matches_legacy_size() {
+        let shape = VmShape::default();
+        assert_eq!(shape.vcpus, 6);
+        assert_eq!(shape.memory_mb, 8192);
     }
 }
@@ -0,0 +1,640 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::UnixStream;
+use tokio::sync::{broadcast, oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// How long to wait for the greeting or for the reply to a single command.
+const QMP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum QmpError {
+    Io(std::io::Error),
+    /// QEMU sent something that is not valid QMP.
+    Protocol(String),
+    /// QEMU rejected the command.
+    Command {
+        class: String,
+        desc: String,
+    },
+    /// The socket closed before a reply arrived.
+    Disconnected,
+    Timeout,
+}
+
+impl std::fmt::Display for QmpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QmpError::Io(e) => write!(f, "QMP I/O error: {e}"),
+            QmpError::Protocol(msg) => write!(f, "QMP protocol error: {msg}"),
+            QmpError::Command { class, desc } => write!(f, "QMP command failed ({class}): {desc}"),
+            QmpError::Disconnected => write!(f, "QMP connection closed"),
+            QmpError::Timeout => write!(f, "timed out waiting for QMP reply"),
+        }
+    }
+}
+
+impl std::error::Error for QmpError {}
+
+impl From<std::io::Error> for QmpError {
+    fn from(e: std::io::Error) -> Self {
+        QmpError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for QmpError {
+    fn from(e: serde_json::Error) -> Self {
+        QmpError::Protocol(e.to_string())
+    }
+}
+
+/// An asynchronous event emitted by QEMU, e.g. `SHUTDOWN` or `STOP`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct QmpEvent {
+    pub event: String,
+    #[serde(default)]
+    pub data: Value,
+    pub timestamp: QmpTimestamp,
+}
+
+/// When QEMU emitted an event. Sub-second precision is not needed here, so
+/// the `microseconds` half is ignored.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct QmpTimestamp {
+    pub seconds: i64,
+}
+
+/// Reply to `query-status`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StatusInfo {
+    pub running: bool,
+    /// QEMU run state, e.g. "running", "paused" or "shutdown".
+    pub status: String,
+}
+
+/// Connections handed out by `QmpClient::shared`, keyed by socket path. Each
+/// slot is locked while connecting, so callers racing for the same socket
+/// wait for one connection instead of opening a second.
+type SharedSlot = Arc<Mutex<Option<Arc<QmpClient>>>>;
+static SHARED: LazyLock<std::sync::Mutex<HashMap<PathBuf, SharedSlot>>> =
+    LazyLock::new(Default::default);
+
+type Pending = Arc<std::sync::Mutex<HashMap<u64, oneshot::Sender<Result<Value, QmpError>>>>>;
+
+/// Client for a QEMU Machine Protocol socket. A background task reads the
+/// socket, routing replies to the caller that issued the matching command `id`
+/// and broadcasting events to every `events()` subscriber.
+pub struct QmpClient {
+    writer: Mutex<OwnedWriteHalf>,
+    pending: Pending,
+    next_id: AtomicU64,
+    events: broadcast::Sender<QmpEvent>,
+    reader: JoinHandle<()>,
+}
+
+impl QmpClient {
+    /// Connect to a QMP socket, read the greeting and negotiate capabilities.
+    pub async fn connect(socket_path: &Path) -> Result<Self, QmpError> {
+        let stream = UnixStream::connect(socket_path).await?;
+        let (read_half, write_half) = stream.into_split();
+        let mut lines = BufReader::new(read_half).lines();
+
+        let greeting = tokio::time::timeout(QMP_TIMEOUT, lines.next_line())
+            .await
+            .map_err(|_| QmpError::Timeout)??
+            .ok_or(QmpError::Disconnected)?;
+        let greeting: Value = serde_json::from_str(&greeting)?;
+        if greeting.get("QMP").is_none() {
+            return Err(QmpError::Protocol(format!(
+                "expected QMP greeting, got {greeting}"
+            )));
+        }
+
+        let pending: Pending = Arc::default();
+        let (events, _) = broadcast::channel(64);
+        let reader = tokio::spawn(read_loop(lines, pending.clone(), events.clone()));
+
+        let client = Self {
+            writer: Mutex::new(write_half),
+            pending,
+            next_id: AtomicU64::new(0),
+            events,
+            reader,
+        };
+        client.execute("qmp_capabilities", None).await?;
+        Ok(client)
+    }
+
+    /// Return the open connection to `socket_path`, connecting if there is
+    /// none. QEMU serves one client per QMP socket at a time, so everything
+    /// that talks to a VM's monitor (its watcher and API requests) shares one
+    /// connection rather than opening its own.
+    pub async fn shared(socket_path: &Path) -> Result<Arc<Self>, QmpError> {
+        let slot = {
+            let mut shared = SHARED.lock().unwrap();
+            // Drop slots nobody else holds whose connection is gone.
+            shared.retain(|_, slot| {
+                Arc::strong_count(slot) > 1
+                    || slot.try_lock().map_or(true, |client| {
+                        client.as_ref().is_some_and(|c| c.is_connected())
+                    })
+            });
+            shared.entry(socket_path.to_path_buf()).or_default().clone()
+        };
+        let mut slot = slot.lock().await;
+        if let Some(client) = slot.as_ref().filter(|client| client.is_connected()) {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(Self::connect(socket_path).await?);
+        *slot = Some(client.clone());
+        Ok(client)
+    }
+
+    /// Whether the socket is still open. Once QEMU closes it every command
+    /// fails with `Disconnected`.
+    pub fn is_connected(&self) -> bool {
+        !self.reader.is_finished()
+    }
+
+    /// Subscribe to events received from now on.
+    pub fn events(&self) -> broadcast::Receiver<QmpEvent> {
+        self.events.subscribe()
+    }
+
+    /// Run a QMP command and return its `return` value.
+    pub async fn execute(
+        &self,
+        command: &str,
+        arguments: Option<Value>,
+    ) -> Result<Value, QmpError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut message = json!({ "execute": command, "id": id });
+        if let Some(arguments) = arguments {
+            message["arguments"] = arguments;
+        }
+        let mut line = serde_json::to_vec(&message)?;
+        line.push(b'\n');
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        debug!("QMP -> {message}");
+        if let Err(e) = self.writer.lock().await.write_all(&line).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e.into());
+        }
+
+        match tokio::time::timeout(QMP_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(QmpError::Disconnected),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(QmpError::Timeout)
+            }
+        }
+    }
+
+    /// Ask the guest to shut down via an ACPI power button press.
+    pub async fn system_powerdown(&self) -> Result<(), QmpError> {
+        self.execute("system_powerdown", None).await.map(|_| ())
+    }
+
+    /// Hard-reset the machine, like pressing a reset button.
+    pub async fn system_reset(&self) -> Result<(), QmpError> {
+        self.execute("system_reset", None).await.map(|_| ())
+    }
+
+    /// Pause all vCPUs.
+    pub async fn stop(&self) -> Result<(), QmpError> {
+        self.execute("stop", None).await.map(|_| ())
+    }
+
+    /// Resume vCPUs paused by `stop` or by a `shutdown=pause` action.
+    pub async fn cont(&self) -> Result<(), QmpError> {
+        self.execute("cont", None).await.map(|_| ())
+    }
+
+    /// Choose what QEMU does when the guest shuts down: `poweroff` (exit,
+    /// the default) or `pause` (keep the process so the machine can be reset).
+    pub async fn set_shutdown_action(&self, action: &str) -> Result<(), QmpError> {
+        self.execute("set-action", Some(json!({ "shutdown": action })))
+            .await
+            .map(|_| ())
+    }
+
+    /// Have QEMU write the VM's display to `path` as a PNG (QEMU 7.1+).
+    pub async fn screendump(&self, path: &Path) -> Result<(), QmpError> {
+        self.execute(
+            "screendump",
+            Some(json!({ "filename": path, "format": "png" })),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Freeze the image behind block device `device` and continue writing
+    /// to a new qcow2 overlay at `overlay`, which QEMU creates.
+    pub async fn blockdev_snapshot_sync(
+        &self,
+        device: &str,
+        overlay: &Path,
+    ) -> Result<(), QmpError> {
+        self.execute(
+            "blockdev-snapshot-sync",
+            Some(json!({ "device": device, "snapshot-file": overlay, "format": "qcow2" })),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Grow block device `device` to `size` bytes while the guest runs.
+    pub async fn block_resize(&self, device: &str, size: u64) -> Result<(), QmpError> {
+        self.execute(
+            "block_resize",
+            Some(json!({ "device": device, "size": size })),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Size in bytes of the disk behind block device `device`.
+    pub async fn block_virtual_size(&self, device: &str) -> Result<u64, QmpError> {
+        let devices = self.execute("query-block", None).await?;
+        devices
+            .as_array()
+            .and_then(|devices| devices.iter().find(|d| d["device"] == device))
+            .and_then(|d| d["inserted"]["image"]["virtual-size"].as_u64())
+            .ok_or_else(|| QmpError::Protocol(format!("query-block has no disk {device}")))
+    }
+
+    pub async fn query_status(&self) -> Result<StatusInfo, QmpError> {
+        let value = self.execute("query-status", None).await?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+impl Drop for QmpClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+async fn read_loop(
+    mut lines: Lines<BufReader<OwnedReadHalf>>,
+    pending: Pending,
+    events: broadcast::Sender<QmpEvent>,
+) {
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                warn!("QMP read failed: {e}");
+                break;
+            }
+        };
+        debug!("QMP <- {line}");
+
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Ignoring malformed QMP message {line:?}: {e}");
+                continue;
+            }
+        };
+
+        if message.get("event").is_some() {
+            match serde_json::from_value::<QmpEvent>(message) {
+                Ok(event) => {
+                    debug!(
+                        "QMP event {} at {}: {}",
+                        event.event, event.timestamp.seconds, event.data
+                    );
+                    // A send error only means nobody is subscribed.
+                    let _ = events.send(event);
+                }
+                Err(e) => warn!("Ignoring malformed QMP event: {e}"),
+            }
+            continue;
+        }
+
+        let Some(id) = message.get("id").and_then(Value::as_u64) else {
+            warn!("Ignoring QMP reply without id: {message}");
+            continue;
+        };
+        let Some(tx) = pending.lock().unwrap().remove(&id) else {
+            continue;
+        };
+        let _ = tx.send(parse_reply(message));
+    }
+
+    for (_, tx) in pending.lock().unwrap().drain() {
+        let _ = tx.send(Err(QmpError::Disconnected));
+    }
+}
+
+pub(crate) fn parse_reply(mut message: Value) -> Result<Value, QmpError> {
+    if let Some(value) = message.get_mut("return") {
+        return Ok(value.take());
+    }
+    if let Some(error) = message.get("error") {
+        let field = |name: &str| {
+            error
+                .get(name)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        return Err(QmpError::Command {
+            class: field("class"),
+            desc: field("desc"),
+        });
+    }
+    Err(QmpError::Protocol(format!(
+        "reply has neither return nor error: {message}"
+    )))
+}
+
+/// A minimal QMP server for tests. It greets each connection, records every
+/// command name it receives and answers with whatever `reply` returns for it
+/// (the `id` is added automatically).
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    pub fn spawn<F>(socket_path: &Path, reply: F) -> Arc<Mutex<Vec<String>>>
+    where
+        F: Fn(&str) -> Value + Send + Sync + 'static,
+    {
+        spawn_scripted(socket_path, move |command| vec![reply(command)])
+    }
+
+    /// Like `spawn`, but each command may answer with several messages, e.g.
+    /// a reply followed by the events it causes. Messages without an `event`
+    /// key are treated as the reply and get the command's `id`.
+    pub fn spawn_scripted<F>(socket_path: &Path, script: F) -> Arc<Mutex<Vec<String>>>
+    where
+        F: Fn(&str) -> Vec<Value> + Send + Sync + 'static,
+    {
+        serve(socket_path, move |command, _| script(command))
+    }
+
+    /// Like `spawn`, but `reply` also sees the command's `arguments` (`null`
+    /// when there are none), e.g. to create the file `screendump` names.
+    pub fn spawn_with_args<F>(socket_path: &Path, reply: F) -> Arc<Mutex<Vec<String>>>
+    where
+        F: Fn(&str, &Value) -> Value + Send + Sync + 'static,
+    {
+        serve(socket_path, move |command, arguments| {
+            vec![reply(command, arguments)]
+        })
+    }
+
+    fn serve<F>(socket_path: &Path, script: F) -> Arc<Mutex<Vec<String>>>
+    where
+        F: Fn(&str, &Value) -> Vec<Value> + Send + Sync + 'static,
+    {
+        let listener = UnixListener::bind(socket_path).unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        let script = Arc::new(script);
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let received = received_clone.clone();
+                let script = script.clone();
+                tokio::spawn(async move {
+                    let (read_half, mut write_half) = stream.into_split();
+                    let greeting = r#"{"QMP": {"version": {"qemu": {"major": 8, "minor": 2, "micro": 0}, "package": ""}, "capabilities": []}}"#;
+                    write_half
+                        .write_all(format!("{greeting}\r\n").as_bytes())
+                        .await
+                        .unwrap();
+
+                    let mut lines = BufReader::new(read_half).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let request: Value = serde_json::from_str(&line).unwrap();
+                        let command = request["execute"].as_str().unwrap_or_default().to_string();
+                        let mut messages = script(&command, &request["arguments"]);
+                        received.lock().await.push(command);
+                        let mut out = String::new();
+                        for message in &mut messages {
+                            if message.get("event").is_none() {
+                                message["id"] = request["id"].clone();
+                            }
+                            out.push_str(&format!("{message}\r\n"));
+                        }
+                        if write_half.write_all(out.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        received
+    }
+
+    /// An event message as QEMU would send it.
+    pub fn event(name: &str, data: Value) -> Value {
+        json!({
+            "event": name,
+            "data": data,
+            "timestamp": { "seconds": 1700000000, "microseconds": 0 }
+        })
+    }
+
+    /// Reply with an empty success to every command.
+    pub fn ok(_: &str) -> Value {
+        json!({ "return": {} })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    #[tokio::test]
+    async fn test_connect_negotiates_capabilities() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let received = fake::spawn(&socket, fake::ok);
+
+        let _client = QmpClient::connect(&socket).await.unwrap();
+
+        assert_eq!(*received.lock().await, ["qmp_capabilities"]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_shared_callers_get_one_connection() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let received = fake::spawn(&socket, fake::ok);
+
+        let (a, b) = tokio::join!(QmpClient::shared(&socket), QmpClient::shared(&socket));
+
+        assert!(Arc::ptr_eq(&a.unwrap(), &b.unwrap()));
+        assert_eq!(*received.lock().await, ["qmp_capabilities"]);
+    }
+
+    #[tokio::test]
+    async fn test_query_status_parses_reply() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        fake::spawn(&socket, |command| match command {
+            "query-status" => json!({ "return": { "running": true, "status": "running" } }),
+            _ => json!({ "return": {} }),
+        });
+
+        let client = QmpClient::connect(&socket).await.unwrap();
+        let status = client.query_status().await.unwrap();
+
+        assert!(status.running);
+        assert_eq!(status.status, "running");
+    }
+
+    #[tokio::test]
+    async fn test_command_error_is_surfaced() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        fake::spawn(&socket, |command| match command {
+            "system_powerdown" => {
+                json!({ "error": { "class": "GenericError", "desc": "not allowed" } })
+            }
+            _ => json!({ "return": {} }),
+        });
+
+        let client = QmpClient::connect(&socket).await.unwrap();
+        match client.system_powerdown().await {
+            Err(QmpError::Command { class, desc }) => {
+                assert_eq!(class, "GenericError");
+                assert_eq!(desc, "not allowed");
+            }
+            other => panic!("expected command error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replies_are_matched_by_id_and_events_broadcast() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        // Answers two commands out of order, with an event in between.
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            write_half
+                .write_all(b"{\"QMP\": {\"version\": {}, \"capabilities\": []}}\r\n")
+                .await
+                .unwrap();
+            let mut lines = BufReader::new(read_half).lines();
+
+            let caps: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            write_half
+                .write_all(format!("{}\r\n", json!({ "return": {}, "id": caps["id"] })).as_bytes())
+                .await
+                .unwrap();
+
+            let first: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            let second: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            let event = json!({
+                "event": "SHUTDOWN",
+                "data": { "guest": true },
+                "timestamp": { "seconds": 1, "microseconds": 2 }
+            });
+            for message in [
+                json!({ "return": "second", "id": second["id"] }),
+                event,
+                json!({ "return": "first", "id": first["id"] }),
+            ] {
+                write_half
+                    .write_all(format!("{message}\r\n").as_bytes())
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let client = QmpClient::connect(&socket).await.unwrap();
+        let mut events = client.events();
+
+        let (first, second) = tokio::join!(
+            client.execute("first", None),
+            client.execute("second", Some(json!({ "x": 1 })))
+        );
+        assert_eq!(first.unwrap(), "first");
+        assert_eq!(second.unwrap(), "second");
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.event, "SHUTDOWN");
+        assert_eq!(event.data["guest"], true);
+        assert_eq!(event.timestamp.seconds, 1);
+    }
+
+    #[tokio::test]
+    async fn test_pending_command_fails_when_socket_closes() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            write_half
+                .write_all(b"{\"QMP\": {\"version\": {}, \"capabilities\": []}}\r\n")
+                .await
+                .unwrap();
+            let mut lines = BufReader::new(read_half).lines();
+            let caps: Value =
+                serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            write_half
+                .write_all(format!("{}\r\n", json!({ "return": {}, "id": caps["id"] })).as_bytes())
+                .await
+                .unwrap();
+            // Read the next command, then hang up without replying.
+            let _ = lines.next_line().await;
+        });
+
+        let client = QmpClient::connect(&socket).await.unwrap();
+        let result = client.execute("quit", None).await;
+        assert!(matches!(result, Err(QmpError::Disconnected)));
+    }
+
+    #[tokio::test]
+    async fn test_connect_rejects_non_qmp_greeting() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.monitor");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"{\"hello\": \"world\"}\r\n")
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        });
+
+        let result = QmpClient::connect(&socket).await;
+        assert!(matches!(result, Err(QmpError::Protocol(_))));
+    }
+
+    #[tokio::test]
+    async fn test_connect_missing_socket_returns_io_error() {
+        let result = QmpClient::connect(Path::new("/nonexistent/path.qmp")).await;
+        assert!(matches!(result, Err(QmpError::Io(_))));
+    }
+}
@@ -1,9 +1,14 @@
+use crate::cloud_init::CloudInitConfig;
+use crate::guest_agent::GuestInterface;
+use crate::qemu::VmShape;
+use crate::vm_state::VmState;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::debug;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct VmInfo {
     pub id: String,
     pub name: String,
@@ -12,6 +17,142 @@ pub struct VmInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mac_address: Option<String>,
     pub pid: u32,
+    /// Instance type the VM was launched with. Empty for VMs created before
+    /// the instance-type catalog existed.
+    #[serde(default)]
+    pub instance_type: String,
+    /// CPU and memory the VM is started with. Persisted rather than looked up
+    /// from the catalog so a relaunch keeps the original shape.
+    #[serde(default)]
+    pub shape: VmShape,
+    /// Catalog image the VM's disk was created from. `None` for VMs created
+    /// from the legacy `alpine.qcow2`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_id: Option<String>,
+    /// Backing files of the VM's qcow2 overlay, nearest first. Empty for VMs
+    /// whose disk is a standalone copy of the base image.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backing_chain: Vec<PathBuf>,
+    /// Guest configuration the cloud-init seed is rebuilt from on every start.
+    #[serde(default, skip_serializing_if = "CloudInitConfig::is_empty")]
+    pub cloud_init: CloudInitConfig,
+    #[serde(default)]
+    pub state: VmState,
+    /// Why the VM entered its current state, e.g. "QEMU exited unexpectedly".
+    #[serde(default)]
+    pub state_reason: String,
+    /// Desired state: whether the VM should be running. Backend startup only
+    /// starts VMs with this set; stop clears it and start sets it again.
+    /// Defaults to true for older metadata, whose VMs were always relaunched.
+    #[serde(default = "default_autostart")]
+    pub autostart: bool,
+    /// Whether QEMU exports the VM's display over VNC. Off unless requested
+    /// at launch; VMs without it run with `-nographic`.
+    #[serde(default)]
+    pub graphics: bool,
+    /// Volumes attached as virtio disks, in attach order. Passed to QEMU on
+    /// every start.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<AttachedVolume>,
+    /// Free-form labels, served to the guest by the metadata service.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Network interfaces last reported by the guest agent while the VM was
+    /// running. Cleared when QEMU exits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub guest_interfaces: Vec<GuestInterface>,
+    /// Disk QEMU writes to: `<qcow2_dir>/<id>.qcow2` at launch, then any new
+    /// overlay a live snapshot moves writes to. Unset for VMs launched by
+    /// older versions, which run from `<qcow2_dir>/<name>.qcow2`. See
+    /// `disk_path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk: Option<PathBuf>,
+    /// Snapshots of the VM's disk, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snapshots: Vec<VmSnapshot>,
+}
+
+/// A named point-in-time state of a VM's disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VmSnapshot {
+    pub name: String,
+    /// Creation time as seconds since the Unix epoch.
+    pub created_secs: u64,
+    pub kind: SnapshotKind,
+    /// For an internal snapshot the qcow2 file holding it; for an external
+    /// one the file it froze, which has been read-only since.
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotKind {
+    /// Stored inside the disk's qcow2 file with `qemu-img snapshot`; taken
+    /// while the VM is stopped.
+    Internal,
+    /// The disk file is frozen and writes go to a new overlay on top of it;
+    /// taken live over QMP.
+    External,
+}
+
+/// A volume attached to a VM. The volume's own metadata records the VM in
+/// `attached_to`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachedVolume {
+    pub id: String,
+    /// Raw image file backing the disk.
+    pub path: PathBuf,
+}
+
+impl AttachedVolume {
+    /// QEMU block node name. QEMU caps node names at 31 characters, too short
+    /// for a UUID, so this keeps the first 28 of its hex digits.
+    pub fn node_name(&self) -> String {
+        let hex: String = self.id.chars().filter(|c| *c != '-').take(28).collect();
+        format!("vol{hex}")
+    }
+
+    /// QEMU device ID of the virtio disk.
+    pub fn device_id(&self) -> String {
+        format!("voldev-{}", self.id)
+    }
+}
+
+fn default_autostart() -> bool {
+    true
+}
+
+impl VmInfo {
+    /// Whether `image` appears anywhere in this VM's backing chain.
+    pub fn is_backed_by(&self, image: &Path) -> bool {
+        self.backing_chain.iter().any(|b| b == image)
+    }
+
+    /// The qcow2 file QEMU runs the VM from.
+    pub fn disk_path(&self, qcow2_dir: &Path) -> PathBuf {
+        self.disk
+            .clone()
+            .unwrap_or_else(|| self.default_disk(qcow2_dir))
+    }
+
+    /// Where the VM's disk lives when `disk` is unset: named by ID, as VM
+    /// names need not be unique.
+    pub fn default_disk(&self, qcow2_dir: &Path) -> PathBuf {
+        qcow2_dir.join(format!("{}.qcow2", self.id))
+    }
+
+    /// Every disk file that belongs to this VM alone: the disk QEMU writes to
+    /// and the files frozen by external snapshots.
+    pub fn own_disk_files(&self, qcow2_dir: &Path) -> Vec<PathBuf> {
+        std::iter::once(self.disk_path(qcow2_dir))
+            .chain(
+                self.snapshots
+                    .iter()
+                    .filter(|s| s.kind == SnapshotKind::External)
+                    .map(|s| s.file.clone()),
+            )
+            .collect()
+    }
 }
 
 pub fn store_vm_info(dir: &Path, vm_info: &VmInfo) -> std::io::Result<()> {
@@ -72,6 +213,27 @@ pub fn delete_vm_by_id(dir: &Path, id: &str) -> std::io::Result<Option<VmInfo>>
     Ok(None)
 }
 
+/// A VM named "web" with the given ID, state and PID, other fields left at
+/// their defaults; shared by the tests of every module that stores VMs.
+#[cfg(test)]
+pub(crate) fn test_vm(id: &str, state: VmState, pid: u32) -> VmInfo {
+    VmInfo {
+        id: id.to_string(),
+        name: "web".to_string(),
+        pid,
+        state,
+        ..Default::default()
+    }
+}
+
+/// Store `test_vm(id, state, pid)` in `dir`.
+#[cfg(test)]
+pub(crate) fn store_test_vm(dir: &Path, id: &str, state: VmState, pid: u32) -> VmInfo {
+    let vm = test_vm(id, state, pid);
+    store_vm_info(dir, &vm).unwrap();
+    vm
+}
+
 fn create_file_path(dir: &Path, id: &str) -> PathBuf {
     dir.join(format!("{id}.json"))
 }
@@ -88,6 +250,7 @@ mod tests {
             ssh_port: Some(2222),
             mac_address: None,
             pid: 1234,
+            ..Default::default()
         }
     }
 
@@ -105,6 +268,57 @@ mod tests {
         assert_eq!(retrieved.pid, vm.pid);
     }
 
+    #[test]
+    fn test_store_and_get_vm_preserves_instance_type_and_shape() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            instance_type: "t2.small".to_string(),
+            shape: VmShape {
+                vcpus: 1,
+                memory_mb: 2048,
+            },
+            ..create_test_vm("test-7", "Test VM 7")
+        };
+
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let retrieved = get_vm_by_id(dir.path(), "test-7").unwrap().unwrap();
+        assert_eq!(retrieved.instance_type, "t2.small");
+        assert_eq!(retrieved.shape, vm.shape);
+    }
+
+    #[test]
+    fn test_get_vm_without_shape_uses_legacy_default() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join("old-vm.json"),
+            r#"{"id":"old-vm","name":"old","ssh_port":2222,"pid":1}"#,
+        )
+        .unwrap();
+
+        let retrieved = get_vm_by_id(dir.path(), "old-vm").unwrap().unwrap();
+        assert_eq!(retrieved.instance_type, "");
+        assert_eq!(retrieved.shape, VmShape::default());
+        assert_eq!(retrieved.state, VmState::Stopped);
+        assert!(retrieved.autostart);
+    }
+
+    #[test]
+    fn test_store_and_get_vm_preserves_backing_chain() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            backing_chain: vec![PathBuf::from("/images/base.qcow2")],
+            ..create_test_vm("test-8", "Test VM 8")
+        };
+
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let retrieved = get_vm_by_id(dir.path(), "test-8").unwrap().unwrap();
+        assert_eq!(retrieved.backing_chain, vm.backing_chain);
+        assert!(retrieved.is_backed_by(Path::new("/images/base.qcow2")));
+        assert!(!retrieved.is_backed_by(Path::new("/images/other.qcow2")));
+    }
+
     #[test]
     fn test_get_nonexistent_vm() {
         let dir = TempDir::new().unwrap();
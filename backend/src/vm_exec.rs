@@ -0,0 +1,504 @@
+use crate::config::Config;
+use crate::guest_agent::{guest_agent_socket_path, ExecOutput, GuestAgent};
+use crate::qmp::QmpError;
+use crate::vm_service::active_vm;
+use crate::vm_state::VmState;
+use axum::{
+    body::Body,
+    extract::{Path as AxumPath, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How long `/vm-exec` waits for a command by default.
+const DEFAULT_EXEC_TIMEOUT_SECS: u64 = 30;
+
+/// How often a running command is checked for completion.
+const EXEC_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecRequest {
+    /// Program to run, looked up on the guest's PATH, followed by its
+    /// arguments. No shell is involved; pass `["sh", "-c", "..."]` for one.
+    pub command: Vec<String>,
+    /// Written to the command's stdin, which is closed afterwards.
+    #[serde(default)]
+    pub input: Option<String>,
+    /// Seconds to wait for the command to exit. Defaults to 30.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VmFileQuery {
+    /// Absolute path of the file inside the guest.
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WriteFileResponse {
+    pub bytes_written: u64,
+}
+
+pub async fn exec_handler(
+    AxumPath(id): AxumPath<String>,
+    Json(payload): Json<ExecRequest>,
+) -> Response {
+    info!("Running {:?} in VM {id}", payload.command);
+    let config = Config::load().expect("Failed to load configuration");
+    exec_response(&config.storage.metadata_dir, &id, &payload).await
+}
+
+/// Run a command through the guest agent and wait for it to exit. A command
+/// still running at the timeout is left running in the guest: the agent has
+/// no way to kill it.
+async fn exec_response(metadata_dir: &Path, id: &str, request: &ExecRequest) -> Response {
+    if request.command.is_empty() {
+        return (StatusCode::BAD_REQUEST, "command must not be empty").into_response();
+    }
+    let mut agent = match connect_agent(metadata_dir, id, "run commands").await {
+        Ok(agent) => agent,
+        Err(error) => return error.into_response(),
+    };
+    let input = request.input.as_deref().map(str::as_bytes);
+    let pid = match agent.exec(&request.command, input).await {
+        Ok(pid) => pid,
+        Err(e) => return agent_error(id, e).into_response(),
+    };
+
+    let timeout_secs = request.timeout_secs.unwrap_or(DEFAULT_EXEC_TIMEOUT_SECS);
+    let wait = wait_for_exit(&mut agent, pid);
+    match tokio::time::timeout(Duration::from_secs(timeout_secs), wait).await {
+        Ok(Ok(output)) => Json(output).into_response(),
+        Ok(Err(e)) => agent_error(id, e).into_response(),
+        Err(_) => (
+            StatusCode::GATEWAY_TIMEOUT,
+            format!(
+                "Command did not exit within {timeout_secs} seconds; it is still running in the guest as PID {pid}"
+            ),
+        )
+            .into_response(),
+    }
+}
+
+async fn wait_for_exit(agent: &mut GuestAgent, pid: i64) -> Result<ExecOutput, QmpError> {
+    loop {
+        if let Some(output) = agent.exec_status(pid).await? {
+            return Ok(output);
+        }
+        tokio::time::sleep(EXEC_POLL_INTERVAL).await;
+    }
+}
+
+pub async fn read_file_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<VmFileQuery>,
+) -> Response {
+    let config = Config::load().expect("Failed to load configuration");
+    read_file_response(&config.storage.metadata_dir, &id, &query.path).await
+}
+
+/// Copy a file out of the guest, streamed as the response body one
+/// `guest-file-read` at a time. The first chunk is read before answering, so
+/// a file that cannot be read gets an error status; a later failure ends the
+/// body early.
+async fn read_file_response(metadata_dir: &Path, id: &str, path: &str) -> Response {
+    if let Err(error) = check_guest_path(path) {
+        return error.into_response();
+    }
+    let mut agent = match connect_agent(metadata_dir, id, "read files").await {
+        Ok(agent) => agent,
+        Err(error) => return error.into_response(),
+    };
+    let handle = match agent.open_file(path, "r").await {
+        Ok(handle) => handle,
+        Err(e) => return agent_error(id, e).into_response(),
+    };
+    let first = match agent.read_file(handle).await {
+        Ok(first) => first,
+        Err(e) => {
+            let _ = agent.close_file(handle).await;
+            return agent_error(id, e).into_response();
+        }
+    };
+
+    let id = id.to_string();
+    let chunks = futures_util::stream::unfold(Some((agent, Some(first))), move |state| {
+        let id = id.clone();
+        async move {
+            let (mut agent, read) = state?;
+            let read = match read {
+                Some(read) => Ok(read),
+                None => agent.read_file(handle).await,
+            };
+            match read {
+                Ok((chunk, false)) => Some((Ok(chunk), Some((agent, None)))),
+                Ok((chunk, true)) => {
+                    let _ = agent.close_file(handle).await;
+                    Some((Ok(chunk), None))
+                }
+                Err(e) => {
+                    warn!("Reading a file from VM {id} failed midway: {e}");
+                    let _ = agent.close_file(handle).await;
+                    Some((Err(std::io::Error::other(e.to_string())), None))
+                }
+            }
+        }
+    });
+    (
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+pub async fn write_file_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<VmFileQuery>,
+    body: Body,
+) -> Response {
+    info!("Writing {} in VM {id}", query.path);
+    let config = Config::load().expect("Failed to load configuration");
+    write_file_response(&config.storage.metadata_dir, &id, &query.path, body).await
+}
+
+/// Copy the request body into a file in the guest, creating or truncating
+/// it. The body is streamed, so its size is not limited.
+async fn write_file_response(metadata_dir: &Path, id: &str, path: &str, body: Body) -> Response {
+    if let Err(error) = check_guest_path(path) {
+        return error.into_response();
+    }
+    let mut agent = match connect_agent(metadata_dir, id, "write files").await {
+        Ok(agent) => agent,
+        Err(error) => return error.into_response(),
+    };
+    let handle = match agent.open_file(path, "w").await {
+        Ok(handle) => handle,
+        Err(e) => return agent_error(id, e).into_response(),
+    };
+
+    let mut bytes_written = 0;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let result = match chunk {
+            Ok(chunk) => match agent.write_file(handle, &chunk).await {
+                Ok(()) => Ok(chunk.len() as u64),
+                Err(e) => Err(agent_error(id, e)),
+            },
+            Err(e) => Err((
+                StatusCode::BAD_REQUEST,
+                format!("Failed to read request body: {e}"),
+            )),
+        };
+        match result {
+            Ok(written) => bytes_written += written,
+            Err(error) => {
+                let _ = agent.close_file(handle).await;
+                return error.into_response();
+            }
+        }
+    }
+
+    // Closing flushes the file, so its errors are the write's errors.
+    match agent.close_file(handle).await {
+        Ok(()) => Json(WriteFileResponse { bytes_written }).into_response(),
+        Err(e) => agent_error(id, e).into_response(),
+    }
+}
+
+/// Paths are resolved by the agent, whose working directory is not
+/// something callers can rely on.
+fn check_guest_path(path: &str) -> Result<(), (StatusCode, String)> {
+    if path.starts_with('/') {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            format!("Guest path must be absolute: {path}"),
+        ))
+    }
+}
+
+/// Connect to the guest agent of a running VM. A paused guest cannot answer,
+/// so only running VMs qualify.
+async fn connect_agent(
+    metadata_dir: &Path,
+    id: &str,
+    action: &str,
+) -> Result<GuestAgent, (StatusCode, String)> {
+    let vm_info = active_vm(metadata_dir, id, action)?;
+    if vm_info.state != VmState::Running {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Cannot {action} while the VM is {}", vm_info.state),
+        ));
+    }
+    GuestAgent::connect(&guest_agent_socket_path(metadata_dir, id))
+        .await
+        .map_err(|e| agent_error(id, e))
+}
+
+pub(crate) fn agent_error(id: &str, error: QmpError) -> (StatusCode, String) {
+    match error {
+        QmpError::Timeout => (
+            StatusCode::GATEWAY_TIMEOUT,
+            "The guest agent did not answer; is qemu-guest-agent running in the guest?".to_string(),
+        ),
+        // The guest ran the request and refused it, e.g. a missing file.
+        QmpError::Command { desc, .. } => (StatusCode::UNPROCESSABLE_ENTITY, desc),
+        // VMs started before the agent channel existed have no socket.
+        QmpError::Io(e) => {
+            warn!("Failed to reach the guest agent of VM {id}: {e}");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("The guest agent channel is unavailable: {e}"),
+            )
+        }
+        e => {
+            error!("Guest agent of VM {id} failed: {e}");
+            (StatusCode::BAD_GATEWAY, format!("Guest agent failed: {e}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guest_agent::fake;
+    use crate::vm_db::store_test_vm;
+    use axum::body::to_bytes;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    fn exec_request(command: &[&str], timeout_secs: Option<u64>) -> ExecRequest {
+        ExecRequest {
+            command: command.iter().map(|s| s.to_string()).collect(),
+            input: None,
+            timeout_secs,
+        }
+    }
+
+    async fn body_json(resp: Response) -> Value {
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    // ── exec ─────────────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_exec_response_waits_for_exit() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        let polls = Arc::new(Mutex::new(0));
+        let polls_clone = polls.clone();
+        fake::spawn(
+            &guest_agent_socket_path(dir.path(), "vm-1"),
+            move |command, _| match command {
+                "guest-exec" => json!({ "return": { "pid": 7 } }),
+                _ => {
+                    let mut polls = polls_clone.lock().unwrap();
+                    *polls += 1;
+                    if *polls < 3 {
+                        json!({ "return": { "exited": false } })
+                    } else {
+                        json!({ "return": {
+                            "exited": true,
+                            "exitcode": 0,
+                            "out-data": BASE64.encode("Linux\n"),
+                        } })
+                    }
+                }
+            },
+        );
+
+        let resp = exec_response(dir.path(), "vm-1", &exec_request(&["uname"], None)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let output = body_json(resp).await;
+        assert_eq!(output["exit_code"], 0);
+        assert_eq!(output["stdout"], "Linux\n");
+        assert_eq!(output["stderr"], "");
+        assert_eq!(*polls.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_exec_response_times_out_while_command_runs() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        fake::spawn(
+            &guest_agent_socket_path(dir.path(), "vm-1"),
+            |command, _| match command {
+                "guest-exec" => json!({ "return": { "pid": 7 } }),
+                _ => json!({ "return": { "exited": false } }),
+            },
+        );
+
+        let resp =
+            exec_response(dir.path(), "vm-1", &exec_request(&["sleep", "60"], Some(1))).await;
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("PID 7"));
+    }
+
+    #[tokio::test]
+    async fn test_exec_response_rejects_empty_command() {
+        let dir = TempDir::new().unwrap();
+        let resp = exec_response(dir.path(), "vm-1", &exec_request(&[], None)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_exec_response_requires_running_vm() {
+        let dir = TempDir::new().unwrap();
+        let resp = exec_response(dir.path(), "vm-1", &exec_request(&["true"], None)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        store_test_vm(dir.path(), "vm-1", VmState::Paused, std::process::id());
+        let resp = exec_response(dir.path(), "vm-1", &exec_request(&["true"], None)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_exec_response_without_agent_socket_is_unavailable() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        let resp = exec_response(dir.path(), "vm-1", &exec_request(&["true"], None)).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    // ── files ────────────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_read_file_response_reads_until_eof() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        let reads = Arc::new(Mutex::new(0));
+        let closed = Arc::new(Mutex::new(false));
+        let (reads_clone, closed_clone) = (reads.clone(), closed.clone());
+        fake::spawn(
+            &guest_agent_socket_path(dir.path(), "vm-1"),
+            move |command, arguments| match command {
+                "guest-file-open" => {
+                    assert_eq!(arguments["path"], "/etc/hostname");
+                    assert_eq!(arguments["mode"], "r");
+                    json!({ "return": 1000 })
+                }
+                "guest-file-read" => {
+                    let mut reads = reads_clone.lock().unwrap();
+                    *reads += 1;
+                    let (chunk, eof) = if *reads == 1 {
+                        ("web-", false)
+                    } else {
+                        ("1\n", true)
+                    };
+                    json!({ "return": { "count": chunk.len(), "buf-b64": BASE64.encode(chunk), "eof": eof } })
+                }
+                _ => {
+                    assert_eq!(arguments["handle"], 1000);
+                    *closed_clone.lock().unwrap() = true;
+                    json!({ "return": {} })
+                }
+            },
+        );
+
+        let resp = read_file_response(dir.path(), "vm-1", "/etc/hostname").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"web-1\n");
+        assert!(*closed.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_read_file_response_failure_midway_ends_body() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        let reads = Arc::new(Mutex::new(0));
+        fake::spawn(
+            &guest_agent_socket_path(dir.path(), "vm-1"),
+            move |command, _| match command {
+                "guest-file-open" => json!({ "return": 1000 }),
+                "guest-file-read" => {
+                    let mut reads = reads.lock().unwrap();
+                    *reads += 1;
+                    if *reads == 1 {
+                        json!({ "return": { "count": 4, "buf-b64": BASE64.encode("web-"), "eof": false } })
+                    } else {
+                        json!({ "error": { "class": "GenericError", "desc": "Input/output error" } })
+                    }
+                }
+                _ => json!({ "return": {} }),
+            },
+        );
+
+        let resp = read_file_response(dir.path(), "vm-1", "/var/log/big").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(to_bytes(resp.into_body(), usize::MAX).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_file_response_missing_file_is_unprocessable() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        fake::spawn(
+            &guest_agent_socket_path(dir.path(), "vm-1"),
+            |_, _| json!({ "error": { "class": "GenericError", "desc": "failed to open file '/nope': No such file or directory" } }),
+        );
+
+        let resp = read_file_response(dir.path(), "vm-1", "/nope").await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_write_file_response_streams_body_into_file() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        let written = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let written_clone = written.clone();
+        fake::spawn(
+            &guest_agent_socket_path(dir.path(), "vm-1"),
+            move |command, arguments| match command {
+                "guest-file-open" => {
+                    assert_eq!(arguments["mode"], "w");
+                    json!({ "return": 1000 })
+                }
+                "guest-file-write" => {
+                    let chunk = BASE64
+                        .decode(arguments["buf-b64"].as_str().unwrap())
+                        .unwrap();
+                    written_clone.lock().unwrap().extend(&chunk);
+                    json!({ "return": { "count": chunk.len(), "eof": false } })
+                }
+                _ => json!({ "return": {} }),
+            },
+        );
+
+        let resp = write_file_response(
+            dir.path(),
+            "vm-1",
+            "/root/setup.sh",
+            Body::from("#!/bin/sh\necho ready\n"),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_json(resp).await["bytes_written"], 21);
+        assert_eq!(&written.lock().unwrap()[..], b"#!/bin/sh\necho ready\n");
+    }
+
+    #[tokio::test]
+    async fn test_file_responses_reject_relative_paths() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        let resp = read_file_response(dir.path(), "vm-1", "etc/hostname").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = write_file_response(dir.path(), "vm-1", "setup.sh", Body::empty()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
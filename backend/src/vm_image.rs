@@ -0,0 +1,489 @@
+use crate::config::Config;
+use crate::guest_agent::{guest_agent_socket_path, while_frozen};
+use crate::image_db::{get_image_by_id, image_file_path, ImageInfo};
+use crate::image_service::{add_to_catalog, error_response, now_secs, RegisterImageResponse};
+use crate::qemu::{convert_image, qmp_socket_path, DISK_DEVICE};
+use crate::qmp::{QmpClient, QmpError, QmpEvent};
+use crate::vm_db::{SnapshotKind, VmInfo, VmSnapshot};
+use crate::vm_exec::agent_error;
+use crate::vm_snapshot::{load_vm, new_overlay_path, record_external_snapshot, SNAPSHOT_LOCK};
+use crate::vm_state::{update_vm, VmState};
+use axum::{http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// How long each phase of committing an image's overlay back may take.
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// QMP job ID of the commit. `SNAPSHOT_LOCK` keeps it unique per QEMU.
+const COMMIT_JOB: &str = "image-commit";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateImageRequest {
+    pub vm_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Defaults to the OS of the image the VM was launched from.
+    #[serde(default)]
+    pub os: Option<String>,
+    /// Defaults to the version of the image the VM was launched from.
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+pub async fn create_image_handler(
+    Json(payload): Json<CreateImageRequest>,
+) -> (StatusCode, Json<RegisterImageResponse>) {
+    info!("Creating image {} from VM {}", payload.name, payload.vm_id);
+    let config = Config::load().expect("Failed to load configuration");
+    create_image_response(
+        &config.storage.metadata_dir,
+        &config.storage.qcow2_dir,
+        &config.storage.images_dir(),
+        payload,
+    )
+    .await
+}
+
+/// Flatten a VM's disk, with everything below it in its backing chain, into
+/// a new catalog image. A running VM's filesystems are frozen through its
+/// guest agent while its disk is snapshotted, so the image holds a
+/// consistent filesystem without stopping the VM.
+async fn create_image_response(
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    images_dir: &Path,
+    payload: CreateImageRequest,
+) -> (StatusCode, Json<RegisterImageResponse>) {
+    // Keeps the disk chain from changing under the copy.
+    let _guard = SNAPSHOT_LOCK.lock().await;
+    let vm_info = match load_vm(metadata_dir, &payload.vm_id) {
+        Ok(vm_info) => vm_info,
+        Err((status, message)) => return error_response(status, message),
+    };
+
+    if let Err(e) = fs::create_dir_all(images_dir).await {
+        error!("Failed to create images directory {images_dir:?}: {e}");
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create images directory: {e}"),
+        );
+    }
+    let id = Uuid::new_v4().to_string();
+    let target = image_file_path(images_dir, &id);
+    let disk = vm_info.disk_path(qcow2_dir);
+
+    let copied = match vm_info.state {
+        VmState::Stopped | VmState::Crashed => convert_image(&disk, &target, false)
+            .await
+            .map_err(|e| copy_error(&vm_info, e)),
+        VmState::Running => {
+            copy_frozen(metadata_dir, qcow2_dir, &vm_info, &disk, &id, &target).await
+        }
+        state => Err((
+            StatusCode::CONFLICT,
+            format!("Cannot create an image while the VM is {state}"),
+        )),
+    };
+    if let Err((status, message)) = copied {
+        let _ = fs::remove_file(&target).await;
+        return error_response(status, message);
+    }
+
+    let source = vm_info
+        .image_id
+        .as_deref()
+        .and_then(|image_id| get_image_by_id(images_dir, image_id).ok().flatten());
+    let image_info = ImageInfo {
+        id,
+        name: payload.name,
+        os: payload
+            .os
+            .or_else(|| source.as_ref().map(|s| s.os.clone()))
+            .unwrap_or_default(),
+        version: payload
+            .version
+            .or_else(|| source.as_ref().map(|s| s.version.clone()))
+            .unwrap_or_default(),
+        description: payload.description,
+        checksum: String::new(),
+        size_bytes: 0,
+        created_secs: 0,
+    };
+    add_to_catalog(images_dir, image_info, None).await
+}
+
+/// Copy a running VM's disk. Its guest agent freezes the guest's
+/// filesystems only while `blockdev-snapshot-sync` moves the VM's writes to
+/// a new overlay; the layer below, now read-only, is copied after the thaw.
+/// The overlay is then committed back into that layer, so creating images
+/// does not lengthen the chain. Only if the commit fails does the layer stay
+/// in the VM's chain, as an external snapshot named after the image.
+async fn copy_frozen(
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    vm_info: &VmInfo,
+    disk: &Path,
+    image_id: &str,
+    target: &Path,
+) -> Result<(), (StatusCode, String)> {
+    let overlay = new_overlay_path(qcow2_dir, vm_info);
+    let qmp = QmpClient::shared(&qmp_socket_path(metadata_dir, &vm_info.id))
+        .await
+        .map_err(|e| copy_error(vm_info, e))?;
+    let socket = guest_agent_socket_path(metadata_dir, &vm_info.id);
+    while_frozen(&socket, qmp.blockdev_snapshot_sync(DISK_DEVICE, &overlay))
+        .await
+        .map_err(|e| agent_error(&vm_info.id, e))?
+        .map_err(|e| copy_error(vm_info, e))?;
+
+    let name = format!("image-{image_id}");
+    let snapshot = VmSnapshot {
+        name: name.clone(),
+        created_secs: now_secs(),
+        kind: SnapshotKind::External,
+        file: disk.to_path_buf(),
+    };
+    record_external_snapshot(metadata_dir, &vm_info.id, snapshot, overlay.clone())
+        .map_err(|e| copy_error(vm_info, e))?;
+    let copied = convert_image(disk, target, true)
+        .await
+        .map_err(|e| copy_error(vm_info, e));
+
+    if let Err(e) = commit_overlay(&qmp, disk, COMMIT_TIMEOUT).await {
+        warn!(
+            "Failed to commit the overlay of VM {} back into {disk:?}, keeping snapshot {name}: {e}",
+            vm_info.id
+        );
+        return copied;
+    }
+    let restored = update_vm(metadata_dir, &vm_info.id, |vm| {
+        vm.disk = vm_info.disk.clone();
+        if vm.backing_chain.first().map(PathBuf::as_path) == Some(disk) {
+            vm.backing_chain.remove(0);
+        }
+        vm.snapshots.retain(|s| s.name != name);
+    });
+    match restored {
+        Ok(_) => {
+            if let Err(e) = fs::remove_file(&overlay).await {
+                warn!("Failed to remove committed overlay {overlay:?}: {e}");
+            }
+        }
+        Err(e) => error!(
+            "VM {} runs from {disk:?} again but its metadata still names {overlay:?}: {e}",
+            vm_info.id
+        ),
+    }
+    copied
+}
+
+/// Merge the active overlay of the VM's disk into `base` with a live
+/// `block-commit`, then pivot the VM back onto `base`. Waits up to `timeout`
+/// for each phase of the job; a commit that never becomes ready is
+/// cancelled, leaving the overlay in use.
+async fn commit_overlay(qmp: &QmpClient, base: &Path, timeout: Duration) -> Result<(), QmpError> {
+    // Subscribe before starting the job, so its events cannot be missed.
+    let mut events = qmp.events();
+    qmp.execute(
+        "block-commit",
+        Some(json!({ "job-id": COMMIT_JOB, "device": DISK_DEVICE, "base": base })),
+    )
+    .await?;
+    if let Err(e) = wait_for_job(&mut events, "BLOCK_JOB_READY", timeout).await {
+        if let Err(cancel) = qmp
+            .execute("block-job-cancel", Some(json!({ "device": COMMIT_JOB })))
+            .await
+        {
+            warn!("Failed to cancel {COMMIT_JOB}: {cancel}");
+        }
+        return Err(e);
+    }
+    qmp.execute("block-job-complete", Some(json!({ "device": COMMIT_JOB })))
+        .await?;
+    wait_for_job(&mut events, "BLOCK_JOB_COMPLETED", timeout).await
+}
+
+/// Wait for `name` from the commit job. A job that ends early, or completes
+/// with an error, fails the wait.
+async fn wait_for_job(
+    events: &mut Receiver<QmpEvent>,
+    name: &str,
+    timeout: Duration,
+) -> Result<(), QmpError> {
+    let wait = async {
+        loop {
+            match events.recv().await {
+                Ok(event) if event.data["device"] != COMMIT_JOB => {}
+                Ok(event) if event.event == name || event.event == "BLOCK_JOB_COMPLETED" => {
+                    return match event.data["error"].as_str() {
+                        Some(desc) => Err(QmpError::Command {
+                            class: "GenericError".to_string(),
+                            desc: desc.to_string(),
+                        }),
+                        None if event.event == name => Ok(()),
+                        None => Err(QmpError::Protocol(format!(
+                            "{COMMIT_JOB} ended before {name}"
+                        ))),
+                    };
+                }
+                Ok(event) if event.event == "BLOCK_JOB_CANCELLED" => {
+                    return Err(QmpError::Protocol(format!("{COMMIT_JOB} was cancelled")))
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(QmpError::Disconnected),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .unwrap_or(Err(QmpError::Timeout))
+}
+
+fn copy_error(vm_info: &VmInfo, e: impl std::fmt::Display) -> (StatusCode, String) {
+    error!("Failed to copy the disk of VM {}: {e}", vm_info.id);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to copy the VM's disk: {e}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guest_agent::fake;
+    use crate::image_db::list_images;
+    use crate::vm_db::{get_vm_by_id, store_test_vm};
+    use crate::vm_stop::test_process;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    struct Dirs {
+        meta: TempDir,
+        qcow2: TempDir,
+        images: TempDir,
+    }
+
+    impl Dirs {
+        fn new() -> Self {
+            Dirs {
+                meta: TempDir::new().unwrap(),
+                qcow2: TempDir::new().unwrap(),
+                images: TempDir::new().unwrap(),
+            }
+        }
+
+        fn store_vm(&self, state: VmState, pid: u32) {
+            store_test_vm(self.meta.path(), "vm-1", state, pid);
+        }
+
+        async fn create(&self) -> (StatusCode, Json<RegisterImageResponse>) {
+            let request = CreateImageRequest {
+                vm_id: "vm-1".to_string(),
+                name: "web-golden".to_string(),
+                description: "nginx configured".to_string(),
+                os: None,
+                version: None,
+            };
+            create_image_response(
+                self.meta.path(),
+                self.qcow2.path(),
+                self.images.path(),
+                request,
+            )
+            .await
+        }
+
+        fn image_files(&self) -> usize {
+            std::fs::read_dir(self.images.path()).unwrap().count()
+        }
+    }
+
+    /// A guest agent that records the commands it receives and refuses
+    /// `fail_on`.
+    fn spawn_agent(dirs: &Dirs, fail_on: &'static str) -> Arc<Mutex<Vec<String>>> {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let seen = received.clone();
+        fake::spawn(
+            &guest_agent_socket_path(dirs.meta.path(), "vm-1"),
+            move |command, _: &Value| {
+                seen.lock().unwrap().push(command.to_string());
+                if command == fail_on {
+                    json!({ "error": { "class": "GenericError", "desc": "fsfreeze is disabled" } })
+                } else {
+                    json!({ "return": 1 })
+                }
+            },
+        );
+        received
+    }
+
+    #[tokio::test]
+    async fn test_create_image_unknown_vm_returns_404() {
+        let dirs = Dirs::new();
+
+        let (status, Json(resp)) = dirs.create().await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(!resp.success);
+    }
+
+    #[tokio::test]
+    async fn test_create_image_rejects_transitional_state() {
+        let dirs = Dirs::new();
+        let pid = test_process::spawn(false);
+        dirs.store_vm(VmState::Paused, pid);
+
+        let (status, _) = dirs.create().await;
+
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(dirs.image_files(), 0);
+        test_process::kill(pid);
+    }
+
+    /// QEMU's monitor, refusing `fail_on` and running the commit job.
+    fn spawn_qmp(dirs: &Dirs, fail_on: &'static str) -> Arc<tokio::sync::Mutex<Vec<String>>> {
+        use crate::qmp::fake::event;
+        crate::qmp::fake::spawn_scripted(
+            &qmp_socket_path(dirs.meta.path(), "vm-1"),
+            move |command| {
+                let job = json!({ "device": COMMIT_JOB, "type": "commit" });
+                match command {
+                    _ if command == fail_on => vec![json!({
+                        "error": { "class": "GenericError", "desc": "No space left on device" }
+                    })],
+                    "block-commit" => vec![json!({ "return": {} }), event("BLOCK_JOB_READY", job)],
+                    "block-job-complete" => {
+                        vec![json!({ "return": {} }), event("BLOCK_JOB_COMPLETED", job)]
+                    }
+                    _ => vec![json!({ "return": {} })],
+                }
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_create_image_from_running_vm_needs_guest_agent() {
+        let dirs = Dirs::new();
+        let pid = test_process::spawn(false);
+        dirs.store_vm(VmState::Running, pid);
+        spawn_qmp(&dirs, "");
+
+        let (status, _) = dirs.create().await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_create_image_thaws_guest_before_copying() {
+        let dirs = Dirs::new();
+        let pid = test_process::spawn(false);
+        dirs.store_vm(VmState::Running, pid);
+        let qmp = spawn_qmp(&dirs, "");
+        let received = spawn_agent(&dirs, "");
+
+        // The VM's disk does not exist, so the copy fails.
+        let (status, _) = dirs.create().await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            *received.lock().unwrap(),
+            ["guest-fsfreeze-freeze", "guest-fsfreeze-thaw"]
+        );
+        assert_eq!(
+            *qmp.lock().await,
+            [
+                "qmp_capabilities",
+                "blockdev-snapshot-sync",
+                "block-commit",
+                "block-job-complete"
+            ]
+        );
+        // The overlay was committed back, leaving the chain as it was.
+        let vm = get_vm_by_id(dirs.meta.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(vm.disk, None);
+        assert!(vm.backing_chain.is_empty());
+        assert!(vm.snapshots.is_empty());
+        assert_eq!(dirs.image_files(), 0);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_create_image_keeps_snapshot_when_commit_fails() {
+        let dirs = Dirs::new();
+        let pid = test_process::spawn(false);
+        dirs.store_vm(VmState::Running, pid);
+        let qmp = spawn_qmp(&dirs, "block-commit");
+        spawn_agent(&dirs, "");
+
+        let (status, _) = dirs.create().await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        assert_eq!(
+            *qmp.lock().await,
+            ["qmp_capabilities", "blockdev-snapshot-sync", "block-commit"]
+        );
+        // The copied layer stays frozen under the VM's new overlay.
+        let disk = dirs.qcow2.path().join("vm-1.qcow2");
+        let vm = get_vm_by_id(dirs.meta.path(), "vm-1").unwrap().unwrap();
+        assert_ne!(vm.disk_path(dirs.qcow2.path()), disk);
+        assert_eq!(vm.backing_chain, [disk]);
+        assert_eq!(vm.snapshots.len(), 1);
+        assert!(vm.snapshots[0].name.starts_with("image-"));
+        assert_eq!(vm.snapshots[0].kind, SnapshotKind::External);
+        assert_eq!(dirs.image_files(), 0);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_create_image_thaws_guest_when_snapshot_fails() {
+        let dirs = Dirs::new();
+        let pid = test_process::spawn(false);
+        dirs.store_vm(VmState::Running, pid);
+        spawn_qmp(&dirs, "blockdev-snapshot-sync");
+        let received = spawn_agent(&dirs, "");
+
+        let (status, _) = dirs.create().await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            *received.lock().unwrap(),
+            ["guest-fsfreeze-freeze", "guest-fsfreeze-thaw"]
+        );
+        let vm = get_vm_by_id(dirs.meta.path(), "vm-1").unwrap().unwrap();
+        assert!(vm.snapshots.is_empty());
+        assert_eq!(vm.disk, None);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_create_image_refused_freeze_is_reported() {
+        let dirs = Dirs::new();
+        let pid = test_process::spawn(false);
+        dirs.store_vm(VmState::Running, pid);
+        let qmp = spawn_qmp(&dirs, "");
+        let received = spawn_agent(&dirs, "guest-fsfreeze-freeze");
+
+        let (status, Json(resp)) = dirs.create().await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(resp.message, "fsfreeze is disabled");
+        assert_eq!(
+            *received.lock().unwrap(),
+            ["guest-fsfreeze-freeze", "guest-fsfreeze-thaw"]
+        );
+        assert_eq!(*qmp.lock().await, ["qmp_capabilities"]);
+        assert!(list_images(dirs.images.path()).unwrap().is_empty());
+        test_process::kill(pid);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::qmp::{QmpClient, QmpError, QmpEvent};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tracing::{info, warn};
+
+/// How a reboot was carried out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RebootStep {
+    /// The guest shut down cleanly on an ACPI power button press and the
+    /// machine was then reset.
+    GuestReboot,
+    /// The machine was reset without the guest's cooperation.
+    HardReset,
+}
+
+impl std::fmt::Display for RebootStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RebootStep::GuestReboot => "guest reboot",
+            RebootStep::HardReset => "hard reset",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Reboot a VM. QEMU has no ACPI reset button, so a clean reboot is a power
+/// button press with the shutdown action switched to `pause`: once the guest
+/// has shut down, the paused machine is reset and resumed instead of QEMU
+/// exiting. If the guest has not shut down within `guest_timeout` (or it is
+/// zero), the machine is hard-reset instead. Either way the machine is resumed
+/// after the reset, as a guest that shut down late may have left it paused.
+pub async fn reboot(qmp: &QmpClient, guest_timeout: Duration) -> Result<RebootStep, QmpError> {
+    if !guest_timeout.is_zero() {
+        match guest_shutdown(qmp, guest_timeout).await {
+            Ok(true) => {
+                qmp.system_reset().await?;
+                qmp.cont().await?;
+                return Ok(RebootStep::GuestReboot);
+            }
+            Ok(false) => info!("Guest did not shut down within {guest_timeout:?}, resetting"),
+            Err(e) => warn!("Guest reboot failed, resetting: {e}"),
+        }
+    }
+    qmp.system_reset().await?;
+    qmp.cont().await?;
+    Ok(RebootStep::HardReset)
+}
+
+/// Press the power button with QEMU set to pause on shutdown, and wait for the
+/// guest to shut down. The default `poweroff` action is restored either way;
+/// failing to restore it is only logged, since the reset that follows still
+/// reboots the guest.
+async fn guest_shutdown(qmp: &QmpClient, timeout: Duration) -> Result<bool, QmpError> {
+    let mut events = qmp.events();
+    qmp.set_shutdown_action("pause").await?;
+    let result = match qmp.system_powerdown().await {
+        Ok(()) => Ok(wait_for_event(&mut events, "SHUTDOWN", timeout).await),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = qmp.set_shutdown_action("poweroff").await {
+        warn!("Failed to restore the poweroff shutdown action: {e}");
+    }
+    result
+}
+
+/// Wait up to `timeout` for an event named `name`. Returns whether it arrived.
+async fn wait_for_event(events: &mut Receiver<QmpEvent>, name: &str, timeout: Duration) -> bool {
+    let wait = async {
+        loop {
+            match events.recv().await {
+                Ok(event) if event.event == name => return true,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    };
+    tokio::time::timeout(timeout, wait).await.unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qmp::fake;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_reboot_resets_after_guest_shutdown() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let received = fake::spawn_scripted(&socket, |command| match command {
+            "system_powerdown" => vec![
+                json!({ "return": {} }),
+                fake::event(
+                    "SHUTDOWN",
+                    json!({ "guest": true, "reason": "guest-shutdown" }),
+                ),
+            ],
+            _ => vec![json!({ "return": {} })],
+        });
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        let step = reboot(&qmp, Duration::from_secs(5)).await.unwrap();
+
+        assert_eq!(step, RebootStep::GuestReboot);
+        assert_eq!(
+            *received.lock().await,
+            [
+                "qmp_capabilities",
+                "set-action",
+                "system_powerdown",
+                "set-action",
+                "system_reset",
+                "cont"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reboot_falls_back_to_hard_reset() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let received = fake::spawn(&socket, fake::ok);
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        let step = reboot(&qmp, Duration::from_millis(200)).await.unwrap();
+
+        assert_eq!(step, RebootStep::HardReset);
+        assert_eq!(
+            *received.lock().await,
+            [
+                "qmp_capabilities",
+                "set-action",
+                "system_powerdown",
+                "set-action",
+                "system_reset",
+                "cont"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reboot_with_zero_timeout_only_resets() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let received = fake::spawn(&socket, fake::ok);
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        let step = reboot(&qmp, Duration::ZERO).await.unwrap();
+
+        assert_eq!(step, RebootStep::HardReset);
+        assert_eq!(
+            *received.lock().await,
+            ["qmp_capabilities", "system_reset", "cont"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reboot_resumes_when_restoring_shutdown_action_fails() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let set_actions = AtomicUsize::new(0);
+        let received = fake::spawn_scripted(&socket, move |command| match command {
+            "system_powerdown" => vec![
+                json!({ "return": {} }),
+                fake::event(
+                    "SHUTDOWN",
+                    json!({ "guest": true, "reason": "guest-shutdown" }),
+                ),
+            ],
+            "set-action" if set_actions.fetch_add(1, Ordering::SeqCst) > 0 => {
+                vec![json!({ "error": { "class": "GenericError", "desc": "failed" } })]
+            }
+            _ => vec![json!({ "return": {} })],
+        });
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        let step = reboot(&qmp, Duration::from_secs(5)).await.unwrap();
+
+        assert_eq!(step, RebootStep::GuestReboot);
+        assert_eq!(
+            received.lock().await[4..],
+            ["system_reset".to_string(), "cont".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reboot_hard_resets_when_set_action_unsupported() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        fake::spawn(&socket, |command| match command {
+            "set-action" => json!({
+                "error": { "class": "CommandNotFound", "desc": "The command set-action has not been found" }
+            }),
+            _ => json!({ "return": {} }),
+        });
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        let step = reboot(&qmp, Duration::from_secs(5)).await.unwrap();
+
+        assert_eq!(step, RebootStep::HardReset);
+    }
+}
@@ -0,0 +1,222 @@
+use crate::config::Config;
+use crate::qemu::{qmp_socket_path, resize_image, virtual_size, DISK_DEVICE, GIB};
+use crate::qmp::QmpClient;
+use crate::vm_snapshot::{load_vm, SNAPSHOT_LOCK};
+use crate::vm_state::VmState;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{error, info};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResizeDiskRequest {
+    pub id: String,
+    /// New size of the VM's disk in GiB. Disks can only grow.
+    pub size_gb: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResizeDiskResponse {
+    pub message: String,
+    pub size_bytes: u64,
+}
+
+pub async fn resize_disk_handler(Json(payload): Json<ResizeDiskRequest>) -> Response {
+    info!(
+        "Resizing the disk of VM {} to {} GiB",
+        payload.id, payload.size_gb
+    );
+    let config = Config::load().expect("Failed to load configuration");
+    resize_disk_response(
+        &config.storage.metadata_dir,
+        &config.storage.qcow2_dir,
+        &payload.id,
+        payload.size_gb,
+    )
+    .await
+}
+
+/// Grow a VM's disk: with `qemu-img resize` while it is stopped, or with QMP
+/// `block_resize` while QEMU runs. The guest still has to grow its partition
+/// and filesystem into the new space.
+async fn resize_disk_response(
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    id: &str,
+    size_gb: u64,
+) -> Response {
+    let _guard = SNAPSHOT_LOCK.lock().await;
+    let vm_info = match load_vm(metadata_dir, id) {
+        Ok(vm_info) => vm_info,
+        Err(error) => return error.into_response(),
+    };
+    let size = size_gb.saturating_mul(GIB);
+
+    let result = match vm_info.state {
+        VmState::Stopped | VmState::Crashed => {
+            let disk = vm_info.disk_path(qcow2_dir);
+            match virtual_size(&disk).await {
+                Ok(current) => match check_growth(current, size) {
+                    Ok(true) => resize_image(&disk, size).await.map_err(|e| e.to_string()),
+                    Ok(false) => Ok(()),
+                    Err(error) => return error.into_response(),
+                },
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        VmState::Running | VmState::Paused => {
+            match QmpClient::shared(&qmp_socket_path(metadata_dir, id)).await {
+                Ok(qmp) => match qmp.block_virtual_size(DISK_DEVICE).await {
+                    Ok(current) => match check_growth(current, size) {
+                        Ok(true) => qmp
+                            .block_resize(DISK_DEVICE, size)
+                            .await
+                            .map_err(|e| e.to_string()),
+                        Ok(false) => Ok(()),
+                        Err(error) => return error.into_response(),
+                    },
+                    Err(e) => Err(e.to_string()),
+                },
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        state => {
+            return (
+                StatusCode::CONFLICT,
+                format!("Cannot resize the disk while the VM is {state}"),
+            )
+                .into_response()
+        }
+    };
+
+    match result {
+        Ok(()) => Json(ResizeDiskResponse {
+            message: format!("Disk of VM {id} is {size_gb} GiB"),
+            size_bytes: size,
+        })
+        .into_response(),
+        Err(e) => {
+            error!("Failed to resize the disk of VM {id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to resize disk: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Whether a disk of `current` bytes has to grow to reach `size`. Shrinking
+/// would cut off whatever the guest stored at the end of the disk.
+fn check_growth(current: u64, size: u64) -> Result<bool, (StatusCode, String)> {
+    if size < current {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Disks cannot shrink: the disk is {:.1} GiB",
+                current as f64 / GIB as f64
+            ),
+        ));
+    }
+    Ok(size > current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qmp::fake;
+    use crate::vm_db::store_test_vm;
+    use crate::vm_stop::test_process;
+    use axum::body::to_bytes;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex as StdMutex};
+    use tempfile::TempDir;
+
+    /// A QEMU whose disk is `size` bytes, recording `block_resize` arguments.
+    fn spawn_qemu(dir: &Path, size: u64) -> Arc<StdMutex<Vec<Value>>> {
+        let resized = Arc::new(StdMutex::new(Vec::new()));
+        let seen = resized.clone();
+        fake::spawn_with_args(
+            &qmp_socket_path(dir, "vm-1"),
+            move |command, args| match command {
+                "query-block" => json!({ "return": [
+                    { "device": "ide1-cd0", "removable": true },
+                    { "device": DISK_DEVICE, "inserted": { "image": { "virtual-size": size } } }
+                ] }),
+                "block_resize" => {
+                    seen.lock().unwrap().push(args.clone());
+                    json!({ "return": {} })
+                }
+                _ => json!({ "return": {} }),
+            },
+        );
+        resized
+    }
+
+    #[test]
+    fn test_check_growth() {
+        assert_eq!(check_growth(GIB, 2 * GIB), Ok(true));
+        assert_eq!(check_growth(GIB, GIB), Ok(false));
+        assert_eq!(
+            check_growth(2 * GIB, GIB).unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resize_unknown_vm_returns_404() {
+        let meta = TempDir::new().unwrap();
+        let resp = resize_disk_response(meta.path(), meta.path(), "vm-1", 10).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_resize_running_vm_uses_block_resize() {
+        let meta = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        store_test_vm(meta.path(), "vm-1", VmState::Running, pid);
+        let resized = spawn_qemu(meta.path(), 2 * GIB);
+
+        let resp = resize_disk_response(meta.path(), meta.path(), "vm-1", 10).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let body: ResizeDiskResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.size_bytes, 10 * GIB);
+        assert_eq!(
+            *resized.lock().unwrap(),
+            [json!({ "device": DISK_DEVICE, "size": 10 * GIB })]
+        );
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_resize_running_vm_rejects_shrinking() {
+        let meta = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        store_test_vm(meta.path(), "vm-1", VmState::Running, pid);
+        let resized = spawn_qemu(meta.path(), 8 * GIB);
+
+        let resp = resize_disk_response(meta.path(), meta.path(), "vm-1", 4).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(resized.lock().unwrap().is_empty());
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_resize_rejects_transitional_state() {
+        let meta = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        store_test_vm(meta.path(), "vm-1", VmState::Stopping, pid);
+
+        let resp = resize_disk_response(meta.path(), meta.path(), "vm-1", 10).await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        test_process::kill(pid);
+    }
+}
@@ -1,23 +1,93 @@
+use crate::cloud_init::{remove_seed, write_seed, CloudInitConfig};
 use crate::config::{Config, NetworkMode};
+use crate::console::{
+    console_log_path, console_socket_path, read_console_log, remove_console_log, rotate_if_needed,
+    ConsoleLogChunk, MAX_LOG_BYTES,
+};
+use crate::guest_agent::{guest_agent_socket_path, poll_interfaces, primary_ipv4, GuestInterface};
+use crate::image_service::resolve_base_image;
+use crate::metadata::{metadata_socket_path, serve_vm_socket};
 use crate::qemu::{
-    is_process_running, mac_from_uuid, send_monitor_command, vm_start, NetworkConfig,
+    create_overlay, is_process_running, is_vm_process, mac_from_uuid, qmp_socket_path,
+    virtual_size, vm_start, vnc_socket_path, ConsoleFiles, NetworkConfig, VmExtras, VmShape, GIB,
 };
+use crate::qmp::QmpClient;
 use crate::vm_db::{delete_vm_by_id, get_vm_by_id, list_vms, store_vm_info, VmInfo};
-use axum::{http::StatusCode, response::IntoResponse, Json};
-use nix::sys::signal::{kill, Signal};
-use nix::unistd::Pid;
+use crate::vm_reboot::{reboot, RebootStep};
+use crate::vm_snapshot::SNAPSHOT_LOCK;
+use crate::vm_state::{
+    record_exit, refresh_state, transition, transition_with, update_vm, TransitionError, VmState,
+};
+use crate::vm_stop::{stop_process, StopStep};
+use crate::volume_attach::ATTACH_LOCK;
+use crate::volume_db::update_volume;
+use crate::ws_bridge::bridge;
+use axum::extract::{ws::WebSocketUpgrade, Path as AxumPath, Query};
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
+use tokio::net::UnixStream;
+use tokio::process::Child;
+use tokio::sync::broadcast::error::RecvError;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+/// How long a newly spawned QEMU has to open its QMP socket.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a running VM's console log is checked for rotation.
+const CONSOLE_ROTATE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a stop or reboot waits for the guest to shut down when the caller
+/// does not say.
+const DEFAULT_GUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long each forced stop step (QMP quit, SIGTERM, SIGKILL) gets to work
+/// before escalating to the next.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LaunchVmRequest {
     pub name: String,
     pub instance_type: String,
     pub region: String,
+    /// Catalog image to create the VM from. Falls back to `alpine.qcow2` in
+    /// the working directory when omitted.
+    #[serde(default)]
+    pub image_id: Option<String>,
+    /// cloud-init user-data, passed to the guest unchanged.
+    #[serde(default)]
+    pub user_data: Option<String>,
+    /// Guest hostname. Defaults to `name` when any cloud-init option is set.
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// SSH public keys to install for the guest's default user.
+    #[serde(default)]
+    pub ssh_authorized_keys: Vec<String>,
+    /// Whether the backend starts the VM again after a restart. Defaults to
+    /// true.
+    #[serde(default)]
+    pub autostart: Option<bool>,
+    /// Export the VM's display over VNC (reachable via `/vm-vnc/:id`).
+    /// Defaults to false.
+    #[serde(default)]
+    pub graphics: bool,
+    /// Free-form labels, readable by the guest from the metadata service.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Size of the VM's disk in GiB. Defaults to the instance type's
+    /// `disk_gb`, or the image's own size when that is larger.
+    #[serde(default)]
+    pub disk_size_gb: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,21 +104,57 @@ pub struct LaunchVmResponse {
 pub struct VmListEntry {
     pub id: String,
     pub name: String,
-    /// SSH host to connect to: "localhost" in user mode, empty in bridge mode
-    /// (the proxy resolves the IP from the dnsmasq lease file).
+    /// SSH host to connect to: "localhost" in user mode. In bridge mode the
+    /// guest agent's address, or empty when the agent has not reported one
+    /// (the proxy then resolves the IP from the dnsmasq lease file).
     pub ssh_host: String,
     pub ssh_port: u16,
     pub pid: u32,
     /// MAC address included in bridge mode so the proxy can resolve the IP.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mac_address: Option<String>,
-    /// Whether the QEMU process is currently alive.
+    /// Whether a QEMU process exists for the VM (starting, running, paused or
+    /// stopping).
     pub running: bool,
+    pub state: VmState,
+    /// Why the VM entered its current state.
+    pub state_reason: String,
+    /// Instance type the VM was launched with; empty for legacy VMs.
+    #[serde(default)]
+    pub instance_type: String,
+    /// Catalog image the VM was created from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_id: Option<String>,
+    /// Whether the VM is started on backend startup.
+    pub autostart: bool,
+    /// Whether the VM's display is exported over VNC.
+    pub graphics: bool,
+    /// IDs of the volumes attached to the VM.
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Guest network interfaces with all their IPv4 and IPv6 addresses, as
+    /// reported by the guest agent. Empty while the VM is not running or has
+    /// no agent.
+    #[serde(default)]
+    pub interfaces: Vec<GuestInterface>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StopVmRequest {
     pub id: String,
+    /// Seconds to wait for the guest to shut down before forcing QEMU to
+    /// exit. Zero skips the guest shutdown. Defaults to 30.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StopVmResponse {
+    pub message: String,
+    /// The step of the stop sequence after which QEMU had exited.
+    pub stopped_by: StopStep,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,37 +162,101 @@ pub struct StartVmRequest {
     pub id: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RebootVmRequest {
+    pub id: String,
+    /// Seconds to wait for the guest to shut down before hard-resetting the
+    /// machine. Zero resets immediately. Defaults to 30.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RebootVmResponse {
+    pub message: String,
+    pub rebooted_by: RebootStep,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PauseVmRequest {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResumeVmRequest {
+    pub id: String,
+}
+
 pub async fn launch_vm(
     Json(payload): Json<LaunchVmRequest>,
 ) -> (StatusCode, Json<LaunchVmResponse>) {
-    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-    let source_qcow2 = current_dir.join("alpine.qcow2");
-
     let config = Config::load().expect("Failed to load configuration");
-    let target_qcow2 = config
-        .storage
-        .qcow2_dir
-        .join(format!("{}.qcow2", payload.name));
+
+    let Some(instance_type) = config.instance_type(&payload.instance_type) else {
+        warn!(
+            "Rejecting launch of {}: unknown instance type {}",
+            payload.name, payload.instance_type
+        );
+        return launch_error(
+            StatusCode::BAD_REQUEST,
+            format!("Unknown instance type: {}", payload.instance_type),
+        );
+    };
+    let shape = VmShape {
+        vcpus: instance_type.vcpus,
+        memory_mb: instance_type.memory_mb,
+    };
+
+    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let source_qcow2 = match resolve_base_image(
+        &config.storage.images_dir(),
+        payload.image_id.as_deref(),
+        &current_dir,
+    ) {
+        Ok(path) => path,
+        Err((status, message)) => {
+            warn!("Rejecting launch of {}: {message}", payload.name);
+            return launch_error(status, message);
+        }
+    };
 
     debug!("source_qcow2: {source_qcow2:?}");
-    debug!("target_qcow2: {target_qcow2:?}");
 
-    if let Err(e) = fs::copy(&source_qcow2, &target_qcow2).await {
-        error!("Failed to copy QCOW2 file from {source_qcow2:?} to {target_qcow2:?}: {e}");
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(LaunchVmResponse {
-                success: false,
-                message: format!("Failed to copy QCOW2 file: {e}"),
-                instance_id: None,
-                ssh_host: None,
-                ssh_port: None,
-                pid: None,
-            }),
-        );
-    }
+    // The overlay records the base image path verbatim, so pin it to an
+    // absolute path that stays valid regardless of the working directory.
+    let base_qcow2 = match std::fs::canonicalize(&source_qcow2) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("Failed to resolve base image {source_qcow2:?}: {e}");
+            return launch_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to resolve base image: {e}"),
+            );
+        }
+    };
+
+    let disk_size = match virtual_size(&base_qcow2).await {
+        Ok(base_size) => overlay_size(payload.disk_size_gb, instance_type.disk_gb, base_size),
+        Err(e) => {
+            error!("Failed to read the size of base image {base_qcow2:?}: {e}");
+            return launch_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read base image size: {e}"),
+            );
+        }
+    };
+    let disk_size = match disk_size {
+        Ok(size) => size,
+        Err((status, message)) => {
+            warn!("Rejecting launch of {}: {message}", payload.name);
+            return launch_error(status, message);
+        }
+    };
 
     let uuid = Uuid::new_v4().to_string();
+    // Named by ID, as VM names need not be unique.
+    let target_qcow2 = config.storage.qcow2_dir.join(format!("{uuid}.qcow2"));
+    debug!("target_qcow2: {target_qcow2:?}");
 
     let (network, vm_info_ssh_port, vm_info_mac, response_ssh_host, response_ssh_port) =
         match config.network_mode {
@@ -115,55 +285,340 @@ pub async fn launch_vm(
             }
         };
 
-    let monitor_socket = config.storage.metadata_dir.join(format!("{uuid}.monitor"));
+    let metadata_dir = &config.storage.metadata_dir;
+    let qmp_socket = qmp_socket_path(metadata_dir, &uuid);
+
+    let vm_info = VmInfo {
+        id: uuid.clone(),
+        name: payload.name.clone(),
+        ssh_port: vm_info_ssh_port,
+        mac_address: vm_info_mac,
+        pid: 0,
+        instance_type: payload.instance_type.clone(),
+        shape,
+        image_id: payload.image_id.clone(),
+        backing_chain: vec![base_qcow2.clone()],
+        cloud_init: CloudInitConfig {
+            user_data: payload.user_data.clone(),
+            hostname: payload.hostname.clone(),
+            ssh_authorized_keys: payload.ssh_authorized_keys.clone(),
+        },
+        state: VmState::Pending,
+        state_reason: "launch requested".to_string(),
+        autostart: payload.autostart.unwrap_or(true),
+        graphics: payload.graphics,
+        volumes: Vec::new(),
+        tags: payload.tags.clone(),
+        guest_interfaces: Vec::new(),
+        disk: Some(target_qcow2.clone()),
+        snapshots: Vec::new(),
+    };
+    if let Err(e) = store_vm_info(metadata_dir, &vm_info) {
+        error!("Failed to store metadata for VM {}: {e}", payload.name);
+        return launch_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to store VM metadata: {e}"),
+        );
+    }
+
+    if let Err(e) = create_overlay(&base_qcow2, &target_qcow2, disk_size).await {
+        return abandon_launch(
+            metadata_dir,
+            &uuid,
+            &target_qcow2,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create QCOW2 overlay: {e}"),
+        )
+        .await;
+    }
+
+    let seed_file =
+        match prepare_seed(metadata_dir, &uuid, &payload.name, &vm_info.cloud_init).await {
+            Ok(seed) => seed,
+            Err(e) => {
+                return abandon_launch(
+                    metadata_dir,
+                    &uuid,
+                    &target_qcow2,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to build cloud-init seed: {e}"),
+                )
+                .await;
+            }
+        };
 
-    match vm_start(
+    let console_socket = console_socket_path(metadata_dir, &uuid);
+    let console_log = console_log_path(metadata_dir, &uuid);
+    let metadata_socket = metadata_socket_path(metadata_dir, &uuid);
+    let guest_agent_socket = guest_agent_socket_path(metadata_dir, &uuid);
+    let vnc_socket = vm_info
+        .graphics
+        .then(|| vnc_socket_path(metadata_dir, &uuid));
+    let mut child = match vm_start(
         target_qcow2.to_str().unwrap(),
+        &shape,
         &network,
-        monitor_socket.to_str().unwrap(),
+        qmp_socket.to_str().unwrap(),
+        &ConsoleFiles {
+            socket: console_socket.to_str().unwrap(),
+            log: console_log.to_str().unwrap(),
+        },
+        &VmExtras {
+            vnc_socket: vnc_socket.as_ref().and_then(|p| p.to_str()),
+            seed_file: seed_file.as_ref().and_then(|p| p.to_str()),
+            volumes: &[],
+            metadata_socket: metadata_socket.to_str(),
+            guest_agent_socket: guest_agent_socket.to_str(),
+        },
     ) {
-        Ok(child) => {
-            let vm_info = VmInfo {
-                id: uuid.clone(),
-                name: payload.name.clone(),
-                ssh_port: vm_info_ssh_port,
-                mac_address: vm_info_mac,
-                pid: child.id().unwrap(),
-            };
-            let _ = store_vm_info(&config.storage.metadata_dir, &vm_info);
-
-            (
-                StatusCode::OK,
-                Json(LaunchVmResponse {
-                    success: true,
-                    message: format!(
-                        "VM launch request received for {} in {}",
-                        payload.name, payload.region
-                    ),
-                    instance_id: Some(uuid),
-                    ssh_host: Some(response_ssh_host),
-                    ssh_port: Some(response_ssh_port),
-                    pid: child.id(),
-                }),
+        Ok(child) => child,
+        Err(e) => {
+            return abandon_launch(
+                metadata_dir,
+                &uuid,
+                &target_qcow2,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to launch VM: {e}"),
             )
+            .await;
         }
+    };
+    let pid = child.id().unwrap();
+    let _ = transition_with(
+        metadata_dir,
+        &uuid,
+        VmState::Starting,
+        "QEMU spawned",
+        |vm| vm.pid = pid,
+    );
+
+    let qmp = match wait_until_started(&mut child, &qmp_socket).await {
+        Ok(qmp) => qmp,
         Err(e) => {
-            error!("Failed to launch VM {}: {e}", payload.name);
-            (
+            return abandon_launch(
+                metadata_dir,
+                &uuid,
+                &target_qcow2,
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(LaunchVmResponse {
-                    success: false,
-                    message: format!("Failed to launch VM: {e}"),
-                    instance_id: None,
-                    ssh_host: None,
-                    ssh_port: None,
-                    pid: None,
-                }),
+                format!("VM failed to start: {e}"),
             )
+            .await;
+        }
+    };
+    let _ = transition(metadata_dir, &uuid, VmState::Running, "QEMU started");
+    watch_vm(metadata_dir.clone(), uuid.clone(), child, qmp);
+
+    (
+        StatusCode::OK,
+        Json(LaunchVmResponse {
+            success: true,
+            message: format!(
+                "VM launch request received for {} in {}",
+                payload.name, payload.region
+            ),
+            instance_id: Some(uuid),
+            ssh_host: Some(response_ssh_host),
+            ssh_port: Some(response_ssh_port),
+            pid: Some(pid),
+        }),
+    )
+}
+
+/// Undo a launch that failed before QEMU was confirmed running, so it leaves
+/// no disk, seed or metadata behind.
+async fn abandon_launch(
+    metadata_dir: &Path,
+    id: &str,
+    target_qcow2: &Path,
+    status: StatusCode,
+    message: String,
+) -> (StatusCode, Json<LaunchVmResponse>) {
+    error!("Launch of VM {id} failed: {message}");
+    let _ = fs::remove_file(target_qcow2).await;
+    let _ = remove_seed(metadata_dir, id).await;
+    let _ = remove_console_log(metadata_dir, id);
+    let _ = delete_vm_by_id(metadata_dir, id);
+    launch_error(status, message)
+}
+
+/// Wait until a freshly spawned QEMU answers `query-status` on its QMP
+/// socket, so a VM that dies during startup (bad disk, missing bridge) is
+/// reported as a failure rather than a successful launch. A QEMU that is
+/// still alive when this fails is killed. Returns the connected client.
+async fn wait_until_started(
+    child: &mut Child,
+    qmp_socket: &Path,
+) -> Result<Arc<QmpClient>, String> {
+    let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("QEMU exited during startup with {status}"));
+        }
+        if let Ok(client) = QmpClient::shared(qmp_socket).await {
+            return match client.query_status().await {
+                Ok(status) => {
+                    debug!("QEMU run state after start: {}", status.status);
+                    Ok(client)
+                }
+                Err(e) => {
+                    let _ = child.start_kill();
+                    Err(e.to_string())
+                }
+            };
+        }
+        if tokio::time::Instant::now() >= deadline {
+            let _ = child.start_kill();
+            return Err("QEMU did not open its QMP socket in time".to_string());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Follow a started VM until its QEMU process exits. See `watch_exit`.
+fn watch_vm(metadata_dir: PathBuf, id: String, mut child: Child, qmp: Arc<QmpClient>) {
+    let exit = async move {
+        match child.wait().await {
+            Ok(status) => status.to_string(),
+            Err(e) => format!("unknown status ({e})"),
+        }
+    };
+    watch_exit(metadata_dir, id, exit, qmp);
+}
+
+/// Follow an adopted VM, whose QEMU is not our child and so can only be
+/// polled, until its process exits. See `watch_exit`.
+fn watch_adopted_vm(metadata_dir: PathBuf, id: String, pid: u32, qmp: Arc<QmpClient>) {
+    let exit = async move {
+        while is_process_running(pid) {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        "unknown status (adopted process)".to_string()
+    };
+    watch_exit(metadata_dir, id, exit, qmp);
+}
+
+/// Wait for `exit` (resolving to the exit status) and record whether the VM
+/// stopped or crashed. A QMP `SHUTDOWN` event moves a running VM to stopping
+/// and marks the exit as a clean one, until a `RESUME` shows the machine was
+/// brought back (as a reboot does). Meanwhile the VM's console log is rotated
+/// as it grows, its metadata socket is served and its guest agent is polled
+/// for network interfaces.
+fn watch_exit(
+    metadata_dir: PathBuf,
+    id: String,
+    exit: impl std::future::Future<Output = String> + Send + 'static,
+    qmp: Arc<QmpClient>,
+) {
+    tokio::spawn(async move {
+        let _metadata = serve_vm_socket(&metadata_dir, &id)
+            .inspect_err(|e| warn!("Failed to serve metadata for VM {id}: {e}"));
+        let interfaces = poll_interfaces(&metadata_dir, &id);
+        let mut events = qmp.events();
+        let mut shutdown: Option<String> = None;
+        let mut rotate = tokio::time::interval(CONSOLE_ROTATE_INTERVAL);
+        tokio::pin!(exit);
+
+        let status = loop {
+            tokio::select! {
+                status = &mut exit => break status,
+                _ = rotate.tick() => {
+                    if let Err(e) = rotate_if_needed(&metadata_dir, &id, MAX_LOG_BYTES) {
+                        warn!("Failed to rotate console log of VM {id}: {e}");
+                    }
+                }
+                event = events.recv() => match event {
+                    Ok(event) if event.event == "SHUTDOWN" => {
+                        let reason = event
+                            .data
+                            .get("reason")
+                            .and_then(|r| r.as_str())
+                            .unwrap_or("shutdown")
+                            .to_string();
+                        let _ = transition(
+                            &metadata_dir,
+                            &id,
+                            VmState::Stopping,
+                            &format!("guest is shutting down ({reason})"),
+                        );
+                        shutdown = Some(reason);
+                    }
+                    Ok(event) if event.event == "RESUME" => shutdown = None,
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break (&mut exit).await,
+                },
+            }
+        };
+
+        // Addresses are only meaningful while the guest runs; the next boot
+        // may well get different ones.
+        drop(interfaces);
+        let _ = update_vm(&metadata_dir, &id, |vm| vm.guest_interfaces.clear());
+        if let Err(e) = record_exit(&metadata_dir, &id, shutdown.as_deref(), &status) {
+            debug!("Not recording exit of VM {id}: {e}");
         }
+    });
+}
+
+/// Build the VM's NoCloud seed when it has any cloud-init configuration.
+/// Returns `None` for VMs without one, which boot with no seed attached.
+async fn prepare_seed(
+    metadata_dir: &Path,
+    id: &str,
+    vm_name: &str,
+    cloud_init: &CloudInitConfig,
+) -> std::io::Result<Option<PathBuf>> {
+    if cloud_init.is_empty() {
+        return Ok(None);
+    }
+    write_seed(metadata_dir, id, vm_name, cloud_init)
+        .await
+        .map(Some)
+}
+
+/// Size to create a VM's overlay with, or `None` to keep the base image's
+/// size. An explicit `requested_gb` smaller than the image is refused; the
+/// instance type's default only ever grows the disk.
+fn overlay_size(
+    requested_gb: Option<u64>,
+    default_gb: u64,
+    base_size: u64,
+) -> Result<Option<u64>, (StatusCode, String)> {
+    let size = requested_gb.unwrap_or(default_gb).saturating_mul(GIB);
+    if size > base_size {
+        Ok(Some(size))
+    } else if requested_gb.is_some() && size < base_size {
+        Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "disk_size_gb is smaller than the image's {:.1} GiB",
+                base_size as f64 / GIB as f64
+            ),
+        ))
+    } else {
+        Ok(None)
     }
 }
 
+fn launch_error(status: StatusCode, message: String) -> (StatusCode, Json<LaunchVmResponse>) {
+    (
+        status,
+        Json(LaunchVmResponse {
+            success: false,
+            message,
+            instance_id: None,
+            ssh_host: None,
+            ssh_port: None,
+            pid: None,
+        }),
+    )
+}
+
+/// Return the instance-type catalog this backend accepts in `/launch-vm`.
+pub async fn list_instance_types_handler() -> impl IntoResponse {
+    let config = Config::load().expect("Failed to load configuration");
+    Json(config.instance_types)
+}
+
 pub async fn list_vms_handler() -> impl IntoResponse {
     let config = Config::load().expect("Failed to load configuration");
     list_vms_response(&config.storage.metadata_dir, &config.network_mode).await
@@ -174,26 +629,52 @@ async fn list_vms_response(dir: &Path, mode: &NetworkMode) -> axum::response::Re
         Ok(vms) => {
             let mut entries = Vec::new();
             for vm in vms {
+                let mut vm = refresh_state(dir, vm);
+                let interfaces = if vm.state.is_active() {
+                    std::mem::take(&mut vm.guest_interfaces)
+                } else {
+                    Vec::new()
+                };
                 let entry = match mode {
                     NetworkMode::User => VmListEntry {
-                        running: is_process_running(vm.pid),
+                        running: vm.state.is_active(),
+                        state: vm.state,
+                        state_reason: vm.state_reason,
+                        instance_type: vm.instance_type,
+                        image_id: vm.image_id,
+                        autostart: vm.autostart,
+                        graphics: vm.graphics,
+                        volumes: vm.volumes.iter().map(|v| v.id.clone()).collect(),
+                        tags: vm.tags,
                         id: vm.id,
                         name: vm.name,
                         ssh_host: "localhost".to_string(),
                         ssh_port: vm.ssh_port.unwrap_or(0),
                         pid: vm.pid,
                         mac_address: None,
+                        interfaces,
                     },
                     NetworkMode::Bridge => VmListEntry {
-                        running: is_process_running(vm.pid),
+                        running: vm.state.is_active(),
+                        state: vm.state,
+                        state_reason: vm.state_reason,
+                        instance_type: vm.instance_type,
+                        image_id: vm.image_id,
+                        autostart: vm.autostart,
+                        graphics: vm.graphics,
+                        volumes: vm.volumes.iter().map(|v| v.id.clone()).collect(),
+                        tags: vm.tags,
                         id: vm.id,
                         name: vm.name,
-                        // Leave ssh_host empty; the proxy resolves it from the
-                        // dnsmasq lease file on the controller node.
-                        ssh_host: String::new(),
+                        // Without a guest agent report ssh_host is left empty
+                        // and the proxy resolves it from the dnsmasq lease
+                        // file on the controller node.
+                        ssh_host: primary_ipv4(&interfaces, vm.mac_address.as_deref())
+                            .unwrap_or_default(),
                         ssh_port: 22,
                         pid: vm.pid,
                         mac_address: vm.mac_address.clone(),
+                        interfaces,
                     },
                 };
                 entries.push(entry);
@@ -207,20 +688,46 @@ async fn list_vms_response(dir: &Path, mode: &NetworkMode) -> axum::response::Re
     }
 }
 
-/// Launch a single VM from its persisted metadata. Updates the stored PID on
-/// success. Called both by `start_all_vms` on startup and `start_vm_handler`
-/// on demand.
+/// Launch a single VM, already moved to `Starting`, from its persisted
+/// metadata. On success the VM is `Running` with its new PID recorded; on
+/// failure it is `Crashed` with the error as the reason. Called both by
+/// `reconcile_vms` on startup and `start_vm_handler` on demand.
 async fn start_single_vm(
     vm_info: &VmInfo,
     metadata_dir: &Path,
     qcow2_dir: &Path,
     network_mode: &NetworkMode,
 ) -> Result<u32, String> {
-    let qcow2_file = qcow2_dir.join(format!("{}.qcow2", vm_info.name));
-    let monitor_socket = metadata_dir.join(format!("{}.monitor", vm_info.id));
+    let result = boot_vm(vm_info, metadata_dir, qcow2_dir, network_mode).await;
+    if let Err(e) = &result {
+        let _ = transition(metadata_dir, &vm_info.id, VmState::Crashed, e);
+    }
+    result
+}
 
-    // Remove any stale monitor socket from a previous run.
-    let _ = fs::remove_file(&monitor_socket).await;
+async fn boot_vm(
+    vm_info: &VmInfo,
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    network_mode: &NetworkMode,
+) -> Result<u32, String> {
+    let qcow2_file = vm_info.disk_path(qcow2_dir);
+    let qmp_socket = qmp_socket_path(metadata_dir, &vm_info.id);
+    let console_socket = console_socket_path(metadata_dir, &vm_info.id);
+    let console_log = console_log_path(metadata_dir, &vm_info.id);
+    let metadata_socket = metadata_socket_path(metadata_dir, &vm_info.id);
+    let guest_agent_socket = guest_agent_socket_path(metadata_dir, &vm_info.id);
+    let vnc_socket = vm_info
+        .graphics
+        .then(|| vnc_socket_path(metadata_dir, &vm_info.id));
+
+    // Remove any stale sockets from a previous run.
+    let _ = fs::remove_file(&qmp_socket).await;
+    let _ = fs::remove_file(&console_socket).await;
+    let _ = fs::remove_file(&guest_agent_socket).await;
+    if let Some(vnc_socket) = &vnc_socket {
+        let _ = fs::remove_file(vnc_socket).await;
+    }
 
     let (network, ssh_port, mac_address) = match network_mode {
         NetworkMode::User => {
@@ -244,190 +751,790 @@ async fn start_single_vm(
         }
     };
 
-    match vm_start(
+    // Rebuilt on every start so the seed always reflects the stored config
+    // and a deleted or stale ISO never blocks a boot.
+    let seed_file = prepare_seed(
+        metadata_dir,
+        &vm_info.id,
+        &vm_info.name,
+        &vm_info.cloud_init,
+    )
+    .await
+    .map_err(|e| format!("Failed to build cloud-init seed: {e}"))?;
+
+    let mut child = vm_start(
         qcow2_file.to_str().unwrap(),
+        &vm_info.shape,
         &network,
-        monitor_socket.to_str().unwrap(),
-    ) {
-        Ok(child) => {
-            let pid = child.id().unwrap();
-            let updated = VmInfo {
-                id: vm_info.id.clone(),
-                name: vm_info.name.clone(),
-                ssh_port,
-                mac_address,
-                pid,
-            };
-            let _ = store_vm_info(metadata_dir, &updated);
-            Ok(pid)
-        }
-        Err(e) => Err(e.to_string()),
-    }
+        qmp_socket.to_str().unwrap(),
+        &ConsoleFiles {
+            socket: console_socket.to_str().unwrap(),
+            log: console_log.to_str().unwrap(),
+        },
+        &VmExtras {
+            vnc_socket: vnc_socket.as_ref().and_then(|p| p.to_str()),
+            seed_file: seed_file.as_ref().and_then(|p| p.to_str()),
+            volumes: &vm_info.volumes,
+            metadata_socket: metadata_socket.to_str(),
+            guest_agent_socket: guest_agent_socket.to_str(),
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    let pid = child.id().unwrap();
+    update_vm(metadata_dir, &vm_info.id, |vm| {
+        vm.ssh_port = ssh_port;
+        vm.mac_address = mac_address;
+        vm.pid = pid;
+    })
+    .map_err(|e| e.to_string())?;
+
+    let qmp = wait_until_started(&mut child, &qmp_socket).await?;
+    transition(metadata_dir, &vm_info.id, VmState::Running, "QEMU started")
+        .map_err(|e| e.to_string())?;
+    watch_vm(metadata_dir.to_path_buf(), vm_info.id.clone(), child, qmp);
+    Ok(pid)
 }
 
-pub async fn start_all_vms() {
+/// Bring the stored VMs in line with reality after a backend (re)start. See
+/// `reconcile_vm`.
+pub async fn reconcile_vms() {
     let config = Config::load().expect("Failed to load configuration");
     let vms = list_vms(&config.storage.metadata_dir).unwrap_or_default();
 
     for vm in vms {
-        let name = vm.name.clone();
-        match start_single_vm(
-            &vm,
+        reconcile_vm(
+            vm,
             &config.storage.metadata_dir,
             &config.storage.qcow2_dir,
             &config.network_mode,
         )
-        .await
-        {
-            Ok(pid) => info!("VM {name} started with PID {pid}"),
-            Err(e) => error!("Failed to start VM {name}: {e}"),
-        }
+        .await;
     }
 }
 
-pub async fn stop_vm_handler(Json(payload): Json<StopVmRequest>) -> impl IntoResponse {
-    info!("Stopping VM: {}", payload.id);
-    let config = Config::load().expect("Failed to load configuration");
-    stop_vm_response(&config.storage.metadata_dir, &payload.id).await
-}
+/// Reconcile one VM on backend startup:
+///
+/// - If its stored PID is still this VM's QEMU (checked via the process
+///   command line), adopt it: take over its QMP socket and watch it again.
+///   A QEMU that is ours but not answering QMP is left alone, since starting
+///   a second one would put two QEMUs on one disk.
+/// - Otherwise record that its QEMU is gone, and start it again only if its
+///   `autostart` flag (the desired state) says it should be running.
+async fn reconcile_vm(
+    vm_info: VmInfo,
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    network_mode: &NetworkMode,
+) {
+    let name = vm_info.name.clone();
+    if vm_info.state == VmState::Deleting {
+        info!("Not reconciling VM {name}: it is being deleted");
+        return;
+    }
 
-async fn stop_vm_response(metadata_dir: &Path, id: &str) -> axum::response::Response {
-    match get_vm_by_id(metadata_dir, id) {
-        Ok(Some(vm_info)) => {
-            if !is_process_running(vm_info.pid) {
-                return (StatusCode::CONFLICT, "VM is not running").into_response();
-            }
-            let socket_path = metadata_dir.join(format!("{}.monitor", vm_info.id));
-            match send_monitor_command(socket_path.to_str().unwrap(), "system_powerdown").await {
-                Ok(_) => {
-                    info!("Sent system_powerdown to VM {}", vm_info.name);
-                    (StatusCode::OK, "VM shutdown initiated").into_response()
-                }
-                Err(e) => {
-                    error!("Failed to reach monitor for VM {}: {e}", vm_info.name);
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Failed to stop VM: {e}"),
-                    )
-                        .into_response()
-                }
-            }
+    let qmp_socket = qmp_socket_path(metadata_dir, &vm_info.id);
+    if is_vm_process(vm_info.pid, &qmp_socket) {
+        match adopt_vm(&vm_info, metadata_dir, &qmp_socket).await {
+            Ok(state) => info!("Adopted VM {name} (PID {}) as {state}", vm_info.pid),
+            Err(e) => error!(
+                "QEMU for VM {name} (PID {}) is running but not answering on QMP; leaving it alone: {e}",
+                vm_info.pid
+            ),
         }
-        Ok(None) => (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        return;
+    }
+
+    // No QEMU of ours is running. The stored PID may even belong to an
+    // unrelated process by now, so do not go through `refresh_state`.
+    if vm_info.state.is_active() {
+        let (to, reason) = if vm_info.state == VmState::Stopping {
+            (VmState::Stopped, "QEMU exited after shutdown request")
+        } else {
+            (VmState::Crashed, "QEMU process is no longer running")
+        };
+        if let Err(e) = transition(metadata_dir, &vm_info.id, to, reason) {
+            error!("Failed to record exit of VM {name}: {e}");
+            return;
+        }
+    }
+
+    if !vm_info.autostart {
+        info!("Not starting VM {name}: autostart is off");
+        return;
+    }
+    let vm_info = match transition(
+        metadata_dir,
+        &vm_info.id,
+        VmState::Starting,
+        "backend startup",
+    ) {
+        Ok(vm_info) => vm_info,
         Err(e) => {
-            error!("Error retrieving VM info: {e}");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Error retrieving VM info: {e}"),
-            )
-                .into_response()
+            info!("Not starting VM {name}: {e}");
+            return;
         }
+    };
+    match start_single_vm(&vm_info, metadata_dir, qcow2_dir, network_mode).await {
+        Ok(pid) => info!("VM {name} started with PID {pid}"),
+        Err(e) => error!("Failed to start VM {name}: {e}"),
     }
 }
 
-pub async fn start_vm_handler(Json(payload): Json<StartVmRequest>) -> impl IntoResponse {
-    info!("Starting VM: {}", payload.id);
+/// Take over a live QEMU for a VM: connect to its QMP socket, record the run
+/// state it reports and watch it for exit as if we had started it. The state
+/// is written directly rather than through `transition`, since the stored one
+/// may be stale (e.g. crashed) while QEMU is in fact running.
+async fn adopt_vm(
+    vm_info: &VmInfo,
+    metadata_dir: &Path,
+    qmp_socket: &Path,
+) -> Result<VmState, String> {
+    let qmp = QmpClient::shared(qmp_socket)
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = qmp.query_status().await.map_err(|e| e.to_string())?;
+    let state = match status.status.as_str() {
+        _ if status.running => VmState::Running,
+        "paused" => VmState::Paused,
+        // E.g. "shutdown": the guest has shut down and QEMU is on its way out.
+        _ => VmState::Stopping,
+    };
+    update_vm(metadata_dir, &vm_info.id, |vm| {
+        vm.state = state;
+        vm.state_reason = "adopted after backend restart".to_string();
+    })
+    .map_err(|e| e.to_string())?;
+    watch_adopted_vm(
+        metadata_dir.to_path_buf(),
+        vm_info.id.clone(),
+        vm_info.pid,
+        qmp,
+    );
+    Ok(state)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsoleLogQuery {
+    /// Byte offset to read from, e.g. a previous response's `next_offset`.
+    pub offset: Option<u64>,
+    /// Only return the last this many lines.
+    pub tail: Option<usize>,
+}
+
+pub async fn console_log_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<ConsoleLogQuery>,
+) -> impl IntoResponse {
     let config = Config::load().expect("Failed to load configuration");
-    start_vm_response(
-        &config.storage.metadata_dir,
-        &config.storage.qcow2_dir,
-        &config.network_mode,
-        &payload.id,
-    )
-    .await
+    console_log_response(&config.storage.metadata_dir, &id, &query)
 }
 
-async fn start_vm_response(
+fn console_log_response(
     metadata_dir: &Path,
-    qcow2_dir: &Path,
-    network_mode: &NetworkMode,
     id: &str,
+    query: &ConsoleLogQuery,
 ) -> axum::response::Response {
     match get_vm_by_id(metadata_dir, id) {
-        Ok(Some(vm_info)) => {
-            if is_process_running(vm_info.pid) {
-                return (StatusCode::CONFLICT, "VM is already running").into_response();
-            }
-            match start_single_vm(&vm_info, metadata_dir, qcow2_dir, network_mode).await {
-                Ok(pid) => {
-                    info!("VM {} restarted with PID {pid}", vm_info.name);
-                    (StatusCode::OK, format!("VM started with PID {pid}")).into_response()
-                }
-                Err(e) => {
-                    error!("Failed to start VM {}: {e}", vm_info.name);
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Failed to start VM: {e}"),
-                    )
-                        .into_response()
-                }
-            }
-        }
-        Ok(None) => (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "VM not found").into_response(),
         Err(e) => {
             error!("Error retrieving VM info: {e}");
-            (
+            return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Error retrieving VM info: {e}"),
+            )
+                .into_response();
+        }
+    }
+    match read_console_log(metadata_dir, id, query.offset, query.tail) {
+        Ok(chunk) => (StatusCode::OK, Json::<ConsoleLogChunk>(chunk)).into_response(),
+        Err(e) => {
+            error!("Failed to read console log of VM {id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read console log: {e}"),
             )
                 .into_response()
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DeleteVmRequest {
-    pub id: String,
+pub async fn console_handler(
+    AxumPath(id): AxumPath<String>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let config = Config::load().expect("Failed to load configuration");
+    match open_console(&config.storage.metadata_dir, &id).await {
+        Ok(console) => {
+            info!("Console session opened for VM {id}");
+            ws.on_upgrade(move |socket| async move {
+                bridge(socket, console).await;
+                info!("Console session closed for VM {id}");
+            })
+        }
+        Err(response) => response,
+    }
 }
 
-pub async fn delete_vm_handler(Json(payload): Json<DeleteVmRequest>) -> impl IntoResponse {
-    info!("Deleting VM: {payload:?}");
-
+pub async fn vnc_handler(
+    AxumPath(id): AxumPath<String>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
     let config = Config::load().expect("Failed to load configuration");
-
-    delete_vm_response(
-        &config.storage.metadata_dir,
-        &config.storage.qcow2_dir,
-        &payload.id,
-    )
-    .await
+    match open_vnc(&config.storage.metadata_dir, &id).await {
+        Ok(vnc) => {
+            info!("VNC session opened for VM {id}");
+            // noVNC asks for the "binary" subprotocol.
+            ws.protocols(["binary"])
+                .on_upgrade(move |socket| async move {
+                    bridge(socket, vnc).await;
+                    info!("VNC session closed for VM {id}");
+                })
+        }
+        Err(response) => response,
+    }
 }
 
-async fn delete_vm_response(
+/// Connect to a VM's serial console socket. QEMU serves one client at a
+/// time, so a second session waits until the first one disconnects.
+async fn open_console(
+    metadata_dir: &Path,
+    id: &str,
+) -> Result<UnixStream, axum::response::Response> {
+    active_vm(metadata_dir, id, "open the console").map_err(IntoResponse::into_response)?;
+    connect_vm_socket(&console_socket_path(metadata_dir, id), id, "console").await
+}
+
+/// Connect to a VM's VNC socket. Only VMs launched with `graphics` have one.
+async fn open_vnc(metadata_dir: &Path, id: &str) -> Result<UnixStream, axum::response::Response> {
+    let vm_info = active_vm(metadata_dir, id, "open VNC").map_err(IntoResponse::into_response)?;
+    if !vm_info.graphics {
+        return Err((StatusCode::CONFLICT, "VM was launched without graphics").into_response());
+    }
+    connect_vm_socket(&vnc_socket_path(metadata_dir, id), id, "VNC").await
+}
+
+/// Load a VM that has a QEMU process, or the status and message explaining
+/// why `action` is not possible.
+pub(crate) fn active_vm(
+    metadata_dir: &Path,
+    id: &str,
+    action: &str,
+) -> Result<VmInfo, (StatusCode, String)> {
+    let vm_info = match get_vm_by_id(metadata_dir, id) {
+        Ok(Some(vm_info)) => refresh_state(metadata_dir, vm_info),
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "VM not found".to_string())),
+        Err(e) => {
+            error!("Error retrieving VM info: {e}");
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving VM info: {e}"),
+            ));
+        }
+    };
+    if !vm_info.state.is_active() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Cannot {action} while the VM is {}", vm_info.state),
+        ));
+    }
+    Ok(vm_info)
+}
+
+async fn connect_vm_socket(
+    path: &Path,
+    id: &str,
+    what: &str,
+) -> Result<UnixStream, axum::response::Response> {
+    UnixStream::connect(path).await.map_err(|e| {
+        warn!("Failed to connect to {what} of VM {id}: {e}");
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("The {what} is unavailable: {e}"),
+        )
+            .into_response()
+    })
+}
+
+pub async fn screenshot_handler(AxumPath(id): AxumPath<String>) -> impl IntoResponse {
+    let config = Config::load().expect("Failed to load configuration");
+    screenshot_response(&config.storage.metadata_dir, &id).await
+}
+
+/// Capture the VM's display as a PNG with QMP `screendump`. Works with or
+/// without `graphics`, since the VGA device exists either way.
+async fn screenshot_response(metadata_dir: &Path, id: &str) -> axum::response::Response {
+    if let Err(error) = active_vm(metadata_dir, id, "take a screenshot") {
+        return error.into_response();
+    }
+
+    // QEMU writes the file itself; a unique name keeps concurrent requests
+    // from reading or removing each other's screenshot.
+    let path = metadata_dir.join(format!("{id}.screendump-{}.png", Uuid::new_v4()));
+    let result = match QmpClient::shared(&qmp_socket_path(metadata_dir, id)).await {
+        Ok(qmp) => qmp.screendump(&path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        error!("screendump failed for VM {id}: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to take screenshot: {e}"),
+        )
+            .into_response();
+    }
+
+    let png = fs::read(&path).await;
+    let _ = fs::remove_file(&path).await;
+    match png {
+        Ok(png) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+        Err(e) => {
+            error!("Failed to read screenshot of VM {id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read screenshot: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn stop_vm_handler(Json(payload): Json<StopVmRequest>) -> impl IntoResponse {
+    info!("Stopping VM: {}", payload.id);
+    let config = Config::load().expect("Failed to load configuration");
+    let timeout = payload
+        .timeout_secs
+        .map_or(DEFAULT_GUEST_TIMEOUT, Duration::from_secs);
+    stop_vm_response(
+        &config.storage.metadata_dir,
+        &payload.id,
+        timeout,
+        STOP_GRACE,
+    )
+    .await
+}
+
+async fn stop_vm_response(
     metadata_dir: &Path,
-    qcow2_dir: &Path,
     id: &str,
+    timeout: Duration,
+    grace: Duration,
 ) -> axum::response::Response {
     match get_vm_by_id(metadata_dir, id) {
-        Ok(Some(vm_info)) => match kill(Pid::from_raw(vm_info.pid as i32), Signal::SIGTERM) {
-            Ok(_) => {
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        Ok(Some(vm_info)) => {
+            let vm_info = refresh_state(metadata_dir, vm_info);
+            // A stopped VM stays stopped across backend restarts.
+            if let Err(e) = transition_with(
+                metadata_dir,
+                id,
+                VmState::Stopping,
+                "stop requested",
+                |vm| vm.autostart = false,
+            ) {
+                return transition_error_response("stop", e);
+            }
+            // A paused guest cannot react to the power button.
+            let timeout = if vm_info.state == VmState::Paused {
+                Duration::ZERO
+            } else {
+                timeout
+            };
+            let socket_path = qmp_socket_path(metadata_dir, &vm_info.id);
+            match stop_process(vm_info.pid, &socket_path, timeout, grace).await {
+                Ok(step) => {
+                    info!("VM {} stopped by {step}", vm_info.name);
+                    record_outcome(
+                        metadata_dir,
+                        id,
+                        VmState::Stopped,
+                        &format!("stopped by {step}"),
+                    );
+                    let response = StopVmResponse {
+                        message: format!("VM stopped by {step}"),
+                        stopped_by: step,
+                    };
+                    (StatusCode::OK, Json(response)).into_response()
+                }
+                Err(e) => {
+                    error!("Failed to stop VM {}: {e}", vm_info.name);
+                    restore_after_failed_stop(
+                        metadata_dir,
+                        &vm_info,
+                        VmState::Stopping,
+                        &format!("stop failed: {e}"),
+                    );
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to stop VM: {e}"),
+                    )
+                        .into_response()
+                }
+            }
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        Err(e) => {
+            error!("Error retrieving VM info: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving VM info: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Undo the move to `during` (stopping or deleting) made before a stop whose
+/// QEMU outlived it: the VM gets back the state and autostart flag in
+/// `before`, unless something else has moved it on since. Should QEMU have
+/// exited after all, `refresh_state` then records that.
+fn restore_after_failed_stop(metadata_dir: &Path, before: &VmInfo, during: VmState, reason: &str) {
+    let result = update_vm(metadata_dir, &before.id, |vm| {
+        if vm.state == during {
+            info!("VM {}: {during} -> {} ({reason})", vm.id, before.state);
+            vm.state = before.state;
+            vm.state_reason = reason.to_string();
+            vm.autostart = before.autostart;
+        }
+    });
+    match result {
+        Ok(vm_info) => {
+            refresh_state(metadata_dir, vm_info);
+        }
+        Err(e) => error!("Failed to restore state of VM {}: {e}", before.name),
+    }
+}
+
+/// Record the end state of an operation. The process watcher may have moved
+/// the VM there first (e.g. on QEMU exit), in which case only the reason is
+/// updated.
+fn record_outcome(metadata_dir: &Path, id: &str, to: VmState, reason: &str) {
+    let result = match transition(metadata_dir, id, to, reason) {
+        Err(TransitionError::Invalid { from, .. }) if from == to => {
+            update_vm(metadata_dir, id, |vm| vm.state_reason = reason.to_string())
+        }
+        other => other,
+    };
+    if let Err(e) = result {
+        warn!("Could not record VM {id} as {to}: {e}");
+    }
+}
+
+pub async fn reboot_vm_handler(Json(payload): Json<RebootVmRequest>) -> impl IntoResponse {
+    info!("Rebooting VM: {}", payload.id);
+    let config = Config::load().expect("Failed to load configuration");
+    let timeout = payload
+        .timeout_secs
+        .map_or(DEFAULT_GUEST_TIMEOUT, Duration::from_secs);
+    reboot_vm_response(&config.storage.metadata_dir, &payload.id, timeout).await
+}
+
+async fn reboot_vm_response(
+    metadata_dir: &Path,
+    id: &str,
+    timeout: Duration,
+) -> axum::response::Response {
+    let vm_info = match get_vm_by_id(metadata_dir, id) {
+        Ok(Some(vm_info)) => refresh_state(metadata_dir, vm_info),
+        Ok(None) => return (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        Err(e) => {
+            error!("Error retrieving VM info: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving VM info: {e}"),
+            )
+                .into_response();
+        }
+    };
+    if vm_info.state != VmState::Running {
+        return (
+            StatusCode::CONFLICT,
+            format!("Cannot reboot VM while it is {}", vm_info.state),
+        )
+            .into_response();
+    }
+
+    let socket_path = qmp_socket_path(metadata_dir, id);
+    let result = match QmpClient::shared(&socket_path).await {
+        Ok(qmp) => reboot(&qmp, timeout).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(step) => {
+            info!("VM {} rebooted by {step}", vm_info.name);
+            // The guest's shutdown made the watcher mark the VM stopping.
+            record_outcome(
+                metadata_dir,
+                id,
+                VmState::Running,
+                &format!("rebooted by {step}"),
+            );
+            let response = RebootVmResponse {
+                message: format!("VM rebooted by {step}"),
+                rebooted_by: step,
+            };
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to reboot VM {}: {e}", vm_info.name);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to reboot VM: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn pause_vm_handler(Json(payload): Json<PauseVmRequest>) -> impl IntoResponse {
+    info!("Pausing VM: {}", payload.id);
+    let config = Config::load().expect("Failed to load configuration");
+    set_paused_response(&config.storage.metadata_dir, &payload.id, true).await
+}
+
+pub async fn resume_vm_handler(Json(payload): Json<ResumeVmRequest>) -> impl IntoResponse {
+    info!("Resuming VM: {}", payload.id);
+    let config = Config::load().expect("Failed to load configuration");
+    set_paused_response(&config.storage.metadata_dir, &payload.id, false).await
+}
+
+/// Pause (QMP `stop`) or resume (QMP `cont`) a VM's vCPUs. The new state is
+/// claimed before the command is sent and reverted if QEMU rejects it.
+async fn set_paused_response(
+    metadata_dir: &Path,
+    id: &str,
+    pause: bool,
+) -> axum::response::Response {
+    let (action, done, to, from) = if pause {
+        ("pause", "paused", VmState::Paused, VmState::Running)
+    } else {
+        ("resume", "resumed", VmState::Running, VmState::Paused)
+    };
+
+    let vm_info = match get_vm_by_id(metadata_dir, id) {
+        Ok(Some(vm_info)) => refresh_state(metadata_dir, vm_info),
+        Ok(None) => return (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        Err(e) => {
+            error!("Error retrieving VM info: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving VM info: {e}"),
+            )
+                .into_response();
+        }
+    };
+    // The transition table alone would also allow e.g. resuming a starting VM.
+    if vm_info.state != from {
+        return (
+            StatusCode::CONFLICT,
+            format!("Cannot {action} VM while it is {}", vm_info.state),
+        )
+            .into_response();
+    }
+    if let Err(e) = transition(metadata_dir, id, to, &format!("{done} by request")) {
+        return transition_error_response(action, e);
+    }
+
+    let socket_path = qmp_socket_path(metadata_dir, id);
+    let result = match QmpClient::shared(&socket_path).await {
+        Ok(qmp) if pause => qmp.stop().await,
+        Ok(qmp) => qmp.cont().await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => {
+            info!("VM {} {done}", vm_info.name);
+            (StatusCode::OK, format!("VM {done}")).into_response()
+        }
+        Err(e) => {
+            error!("Failed to {action} VM {}: {e}", vm_info.name);
+            let _ = transition(metadata_dir, id, from, &format!("{action} failed: {e}"));
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to {action} VM: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Map a failed state transition to a response: an invalid move is a 409 so
+/// callers can tell "not allowed now" from real failures.
+fn transition_error_response(action: &str, e: TransitionError) -> axum::response::Response {
+    match e {
+        TransitionError::NotFound => (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        TransitionError::Invalid { from, .. } => (
+            StatusCode::CONFLICT,
+            format!("Cannot {action} VM while it is {from}"),
+        )
+            .into_response(),
+        TransitionError::Io(e) => {
+            error!("Error updating VM state: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error updating VM state: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn start_vm_handler(Json(payload): Json<StartVmRequest>) -> impl IntoResponse {
+    info!("Starting VM: {}", payload.id);
+    let config = Config::load().expect("Failed to load configuration");
+    start_vm_response(
+        &config.storage.metadata_dir,
+        &config.storage.qcow2_dir,
+        &config.network_mode,
+        &payload.id,
+    )
+    .await
+}
 
-                if kill(Pid::from_raw(vm_info.pid as i32), Signal::SIGKILL).is_ok() {
-                    warn!("Process {} was still running, force killed", vm_info.pid);
+async fn start_vm_response(
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    network_mode: &NetworkMode,
+    id: &str,
+) -> axum::response::Response {
+    // An attach decided on against the stopped VM must be recorded before
+    // its volumes are read for the QEMU command line, or it is never plugged.
+    let _attach = ATTACH_LOCK.lock().await;
+    // QEMU must open the disk chain as recorded, not halfway through a change.
+    let _snapshot = SNAPSHOT_LOCK.lock().await;
+    match get_vm_by_id(metadata_dir, id) {
+        Ok(Some(vm_info)) => {
+            refresh_state(metadata_dir, vm_info);
+            let vm_info = match transition_with(
+                metadata_dir,
+                id,
+                VmState::Starting,
+                "start requested",
+                |vm| vm.autostart = true,
+            ) {
+                Ok(vm_info) => vm_info,
+                Err(e) => return transition_error_response("start", e),
+            };
+            match start_single_vm(&vm_info, metadata_dir, qcow2_dir, network_mode).await {
+                Ok(pid) => {
+                    info!("VM {} restarted with PID {pid}", vm_info.name);
+                    (StatusCode::OK, format!("VM started with PID {pid}")).into_response()
+                }
+                Err(e) => {
+                    error!("Failed to start VM {}: {e}", vm_info.name);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to start VM: {e}"),
+                    )
+                        .into_response()
                 }
+            }
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        Err(e) => {
+            error!("Error retrieving VM info: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving VM info: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteVmRequest {
+    pub id: String,
+}
+
+pub async fn delete_vm_handler(Json(payload): Json<DeleteVmRequest>) -> impl IntoResponse {
+    info!("Deleting VM: {payload:?}");
+
+    let config = Config::load().expect("Failed to load configuration");
+
+    delete_vm_response(
+        &config.storage.metadata_dir,
+        &config.storage.qcow2_dir,
+        &config.storage.volume_data_dir,
+        &payload.id,
+        STOP_GRACE,
+    )
+    .await
+}
+
+async fn delete_vm_response(
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    volume_data_dir: &Path,
+    id: &str,
+    grace: Duration,
+) -> axum::response::Response {
+    // Volumes attached meanwhile would stay attached to the deleted VM.
+    let _attach = ATTACH_LOCK.lock().await;
+    // A snapshot in progress would leave files behind that delete never saw.
+    let _snapshot = SNAPSHOT_LOCK.lock().await;
+    match get_vm_by_id(metadata_dir, id) {
+        Ok(Some(vm_info)) => {
+            let vm_info = refresh_state(metadata_dir, vm_info);
+            let was_active = vm_info.state.is_active();
+            if let Err(e) = transition(metadata_dir, id, VmState::Deleting, "delete requested") {
+                return transition_error_response("delete", e);
+            }
 
-                let qcow2_file_path = qcow2_dir.join(format!("{}.qcow2", vm_info.name));
+            // The disk is about to be removed, so there is no point waiting
+            // for the guest to shut down cleanly.
+            if was_active && is_process_running(vm_info.pid) {
+                let socket_path = qmp_socket_path(metadata_dir, &vm_info.id);
+                match stop_process(vm_info.pid, &socket_path, Duration::ZERO, grace).await {
+                    Ok(step) => info!("VM {} stopped by {step}", vm_info.name),
+                    // QEMU may still be writing to the disk and volumes, so
+                    // none of them can be removed or released.
+                    Err(e) => {
+                        error!("Failed to stop VM {} for deletion: {e}", vm_info.name);
+                        restore_after_failed_stop(
+                            metadata_dir,
+                            &vm_info,
+                            VmState::Deleting,
+                            &format!("delete failed: {e}"),
+                        );
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            format!("Failed to stop VM: {e}"),
+                        )
+                            .into_response();
+                    }
+                }
+            }
 
+            // The disk and any files frozen by its external snapshots.
+            for qcow2_file_path in vm_info.own_disk_files(qcow2_dir) {
                 if let Err(e) = fs::remove_file(&qcow2_file_path).await {
                     warn!("Could not delete QCOW2 file: {qcow2_file_path:?} - {e}");
                 } else {
                     info!("Successfully deleted QCOW2 file: {qcow2_file_path:?}");
                 }
+            }
 
-                let _ = delete_vm_by_id(metadata_dir, &vm_info.id);
-
-                (StatusCode::OK, "VM successfully terminated and removed").into_response()
+            if let Err(e) = remove_seed(metadata_dir, &vm_info.id).await {
+                warn!("Could not delete cloud-init seed for {}: {e}", vm_info.id);
             }
-            Err(e) => {
-                error!("Error terminating process {}: {}", vm_info.pid, e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to terminate VM: {e}"),
-                )
-                    .into_response()
+            if let Err(e) = remove_console_log(metadata_dir, &vm_info.id) {
+                warn!("Could not delete console log for {}: {e}", vm_info.id);
             }
-        },
+            // Attached volumes outlive the VM and become free to attach again.
+            for volume in &vm_info.volumes {
+                if let Err(e) = update_volume(volume_data_dir, &volume.id, |v| v.attached_to = None)
+                {
+                    warn!(
+                        "Could not release volume {} of {}: {e}",
+                        volume.id, vm_info.id
+                    );
+                }
+            }
+
+            let _ = delete_vm_by_id(metadata_dir, &vm_info.id);
+
+            (StatusCode::OK, "VM successfully terminated and removed").into_response()
+        }
         Ok(None) => (StatusCode::NOT_FOUND, "VM not found").into_response(),
         Err(e) => {
             error!("Error retrieving VM info: {e}");
@@ -440,173 +1547,835 @@ async fn delete_vm_response(
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::body::to_bytes;
-    use tempfile::TempDir;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm_db::store_test_vm;
+    use crate::vm_stop::test_process;
+    use axum::body::to_bytes;
+    use tempfile::TempDir;
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+    const TEST_GRACE: Duration = Duration::from_millis(500);
+
+    // ── overlay_size ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_overlay_size_defaults_to_instance_type_disk() {
+        assert_eq!(overlay_size(None, 8, GIB), Ok(Some(8 * GIB)));
+        // A default smaller than the image keeps the image's size.
+        assert_eq!(overlay_size(None, 8, 20 * GIB), Ok(None));
+    }
+
+    #[test]
+    fn test_overlay_size_honours_request() {
+        assert_eq!(overlay_size(Some(30), 8, GIB), Ok(Some(30 * GIB)));
+        assert_eq!(overlay_size(Some(1), 8, GIB), Ok(None));
+        assert_eq!(
+            overlay_size(Some(1), 8, 2 * GIB).unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    // ── list_vms_response ────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_list_vms_response_empty_dir() {
+        let dir = TempDir::new().unwrap();
+        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
+        assert!(vms.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_response_returns_stored_vms() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "abc-1".to_string(),
+            name: "my-vm".to_string(),
+            ssh_port: Some(55000),
+            mac_address: None,
+            pid: 42,
+            ..Default::default()
+        };
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vms.len(), 1);
+        assert_eq!(vms[0].id, "abc-1");
+        assert_eq!(vms[0].ssh_host, "localhost");
+        assert_eq!(vms[0].ssh_port, 55000);
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_response_includes_instance_type() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "abc-1".to_string(),
+            name: "my-vm".to_string(),
+            ssh_port: Some(55000),
+            pid: 42,
+            instance_type: "t2.micro".to_string(),
+            ..Default::default()
+        };
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vms[0].instance_type, "t2.micro");
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_response_bridge_mode_includes_mac_address() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "abc-1".to_string(),
+            name: "my-vm".to_string(),
+            ssh_port: None,
+            mac_address: Some("52:54:00:ab:cd:ef".to_string()),
+            pid: 42,
+            ..Default::default()
+        };
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let resp = list_vms_response(dir.path(), &NetworkMode::Bridge).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vms.len(), 1);
+        // MAC is passed through for the proxy to resolve.
+        assert_eq!(vms[0].mac_address.as_deref(), Some("52:54:00:ab:cd:ef"));
+        // ssh_host is left empty; the proxy fills it in.
+        assert_eq!(vms[0].ssh_host, "");
+        assert_eq!(vms[0].ssh_port, 22);
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_response_bridge_mode_uses_guest_agent_address() {
+        let dir = TempDir::new().unwrap();
+        let interfaces = vec![
+            GuestInterface {
+                name: "lo".to_string(),
+                mac_address: None,
+                ipv4: vec!["127.0.0.1".to_string()],
+                ipv6: vec!["::1".to_string()],
+            },
+            GuestInterface {
+                name: "eth0".to_string(),
+                mac_address: Some("52:54:00:ab:cd:ef".to_string()),
+                ipv4: vec!["192.168.100.23".to_string()],
+                ipv6: vec!["2001:db8::23".to_string()],
+            },
+        ];
+        let running = VmInfo {
+            id: "abc-1".to_string(),
+            mac_address: Some("52:54:00:ab:cd:ef".to_string()),
+            pid: std::process::id(),
+            state: VmState::Running,
+            guest_interfaces: interfaces.clone(),
+            ..Default::default()
+        };
+        // A stopped VM's last report is stale and not shown.
+        let stopped = VmInfo {
+            id: "abc-2".to_string(),
+            mac_address: Some("52:54:00:00:00:02".to_string()),
+            state: VmState::Stopped,
+            guest_interfaces: interfaces.clone(),
+            ..Default::default()
+        };
+        store_vm_info(dir.path(), &running).unwrap();
+        store_vm_info(dir.path(), &stopped).unwrap();
+
+        let resp = list_vms_response(dir.path(), &NetworkMode::Bridge).await;
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
+        let vm = |id: &str| vms.iter().find(|v| v.id == id).unwrap();
+
+        assert_eq!(vm("abc-1").ssh_host, "192.168.100.23");
+        assert_eq!(vm("abc-1").interfaces, interfaces);
+        assert_eq!(vm("abc-2").ssh_host, "");
+        assert!(vm("abc-2").interfaces.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_response_running_true_when_process_alive() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "abc-1".to_string(),
+            name: "my-vm".to_string(),
+            ssh_port: Some(55000),
+            mac_address: None,
+            pid: std::process::id(), // current test process is definitely alive
+            state: VmState::Running,
+            ..Default::default()
+        };
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
+        assert!(vms[0].running);
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_response_running_false_when_process_dead() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "abc-1".to_string(),
+            name: "my-vm".to_string(),
+            ssh_port: Some(55000),
+            mac_address: None,
+            pid: u32::MAX, // guaranteed not to be a running process
+            ..Default::default()
+        };
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
+        assert!(!vms[0].running);
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_response_exposes_state_and_reason() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "abc-1".to_string(),
+            name: "my-vm".to_string(),
+            pid: std::process::id(),
+            state: VmState::Running,
+            state_reason: "QEMU started".to_string(),
+            ..Default::default()
+        };
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vms[0]["state"], "running");
+        assert_eq!(vms[0]["state_reason"], "QEMU started");
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_response_marks_dead_running_vm_crashed() {
+        let dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "abc-1".to_string(),
+            name: "my-vm".to_string(),
+            pid: u32::MAX,
+            state: VmState::Running,
+            ..Default::default()
+        };
+        store_vm_info(dir.path(), &vm).unwrap();
+
+        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vms[0].state, VmState::Crashed);
+        assert!(!vms[0].running);
+
+        let stored = get_vm_by_id(dir.path(), "abc-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Crashed);
+    }
+
+    // ── reconcile_vm ─────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_reconcile_vm_marks_dead_vm_crashed_without_autostart() {
+        let meta_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: u32::MAX,
+            state: VmState::Running,
+            autostart: false,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        reconcile_vm(vm, meta_dir.path(), meta_dir.path(), &NetworkMode::User).await;
+
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Crashed);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_vm_does_not_adopt_unrelated_process() {
+        let meta_dir = TempDir::new().unwrap();
+        // The stored PID is alive but is not a QEMU, as after PID reuse.
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: std::process::id(),
+            state: VmState::Running,
+            autostart: false,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        reconcile_vm(vm, meta_dir.path(), meta_dir.path(), &NetworkMode::User).await;
+
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Crashed);
+        assert_eq!(stored.state_reason, "QEMU process is no longer running");
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_vm_restarts_autostart_vm() {
+        let meta_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: u32::MAX,
+            state: VmState::Stopped,
+            autostart: true,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        reconcile_vm(vm, meta_dir.path(), meta_dir.path(), &NetworkMode::User).await;
+
+        // QEMU is not installed here, so the start attempt itself fails.
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Crashed);
+        assert_ne!(stored.state_reason, "QEMU process is no longer running");
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_vm_leaves_stopped_vm_without_autostart() {
+        let meta_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: u32::MAX,
+            state: VmState::Stopped,
+            state_reason: "stopped by guest shutdown".to_string(),
+            autostart: false,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        reconcile_vm(vm, meta_dir.path(), meta_dir.path(), &NetworkMode::User).await;
+
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Stopped);
+        assert_eq!(stored.state_reason, "stopped by guest shutdown");
+        assert!(!stored.autostart);
+    }
+
+    #[tokio::test]
+    async fn test_adopt_vm_records_reported_run_state() {
+        let meta_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: std::process::id(),
+            state: VmState::Crashed,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+        let socket_path = qmp_socket_path(meta_dir.path(), "vm-1");
+        crate::qmp::fake::spawn(&socket_path, |command| match command {
+            "query-status" => serde_json::json!({
+                "return": { "running": false, "status": "paused" }
+            }),
+            _ => serde_json::json!({ "return": {} }),
+        });
+
+        let state = adopt_vm(&vm, meta_dir.path(), &socket_path).await.unwrap();
+
+        assert_eq!(state, VmState::Paused);
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Paused);
+        assert_eq!(stored.state_reason, "adopted after backend restart");
+    }
+
+    #[tokio::test]
+    async fn test_adopt_vm_fails_without_qmp() {
+        let meta_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: std::process::id(),
+            state: VmState::Running,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+        let socket_path = qmp_socket_path(meta_dir.path(), "vm-1");
+
+        assert!(adopt_vm(&vm, meta_dir.path(), &socket_path).await.is_err());
+    }
+
+    // ── console_log_response ─────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_console_log_response_unknown_vm() {
+        let meta_dir = TempDir::new().unwrap();
+        let query = ConsoleLogQuery {
+            offset: None,
+            tail: None,
+        };
+        let resp = console_log_response(meta_dir.path(), "nope", &query);
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_console_log_response_returns_tail() {
+        let meta_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+        std::fs::write(
+            console_log_path(meta_dir.path(), "vm-1"),
+            "SeaBIOS\nBooting from Hard Disk...\nwelcome\n",
+        )
+        .unwrap();
+
+        let query = ConsoleLogQuery {
+            offset: None,
+            tail: Some(1),
+        };
+        let resp = console_log_response(meta_dir.path(), "vm-1", &query);
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let chunk: ConsoleLogChunk = serde_json::from_slice(&body).unwrap();
+        assert_eq!(chunk.content, "welcome\n");
+        assert_eq!(chunk.next_offset, 42);
+    }
+
+    // ── open_console ─────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_open_console_unknown_vm() {
+        let meta_dir = TempDir::new().unwrap();
+        let err = open_console(meta_dir.path(), "nope").await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_open_console_rejects_stopped_vm() {
+        let meta_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            state: VmState::Stopped,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        let err = open_console(meta_dir.path(), "vm-1").await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_open_console_connects_to_running_vm() {
+        let meta_dir = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid,
+            state: VmState::Running,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        // No QEMU listening on the console socket yet.
+        let err = open_console(meta_dir.path(), "vm-1").await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let _listener =
+            tokio::net::UnixListener::bind(console_socket_path(meta_dir.path(), "vm-1")).unwrap();
+        assert!(open_console(meta_dir.path(), "vm-1").await.is_ok());
+        test_process::kill(pid);
+    }
+
+    // ── open_vnc / screenshot_response ───────────────────────────────────────
+
+    fn store_running_vm(dir: &Path, graphics: bool) -> u32 {
+        let pid = test_process::spawn(false);
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid,
+            state: VmState::Running,
+            graphics,
+            ..Default::default()
+        };
+        store_vm_info(dir, &vm).unwrap();
+        pid
+    }
+
+    #[tokio::test]
+    async fn test_open_vnc_requires_graphics() {
+        let meta_dir = TempDir::new().unwrap();
+        let pid = store_running_vm(meta_dir.path(), false);
+
+        let err = open_vnc(meta_dir.path(), "vm-1").await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_open_vnc_connects_to_vnc_socket() {
+        let meta_dir = TempDir::new().unwrap();
+        let pid = store_running_vm(meta_dir.path(), true);
+        let _listener =
+            tokio::net::UnixListener::bind(vnc_socket_path(meta_dir.path(), "vm-1")).unwrap();
+
+        assert!(open_vnc(meta_dir.path(), "vm-1").await.is_ok());
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_screenshot_response_unknown_vm() {
+        let meta_dir = TempDir::new().unwrap();
+        let resp = screenshot_response(meta_dir.path(), "nope").await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_screenshot_response_returns_png() {
+        let meta_dir = TempDir::new().unwrap();
+        let pid = store_running_vm(meta_dir.path(), false);
+        crate::qmp::fake::spawn_with_args(
+            &qmp_socket_path(meta_dir.path(), "vm-1"),
+            |command, arguments| {
+                if command == "screendump" {
+                    assert_eq!(arguments["format"], "png");
+                    let filename = arguments["filename"].as_str().unwrap();
+                    std::fs::write(filename, b"\x89PNG fake").unwrap();
+                }
+                serde_json::json!({ "return": {} })
+            },
+        );
+
+        let resp = screenshot_response(meta_dir.path(), "vm-1").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "image/png");
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"\x89PNG fake");
+
+        // The temporary file QEMU wrote is cleaned up.
+        let leftovers = std::fs::read_dir(meta_dir.path())
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension() == Some("png".as_ref()))
+            .count();
+        assert_eq!(leftovers, 0);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_screenshot_response_surfaces_qmp_error() {
+        let meta_dir = TempDir::new().unwrap();
+        let pid = store_running_vm(meta_dir.path(), false);
+        crate::qmp::fake::spawn(&qmp_socket_path(meta_dir.path(), "vm-1"), |command| {
+            if command == "screendump" {
+                serde_json::json!({
+                    "error": { "class": "GenericError", "desc": "no surface" }
+                })
+            } else {
+                serde_json::json!({ "return": {} })
+            }
+        });
+
+        let resp = screenshot_response(meta_dir.path(), "vm-1").await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("no surface"));
+        test_process::kill(pid);
+    }
+
+    // ── stop_vm_response ─────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_stop_vm_response_not_found() {
+        let meta_dir = TempDir::new().unwrap();
+        let resp = stop_vm_response(meta_dir.path(), "no-such-id", TEST_TIMEOUT, TEST_GRACE).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_stop_vm_response_not_running_returns_conflict() {
+        let meta_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            ssh_port: Some(22222),
+            mac_address: None,
+            pid: u32::MAX, // not running
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        let resp = stop_vm_response(meta_dir.path(), "vm-1", TEST_TIMEOUT, TEST_GRACE).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_stop_vm_response_waits_for_guest_shutdown() {
+        let meta_dir = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            ssh_port: Some(22222),
+            mac_address: None,
+            pid,
+            state: VmState::Running,
+            autostart: true,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        let socket_path = qmp_socket_path(meta_dir.path(), "vm-1");
+        let received = crate::qmp::fake::spawn(&socket_path, move |command| {
+            if command == "system_powerdown" {
+                test_process::kill(pid);
+            }
+            serde_json::json!({ "return": {} })
+        });
 
-    // ── list_vms_response ────────────────────────────────────────────────────
+        let resp = stop_vm_response(meta_dir.path(), "vm-1", TEST_TIMEOUT, TEST_GRACE).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let stop: StopVmResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stop.stopped_by, StopStep::GuestShutdown);
+        assert_eq!(
+            *received.lock().await,
+            ["qmp_capabilities", "system_powerdown"]
+        );
+
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Stopped);
+        assert_eq!(stored.state_reason, "stopped by guest shutdown");
+    }
 
     #[tokio::test]
-    async fn test_list_vms_response_empty_dir() {
-        let dir = TempDir::new().unwrap();
-        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
-        assert_eq!(resp.status(), StatusCode::OK);
+    async fn test_stop_vm_response_escalates_when_powerdown_rejected() {
+        let meta_dir = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid,
+            state: VmState::Running,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        crate::qmp::fake::spawn(&qmp_socket_path(meta_dir.path(), "vm-1"), move |command| {
+            match command {
+                "system_powerdown" => serde_json::json!({
+                    "error": { "class": "GenericError", "desc": "guest not responding" }
+                }),
+                "quit" => {
+                    test_process::kill(pid);
+                    serde_json::json!({ "return": {} })
+                }
+                _ => serde_json::json!({ "return": {} }),
+            }
+        });
 
+        let resp = stop_vm_response(meta_dir.path(), "vm-1", TEST_TIMEOUT, TEST_GRACE).await;
+        assert_eq!(resp.status(), StatusCode::OK);
         let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
-        assert!(vms.is_empty());
+        let stop: StopVmResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stop.stopped_by, StopStep::QmpQuit);
+
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Stopped);
+        assert_eq!(stored.state_reason, "stopped by QMP quit");
     }
 
     #[tokio::test]
-    async fn test_list_vms_response_returns_stored_vms() {
-        let dir = TempDir::new().unwrap();
+    async fn test_stop_vm_response_failure_restores_state_and_autostart() {
+        let meta_dir = TempDir::new().unwrap();
+        let mut child = test_process::spawn_unreaped(&qmp_socket_path(meta_dir.path(), "vm-1"));
         let vm = VmInfo {
-            id: "abc-1".to_string(),
-            name: "my-vm".to_string(),
-            ssh_port: Some(55000),
-            mac_address: None,
-            pid: 42,
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: child.id(),
+            state: VmState::Running,
+            autostart: true,
+            ..Default::default()
         };
-        store_vm_info(dir.path(), &vm).unwrap();
+        store_vm_info(meta_dir.path(), &vm).unwrap();
 
-        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
-        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = stop_vm_response(meta_dir.path(), "vm-1", TEST_TIMEOUT, TEST_GRACE).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
 
-        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
-        assert_eq!(vms.len(), 1);
-        assert_eq!(vms[0].id, "abc-1");
-        assert_eq!(vms[0].ssh_host, "localhost");
-        assert_eq!(vms[0].ssh_port, 55000);
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Running);
+        assert!(stored.autostart);
+        child.wait().unwrap();
     }
 
     #[tokio::test]
-    async fn test_list_vms_response_bridge_mode_includes_mac_address() {
-        let dir = TempDir::new().unwrap();
+    async fn test_record_outcome_updates_reason_when_already_there() {
+        let meta_dir = TempDir::new().unwrap();
         let vm = VmInfo {
-            id: "abc-1".to_string(),
-            name: "my-vm".to_string(),
-            ssh_port: None,
-            mac_address: Some("52:54:00:ab:cd:ef".to_string()),
-            pid: 42,
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            state: VmState::Stopped,
+            state_reason: "QEMU exited after shutdown request".to_string(),
+            ..Default::default()
         };
-        store_vm_info(dir.path(), &vm).unwrap();
+        store_vm_info(meta_dir.path(), &vm).unwrap();
 
-        let resp = list_vms_response(dir.path(), &NetworkMode::Bridge).await;
-        assert_eq!(resp.status(), StatusCode::OK);
+        record_outcome(
+            meta_dir.path(),
+            "vm-1",
+            VmState::Stopped,
+            "stopped by SIGTERM",
+        );
 
-        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
-        assert_eq!(vms.len(), 1);
-        // MAC is passed through for the proxy to resolve.
-        assert_eq!(vms[0].mac_address.as_deref(), Some("52:54:00:ab:cd:ef"));
-        // ssh_host is left empty; the proxy fills it in.
-        assert_eq!(vms[0].ssh_host, "");
-        assert_eq!(vms[0].ssh_port, 22);
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Stopped);
+        assert_eq!(stored.state_reason, "stopped by SIGTERM");
     }
 
+    // ── reboot_vm_response ───────────────────────────────────────────────────
+
     #[tokio::test]
-    async fn test_list_vms_response_running_true_when_process_alive() {
-        let dir = TempDir::new().unwrap();
+    async fn test_reboot_vm_response_rejects_stopped_vm() {
+        let meta_dir = TempDir::new().unwrap();
         let vm = VmInfo {
-            id: "abc-1".to_string(),
-            name: "my-vm".to_string(),
-            ssh_port: Some(55000),
-            mac_address: None,
-            pid: std::process::id(), // current test process is definitely alive
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            state: VmState::Stopped,
+            ..Default::default()
         };
-        store_vm_info(dir.path(), &vm).unwrap();
+        store_vm_info(meta_dir.path(), &vm).unwrap();
 
-        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
-        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
-        assert!(vms[0].running);
+        let resp = reboot_vm_response(meta_dir.path(), "vm-1", TEST_TIMEOUT).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
     }
 
     #[tokio::test]
-    async fn test_list_vms_response_running_false_when_process_dead() {
-        let dir = TempDir::new().unwrap();
+    async fn test_reboot_vm_response_returns_vm_to_running() {
+        let meta_dir = TempDir::new().unwrap();
         let vm = VmInfo {
-            id: "abc-1".to_string(),
-            name: "my-vm".to_string(),
-            ssh_port: Some(55000),
-            mac_address: None,
-            pid: u32::MAX, // guaranteed not to be a running process
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: std::process::id(),
+            state: VmState::Running,
+            ..Default::default()
         };
-        store_vm_info(dir.path(), &vm).unwrap();
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+        let dir = meta_dir.path().to_path_buf();
+        crate::qmp::fake::spawn_scripted(
+            &qmp_socket_path(meta_dir.path(), "vm-1"),
+            move |command| {
+                let ok = serde_json::json!({ "return": {} });
+                if command != "system_powerdown" {
+                    return vec![ok];
+                }
+                // Stand in for the watcher reacting to the SHUTDOWN event.
+                transition(&dir, "vm-1", VmState::Stopping, "guest is shutting down").unwrap();
+                vec![
+                    ok,
+                    crate::qmp::fake::event("SHUTDOWN", serde_json::json!({ "guest": true })),
+                ]
+            },
+        );
 
-        let resp = list_vms_response(dir.path(), &NetworkMode::User).await;
+        let resp = reboot_vm_response(meta_dir.path(), "vm-1", TEST_TIMEOUT).await;
+        assert_eq!(resp.status(), StatusCode::OK);
         let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        let vms: Vec<VmListEntry> = serde_json::from_slice(&body).unwrap();
-        assert!(!vms[0].running);
+        let reboot: RebootVmResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(reboot.rebooted_by, RebootStep::GuestReboot);
+
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Running);
+        assert_eq!(stored.state_reason, "rebooted by guest reboot");
     }
 
-    // ── stop_vm_response ─────────────────────────────────────────────────────
+    // ── set_paused_response ──────────────────────────────────────────────────
 
     #[tokio::test]
-    async fn test_stop_vm_response_not_found() {
+    async fn test_pause_and_resume_vm() {
         let meta_dir = TempDir::new().unwrap();
-        let resp = stop_vm_response(meta_dir.path(), "no-such-id").await;
-        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: std::process::id(),
+            state: VmState::Running,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+        let received = crate::qmp::fake::spawn(
+            &qmp_socket_path(meta_dir.path(), "vm-1"),
+            crate::qmp::fake::ok,
+        );
+
+        let resp = set_paused_response(meta_dir.path(), "vm-1", true).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Paused);
+
+        let resp = set_paused_response(meta_dir.path(), "vm-1", false).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Running);
+
+        assert_eq!(*received.lock().await, ["qmp_capabilities", "stop", "cont"]);
     }
 
     #[tokio::test]
-    async fn test_stop_vm_response_not_running_returns_conflict() {
+    async fn test_resume_running_vm_returns_conflict() {
         let meta_dir = TempDir::new().unwrap();
         let vm = VmInfo {
             id: "vm-1".to_string(),
             name: "test".to_string(),
-            ssh_port: Some(22222),
-            mac_address: None,
-            pid: u32::MAX, // not running
+            pid: std::process::id(),
+            state: VmState::Running,
+            ..Default::default()
         };
         store_vm_info(meta_dir.path(), &vm).unwrap();
 
-        let resp = stop_vm_response(meta_dir.path(), "vm-1").await;
+        let resp = set_paused_response(meta_dir.path(), "vm-1", false).await;
         assert_eq!(resp.status(), StatusCode::CONFLICT);
     }
 
     #[tokio::test]
-    async fn test_stop_vm_response_sends_powerdown_to_monitor_socket() {
-        use std::sync::Arc;
-        use tokio::io::{AsyncReadExt, AsyncWriteExt};
-        use tokio::net::UnixListener;
-        use tokio::sync::Mutex;
-
+    async fn test_pause_reverts_state_when_qemu_rejects() {
         let meta_dir = TempDir::new().unwrap();
         let vm = VmInfo {
             id: "vm-1".to_string(),
             name: "test".to_string(),
-            ssh_port: Some(22222),
-            mac_address: None,
-            pid: std::process::id(), // current process is alive
+            pid: std::process::id(),
+            state: VmState::Running,
+            ..Default::default()
         };
         store_vm_info(meta_dir.path(), &vm).unwrap();
+        crate::qmp::fake::spawn(
+            &qmp_socket_path(meta_dir.path(), "vm-1"),
+            |command| match command {
+                "stop" => serde_json::json!({
+                    "error": { "class": "GenericError", "desc": "migration in progress" }
+                }),
+                _ => serde_json::json!({ "return": {} }),
+            },
+        );
 
-        // Set up a mock monitor socket
-        let socket_path = meta_dir.path().join("vm-1.monitor");
-        let listener = UnixListener::bind(&socket_path).unwrap();
-        let received = Arc::new(Mutex::new(String::new()));
-        let received_clone = received.clone();
-
-        tokio::spawn(async move {
-            let (mut stream, _) = listener.accept().await.unwrap();
-            stream.write_all(b"(qemu) ").await.unwrap();
-            let mut buf = vec![0u8; 64];
-            if let Ok(n) = stream.read(&mut buf).await {
-                *received_clone.lock().await = String::from_utf8_lossy(&buf[..n]).to_string();
-            }
-        });
-
-        tokio::task::yield_now().await;
-
-        let resp = stop_vm_response(meta_dir.path(), "vm-1").await;
-        assert_eq!(resp.status(), StatusCode::OK);
-
-        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-        assert!(received.lock().await.contains("system_powerdown"));
+        let resp = set_paused_response(meta_dir.path(), "vm-1", true).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Running);
+        assert!(stored.state_reason.contains("migration in progress"));
     }
 
     // ── start_vm_response ────────────────────────────────────────────────────
@@ -635,6 +2404,8 @@ mod tests {
             ssh_port: Some(22222),
             mac_address: None,
             pid: std::process::id(), // current process is alive
+            state: VmState::Running,
+            ..Default::default()
         };
         store_vm_info(meta_dir.path(), &vm).unwrap();
 
@@ -655,10 +2426,197 @@ mod tests {
         let meta_dir = TempDir::new().unwrap();
         let qcow2_dir = TempDir::new().unwrap();
 
-        let resp = delete_vm_response(meta_dir.path(), qcow2_dir.path(), "no-such-id").await;
+        let resp = delete_vm_response(
+            meta_dir.path(),
+            qcow2_dir.path(),
+            meta_dir.path(),
+            "no-such-id",
+            TEST_GRACE,
+        )
+        .await;
         assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
+    #[tokio::test]
+    async fn test_delete_vm_response_removes_stopped_vm() {
+        let meta_dir = TempDir::new().unwrap();
+        let qcow2_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: u32::MAX,
+            state: VmState::Stopped,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+        std::fs::write(qcow2_dir.path().join("vm-1.qcow2"), "disk").unwrap();
+
+        let resp = delete_vm_response(
+            meta_dir.path(),
+            qcow2_dir.path(),
+            meta_dir.path(),
+            "vm-1",
+            TEST_GRACE,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(get_vm_by_id(meta_dir.path(), "vm-1").unwrap().is_none());
+        assert!(!qcow2_dir.path().join("vm-1.qcow2").exists());
+    }
+
+    #[tokio::test]
+    async fn test_delete_vm_response_waits_for_attach_lock() {
+        let meta_dir = TempDir::new().unwrap();
+        let qcow2_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            state: VmState::Stopped,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        let guard = ATTACH_LOCK.lock().await;
+        let (meta, qcow2) = (
+            meta_dir.path().to_path_buf(),
+            qcow2_dir.path().to_path_buf(),
+        );
+        let delete = tokio::spawn(async move {
+            delete_vm_response(&meta, &qcow2, &meta, "vm-1", TEST_GRACE).await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!delete.is_finished());
+        assert!(get_vm_by_id(meta_dir.path(), "vm-1").unwrap().is_some());
+
+        drop(guard);
+        assert_eq!(delete.await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_start_vm_response_waits_for_snapshot_lock() {
+        let meta_dir = TempDir::new().unwrap();
+        let qcow2_dir = TempDir::new().unwrap();
+        store_test_vm(meta_dir.path(), "vm-1", VmState::Stopped, 0);
+
+        let guard = SNAPSHOT_LOCK.lock().await;
+        let (meta, qcow2) = (
+            meta_dir.path().to_path_buf(),
+            qcow2_dir.path().to_path_buf(),
+        );
+        let start = tokio::spawn(async move {
+            start_vm_response(&meta, &qcow2, &NetworkMode::User, "vm-1").await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!start.is_finished());
+        let vm = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(vm.state, VmState::Stopped);
+
+        drop(guard);
+        start.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete_vm_response_already_deleting_returns_conflict() {
+        let meta_dir = TempDir::new().unwrap();
+        let qcow2_dir = TempDir::new().unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            state: VmState::Deleting,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        let resp = delete_vm_response(
+            meta_dir.path(),
+            qcow2_dir.path(),
+            meta_dir.path(),
+            "vm-1",
+            TEST_GRACE,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_delete_vm_response_releases_attached_volumes() {
+        let meta_dir = TempDir::new().unwrap();
+        let qcow2_dir = TempDir::new().unwrap();
+        let volume_dir = TempDir::new().unwrap();
+        crate::volume_db::store_volume_info(
+            volume_dir.path(),
+            &crate::volume_db::VolumeInfo {
+                id: "vol-1".to_string(),
+                name: "data".to_string(),
+                size_gb: 1,
+                mount_path: String::new(),
+                loop_device: None,
+                mounted: false,
+                attached_to: Some("vm-1".to_string()),
+                snapshots: Vec::new(),
+            },
+        )
+        .unwrap();
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            state: VmState::Stopped,
+            volumes: vec![crate::vm_db::AttachedVolume {
+                id: "vol-1".to_string(),
+                path: volume_dir.path().join("vol-1.img"),
+            }],
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+
+        let resp = delete_vm_response(
+            meta_dir.path(),
+            qcow2_dir.path(),
+            volume_dir.path(),
+            "vm-1",
+            TEST_GRACE,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let volume = crate::volume_db::get_volume_by_id(volume_dir.path(), "vol-1")
+            .unwrap()
+            .unwrap();
+        assert!(volume.attached_to.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_vm_response_keeps_vm_when_stop_fails() {
+        let meta_dir = TempDir::new().unwrap();
+        let qcow2_dir = TempDir::new().unwrap();
+        let volume_dir = TempDir::new().unwrap();
+        let mut child = test_process::spawn_unreaped(&qmp_socket_path(meta_dir.path(), "vm-1"));
+        let vm = VmInfo {
+            id: "vm-1".to_string(),
+            name: "test".to_string(),
+            pid: child.id(),
+            state: VmState::Running,
+            ..Default::default()
+        };
+        store_vm_info(meta_dir.path(), &vm).unwrap();
+        let disk = qcow2_dir.path().join("test.qcow2");
+        std::fs::write(&disk, "disk").unwrap();
+
+        let resp = delete_vm_response(
+            meta_dir.path(),
+            qcow2_dir.path(),
+            volume_dir.path(),
+            "vm-1",
+            TEST_GRACE,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let stored = get_vm_by_id(meta_dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Running);
+        assert!(disk.exists());
+        child.wait().unwrap();
+    }
+
     #[tokio::test]
     async fn test_delete_vm_response_corrupted_metadata_returns_500() {
         let meta_dir = TempDir::new().unwrap();
@@ -666,7 +2624,14 @@ mod tests {
 
         std::fs::write(meta_dir.path().join("bad-id.json"), "not json").unwrap();
 
-        let resp = delete_vm_response(meta_dir.path(), qcow2_dir.path(), "bad-id").await;
+        let resp = delete_vm_response(
+            meta_dir.path(),
+            qcow2_dir.path(),
+            meta_dir.path(),
+            "bad-id",
+            TEST_GRACE,
+        )
+        .await;
         assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
 }
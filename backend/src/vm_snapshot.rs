@@ -0,0 +1,753 @@
+use crate::config::Config;
+use crate::image_service::now_secs;
+use crate::qemu::{
+    create_overlay, internal_snapshot, qmp_socket_path, rebase, SnapshotOp, DISK_DEVICE,
+};
+use crate::qmp::QmpClient;
+use crate::vm_db::{get_vm_by_id, SnapshotKind, VmInfo, VmSnapshot};
+use crate::vm_state::{refresh_state, update_vm, TransitionError, VmState};
+use axum::{
+    extract::Path as AxumPath,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// Serializes operations that read a VM's disk chain and then change its
+/// files or record a new chain: snapshots, and creating images from VMs.
+/// Starting and deleting VMs take it too, so QEMU never opens or loses a
+/// chain mid-change. Taken after `ATTACH_LOCK` wherever both are held.
+pub(crate) static SNAPSHOT_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotRequest {
+    pub vm_id: String,
+    pub name: String,
+}
+
+pub async fn create_snapshot_handler(Json(payload): Json<SnapshotRequest>) -> Response {
+    info!("Creating snapshot {} of VM {}", payload.name, payload.vm_id);
+    let config = Config::load().expect("Failed to load configuration");
+    create_snapshot_response(
+        &config.storage.metadata_dir,
+        &config.storage.qcow2_dir,
+        &payload.vm_id,
+        &payload.name,
+    )
+    .await
+}
+
+/// Snapshot a VM's disk: internally with `qemu-img` while it is stopped, or
+/// externally over QMP while QEMU runs, which moves the VM's writes to a new
+/// overlay.
+async fn create_snapshot_response(
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    vm_id: &str,
+    name: &str,
+) -> Response {
+    if let Err(error) = check_snapshot_name(name) {
+        return error.into_response();
+    }
+    let _guard = SNAPSHOT_LOCK.lock().await;
+    let vm_info = match load_vm(metadata_dir, vm_id) {
+        Ok(vm_info) => vm_info,
+        Err(error) => return error.into_response(),
+    };
+    if vm_info.snapshots.iter().any(|s| s.name == name) {
+        return (
+            StatusCode::CONFLICT,
+            format!("VM already has a snapshot named {name}"),
+        )
+            .into_response();
+    }
+
+    let disk = vm_info.disk_path(qcow2_dir);
+    let snapshot = VmSnapshot {
+        name: name.to_string(),
+        created_secs: now_secs(),
+        kind: SnapshotKind::Internal,
+        file: disk.clone(),
+    };
+    let result = match vm_info.state {
+        VmState::Stopped | VmState::Crashed => {
+            match internal_snapshot(SnapshotOp::Create, name, &disk).await {
+                Ok(()) => update_vm(metadata_dir, vm_id, |vm| vm.snapshots.push(snapshot)),
+                Err(e) => return snapshot_error("create", vm_id, e),
+            }
+        }
+        VmState::Running | VmState::Paused => {
+            let overlay = new_overlay_path(qcow2_dir, &vm_info);
+            let qmp = match QmpClient::shared(&qmp_socket_path(metadata_dir, vm_id)).await {
+                Ok(qmp) => qmp,
+                Err(e) => return snapshot_error("create", vm_id, e),
+            };
+            if let Err(e) = qmp.blockdev_snapshot_sync(DISK_DEVICE, &overlay).await {
+                return snapshot_error("create", vm_id, e);
+            }
+            record_external_snapshot(metadata_dir, vm_id, snapshot, overlay)
+        }
+        state => {
+            return (
+                StatusCode::CONFLICT,
+                format!("Cannot snapshot the VM while it is {state}"),
+            )
+                .into_response()
+        }
+    };
+
+    match result {
+        Ok(vm_info) => Json(vm_info.snapshots.last().cloned()).into_response(),
+        Err(e) => {
+            error!("Failed to record snapshot {name} of VM {vm_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to record snapshot: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn list_snapshots_handler(AxumPath(vm_id): AxumPath<String>) -> Response {
+    let config = Config::load().expect("Failed to load configuration");
+    list_snapshots_response(&config.storage.metadata_dir, &vm_id)
+}
+
+fn list_snapshots_response(metadata_dir: &Path, vm_id: &str) -> Response {
+    match load_vm(metadata_dir, vm_id) {
+        Ok(vm_info) => Json(vm_info.snapshots).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn revert_snapshot_handler(Json(payload): Json<SnapshotRequest>) -> Response {
+    info!(
+        "Reverting VM {} to snapshot {}",
+        payload.vm_id, payload.name
+    );
+    let config = Config::load().expect("Failed to load configuration");
+    revert_snapshot_response(
+        &config.storage.metadata_dir,
+        &config.storage.qcow2_dir,
+        &payload.vm_id,
+        &payload.name,
+    )
+    .await
+}
+
+/// Return a stopped VM's disk to a snapshot. Everything written since is
+/// discarded, along with the snapshots taken after it that lived in those
+/// writes.
+async fn revert_snapshot_response(
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    vm_id: &str,
+    name: &str,
+) -> Response {
+    let _guard = SNAPSHOT_LOCK.lock().await;
+    let (vm_info, snapshot) = match load_stopped_snapshot(metadata_dir, vm_id, name, "revert") {
+        Ok(found) => found,
+        Err(error) => return error.into_response(),
+    };
+    let plan = match plan_revert(&vm_info, qcow2_dir, &snapshot) {
+        Some(plan) => plan,
+        None => return missing_file_error(&snapshot),
+    };
+
+    // An internal snapshot is applied to its file, which becomes the disk
+    // again. The file an external snapshot froze stays frozen under a new,
+    // empty overlay.
+    let result = match snapshot.kind {
+        SnapshotKind::Internal => internal_snapshot(SnapshotOp::Apply, name, &snapshot.file).await,
+        SnapshotKind::External => create_overlay(&snapshot.file, &plan.disk, None).await,
+    };
+    if let Err(e) = result {
+        return snapshot_error("revert", vm_id, e);
+    }
+
+    let default_disk = vm_info.default_disk(qcow2_dir);
+    let recorded = update_vm(metadata_dir, vm_id, |vm| {
+        vm.disk = (plan.disk != default_disk).then(|| plan.disk.clone());
+        vm.backing_chain = plan.backing_chain.clone();
+        vm.snapshots.retain(|s| plan.keeps(s));
+    });
+    if let Err(e) = recorded {
+        error!("Failed to record revert of VM {vm_id}: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to record revert: {e}"),
+        )
+            .into_response();
+    }
+    for file in &plan.removed {
+        if let Err(e) = tokio::fs::remove_file(file).await {
+            warn!("Could not delete discarded disk layer {file:?}: {e}");
+        }
+    }
+
+    (
+        StatusCode::OK,
+        format!("VM {vm_id} reverted to snapshot {name}"),
+    )
+        .into_response()
+}
+
+/// The disk chain after reverting to a snapshot.
+#[derive(Debug, PartialEq)]
+struct RevertPlan {
+    /// Disk the VM runs from afterwards.
+    disk: PathBuf,
+    backing_chain: Vec<PathBuf>,
+    /// Overlays written after the snapshot, which are discarded.
+    removed: Vec<PathBuf>,
+    /// File that becomes writable again, so an external snapshot that froze
+    /// it no longer holds.
+    thawed: Option<PathBuf>,
+}
+
+impl RevertPlan {
+    /// Whether snapshot `s` survives the revert.
+    fn keeps(&self, s: &VmSnapshot) -> bool {
+        let thawed = s.kind == SnapshotKind::External && self.thawed.as_ref() == Some(&s.file);
+        !thawed && !self.removed.contains(&s.file)
+    }
+}
+
+/// Work out the chain after reverting to `snapshot`, or `None` if its file is
+/// no longer part of the VM's chain.
+fn plan_revert(vm_info: &VmInfo, qcow2_dir: &Path, snapshot: &VmSnapshot) -> Option<RevertPlan> {
+    let chain: Vec<PathBuf> = std::iter::once(vm_info.disk_path(qcow2_dir))
+        .chain(vm_info.backing_chain.iter().cloned())
+        .collect();
+    let index = chain.iter().position(|f| *f == snapshot.file)?;
+    let removed = chain[..index].to_vec();
+    Some(match snapshot.kind {
+        SnapshotKind::Internal => RevertPlan {
+            disk: snapshot.file.clone(),
+            backing_chain: chain[index + 1..].to_vec(),
+            removed,
+            thawed: Some(snapshot.file.clone()),
+        },
+        SnapshotKind::External => RevertPlan {
+            disk: new_overlay_path(qcow2_dir, vm_info),
+            backing_chain: chain[index..].to_vec(),
+            removed,
+            thawed: None,
+        },
+    })
+}
+
+pub async fn delete_snapshot_handler(Json(payload): Json<SnapshotRequest>) -> Response {
+    info!("Deleting snapshot {} of VM {}", payload.name, payload.vm_id);
+    let config = Config::load().expect("Failed to load configuration");
+    delete_snapshot_response(
+        &config.storage.metadata_dir,
+        &config.storage.qcow2_dir,
+        &payload.vm_id,
+        &payload.name,
+    )
+    .await
+}
+
+/// Delete a stopped VM's snapshot. The VM's current disk is unaffected: an
+/// external snapshot's frozen file is merged into the overlay above it.
+async fn delete_snapshot_response(
+    metadata_dir: &Path,
+    qcow2_dir: &Path,
+    vm_id: &str,
+    name: &str,
+) -> Response {
+    let _guard = SNAPSHOT_LOCK.lock().await;
+    let (vm_info, snapshot) = match load_stopped_snapshot(metadata_dir, vm_id, name, "delete") {
+        Ok(found) => found,
+        Err(error) => return error.into_response(),
+    };
+
+    let result = match snapshot.kind {
+        SnapshotKind::Internal => internal_snapshot(SnapshotOp::Delete, name, &snapshot.file).await,
+        SnapshotKind::External => {
+            let inside: Vec<&str> = vm_info
+                .snapshots
+                .iter()
+                .filter(|s| s.kind == SnapshotKind::Internal && s.file == snapshot.file)
+                .map(|s| s.name.as_str())
+                .collect();
+            if !inside.is_empty() {
+                return (
+                    StatusCode::CONFLICT,
+                    format!(
+                        "Snapshot {name} holds internal snapshots; delete them first: {}",
+                        inside.join(", ")
+                    ),
+                )
+                    .into_response();
+            }
+            let chain: Vec<PathBuf> = std::iter::once(vm_info.disk_path(qcow2_dir))
+                .chain(vm_info.backing_chain.iter().cloned())
+                .collect();
+            let index = match chain.iter().position(|f| *f == snapshot.file) {
+                Some(index) if index > 0 => index,
+                _ => return missing_file_error(&snapshot),
+            };
+            // The overlay above takes over the frozen file's data and its
+            // backing file; then the frozen file is no longer needed.
+            let merged = rebase(
+                &chain[index - 1],
+                chain.get(index + 1).map(PathBuf::as_path),
+            )
+            .await;
+            if merged.is_ok() {
+                if let Err(e) = tokio::fs::remove_file(&snapshot.file).await {
+                    warn!(
+                        "Could not delete merged disk layer {:?}: {e}",
+                        snapshot.file
+                    );
+                }
+            }
+            merged
+        }
+    };
+    if let Err(e) = result {
+        return snapshot_error("delete", vm_id, e);
+    }
+
+    let recorded = update_vm(metadata_dir, vm_id, |vm| {
+        vm.snapshots.retain(|s| s.name != name);
+        if snapshot.kind == SnapshotKind::External {
+            vm.backing_chain.retain(|f| *f != snapshot.file);
+        }
+    });
+    match recorded {
+        Ok(_) => (StatusCode::OK, format!("Snapshot {name} deleted")).into_response(),
+        Err(e) => {
+            error!("Failed to record deletion of snapshot {name} of VM {vm_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to record deletion: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Snapshot names become `qemu-img` arguments, so keep them to a safe set.
+pub(crate) fn check_snapshot_name(name: &str) -> Result<(), (StatusCode, String)> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            "Snapshot names must be 1-64 letters, digits, '-', '_' or '.'".to_string(),
+        ))
+    }
+}
+
+/// Record that `blockdev-snapshot-sync` froze `snapshot.file`, the VM's disk
+/// until then, and moved its writes to `overlay`.
+pub(crate) fn record_external_snapshot(
+    metadata_dir: &Path,
+    vm_id: &str,
+    snapshot: VmSnapshot,
+    overlay: PathBuf,
+) -> Result<VmInfo, TransitionError> {
+    update_vm(metadata_dir, vm_id, |vm| {
+        vm.backing_chain.insert(0, snapshot.file.clone());
+        vm.disk = Some(overlay);
+        vm.snapshots.push(VmSnapshot {
+            kind: SnapshotKind::External,
+            ..snapshot
+        });
+    })
+}
+
+/// A fresh file name for an overlay in the VM's disk chain.
+pub(crate) fn new_overlay_path(qcow2_dir: &Path, vm_info: &VmInfo) -> PathBuf {
+    let suffix = Uuid::new_v4().simple().to_string();
+    qcow2_dir.join(format!("{}.{}.qcow2", vm_info.id, &suffix[..8]))
+}
+
+pub(crate) fn load_vm(metadata_dir: &Path, vm_id: &str) -> Result<VmInfo, (StatusCode, String)> {
+    match get_vm_by_id(metadata_dir, vm_id) {
+        Ok(Some(vm_info)) => Ok(refresh_state(metadata_dir, vm_info)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "VM not found".to_string())),
+        Err(e) => {
+            error!("Error retrieving VM info: {e}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving VM info: {e}"),
+            ))
+        }
+    }
+}
+
+/// Load a VM and one of its snapshots for an operation that rewrites disk
+/// files, which QEMU must not have open.
+fn load_stopped_snapshot(
+    metadata_dir: &Path,
+    vm_id: &str,
+    name: &str,
+    action: &str,
+) -> Result<(VmInfo, VmSnapshot), (StatusCode, String)> {
+    let vm_info = load_vm(metadata_dir, vm_id)?;
+    let snapshot = vm_info
+        .snapshots
+        .iter()
+        .find(|s| s.name == name)
+        .cloned()
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Snapshot {name} not found")))?;
+    if !matches!(vm_info.state, VmState::Stopped | VmState::Crashed) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!(
+                "Cannot {action} a snapshot while the VM is {}; stop it first",
+                vm_info.state
+            ),
+        ));
+    }
+    Ok((vm_info, snapshot))
+}
+
+fn missing_file_error(snapshot: &VmSnapshot) -> Response {
+    error!(
+        "Snapshot {} refers to {:?}, which is not in its VM's disk chain",
+        snapshot.name, snapshot.file
+    );
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!(
+            "Snapshot {} refers to a file no longer in the VM's disk chain",
+            snapshot.name
+        ),
+    )
+        .into_response()
+}
+
+fn snapshot_error(action: &str, vm_id: &str, e: impl std::fmt::Display) -> Response {
+    error!("Failed to {action} snapshot of VM {vm_id}: {e}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to {action} snapshot: {e}"),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qmp::fake;
+    use crate::vm_db::{store_vm_info, test_vm};
+    use crate::vm_stop::test_process;
+    use axum::body::to_bytes;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex as StdMutex};
+    use tempfile::TempDir;
+
+    fn store_vm(dir: &Path, state: VmState, pid: u32, snapshots: Vec<VmSnapshot>) -> VmInfo {
+        let vm = VmInfo {
+            snapshots,
+            ..test_vm("vm-1", state, pid)
+        };
+        store_vm_info(dir, &vm).unwrap();
+        vm
+    }
+
+    fn snapshot(name: &str, kind: SnapshotKind, file: &Path) -> VmSnapshot {
+        VmSnapshot {
+            name: name.to_string(),
+            created_secs: 1700000000,
+            kind,
+            file: file.to_path_buf(),
+        }
+    }
+
+    fn stored_vm(dir: &Path) -> VmInfo {
+        get_vm_by_id(dir, "vm-1").unwrap().unwrap()
+    }
+
+    // ── names ────────────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_check_snapshot_name() {
+        assert!(check_snapshot_name("before-upgrade_2.1").is_ok());
+        for name in ["", "with space", "../escape", "semi;colon", &"x".repeat(65)] {
+            assert_eq!(
+                check_snapshot_name(name).unwrap_err().0,
+                StatusCode::BAD_REQUEST,
+                "{name:?}"
+            );
+        }
+    }
+
+    // ── create ───────────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_create_rejects_unknown_vm() {
+        let meta = TempDir::new().unwrap();
+
+        let resp = create_snapshot_response(meta.path(), meta.path(), "vm-1", "s1").await;
+
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_create_rejects_duplicate_name() {
+        let meta = TempDir::new().unwrap();
+        let disk = meta.path().join("vm-1.qcow2");
+        store_vm(
+            meta.path(),
+            VmState::Stopped,
+            0,
+            vec![snapshot("s1", SnapshotKind::Internal, &disk)],
+        );
+
+        let resp = create_snapshot_response(meta.path(), meta.path(), "vm-1", "s1").await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_create_rejects_transitional_state() {
+        let meta = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        store_vm(meta.path(), VmState::Stopping, pid, Vec::new());
+
+        let resp = create_snapshot_response(meta.path(), meta.path(), "vm-1", "s1").await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_create_on_running_vm_moves_writes_to_overlay() {
+        let meta = TempDir::new().unwrap();
+        let qcow2 = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        store_vm(meta.path(), VmState::Running, pid, Vec::new());
+        let arguments = Arc::new(StdMutex::new(Vec::new()));
+        let seen = arguments.clone();
+        let received = fake::spawn_with_args(
+            &qmp_socket_path(meta.path(), "vm-1"),
+            move |_, args: &Value| {
+                seen.lock().unwrap().push(args.clone());
+                json!({ "return": {} })
+            },
+        );
+
+        let resp = create_snapshot_response(meta.path(), qcow2.path(), "vm-1", "s1").await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            *received.lock().await,
+            ["qmp_capabilities", "blockdev-snapshot-sync"]
+        );
+        let old_disk = qcow2.path().join("vm-1.qcow2");
+        let vm = stored_vm(meta.path());
+        let overlay = vm.disk.clone().unwrap();
+        assert_eq!(overlay.parent(), Some(qcow2.path()));
+        assert_ne!(overlay, old_disk);
+        assert_eq!(vm.backing_chain, std::slice::from_ref(&old_disk));
+        assert_eq!(
+            vm.snapshots
+                .iter()
+                .map(|s| (&s.kind, &s.file))
+                .collect::<Vec<_>>(),
+            [(&SnapshotKind::External, &old_disk)]
+        );
+        let arguments = arguments.lock().unwrap();
+        assert_eq!(arguments[1]["device"], DISK_DEVICE);
+        assert_eq!(
+            arguments[1]["snapshot-file"],
+            overlay.to_string_lossy().as_ref()
+        );
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_failed_live_snapshot_leaves_metadata_alone() {
+        let meta = TempDir::new().unwrap();
+        let pid = test_process::spawn(false);
+        store_vm(meta.path(), VmState::Running, pid, Vec::new());
+        fake::spawn(
+            &qmp_socket_path(meta.path(), "vm-1"),
+            |command| match command {
+                "blockdev-snapshot-sync" => json!({
+                    "error": { "class": "GenericError", "desc": "No space left" }
+                }),
+                _ => json!({ "return": {} }),
+            },
+        );
+
+        let resp = create_snapshot_response(meta.path(), meta.path(), "vm-1", "s1").await;
+
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let vm = stored_vm(meta.path());
+        assert!(vm.disk.is_none());
+        assert!(vm.snapshots.is_empty());
+        test_process::kill(pid);
+    }
+
+    // ── list ─────────────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_list_snapshots() {
+        let meta = TempDir::new().unwrap();
+        let disk = meta.path().join("vm-1.qcow2");
+        store_vm(
+            meta.path(),
+            VmState::Stopped,
+            0,
+            vec![snapshot("s1", SnapshotKind::Internal, &disk)],
+        );
+
+        let resp = list_snapshots_response(meta.path(), "vm-1");
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let listed: Vec<VmSnapshot> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "s1");
+        assert_eq!(
+            list_snapshots_response(meta.path(), "vm-2").status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    // ── revert / delete ──────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_revert_and_delete_require_stopped_vm() {
+        let meta = TempDir::new().unwrap();
+        let disk = meta.path().join("vm-1.qcow2");
+        let pid = test_process::spawn(false);
+        store_vm(
+            meta.path(),
+            VmState::Running,
+            pid,
+            vec![snapshot("s1", SnapshotKind::Internal, &disk)],
+        );
+
+        let reverted = revert_snapshot_response(meta.path(), meta.path(), "vm-1", "s1").await;
+        let deleted = delete_snapshot_response(meta.path(), meta.path(), "vm-1", "s1").await;
+
+        assert_eq!(reverted.status(), StatusCode::CONFLICT);
+        assert_eq!(deleted.status(), StatusCode::CONFLICT);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_revert_unknown_snapshot() {
+        let meta = TempDir::new().unwrap();
+        store_vm(meta.path(), VmState::Stopped, 0, Vec::new());
+
+        let resp = revert_snapshot_response(meta.path(), meta.path(), "vm-1", "s1").await;
+
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_external_snapshot_holding_internal_ones_conflicts() {
+        let meta = TempDir::new().unwrap();
+        let base = meta.path().join("vm-1.qcow2");
+        store_vm(
+            meta.path(),
+            VmState::Stopped,
+            0,
+            vec![
+                snapshot("offline", SnapshotKind::Internal, &base),
+                snapshot("live", SnapshotKind::External, &base),
+            ],
+        );
+
+        let resp = delete_snapshot_response(meta.path(), meta.path(), "vm-1", "live").await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(stored_vm(meta.path()).snapshots.len(), 2);
+    }
+
+    // ── revert plans ─────────────────────────────────────────────────────────
+
+    /// A VM whose disk `c` sits on `b`, which sits on `a`; `a` is the first
+    /// file the VM wrote to, and the image below it is shared.
+    fn layered_vm(dir: &Path) -> VmInfo {
+        VmInfo {
+            disk: Some(dir.join("c.qcow2")),
+            backing_chain: vec![
+                dir.join("b.qcow2"),
+                dir.join("vm-1.qcow2"),
+                dir.join("image.qcow2"),
+            ],
+            ..test_vm("vm-1", VmState::Stopped, 0)
+        }
+    }
+
+    #[test]
+    fn test_plan_revert_to_internal_snapshot() {
+        let dir = Path::new("/qcow2");
+        let vm = layered_vm(dir);
+        let target = snapshot("s", SnapshotKind::Internal, &dir.join("b.qcow2"));
+
+        let plan = plan_revert(&vm, dir, &target).unwrap();
+
+        assert_eq!(plan.disk, dir.join("b.qcow2"));
+        assert_eq!(
+            plan.backing_chain,
+            [dir.join("vm-1.qcow2"), dir.join("image.qcow2")]
+        );
+        assert_eq!(plan.removed, [dir.join("c.qcow2")]);
+        // The live snapshot that froze `b` no longer holds; the one that
+        // froze `web` still does.
+        assert!(!plan.keeps(&snapshot("x", SnapshotKind::External, &dir.join("b.qcow2"))));
+        assert!(plan.keeps(&snapshot(
+            "y",
+            SnapshotKind::External,
+            &dir.join("vm-1.qcow2")
+        )));
+        assert!(!plan.keeps(&snapshot("z", SnapshotKind::Internal, &dir.join("c.qcow2"))));
+        assert!(plan.keeps(&target));
+    }
+
+    #[test]
+    fn test_plan_revert_to_external_snapshot() {
+        let dir = Path::new("/qcow2");
+        let vm = layered_vm(dir);
+        let target = snapshot("s", SnapshotKind::External, &dir.join("vm-1.qcow2"));
+
+        let plan = plan_revert(&vm, dir, &target).unwrap();
+
+        assert_eq!(plan.disk.parent(), Some(dir));
+        // Named by VM ID: another VM called "web" must not share the file.
+        assert!(plan
+            .disk
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("vm-1."));
+        assert!(!vm.backing_chain.contains(&plan.disk));
+        assert_eq!(
+            plan.backing_chain,
+            [dir.join("vm-1.qcow2"), dir.join("image.qcow2")]
+        );
+        assert_eq!(plan.removed, [dir.join("c.qcow2"), dir.join("b.qcow2")]);
+        assert!(plan.keeps(&target));
+        assert!(!plan.keeps(&snapshot("x", SnapshotKind::External, &dir.join("b.qcow2"))));
+    }
+
+    #[test]
+    fn test_plan_revert_of_file_outside_chain() {
+        let dir = Path::new("/qcow2");
+        let target = snapshot("s", SnapshotKind::Internal, &dir.join("gone.qcow2"));
+
+        assert!(plan_revert(&layered_vm(dir), dir, &target).is_none());
+    }
+}
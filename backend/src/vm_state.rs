@@ -0,0 +1,367 @@
+use crate::qemu::is_process_running;
+use crate::vm_db::{get_vm_by_id, store_vm_info, VmInfo};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::info;
+
+/// Lifecycle state of a VM, persisted in its metadata.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VmState {
+    /// Accepted; disk and seed are being prepared.
+    Pending,
+    /// QEMU spawned; waiting for it to answer on QMP.
+    Starting,
+    Running,
+    /// vCPUs halted by a pause request; QEMU and guest memory are kept.
+    Paused,
+    /// Shutdown requested by the API or the guest; QEMU has not exited yet.
+    Stopping,
+    /// Also assumed for metadata written before states were tracked. Those
+    /// VMs are adopted or relaunched by `reconcile_vms` on backend startup,
+    /// so treating them as stopped until then is accurate.
+    #[default]
+    Stopped,
+    /// QEMU exited without a shutdown being requested.
+    Crashed,
+    Deleting,
+}
+
+impl VmState {
+    /// Whether a QEMU process is expected to exist in this state.
+    pub fn is_active(self) -> bool {
+        matches!(
+            self,
+            VmState::Starting | VmState::Running | VmState::Paused | VmState::Stopping
+        )
+    }
+
+    pub fn can_transition_to(self, next: VmState) -> bool {
+        use VmState::*;
+        match (self, next) {
+            (Deleting, _) => false,
+            (_, Deleting) => true,
+            (Pending | Stopped | Crashed, Starting) => true,
+            (Starting, Running | Stopped | Crashed) => true,
+            (Running, Paused | Stopping | Stopped | Crashed) => true,
+            (Paused, Running | Stopping | Stopped | Crashed) => true,
+            // Back to running covers a shutdown request QEMU rejected.
+            (Stopping, Running | Stopped | Crashed) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for VmState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = serde_json::to_value(self).expect("state serialization cannot fail");
+        write!(f, "{}", name.as_str().unwrap_or_default())
+    }
+}
+
+#[derive(Debug)]
+pub enum TransitionError {
+    NotFound,
+    Invalid { from: VmState, to: VmState },
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransitionError::NotFound => write!(f, "VM not found"),
+            TransitionError::Invalid { from, to } => {
+                write!(f, "Cannot move VM from {from} to {to}")
+            }
+            TransitionError::Io(e) => write!(f, "Error updating VM state: {e}"),
+        }
+    }
+}
+
+/// Serializes read-modify-write cycles on VM metadata so concurrent
+/// transitions (API requests and process watchers) cannot overwrite each other.
+static TRANSITION_LOCK: Mutex<()> = Mutex::new(());
+
+/// Move a VM to `to`, recording `reason`, if its current state allows it.
+pub fn transition(
+    metadata_dir: &Path,
+    id: &str,
+    to: VmState,
+    reason: &str,
+) -> Result<VmInfo, TransitionError> {
+    transition_with(metadata_dir, id, to, reason, |_| {})
+}
+
+/// Like `transition`, additionally applying `update` to the stored metadata
+/// in the same write (e.g. to record the PID of a newly spawned QEMU).
+pub fn transition_with(
+    metadata_dir: &Path,
+    id: &str,
+    to: VmState,
+    reason: &str,
+    update: impl FnOnce(&mut VmInfo),
+) -> Result<VmInfo, TransitionError> {
+    transition_decided(metadata_dir, id, |_| Some((to, reason.to_string())), update)
+}
+
+/// Move a VM to the state and reason `decide` picks from its stored metadata,
+/// or leave it untouched if `decide` returns `None`. The read, the decision
+/// and the write all happen under `TRANSITION_LOCK`, so the decision cannot
+/// act on a state another transition has since replaced.
+fn transition_decided(
+    metadata_dir: &Path,
+    id: &str,
+    decide: impl FnOnce(&VmInfo) -> Option<(VmState, String)>,
+    update: impl FnOnce(&mut VmInfo),
+) -> Result<VmInfo, TransitionError> {
+    let _guard = TRANSITION_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vm_info = match get_vm_by_id(metadata_dir, id) {
+        Ok(Some(vm_info)) => vm_info,
+        Ok(None) => return Err(TransitionError::NotFound),
+        Err(e) => return Err(TransitionError::Io(e)),
+    };
+    let Some((to, reason)) = decide(&vm_info) else {
+        return Ok(vm_info);
+    };
+    if !vm_info.state.can_transition_to(to) {
+        return Err(TransitionError::Invalid {
+            from: vm_info.state,
+            to,
+        });
+    }
+
+    info!("VM {id}: {} -> {to} ({reason})", vm_info.state);
+    vm_info.state = to;
+    vm_info.state_reason = reason;
+    update(&mut vm_info);
+    store_vm_info(metadata_dir, &vm_info).map_err(TransitionError::Io)?;
+    Ok(vm_info)
+}
+
+/// Apply `update` to a VM's stored metadata without changing its state.
+pub fn update_vm(
+    metadata_dir: &Path,
+    id: &str,
+    update: impl FnOnce(&mut VmInfo),
+) -> Result<VmInfo, TransitionError> {
+    let _guard = TRANSITION_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut vm_info = match get_vm_by_id(metadata_dir, id) {
+        Ok(Some(vm_info)) => vm_info,
+        Ok(None) => return Err(TransitionError::NotFound),
+        Err(e) => return Err(TransitionError::Io(e)),
+    };
+    update(&mut vm_info);
+    store_vm_info(metadata_dir, &vm_info).map_err(TransitionError::Io)?;
+    Ok(vm_info)
+}
+
+/// Record the exit of a VM's QEMU process. `shutdown` carries the reason from
+/// the QMP `SHUTDOWN` event if one was seen before the exit.
+pub fn record_exit(
+    metadata_dir: &Path,
+    id: &str,
+    shutdown: Option<&str>,
+    exit_status: &str,
+) -> Result<VmInfo, TransitionError> {
+    let decide = |vm_info: &VmInfo| match (vm_info.state, shutdown) {
+        (VmState::Stopping, _) | (_, Some(_)) => Some((
+            VmState::Stopped,
+            format!(
+                "QEMU exited after {}",
+                shutdown.unwrap_or("shutdown request")
+            ),
+        )),
+        _ => Some((
+            VmState::Crashed,
+            format!("QEMU exited unexpectedly with {exit_status}"),
+        )),
+    };
+    transition_decided(metadata_dir, id, decide, |_| {})
+}
+
+/// Reconcile a stored state with reality: an active VM whose QEMU process is
+/// gone exited while nothing was watching it (e.g. the backend restarted).
+/// A `Starting` VM is left to the start in progress: its stored PID is still
+/// the previous run's until the new QEMU has been spawned.
+pub fn refresh_state(metadata_dir: &Path, vm_info: VmInfo) -> VmInfo {
+    if !exited_unwatched(&vm_info) {
+        return vm_info;
+    }
+    let decide = |stored: &VmInfo| {
+        if !exited_unwatched(stored) {
+            return None;
+        }
+        Some(if stored.state == VmState::Stopping {
+            (
+                VmState::Stopped,
+                "QEMU exited after shutdown request".to_string(),
+            )
+        } else {
+            (
+                VmState::Crashed,
+                "QEMU process is no longer running".to_string(),
+            )
+        })
+    };
+    transition_decided(metadata_dir, &vm_info.id, decide, |_| {}).unwrap_or(vm_info)
+}
+
+fn exited_unwatched(vm_info: &VmInfo) -> bool {
+    vm_info.state.is_active()
+        && vm_info.state != VmState::Starting
+        && !is_process_running(vm_info.pid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm_db::store_test_vm;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_state_serializes_lowercase() {
+        assert_eq!(
+            serde_json::to_string(&VmState::Crashed).unwrap(),
+            "\"crashed\""
+        );
+        assert_eq!(VmState::Stopping.to_string(), "stopping");
+    }
+
+    #[test]
+    fn test_valid_and_invalid_transitions() {
+        assert!(VmState::Pending.can_transition_to(VmState::Starting));
+        assert!(VmState::Running.can_transition_to(VmState::Stopping));
+        assert!(VmState::Crashed.can_transition_to(VmState::Starting));
+        assert!(VmState::Running.can_transition_to(VmState::Deleting));
+        assert!(VmState::Running.can_transition_to(VmState::Paused));
+        assert!(VmState::Paused.can_transition_to(VmState::Running));
+        assert!(VmState::Paused.can_transition_to(VmState::Stopping));
+
+        assert!(!VmState::Running.can_transition_to(VmState::Starting));
+        assert!(!VmState::Stopped.can_transition_to(VmState::Stopping));
+        assert!(!VmState::Stopped.can_transition_to(VmState::Paused));
+        assert!(!VmState::Paused.can_transition_to(VmState::Starting));
+        assert!(!VmState::Deleting.can_transition_to(VmState::Deleting));
+        assert!(!VmState::Deleting.can_transition_to(VmState::Starting));
+    }
+
+    #[test]
+    fn test_transition_persists_state_and_reason() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Stopped, 0);
+
+        transition_with(
+            dir.path(),
+            "vm-1",
+            VmState::Starting,
+            "start requested",
+            |vm| vm.pid = 42,
+        )
+        .unwrap();
+
+        let vm = get_vm_by_id(dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(vm.state, VmState::Starting);
+        assert_eq!(vm.state_reason, "start requested");
+        assert_eq!(vm.pid, 42);
+    }
+
+    #[test]
+    fn test_transition_rejects_invalid_move() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Stopped, 0);
+
+        let err = transition(dir.path(), "vm-1", VmState::Stopping, "stop").unwrap_err();
+        assert!(matches!(
+            err,
+            TransitionError::Invalid {
+                from: VmState::Stopped,
+                to: VmState::Stopping
+            }
+        ));
+        let vm = get_vm_by_id(dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(vm.state, VmState::Stopped);
+    }
+
+    #[test]
+    fn test_transition_unknown_vm() {
+        let dir = TempDir::new().unwrap();
+        let err = transition(dir.path(), "nope", VmState::Starting, "").unwrap_err();
+        assert!(matches!(err, TransitionError::NotFound));
+    }
+
+    #[test]
+    fn test_record_exit_while_running_is_a_crash() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, 0);
+
+        let vm = record_exit(dir.path(), "vm-1", None, "signal: 9").unwrap();
+        assert_eq!(vm.state, VmState::Crashed);
+        assert!(vm.state_reason.contains("signal: 9"));
+    }
+
+    #[test]
+    fn test_record_exit_after_guest_shutdown_is_stopped() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, 0);
+
+        let vm = record_exit(dir.path(), "vm-1", Some("guest-shutdown"), "exit status: 0").unwrap();
+        assert_eq!(vm.state, VmState::Stopped);
+        assert!(vm.state_reason.contains("guest-shutdown"));
+    }
+
+    #[test]
+    fn test_record_exit_while_stopping_is_stopped() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Stopping, 0);
+
+        let vm = record_exit(dir.path(), "vm-1", None, "exit status: 0").unwrap();
+        assert_eq!(vm.state, VmState::Stopped);
+    }
+
+    #[test]
+    fn test_record_exit_while_deleting_is_ignored() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Deleting, 0);
+
+        assert!(record_exit(dir.path(), "vm-1", None, "signal: 15").is_err());
+        let vm = get_vm_by_id(dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(vm.state, VmState::Deleting);
+    }
+
+    #[test]
+    fn test_refresh_state_marks_dead_running_vm_crashed() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, u32::MAX);
+        let vm = get_vm_by_id(dir.path(), "vm-1").unwrap().unwrap();
+
+        let vm = refresh_state(dir.path(), vm);
+        assert_eq!(vm.state, VmState::Crashed);
+        let stored = get_vm_by_id(dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Crashed);
+    }
+
+    #[test]
+    fn test_refresh_state_leaves_live_vm_running() {
+        let dir = TempDir::new().unwrap();
+        store_test_vm(dir.path(), "vm-1", VmState::Running, std::process::id());
+        let vm = get_vm_by_id(dir.path(), "vm-1").unwrap().unwrap();
+
+        assert_eq!(refresh_state(dir.path(), vm).state, VmState::Running);
+    }
+
+    #[test]
+    fn test_refresh_state_leaves_starting_vm_alone() {
+        let dir = TempDir::new().unwrap();
+        // The previous run's dead PID, not yet replaced by the new QEMU's.
+        let stale = store_test_vm(dir.path(), "vm-1", VmState::Running, u32::MAX);
+        store_test_vm(dir.path(), "vm-1", VmState::Starting, u32::MAX);
+
+        let vm = refresh_state(dir.path(), stale);
+        assert_eq!(vm.state, VmState::Starting);
+        let stored = get_vm_by_id(dir.path(), "vm-1").unwrap().unwrap();
+        assert_eq!(stored.state, VmState::Starting);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::qemu::{is_process_running, is_vm_process};
+use crate::qmp::QmpClient;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often to check whether QEMU has exited while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The step of the stop sequence after which QEMU had exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopStep {
+    /// The guest shut down in response to an ACPI power button press.
+    GuestShutdown,
+    /// QEMU exited on a QMP `quit`.
+    QmpQuit,
+    Sigterm,
+    Sigkill,
+}
+
+impl std::fmt::Display for StopStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            StopStep::GuestShutdown => "guest shutdown",
+            StopStep::QmpQuit => "QMP quit",
+            StopStep::Sigterm => "SIGTERM",
+            StopStep::Sigkill => "SIGKILL",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Stop the QEMU process `pid`, escalating until it exits:
+///
+/// 1. `system_powerdown` over QMP, then wait up to `guest_timeout` for the
+///    guest to shut down. Skipped when `guest_timeout` is zero.
+/// 2. QMP `quit`, then wait up to `grace`.
+/// 3. SIGTERM, then wait up to `grace`.
+/// 4. SIGKILL, then wait up to `grace`.
+///
+/// Steps that cannot be attempted (e.g. the QMP socket is unreachable) are
+/// skipped. Signals are only sent while `pid` is still the QEMU serving
+/// `qmp_socket`; a PID taken over by another process counts as exited.
+/// Returns the step after which the process was gone.
+pub async fn stop_process(
+    pid: u32,
+    qmp_socket: &Path,
+    guest_timeout: Duration,
+    grace: Duration,
+) -> Result<StopStep, String> {
+    let qmp = match QmpClient::shared(qmp_socket).await {
+        Ok(client) => Some(client),
+        Err(e) => {
+            warn!("QMP unavailable for PID {pid}, skipping to signals: {e}");
+            None
+        }
+    };
+
+    if let Some(qmp) = &qmp {
+        if !guest_timeout.is_zero() {
+            match qmp.system_powerdown().await {
+                Ok(()) => {
+                    if wait_for_exit(pid, guest_timeout).await {
+                        return Ok(StopStep::GuestShutdown);
+                    }
+                    info!("PID {pid} still running {guest_timeout:?} after system_powerdown");
+                }
+                Err(e) => warn!("system_powerdown failed for PID {pid}: {e}"),
+            }
+        }
+
+        // QEMU closes the socket as it exits, so a disconnect here is the
+        // expected outcome rather than a failure.
+        if let Err(e) = qmp.execute("quit", None).await {
+            info!("QMP quit for PID {pid} returned: {e}");
+        }
+        if wait_for_exit(pid, grace).await {
+            return Ok(StopStep::QmpQuit);
+        }
+    }
+
+    for (signal, step) in [
+        (Signal::SIGTERM, StopStep::Sigterm),
+        (Signal::SIGKILL, StopStep::Sigkill),
+    ] {
+        if !is_process_running(pid) {
+            return Ok(step);
+        }
+        if !is_vm_process(pid, qmp_socket) {
+            warn!("PID {pid} is no longer this VM's QEMU, not sending it {signal}");
+            return Ok(step);
+        }
+        if let Err(e) = kill(Pid::from_raw(pid as i32), signal) {
+            warn!("Failed to send {signal} to PID {pid}: {e}");
+        }
+        if wait_for_exit(pid, grace).await {
+            return Ok(step);
+        }
+    }
+
+    Err(format!("Process {pid} survived SIGKILL"))
+}
+
+/// Poll until `pid` has exited or `timeout` elapses. Returns whether it exited.
+async fn wait_for_exit(pid: u32, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if !is_process_running(pid) {
+            return true;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Helpers for tests that need a process to stop. The child is reaped in the
+/// background so `is_process_running` turns false as soon as it exits.
+#[cfg(test)]
+pub mod test_process {
+    use crate::qemu::qmp_listen_arg;
+    use std::path::Path;
+    use std::time::Duration;
+    use tokio::process::Command;
+
+    /// Spawn a long-running process; `ignore_sigterm` makes it survive SIGTERM.
+    pub fn spawn(ignore_sigterm: bool) -> u32 {
+        reaped(Command::new("sh").args(["-c", script(ignore_sigterm)]))
+    }
+
+    /// Like `spawn`, with a command line that `is_vm_process` takes for the
+    /// QEMU serving QMP on `qmp_socket`.
+    pub fn spawn_vm(ignore_sigterm: bool, qmp_socket: &Path) -> u32 {
+        let listen = qmp_listen_arg(&qmp_socket.to_string_lossy());
+        let pid = reaped(Command::new("sh").arg0("qemu-system-x86_64").args([
+            "-c",
+            script(ignore_sigterm),
+            "sh",
+            &listen,
+        ]));
+        wait_for_exec(pid, qmp_socket);
+        pid
+    }
+
+    /// Spawn a QEMU lookalike for `qmp_socket` that ignores SIGTERM and that
+    /// nothing waits for until the caller does, so once killed it lingers as
+    /// a zombie that still looks alive to signal 0.
+    pub fn spawn_unreaped(qmp_socket: &Path) -> std::process::Child {
+        use std::os::unix::process::CommandExt;
+
+        let listen = qmp_listen_arg(&qmp_socket.to_string_lossy());
+        let child = std::process::Command::new("sh")
+            .arg0("qemu-system-x86_64")
+            .args(["-c", script(true), "sh", &listen])
+            .spawn()
+            .unwrap();
+        wait_for_exec(child.id(), qmp_socket);
+        // Give the shell time to install its trap before it is signalled.
+        std::thread::sleep(Duration::from_millis(100));
+        child
+    }
+
+    /// Until the child has exec'd, its command line is still the test
+    /// binary's.
+    fn wait_for_exec(pid: u32, qmp_socket: &Path) {
+        while !crate::qemu::is_vm_process(pid, qmp_socket) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn script(ignore_sigterm: bool) -> &'static str {
+        if ignore_sigterm {
+            "trap '' TERM; while true; do sleep 0.1; done"
+        } else {
+            "while true; do sleep 0.1; done"
+        }
+    }
+
+    fn reaped(command: &mut Command) -> u32 {
+        let mut child = command.spawn().unwrap();
+        let pid = child.id().unwrap();
+        tokio::spawn(async move {
+            let _ = child.wait().await;
+        });
+        pid
+    }
+
+    pub fn kill(pid: u32) {
+        let _ = nix::sys::signal::kill(
+            nix::unistd::Pid::from_raw(pid as i32),
+            nix::sys::signal::Signal::SIGKILL,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qmp::fake;
+    use serde_json::json;
+
+    const GRACE: Duration = Duration::from_millis(500);
+
+    #[tokio::test]
+    async fn test_guest_shutdown_ends_vm() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let pid = test_process::spawn(false);
+        let received = fake::spawn(&socket, move |command| {
+            if command == "system_powerdown" {
+                test_process::kill(pid);
+            }
+            json!({ "return": {} })
+        });
+
+        let step = stop_process(pid, &socket, Duration::from_secs(5), GRACE)
+            .await
+            .unwrap();
+
+        assert_eq!(step, StopStep::GuestShutdown);
+        assert!(!received.lock().await.contains(&"quit".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_escalates_to_qmp_quit_after_timeout() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let pid = test_process::spawn(false);
+        let received = fake::spawn(&socket, move |command| {
+            if command == "quit" {
+                test_process::kill(pid);
+            }
+            json!({ "return": {} })
+        });
+
+        let step = stop_process(pid, &socket, Duration::from_millis(200), GRACE)
+            .await
+            .unwrap();
+
+        assert_eq!(step, StopStep::QmpQuit);
+        assert_eq!(
+            *received.lock().await,
+            ["qmp_capabilities", "system_powerdown", "quit"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zero_timeout_skips_powerdown() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let pid = test_process::spawn(false);
+        let received = fake::spawn(&socket, move |command| {
+            if command == "quit" {
+                test_process::kill(pid);
+            }
+            json!({ "return": {} })
+        });
+
+        let step = stop_process(pid, &socket, Duration::ZERO, GRACE)
+            .await
+            .unwrap();
+
+        assert_eq!(step, StopStep::QmpQuit);
+        assert_eq!(*received.lock().await, ["qmp_capabilities", "quit"]);
+    }
+
+    #[tokio::test]
+    async fn test_without_qmp_falls_back_to_sigterm() {
+        let socket = Path::new("/nonexistent/vm.qmp");
+        let pid = test_process::spawn_vm(false, socket);
+
+        let step = stop_process(pid, socket, Duration::from_secs(5), GRACE)
+            .await
+            .unwrap();
+
+        assert_eq!(step, StopStep::Sigterm);
+    }
+
+    #[tokio::test]
+    async fn test_process_ignoring_sigterm_is_killed() {
+        let socket = Path::new("/nonexistent/vm.qmp");
+        let pid = test_process::spawn_vm(true, socket);
+        // Give the shell time to install its trap before signalling it.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let step = stop_process(pid, socket, Duration::ZERO, GRACE)
+            .await
+            .unwrap();
+
+        assert_eq!(step, StopStep::Sigkill);
+    }
+
+    #[tokio::test]
+    async fn test_foreign_process_is_not_signalled() {
+        let pid = test_process::spawn(false);
+
+        let step = stop_process(pid, Path::new("/nonexistent/vm.qmp"), Duration::ZERO, GRACE)
+            .await
+            .unwrap();
+
+        assert_eq!(step, StopStep::Sigterm);
+        assert!(is_process_running(pid));
+        test_process::kill(pid);
+    }
+}
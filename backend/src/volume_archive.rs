@@ -0,0 +1,604 @@
+use crate::config::Config;
+use crate::image_service::DOWNLOAD_CHUNK_BYTES;
+use crate::volume_db::{store_volume_info, VolumeInfo};
+use crate::volume_files::mounted_root;
+use crate::volume_service::{
+    error_response, provision_volume, unmount_image, volume_image_path, LaunchVolumeResponse,
+};
+use axum::{
+    body::Body,
+    extract::{Path as AxumPath, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportVolumeQuery {
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportVolumeQuery {
+    /// Name of the new volume.
+    pub name: String,
+    /// Size of the new volume in GiB; the archive's contents must fit.
+    pub size_gb: u64,
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+pub async fn export_volume_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<ExportVolumeQuery>,
+) -> Response {
+    info!("Exporting volume {id}");
+    let config = Config::load().expect("Failed to load configuration");
+    export_volume_response(&config.storage.volume_data_dir, &id, query.compression).await
+}
+
+/// Stream a mounted volume's contents as a tar archive. The archive is read
+/// from the live filesystem, so files written during the export may be
+/// caught mid-change; export a clone of a snapshot for a consistent copy.
+async fn export_volume_response(
+    volume_data_dir: &Path,
+    id: &str,
+    compression: Compression,
+) -> Response {
+    let root = match mounted_root(volume_data_dir, id) {
+        Ok(root) => root,
+        Err(error) => return error.into_response(),
+    };
+    let mut child = match tar_command(compression)
+        .args(["--create", "--file=-", "--one-file-system", "--sparse"])
+        .arg("--exclude=./lost+found")
+        .arg("--directory")
+        .arg(&root)
+        .arg(".")
+        .stdout(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to run tar for volume {id}: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to run tar: {e}"),
+            )
+                .into_response();
+        }
+    };
+    let stderr = collect_stderr(&mut child);
+    let stdout = child.stdout.take().expect("stdout is piped");
+
+    // A tar failure after the first bytes can only end the stream with an
+    // error, which the client sees as a truncated body.
+    let chunks = futures_util::stream::unfold(
+        Some((stdout, child, stderr)),
+        |state: Option<(ChildStdout, Child, JoinHandle<String>)>| async move {
+            let (mut stdout, child, stderr) = state?;
+            let mut buf = vec![0; DOWNLOAD_CHUNK_BYTES];
+            match stdout.read(&mut buf).await {
+                Ok(0) => match finish_tar(child, stderr).await {
+                    Ok(()) => None,
+                    Err(e) => Some((Err(e), None)),
+                },
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok(buf), Some((stdout, child, stderr))))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        },
+    );
+    let (content_type, extension) = match compression {
+        Compression::None => ("application/x-tar", "tar"),
+        Compression::Zstd => ("application/zstd", "tar.zst"),
+    };
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{id}.{extension}\""),
+            ),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+pub async fn import_volume_handler(
+    Query(query): Query<ImportVolumeQuery>,
+    body: Body,
+) -> (StatusCode, Json<LaunchVolumeResponse>) {
+    info!("Importing volume {} ({} GiB)", query.name, query.size_gb);
+    let config = Config::load().expect("Failed to load configuration");
+    import_volume_response(&config.storage.volume_data_dir, query, body).await
+}
+
+/// Create and mount a new volume, then unpack the tar archive in the request
+/// body into it. The volume is only recorded once the archive is unpacked,
+/// so a failed import leaves nothing behind.
+async fn import_volume_response(
+    volume_data_dir: &Path,
+    query: ImportVolumeQuery,
+    body: Body,
+) -> (StatusCode, Json<LaunchVolumeResponse>) {
+    if query.size_gb == 0 {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "size_gb must be at least 1".to_string(),
+        );
+    }
+    let id = Uuid::new_v4().to_string();
+    let img_path = volume_image_path(volume_data_dir, &id);
+    let mount_path = volume_data_dir.join("volumes").join(&id);
+
+    if let Err(message) = provision_volume(&img_path, &mount_path, query.size_gb).await {
+        let _ = fs::remove_dir(&mount_path).await;
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, message);
+    }
+    finish_import(volume_data_dir, id, &img_path, &mount_path, query, body).await
+}
+
+/// Unpack the archive into the freshly mounted volume `id` and record it.
+/// On failure the volume is unmounted and its image and mount point removed.
+async fn finish_import(
+    volume_data_dir: &Path,
+    id: String,
+    img_path: &Path,
+    mount_path: &Path,
+    query: ImportVolumeQuery,
+    body: Body,
+) -> (StatusCode, Json<LaunchVolumeResponse>) {
+    let result = match extract_archive(mount_path, query.compression, body).await {
+        Ok(()) => {
+            let volume_info = VolumeInfo {
+                id: id.clone(),
+                name: query.name.clone(),
+                size_gb: query.size_gb,
+                mount_path: mount_path.to_string_lossy().to_string(),
+                loop_device: None,
+                mounted: true,
+                attached_to: None,
+                snapshots: Vec::new(),
+            };
+            store_volume_info(volume_data_dir, &volume_info).map_err(|e| {
+                error!("Failed to store volume metadata for {id}: {e}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to store volume metadata: {e}"),
+                )
+            })
+        }
+        Err(error) => Err(error),
+    };
+    if let Err((status, message)) = result {
+        if let Err(e) = unmount_image(mount_path).await {
+            warn!("Could not unmount {mount_path:?} after a failed import: {e}");
+        }
+        let _ = fs::remove_file(img_path).await;
+        let _ = fs::remove_dir(mount_path).await;
+        return error_response(status, message);
+    }
+
+    let mount_path_str = mount_path.to_string_lossy().to_string();
+    info!(
+        "Volume {} ({}) imported, mounted at {}",
+        query.name, id, mount_path_str
+    );
+    (
+        StatusCode::OK,
+        Json(LaunchVolumeResponse {
+            success: true,
+            message: format!("Volume {} imported and mounted", query.name),
+            id: Some(id),
+            name: Some(query.name),
+            mount_path: Some(mount_path_str),
+        }),
+    )
+}
+
+/// Unpack a tar archive streamed in `body` into `dir`. GNU tar refuses
+/// members with `..` or an absolute path, and creates symlinks pointing out
+/// of `dir` only after every file is written, so no member can land outside
+/// it; archives that try fail the import. Archives holding device nodes are
+/// rejected too.
+async fn extract_archive(
+    dir: &Path,
+    compression: Compression,
+    body: Body,
+) -> Result<(), (StatusCode, String)> {
+    let mut child = tar_command(compression)
+        .args(["--extract", "--file=-"])
+        .arg("--directory")
+        .arg(dir)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            error!("Failed to run tar in {dir:?}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to run tar: {e}"),
+            )
+        })?;
+    let stderr = collect_stderr(&mut child);
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            // A write only fails once tar has exited; its status says why.
+            Ok(chunk) => {
+                if stdin.write_all(&chunk).await.is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                drop(stdin);
+                let _ = finish_tar(child, stderr).await;
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Failed to read request body: {e}"),
+                ));
+            }
+        }
+    }
+    drop(stdin);
+    finish_tar(child, stderr).await.map_err(|e| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Failed to extract archive: {e}"),
+        )
+    })?;
+
+    let root = dir.to_path_buf();
+    match tokio::task::spawn_blocking(move || find_device_node(&root)).await {
+        Ok(Ok(None)) => Ok(()),
+        Ok(Ok(Some(node))) => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Archive contains device node {}",
+                node.strip_prefix(dir).unwrap_or(&node).display()
+            ),
+        )),
+        Ok(Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to inspect extracted archive: {e}"),
+        )),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to inspect extracted archive: {e}"),
+        )),
+    }
+}
+
+/// The first block or character device under `dir`, if any. Symlinks are
+/// not followed.
+fn find_device_node(dir: &Path) -> std::io::Result<Option<PathBuf>> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_block_device() || file_type.is_char_device() {
+            return Ok(Some(entry.path()));
+        }
+        if file_type.is_dir() {
+            if let Some(node) = find_device_node(&entry.path())? {
+                return Ok(Some(node));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Owners are kept as numeric IDs: names on the worker mean nothing to the
+/// VMs that use the volume.
+fn tar_command(compression: Compression) -> Command {
+    let mut command = Command::new("tar");
+    command
+        .arg("--numeric-owner")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if compression == Compression::Zstd {
+        command.arg("--zstd");
+    }
+    command
+}
+
+/// Read a child's stderr as it runs, so a chatty tar never blocks on a full
+/// pipe.
+fn collect_stderr(child: &mut Child) -> JoinHandle<String> {
+    let stderr = child.stderr.take();
+    tokio::spawn(async move {
+        let mut output = String::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_string(&mut output).await;
+        }
+        output
+    })
+}
+
+/// Wait for tar to exit. Status 1 only means files changed while they were
+/// read, which a live export expects.
+async fn finish_tar(mut child: Child, stderr: JoinHandle<String>) -> std::io::Result<()> {
+    let status = child.wait().await?;
+    let stderr = stderr.await.unwrap_or_default();
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(1) => {
+            warn!("tar: {}", stderr.trim());
+            Ok(())
+        }
+        _ => Err(std::io::Error::other(format!(
+            "tar exited with {status}: {}",
+            stderr.trim()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume_db::{get_volume_by_id, mounted_test_volume};
+    use axum::body::to_bytes;
+    use tempfile::TempDir;
+
+    fn tar(args: &[&str], dir: &Path) {
+        let status = std::process::Command::new("tar")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "tar {args:?}");
+    }
+
+    async fn export_and_extract(compression: Compression) {
+        let (dir, mnt) = mounted_test_volume(true);
+        std::fs::create_dir(mnt.join("docs")).unwrap();
+        std::fs::write(mnt.join("docs/a.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink("docs/a.txt", mnt.join("link")).unwrap();
+        let resp = export_volume_response(dir.path(), "vol-1", compression).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let archive = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+
+        let target = dir.path().join("restored");
+        std::fs::create_dir(&target).unwrap();
+        extract_archive(&target, compression, Body::from(archive))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(target.join("docs/a.txt")).unwrap(),
+            "hello"
+        );
+        assert_eq!(
+            std::fs::read_link(target.join("link")).unwrap(),
+            Path::new("docs/a.txt")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_then_extract_round_trips() {
+        export_and_extract(Compression::None).await;
+    }
+
+    #[tokio::test]
+    async fn test_export_then_extract_round_trips_with_zstd() {
+        export_and_extract(Compression::Zstd).await;
+    }
+
+    #[tokio::test]
+    async fn test_export_sets_archive_headers() {
+        let (dir, _mnt) = mounted_test_volume(true);
+        let resp = export_volume_response(dir.path(), "vol-1", Compression::Zstd).await;
+        assert_eq!(resp.headers()["content-type"], "application/zstd");
+        assert_eq!(
+            resp.headers()["content-disposition"],
+            "attachment; filename=\"vol-1.tar.zst\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_needs_mounted_test_volume() {
+        let (dir, _mnt) = mounted_test_volume(false);
+        let resp = export_volume_response(dir.path(), "vol-1", Compression::None).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let resp = export_volume_response(dir.path(), "vol-2", Compression::None).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_extract_rejects_garbage() {
+        let dir = TempDir::new().unwrap();
+        let error = extract_archive(dir.path(), Compression::None, Body::from("not a tar"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_extract_keeps_members_inside() {
+        let dir = TempDir::new().unwrap();
+        let outside = dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        // An archive holding a symlink to `outside`, then a file "through" it.
+        let first = dir.path().join("first");
+        std::fs::create_dir(&first).unwrap();
+        std::os::unix::fs::symlink(&outside, first.join("x")).unwrap();
+        let second = dir.path().join("second");
+        std::fs::create_dir_all(second.join("x")).unwrap();
+        std::fs::write(second.join("x/evil"), "pwned").unwrap();
+        tar(&["--create", "--file=../evil.tar", "x"], &first);
+        tar(&["--append", "--file=../evil.tar", "x/evil"], &second);
+        let archive = std::fs::read(dir.path().join("evil.tar")).unwrap();
+
+        let target = dir.path().join("target");
+        std::fs::create_dir(&target).unwrap();
+        let error = extract_archive(&target, Compression::None, Body::from(archive))
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.0, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(!outside.join("evil").exists());
+    }
+
+    /// A ustar archive holding a single character device, /dev/null's 1:3.
+    fn device_archive() -> Vec<u8> {
+        fn field(header: &mut [u8], offset: usize, value: &[u8]) {
+            header[offset..offset + value.len()].copy_from_slice(value);
+        }
+        let mut header = [0u8; 512];
+        field(&mut header, 0, b"null");
+        field(&mut header, 100, b"0000666\0");
+        field(&mut header, 108, b"0000000\0");
+        field(&mut header, 116, b"0000000\0");
+        field(&mut header, 124, b"00000000000\0");
+        field(&mut header, 136, b"00000000000\0");
+        field(&mut header, 148, b"        ");
+        field(&mut header, 156, b"3");
+        field(&mut header, 257, b"ustar\0");
+        field(&mut header, 263, b"00");
+        field(&mut header, 329, b"0000001\0");
+        field(&mut header, 337, b"0000003\0");
+        let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        field(&mut header, 148, format!("{checksum:06o}\0 ").as_bytes());
+        let mut archive = header.to_vec();
+        archive.resize(512 * 20, 0);
+        archive
+    }
+
+    #[tokio::test]
+    async fn test_extract_rejects_device_nodes() {
+        let dir = TempDir::new().unwrap();
+        let error = extract_archive(dir.path(), Compression::None, Body::from(device_archive()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.0, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    fn import_query() -> ImportVolumeQuery {
+        ImportVolumeQuery {
+            name: "copy".to_string(),
+            size_gb: 1,
+            compression: Compression::None,
+        }
+    }
+
+    /// The image and mount point `import_volume_response` provisions for
+    /// "vol-2", with a plain directory standing in for the mounted image.
+    fn provisioned(dir: &Path) -> (PathBuf, PathBuf) {
+        let img_path = volume_image_path(dir, "vol-2");
+        std::fs::write(&img_path, "image").unwrap();
+        let mount_path = dir.join("volumes").join("vol-2");
+        std::fs::create_dir_all(&mount_path).unwrap();
+        (img_path, mount_path)
+    }
+
+    #[tokio::test]
+    async fn test_import_of_export_round_trips() {
+        let (source, mnt) = mounted_test_volume(true);
+        std::fs::create_dir(mnt.join("docs")).unwrap();
+        std::fs::write(mnt.join("docs/a.txt"), "hello").unwrap();
+        let resp = export_volume_response(source.path(), "vol-1", Compression::None).await;
+        let archive = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let (img_path, mount_path) = provisioned(dir.path());
+        let (status, Json(resp)) = finish_import(
+            dir.path(),
+            "vol-2".to_string(),
+            &img_path,
+            &mount_path,
+            import_query(),
+            Body::from(archive),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(resp.id.as_deref(), Some("vol-2"));
+        let volume = get_volume_by_id(dir.path(), "vol-2").unwrap().unwrap();
+        assert_eq!(volume.name, "copy");
+        assert!(volume.mounted);
+        assert_eq!(
+            std::fs::read_to_string(mount_path.join("docs/a.txt")).unwrap(),
+            "hello"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_device_nodes() {
+        let dir = TempDir::new().unwrap();
+        let (img_path, mount_path) = provisioned(dir.path());
+
+        let (status, _) = finish_import(
+            dir.path(),
+            "vol-2".to_string(),
+            &img_path,
+            &mount_path,
+            import_query(),
+            Body::from(device_archive()),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(get_volume_by_id(dir.path(), "vol-2").unwrap().is_none());
+        assert!(!img_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_import_leaves_nothing_behind() {
+        let dir = TempDir::new().unwrap();
+        let (img_path, mount_path) = provisioned(dir.path());
+
+        let (status, _) = finish_import(
+            dir.path(),
+            "vol-2".to_string(),
+            &img_path,
+            &mount_path,
+            import_query(),
+            Body::from("not a tar"),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(get_volume_by_id(dir.path(), "vol-2").unwrap().is_none());
+        assert!(!img_path.exists());
+        assert!(!mount_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_zero_size() {
+        let dir = TempDir::new().unwrap();
+        let query = ImportVolumeQuery {
+            name: "data".to_string(),
+            size_gb: 0,
+            compression: Compression::None,
+        };
+        let (status, _) = import_volume_response(dir.path(), query, Body::empty()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(!dir.path().join("volumes").exists());
+    }
+}
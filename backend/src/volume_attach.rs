@@ -0,0 +1,710 @@
+use crate::config::Config;
+use crate::qemu::qmp_socket_path;
+use crate::qmp::{QmpClient, QmpError, QmpEvent};
+use crate::vm_db::{get_vm_by_id, AttachedVolume, VmInfo};
+use crate::vm_state::{refresh_state, update_vm, TransitionError, VmState};
+use crate::volume_db::{get_volume_by_id, update_volume, VolumeInfo};
+use crate::volume_service::volume_image_path;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+/// How long the guest gets to release a disk after `device_del`. Removal of a
+/// virtio disk needs the guest's cooperation, like an ACPI eject.
+const DETACH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serializes attach, detach, mount, unmount, resize, snapshot and delete
+/// requests for volumes, and starting and deleting VMs, so two requests
+/// cannot both see a volume as free and attach it to different VMs, mount
+/// one that is being attached, attach one whose image is being grown, copied
+/// or removed, or attach one to a VM that is booting or going away.
+pub(crate) static ATTACH_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttachVolumeRequest {
+    pub volume_id: String,
+    pub vm_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DetachVolumeRequest {
+    pub volume_id: String,
+    pub vm_id: String,
+}
+
+/// Hot-plug a volume's image into a running QEMU as a virtio disk. If the
+/// device cannot be added, the block node is removed again.
+pub async fn hotplug(qmp: &QmpClient, volume: &AttachedVolume) -> Result<(), QmpError> {
+    let node_name = volume.node_name();
+    qmp.execute(
+        "blockdev-add",
+        Some(json!({
+            "driver": "raw",
+            "node-name": node_name,
+            "file": { "driver": "file", "filename": volume.path },
+        })),
+    )
+    .await?;
+
+    let device = json!({
+        "driver": "virtio-blk-pci",
+        "drive": node_name,
+        "id": volume.device_id(),
+    });
+    if let Err(e) = qmp.execute("device_add", Some(device)).await {
+        if let Err(del) = delete_node(qmp, &node_name).await {
+            warn!("Could not remove block node {node_name} after failed device_add: {del}");
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Unplug a volume's virtio disk and close its image. QEMU only removes the
+/// device once the guest has released it, signalled by `DEVICE_DELETED`, so
+/// this waits up to `timeout` for that before closing the block node.
+pub async fn unplug(
+    qmp: &QmpClient,
+    volume: &AttachedVolume,
+    timeout: Duration,
+) -> Result<(), QmpError> {
+    let device_id = volume.device_id();
+    // Subscribe before asking, so the event cannot be missed.
+    let mut events = qmp.events();
+    match qmp
+        .execute("device_del", Some(json!({ "id": device_id })))
+        .await
+    {
+        Ok(_) => {
+            if !wait_for_device_deleted(&mut events, &device_id, timeout).await {
+                return Err(QmpError::Timeout);
+            }
+        }
+        // Already gone, e.g. the guest ejected it itself.
+        Err(QmpError::Command { class, .. }) if class == "DeviceNotFound" => {}
+        Err(e) => return Err(e),
+    }
+    delete_node(qmp, &volume.node_name()).await
+}
+
+async fn delete_node(qmp: &QmpClient, node_name: &str) -> Result<(), QmpError> {
+    qmp.execute("blockdev-del", Some(json!({ "node-name": node_name })))
+        .await
+        .map(|_| ())
+}
+
+async fn wait_for_device_deleted(
+    events: &mut Receiver<QmpEvent>,
+    device_id: &str,
+    timeout: Duration,
+) -> bool {
+    let wait = async {
+        loop {
+            match events.recv().await {
+                Ok(event)
+                    if event.event == "DEVICE_DELETED" && event.data["device"] == device_id =>
+                {
+                    return true
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return false,
+            }
+        }
+    };
+    tokio::time::timeout(timeout, wait).await.unwrap_or(false)
+}
+
+pub async fn attach_volume_handler(Json(payload): Json<AttachVolumeRequest>) -> Response {
+    info!(
+        "Attaching volume {} to VM {}",
+        payload.volume_id, payload.vm_id
+    );
+    let config = Config::load().expect("Failed to load configuration");
+    attach_volume_response(
+        &config.storage.metadata_dir,
+        &config.storage.volume_data_dir,
+        &payload.vm_id,
+        &payload.volume_id,
+    )
+    .await
+}
+
+/// Attach a volume to a VM. A running or paused VM gets the disk hot-plugged;
+/// a stopped one gets it when it next boots. A volume can only be attached to
+/// one VM at a time and not while it is mounted on the host.
+pub async fn attach_volume_response(
+    metadata_dir: &Path,
+    volume_data_dir: &Path,
+    vm_id: &str,
+    volume_id: &str,
+) -> Response {
+    let _guard = ATTACH_LOCK.lock().await;
+
+    let volume = match load_volume(volume_data_dir, volume_id) {
+        Ok(volume) => volume,
+        Err(error) => return error.into_response(),
+    };
+    if let Some(other) = &volume.attached_to {
+        return (
+            StatusCode::CONFLICT,
+            format!("Volume is already attached to VM {other}"),
+        )
+            .into_response();
+    }
+    if volume.mounted {
+        return (
+            StatusCode::CONFLICT,
+            "Volume is mounted on the host; unmount it first",
+        )
+            .into_response();
+    }
+    let vm_info = match load_vm(metadata_dir, vm_id) {
+        Ok(Some(vm_info)) => vm_info,
+        Ok(None) => return (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        Err(error) => return error.into_response(),
+    };
+
+    let attached = AttachedVolume {
+        id: volume_id.to_string(),
+        path: volume_image_path(volume_data_dir, volume_id),
+    };
+    match vm_info.state {
+        VmState::Running | VmState::Paused => {
+            let result = match QmpClient::shared(&qmp_socket_path(metadata_dir, vm_id)).await {
+                Ok(qmp) => hotplug(&qmp, &attached).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("Failed to hot-plug volume {volume_id} into VM {vm_id}: {e}");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to attach volume: {e}"),
+                )
+                    .into_response();
+            }
+        }
+        // Recorded only; `boot_vm` adds the disk to the QEMU command line.
+        VmState::Stopped | VmState::Crashed => {}
+        state => {
+            return (
+                StatusCode::CONFLICT,
+                format!("Cannot attach a volume while the VM is {state}"),
+            )
+                .into_response()
+        }
+    }
+
+    if let Err(e) = update_vm(metadata_dir, vm_id, |vm| vm.volumes.push(attached)) {
+        return record_error(e);
+    }
+    set_attached_to(volume_data_dir, volume_id, Some(vm_id))
+}
+
+pub async fn detach_volume_handler(Json(payload): Json<DetachVolumeRequest>) -> Response {
+    info!(
+        "Detaching volume {} from VM {}",
+        payload.volume_id, payload.vm_id
+    );
+    let config = Config::load().expect("Failed to load configuration");
+    detach_volume_response(
+        &config.storage.metadata_dir,
+        &config.storage.volume_data_dir,
+        &payload.vm_id,
+        &payload.volume_id,
+    )
+    .await
+}
+
+/// Detach a volume from a VM, unplugging it first if the VM is running. A
+/// paused guest cannot release the disk, so it has to be resumed first.
+pub async fn detach_volume_response(
+    metadata_dir: &Path,
+    volume_data_dir: &Path,
+    vm_id: &str,
+    volume_id: &str,
+) -> Response {
+    let _guard = ATTACH_LOCK.lock().await;
+
+    let volume = match load_volume(volume_data_dir, volume_id) {
+        Ok(volume) => volume,
+        Err(error) => return error.into_response(),
+    };
+    if volume.attached_to.as_deref() != Some(vm_id) {
+        return (
+            StatusCode::CONFLICT,
+            format!("Volume is not attached to VM {vm_id}"),
+        )
+            .into_response();
+    }
+    let vm_info = match load_vm(metadata_dir, vm_id) {
+        Ok(vm_info) => vm_info,
+        Err(error) => return error.into_response(),
+    };
+    // The VM no longer records the volume (e.g. it was deleted while the
+    // backend was down): only the volume's side needs releasing.
+    let Some(attached) = vm_info
+        .as_ref()
+        .and_then(|vm| vm.volumes.iter().find(|v| v.id == volume_id))
+        .cloned()
+    else {
+        return set_attached_to(volume_data_dir, volume_id, None);
+    };
+
+    match vm_info.map(|vm| vm.state) {
+        Some(VmState::Running) => {
+            let result = match QmpClient::shared(&qmp_socket_path(metadata_dir, vm_id)).await {
+                Ok(qmp) => unplug(&qmp, &attached, DETACH_TIMEOUT).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {}
+                Err(QmpError::Timeout) => {
+                    return (
+                        StatusCode::GATEWAY_TIMEOUT,
+                        "The guest did not release the disk; unmount it in the guest and retry",
+                    )
+                        .into_response()
+                }
+                Err(e) => {
+                    error!("Failed to unplug volume {volume_id} from VM {vm_id}: {e}");
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to detach volume: {e}"),
+                    )
+                        .into_response();
+                }
+            }
+        }
+        Some(VmState::Stopped | VmState::Crashed) | None => {}
+        Some(state) => {
+            let hint = if state == VmState::Paused {
+                "; resume it first"
+            } else {
+                ""
+            };
+            return (
+                StatusCode::CONFLICT,
+                format!("Cannot detach a volume while the VM is {state}{hint}"),
+            )
+                .into_response();
+        }
+    }
+
+    if let Err(e) = update_vm(metadata_dir, vm_id, |vm| {
+        vm.volumes.retain(|v| v.id != volume_id)
+    }) {
+        return record_error(e);
+    }
+    set_attached_to(volume_data_dir, volume_id, None)
+}
+
+pub(crate) fn load_volume(
+    volume_data_dir: &Path,
+    id: &str,
+) -> Result<VolumeInfo, (StatusCode, String)> {
+    match get_volume_by_id(volume_data_dir, id) {
+        Ok(Some(volume)) => Ok(volume),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Volume not found".to_string())),
+        Err(e) => {
+            error!("Error retrieving volume info for {id}: {e}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving volume info: {e}"),
+            ))
+        }
+    }
+}
+
+fn load_vm(metadata_dir: &Path, id: &str) -> Result<Option<VmInfo>, (StatusCode, String)> {
+    match get_vm_by_id(metadata_dir, id) {
+        Ok(vm_info) => Ok(vm_info.map(|vm_info| refresh_state(metadata_dir, vm_info))),
+        Err(e) => {
+            error!("Error retrieving VM info: {e}");
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error retrieving VM info: {e}"),
+            ))
+        }
+    }
+}
+
+fn set_attached_to(volume_data_dir: &Path, id: &str, vm_id: Option<&str>) -> Response {
+    match update_volume(volume_data_dir, id, |v| {
+        v.attached_to = vm_id.map(str::to_string)
+    }) {
+        Ok(Some(volume)) => (StatusCode::OK, Json(volume)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Volume not found").into_response(),
+        Err(e) => {
+            error!("Failed to store volume metadata for {id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to store volume metadata: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn record_error(e: TransitionError) -> Response {
+    match e {
+        TransitionError::NotFound => (StatusCode::NOT_FOUND, "VM not found").into_response(),
+        TransitionError::Invalid { from, .. } => (
+            StatusCode::CONFLICT,
+            format!("Cannot change volumes while the VM is {from}"),
+        )
+            .into_response(),
+        TransitionError::Io(e) => {
+            error!("Error updating VM metadata: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error updating VM metadata: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qmp::fake;
+    use crate::vm_db::{store_vm_info, test_vm};
+    use crate::vm_stop::test_process;
+    use crate::volume_db::store_test_volume;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex as StdMutex};
+    use tempfile::TempDir;
+
+    const VOLUME_ID: &str = "e8bb6971-e57e-4263-8e3b-e554926fcfe0";
+
+    fn test_volume(dir: &Path) -> AttachedVolume {
+        AttachedVolume {
+            id: VOLUME_ID.to_string(),
+            path: volume_image_path(dir, VOLUME_ID),
+        }
+    }
+
+    fn store_vm(dir: &Path, id: &str, state: VmState, pid: u32, volumes: Vec<AttachedVolume>) {
+        let vm = VmInfo {
+            volumes,
+            ..test_vm(id, state, pid)
+        };
+        store_vm_info(dir, &vm).unwrap();
+    }
+
+    struct Dirs {
+        meta: TempDir,
+        volumes: TempDir,
+    }
+
+    impl Dirs {
+        fn new() -> Self {
+            Dirs {
+                meta: TempDir::new().unwrap(),
+                volumes: TempDir::new().unwrap(),
+            }
+        }
+
+        fn attached_to(&self) -> Option<String> {
+            get_volume_by_id(self.volumes.path(), VOLUME_ID)
+                .unwrap()
+                .unwrap()
+                .attached_to
+        }
+
+        fn vm_volumes(&self, id: &str) -> Vec<AttachedVolume> {
+            get_vm_by_id(self.meta.path(), id).unwrap().unwrap().volumes
+        }
+    }
+
+    // ── hotplug / unplug ─────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_hotplug_adds_node_then_device() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let volume = test_volume(tmp.path());
+        let arguments = Arc::new(StdMutex::new(Vec::new()));
+        let seen = arguments.clone();
+        let received = fake::spawn_with_args(&socket, move |_, args: &Value| {
+            seen.lock().unwrap().push(args.clone());
+            json!({ "return": {} })
+        });
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        hotplug(&qmp, &volume).await.unwrap();
+
+        assert_eq!(
+            *received.lock().await,
+            ["qmp_capabilities", "blockdev-add", "device_add"]
+        );
+        let arguments = arguments.lock().unwrap();
+        assert_eq!(arguments[1]["node-name"], volume.node_name());
+        assert_eq!(
+            arguments[1]["file"]["filename"],
+            volume.path.to_string_lossy().as_ref()
+        );
+        assert_eq!(arguments[2]["driver"], "virtio-blk-pci");
+        assert_eq!(arguments[2]["drive"], volume.node_name());
+        assert_eq!(arguments[2]["id"], volume.device_id());
+    }
+
+    #[tokio::test]
+    async fn test_hotplug_removes_node_when_device_add_fails() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let received = fake::spawn(&socket, |command| match command {
+            "device_add" => json!({
+                "error": { "class": "GenericError", "desc": "no free PCI slot" }
+            }),
+            _ => json!({ "return": {} }),
+        });
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        let result = hotplug(&qmp, &test_volume(tmp.path())).await;
+
+        assert!(matches!(result, Err(QmpError::Command { .. })));
+        assert_eq!(
+            *received.lock().await,
+            [
+                "qmp_capabilities",
+                "blockdev-add",
+                "device_add",
+                "blockdev-del"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unplug_waits_for_device_deleted() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let volume = test_volume(tmp.path());
+        let device_id = volume.device_id();
+        let received = fake::spawn_scripted(&socket, move |command| match command {
+            "device_del" => vec![
+                json!({ "return": {} }),
+                fake::event("DEVICE_DELETED", json!({ "path": "/machine/peripheral/x" })),
+                fake::event("DEVICE_DELETED", json!({ "device": device_id })),
+            ],
+            _ => vec![json!({ "return": {} })],
+        });
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        unplug(&qmp, &volume, Duration::from_secs(5)).await.unwrap();
+
+        assert_eq!(
+            *received.lock().await,
+            ["qmp_capabilities", "device_del", "blockdev-del"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unplug_times_out_when_guest_keeps_disk() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let received = fake::spawn(&socket, fake::ok);
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        let result = unplug(&qmp, &test_volume(tmp.path()), Duration::from_millis(200)).await;
+
+        assert!(matches!(result, Err(QmpError::Timeout)));
+        // The image stays open while the guest may still be using it.
+        assert_eq!(*received.lock().await, ["qmp_capabilities", "device_del"]);
+    }
+
+    #[tokio::test]
+    async fn test_unplug_of_missing_device_closes_node() {
+        let tmp = TempDir::new().unwrap();
+        let socket = tmp.path().join("vm.qmp");
+        let received = fake::spawn(&socket, |command| match command {
+            "device_del" => json!({
+                "error": { "class": "DeviceNotFound", "desc": "Device not found" }
+            }),
+            _ => json!({ "return": {} }),
+        });
+        let qmp = QmpClient::connect(&socket).await.unwrap();
+
+        unplug(&qmp, &test_volume(tmp.path()), Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *received.lock().await,
+            ["qmp_capabilities", "device_del", "blockdev-del"]
+        );
+    }
+
+    // ── attach_volume_response ───────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_attach_to_stopped_vm_records_both_sides() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, None);
+        store_vm(dirs.meta.path(), "vm-1", VmState::Stopped, 0, Vec::new());
+
+        let resp =
+            attach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(dirs.attached_to().as_deref(), Some("vm-1"));
+        assert_eq!(dirs.vm_volumes("vm-1"), [test_volume(dirs.volumes.path())]);
+    }
+
+    #[tokio::test]
+    async fn test_attach_to_running_vm_hotplugs() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, None);
+        let pid = test_process::spawn(false);
+        store_vm(dirs.meta.path(), "vm-1", VmState::Running, pid, Vec::new());
+        let received = fake::spawn(&qmp_socket_path(dirs.meta.path(), "vm-1"), fake::ok);
+
+        let resp =
+            attach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(received.lock().await.contains(&"device_add".to_string()));
+        assert_eq!(dirs.vm_volumes("vm-1").len(), 1);
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_attach_rejects_mounted_volume() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, true, None);
+        store_vm(dirs.meta.path(), "vm-1", VmState::Stopped, 0, Vec::new());
+
+        let resp =
+            attach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert!(dirs.vm_volumes("vm-1").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_attach_rejects_volume_attached_elsewhere() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, Some("vm-2"));
+        store_vm(dirs.meta.path(), "vm-1", VmState::Stopped, 0, Vec::new());
+
+        let resp =
+            attach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(dirs.attached_to().as_deref(), Some("vm-2"));
+    }
+
+    #[tokio::test]
+    async fn test_attach_unknown_vm_or_volume_returns_404() {
+        let dirs = Dirs::new();
+        let resp =
+            attach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, None);
+        let resp =
+            attach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert!(dirs.attached_to().is_none());
+    }
+
+    // ── detach_volume_response ───────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_detach_from_stopped_vm_releases_volume() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, Some("vm-1"));
+        let volumes = vec![test_volume(dirs.volumes.path())];
+        store_vm(dirs.meta.path(), "vm-1", VmState::Stopped, 0, volumes);
+
+        let resp =
+            detach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(dirs.attached_to().is_none());
+        assert!(dirs.vm_volumes("vm-1").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_detach_from_running_vm_unplugs() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, Some("vm-1"));
+        let volume = test_volume(dirs.volumes.path());
+        let device_id = volume.device_id();
+        let pid = test_process::spawn(false);
+        store_vm(
+            dirs.meta.path(),
+            "vm-1",
+            VmState::Running,
+            pid,
+            vec![volume],
+        );
+        let received =
+            fake::spawn_scripted(&qmp_socket_path(dirs.meta.path(), "vm-1"), move |command| {
+                match command {
+                    "device_del" => vec![
+                        json!({ "return": {} }),
+                        fake::event("DEVICE_DELETED", json!({ "device": device_id })),
+                    ],
+                    _ => vec![json!({ "return": {} })],
+                }
+            });
+
+        let resp =
+            detach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(received.lock().await.contains(&"blockdev-del".to_string()));
+        assert!(dirs.attached_to().is_none());
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_detach_from_paused_vm_returns_conflict() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, Some("vm-1"));
+        let pid = test_process::spawn(false);
+        let volumes = vec![test_volume(dirs.volumes.path())];
+        store_vm(dirs.meta.path(), "vm-1", VmState::Paused, pid, volumes);
+
+        let resp =
+            detach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(dirs.attached_to().as_deref(), Some("vm-1"));
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_detach_rejects_volume_attached_elsewhere() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, Some("vm-2"));
+        store_vm(dirs.meta.path(), "vm-1", VmState::Stopped, 0, Vec::new());
+
+        let resp =
+            detach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_detach_from_deleted_vm_releases_volume() {
+        let dirs = Dirs::new();
+        store_test_volume(dirs.volumes.path(), VOLUME_ID, false, Some("vm-1"));
+
+        let resp =
+            detach_volume_response(dirs.meta.path(), dirs.volumes.path(), "vm-1", VOLUME_ID).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(dirs.attached_to().is_none());
+    }
+}
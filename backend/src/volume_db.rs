@@ -7,11 +7,44 @@ use tracing::debug;
 pub struct VolumeInfo {
     pub id: String,
     pub name: String,
+    /// Size of the volume's image in GiB. Zero for volumes created before
+    /// sizes were recorded.
+    #[serde(default)]
+    pub size_gb: u64,
     pub mount_path: String,
     // Path to the loop device (e.g. /dev/loop0) used to mount this volume.
     // Used to detach the loop device when the volume is deleted.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub loop_device: Option<String>,
+    /// Whether the image is loop-mounted at `mount_path`. Volumes are mounted
+    /// when created; older metadata predates unmounting, hence the default.
+    #[serde(default = "default_mounted")]
+    pub mounted: bool,
+    /// VM the volume is attached to as a virtio disk. A volume is never both
+    /// mounted and attached, since two kernels writing one ext4 corrupts it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attached_to: Option<String>,
+    /// Point-in-time copies of the volume's image, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snapshots: Vec<VolumeSnapshot>,
+}
+
+/// A point-in-time copy of a volume's image, stored at
+/// `volume_snapshot_path`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeSnapshot {
+    pub name: String,
+    /// Creation time as seconds since the Unix epoch.
+    pub created_secs: u64,
+    /// Apparent size of the copy, i.e. the volume's size when it was taken.
+    pub size_bytes: u64,
+    /// Host disk space the copy occupied when it was taken. Reflinked copies
+    /// share their blocks with the volume, so they may take up less.
+    pub allocated_bytes: u64,
+}
+
+fn default_mounted() -> bool {
+    true
 }
 
 pub fn store_volume_info(dir: &Path, volume_info: &VolumeInfo) -> std::io::Result<()> {
@@ -58,6 +91,21 @@ pub fn get_volume_by_id(dir: &Path, id: &str) -> std::io::Result<Option<VolumeIn
     }
 }
 
+/// Apply `update` to a stored volume and write it back. Returns the updated
+/// volume, or `None` if it does not exist.
+pub fn update_volume(
+    dir: &Path,
+    id: &str,
+    update: impl FnOnce(&mut VolumeInfo),
+) -> std::io::Result<Option<VolumeInfo>> {
+    let Some(mut volume_info) = get_volume_by_id(dir, id)? else {
+        return Ok(None);
+    };
+    update(&mut volume_info);
+    store_volume_info(dir, &volume_info)?;
+    Ok(Some(volume_info))
+}
+
 pub fn delete_volume_by_id(dir: &Path, id: &str) -> std::io::Result<()> {
     let file_path = create_file_path(dir, id);
 
@@ -67,6 +115,45 @@ pub fn delete_volume_by_id(dir: &Path, id: &str) -> std::io::Result<()> {
     Ok(())
 }
 
+/// The copy of a volume's image holding one of its snapshots.
+pub fn volume_snapshot_path(dir: &Path, id: &str, name: &str) -> PathBuf {
+    dir.join("snapshots").join(format!("{id}.{name}.img"))
+}
+
+/// Store a 1 GiB volume mounted at `<dir>/volumes/<id>`; shared by the tests
+/// of every module that stores volumes.
+#[cfg(test)]
+pub(crate) fn store_test_volume(
+    dir: &Path,
+    id: &str,
+    mounted: bool,
+    attached_to: Option<&str>,
+) -> VolumeInfo {
+    let volume = VolumeInfo {
+        id: id.to_string(),
+        name: "data".to_string(),
+        size_gb: 1,
+        mount_path: dir.join("volumes").join(id).to_string_lossy().into_owned(),
+        loop_device: None,
+        mounted,
+        attached_to: attached_to.map(str::to_string),
+        snapshots: Vec::new(),
+    };
+    store_volume_info(dir, &volume).unwrap();
+    volume
+}
+
+/// A volume "vol-1" stored in a fresh metadata directory, "mounted" on a
+/// plain directory that is returned alongside it.
+#[cfg(test)]
+pub(crate) fn mounted_test_volume(mounted: bool) -> (tempfile::TempDir, PathBuf) {
+    let dir = tempfile::TempDir::new().unwrap();
+    let volume = store_test_volume(dir.path(), "vol-1", mounted, None);
+    let mount_path = PathBuf::from(volume.mount_path);
+    fs::create_dir_all(&mount_path).unwrap();
+    (dir, mount_path)
+}
+
 fn create_file_path(dir: &Path, id: &str) -> PathBuf {
     dir.join(format!("{id}.json"))
 }
@@ -80,8 +167,12 @@ mod tests {
         VolumeInfo {
             id: id.to_string(),
             name: name.to_string(),
+            size_gb: 1,
             mount_path: format!("/mnt/volumes/{id}"),
             loop_device: None,
+            mounted: true,
+            attached_to: None,
+            snapshots: Vec::new(),
         }
     }
 
@@ -98,6 +189,36 @@ mod tests {
         assert_eq!(retrieved.mount_path, volume.mount_path);
     }
 
+    #[test]
+    fn test_update_volume() {
+        let dir = TempDir::new().unwrap();
+        store_volume_info(dir.path(), &create_test_volume("vol-1", "data")).unwrap();
+
+        let updated = update_volume(dir.path(), "vol-1", |v| {
+            v.mounted = false;
+            v.attached_to = Some("vm-1".to_string());
+        })
+        .unwrap()
+        .unwrap();
+        assert!(!updated.mounted);
+
+        let stored = get_volume_by_id(dir.path(), "vol-1").unwrap().unwrap();
+        assert_eq!(stored.attached_to.as_deref(), Some("vm-1"));
+        assert!(update_volume(dir.path(), "missing", |_| {})
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_legacy_volume_is_mounted_and_unattached() {
+        let volume: VolumeInfo = serde_json::from_str(
+            r#"{"id": "vol-1", "name": "old", "mount_path": "/mnt/volumes/vol-1"}"#,
+        )
+        .unwrap();
+        assert!(volume.mounted);
+        assert!(volume.attached_to.is_none());
+    }
+
     #[test]
     fn test_get_nonexistent_volume() {
         let dir = TempDir::new().unwrap();
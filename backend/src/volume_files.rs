@@ -0,0 +1,1087 @@
+use crate::config::Config;
+use crate::image_service::file_response;
+use crate::vm_exec::WriteFileResponse;
+use crate::volume_attach::load_volume;
+use axum::{
+    body::Body,
+    extract::{Path as AxumPath, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use base64::Engine;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct VolumeFileQuery {
+    /// Path inside the volume, relative to its root. A leading `/` is
+    /// allowed and means the same thing.
+    pub path: String,
+    /// Whether deleting a directory also deletes its contents.
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameVolumeFileRequest {
+    pub from: String,
+    pub to: String,
+}
+
+/// Entries per page when a listing does not ask for a size.
+const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/// Largest page a listing returns, whatever it asks for.
+const MAX_PAGE_SIZE: usize = 10_000;
+
+/// Deepest a recursive listing descends below the listed directory.
+const MAX_LIST_DEPTH: u32 = 64;
+
+#[derive(Debug, Deserialize)]
+pub struct ListVolumeFilesQuery {
+    /// Directory to list, relative to the volume's root. Defaults to the root.
+    #[serde(default)]
+    pub path: String,
+    /// Levels to descend: 1 lists the directory's own entries, 2 adds those
+    /// of its subdirectories, and so on.
+    #[serde(default = "default_depth")]
+    pub depth: u32,
+    #[serde(default)]
+    pub sort: FileSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Entries per page; defaults to 1000 and is capped at 10000.
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page, to continue a listing.
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+fn default_depth() -> u32 {
+    1
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileSort {
+    /// By path.
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VolumeFileEntry {
+    pub name: String,
+    /// Path relative to the volume's root, usable as `path` in the other
+    /// volume file endpoints.
+    pub path: String,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub size_bytes: u64,
+    pub modified_secs: u64,
+    /// Permission bits, e.g. 0o644.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Where a symlink points, as stored in the link.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VolumeFileListing {
+    /// The listed directory, relative to the volume's root.
+    pub path: String,
+    pub entries: Vec<VolumeFileEntry>,
+    /// Pass as `cursor` to get the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Position of the last entry of a page in the listing's order. Encoded
+/// into `next_cursor`, so a page picks up after that entry even if entries
+/// were added or removed in between.
+#[derive(Debug, Serialize, Deserialize)]
+struct ListCursor {
+    key: u64,
+    path: String,
+}
+
+pub async fn list_volume_files_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<ListVolumeFilesQuery>,
+) -> Response {
+    let config = Config::load().expect("Failed to load configuration");
+    list_volume_files_response(&config.storage.volume_data_dir, &id, &query)
+}
+
+/// List a directory of a mounted volume, one page at a time. Symlinks are
+/// listed but never descended into.
+fn list_volume_files_response(
+    volume_data_dir: &Path,
+    id: &str,
+    query: &ListVolumeFilesQuery,
+) -> Response {
+    if query.depth == 0 || query.limit == Some(0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "depth and limit must be at least 1",
+        )
+            .into_response();
+    }
+    let after = match query.cursor.as_deref().map(decode_cursor).transpose() {
+        Ok(after) => after,
+        Err(error) => return error.into_response(),
+    };
+    let (root, dir) = match mounted_root(volume_data_dir, id).and_then(|root| {
+        let dir = resolve_volume_path(&root, &query.path, true)?;
+        let root = std::fs::canonicalize(&root).map_err(|e| file_error(&query.path, e))?;
+        Ok((root, dir))
+    }) {
+        Ok(paths) => paths,
+        Err(error) => return error.into_response(),
+    };
+    match std::fs::metadata(&dir) {
+        Ok(metadata) if !metadata.is_dir() => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{} is not a directory", query.path),
+            )
+                .into_response()
+        }
+        Ok(_) => {}
+        Err(e) => return file_error(&query.path, e).into_response(),
+    }
+    // Canonical, so the entries' paths are where they really are.
+    let prefix = dir
+        .strip_prefix(&root)
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let compare = |a: &VolumeFileEntry, b: &VolumeFileEntry| {
+        order_by(
+            query.order,
+            (sort_key(a, query.sort), &a.path),
+            (sort_key(b, query.sort), &b.path),
+        )
+    };
+    // Only the first `limit + 1` entries after the cursor are kept, the
+    // extra one telling whether there is another page.
+    let mut page = Vec::new();
+    let result = read_volume_files(
+        &dir,
+        &prefix,
+        query.depth.min(MAX_LIST_DEPTH),
+        &mut |entry| {
+            if let Some(after) = &after {
+                let position = (sort_key(&entry, query.sort), entry.path.as_str());
+                if order_by(query.order, position, (after.key, &after.path)).is_le() {
+                    return;
+                }
+            }
+            page.push(entry);
+            if page.len() >= 2 * (limit + 1) {
+                page.sort_by(compare);
+                page.truncate(limit + 1);
+            }
+        },
+    );
+    if let Err(e) = result {
+        return file_error(&query.path, e).into_response();
+    }
+    page.sort_by(compare);
+
+    let next_cursor = if page.len() > limit {
+        page.truncate(limit);
+        page.last().map(|last| {
+            encode_cursor(&ListCursor {
+                key: sort_key(last, query.sort),
+                path: last.path.clone(),
+            })
+        })
+    } else {
+        None
+    };
+    Json(VolumeFileListing {
+        path: prefix.to_string_lossy().into_owned(),
+        entries: page,
+        next_cursor,
+    })
+    .into_response()
+}
+
+fn sort_key(entry: &VolumeFileEntry, sort: FileSort) -> u64 {
+    match sort {
+        FileSort::Name => 0,
+        FileSort::Size => entry.size_bytes,
+        FileSort::Modified => entry.modified_secs,
+    }
+}
+
+/// Listings are ordered by sort key, then by path, so every entry has one
+/// place in the order and cursors never skip or repeat entries. Paths are
+/// compared component by component, which keeps a directory's contents
+/// right after it.
+fn order_by(order: SortOrder, a: (u64, &str), b: (u64, &str)) -> std::cmp::Ordering {
+    let a = (a.0, Path::new(a.1));
+    let b = (b.0, Path::new(b.1));
+    match order {
+        SortOrder::Asc => a.cmp(&b),
+        SortOrder::Desc => b.cmp(&a),
+    }
+}
+
+fn encode_cursor(cursor: &ListCursor) -> String {
+    BASE64.encode(serde_json::to_vec(cursor).unwrap_or_default())
+}
+
+fn decode_cursor(cursor: &str) -> Result<ListCursor, (StatusCode, String)> {
+    BASE64
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid cursor".to_string()))
+}
+
+/// Call `visit` with every entry under `dir`, down to `depth` levels.
+/// `prefix` is `dir`'s path relative to the volume's root. Subdirectories
+/// that cannot be read are skipped with a warning.
+fn read_volume_files(
+    dir: &Path,
+    prefix: &Path,
+    depth: u32,
+    visit: &mut dyn FnMut(VolumeFileEntry),
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        // Not followed: a symlink is listed as itself.
+        let metadata = match entry.metadata() {
+            Ok(m) => m,
+            Err(e) => {
+                warn!("Could not read metadata for {:?}: {e}", entry.path());
+                continue;
+            }
+        };
+        let modified_secs = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let symlink_target = if metadata.is_symlink() {
+            std::fs::read_link(entry.path())
+                .ok()
+                .map(|target| target.to_string_lossy().into_owned())
+        } else {
+            None
+        };
+        let path = prefix.join(entry.file_name());
+        if metadata.is_dir() && depth > 1 {
+            if let Err(e) = read_volume_files(&entry.path(), &path, depth - 1, visit) {
+                warn!("Could not list {:?}: {e}", entry.path());
+            }
+        }
+        visit(VolumeFileEntry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            path: path.to_string_lossy().into_owned(),
+            is_dir: metadata.is_dir(),
+            is_symlink: metadata.is_symlink(),
+            size_bytes: metadata.len(),
+            modified_secs,
+            mode: metadata.mode() & 0o7777,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            symlink_target,
+        });
+    }
+    Ok(())
+}
+
+pub async fn read_volume_file_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<VolumeFileQuery>,
+) -> Response {
+    let config = Config::load().expect("Failed to load configuration");
+    read_volume_file_response(&config.storage.volume_data_dir, &id, &query.path).await
+}
+
+/// Stream a file out of a mounted volume.
+async fn read_volume_file_response(volume_data_dir: &Path, id: &str, path: &str) -> Response {
+    let file = match mounted_root(volume_data_dir, id)
+        .and_then(|root| resolve_volume_path(&root, path, true))
+    {
+        Ok(file) => file,
+        Err(error) => return error.into_response(),
+    };
+    // Only regular files are served: a device node would stream the host
+    // device and opening a FIFO would block forever.
+    match fs::symlink_metadata(&file).await {
+        Ok(metadata) if metadata.is_dir() => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{path} is a directory; list it with /volume-files"),
+            )
+                .into_response()
+        }
+        Ok(metadata) if !metadata.is_file() => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{path} is not a regular file"),
+            )
+                .into_response()
+        }
+        Ok(_) => {}
+        Err(e) => return file_error(path, e).into_response(),
+    }
+    match fs::File::open(&file).await {
+        Ok(file) => file_response(file).await,
+        Err(e) => file_error(path, e).into_response(),
+    }
+}
+
+pub async fn write_volume_file_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<VolumeFileQuery>,
+    body: Body,
+) -> Response {
+    info!("Writing {} in volume {id}", query.path);
+    let config = Config::load().expect("Failed to load configuration");
+    write_volume_file_response(&config.storage.volume_data_dir, &id, &query.path, body).await
+}
+
+/// Stream the request body into a file in a mounted volume, creating or
+/// replacing it. The body goes to a temporary file next to the target first,
+/// so a failed upload never leaves a truncated file behind.
+async fn write_volume_file_response(
+    volume_data_dir: &Path,
+    id: &str,
+    path: &str,
+    body: Body,
+) -> Response {
+    let file = match mounted_root(volume_data_dir, id)
+        .and_then(|root| resolve_volume_entry(&root, path))
+    {
+        Ok(file) => file,
+        Err(error) => return error.into_response(),
+    };
+    if fs::metadata(&file).await.is_ok_and(|m| m.is_dir()) {
+        return (StatusCode::CONFLICT, format!("{path} is a directory")).into_response();
+    }
+
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file.file_name().unwrap_or_default());
+    temp_name.push(format!(".upload-{}", Uuid::new_v4()));
+    let temp = file.with_file_name(temp_name);
+
+    let result = match write_body(&temp, body).await {
+        Ok(bytes_written) => fs::rename(&temp, &file)
+            .await
+            .map(|()| bytes_written)
+            .map_err(|e| file_error(path, e)),
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(bytes_written) => Json(WriteFileResponse { bytes_written }).into_response(),
+        Err(error) => {
+            if let Err(e) = fs::remove_file(&temp).await {
+                if e.kind() != ErrorKind::NotFound {
+                    warn!("Could not remove partial upload {temp:?}: {e}");
+                }
+            }
+            error.into_response()
+        }
+    }
+}
+
+async fn write_body(temp: &Path, body: Body) -> Result<u64, (StatusCode, String)> {
+    let display = temp.display().to_string();
+    let mut file = fs::File::create(temp).await.map_err(|e| match e.kind() {
+        ErrorKind::NotFound => (
+            StatusCode::NOT_FOUND,
+            "Parent directory not found".to_string(),
+        ),
+        _ => file_error(&display, e),
+    })?;
+    let mut bytes_written = 0;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Failed to read request body: {e}"),
+            )
+        })?;
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| file_error(&display, e))?;
+        bytes_written += chunk.len() as u64;
+    }
+    file.sync_all().await.map_err(|e| file_error(&display, e))?;
+    Ok(bytes_written)
+}
+
+pub async fn delete_volume_file_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<VolumeFileQuery>,
+) -> Response {
+    info!("Deleting {} in volume {id}", query.path);
+    let config = Config::load().expect("Failed to load configuration");
+    delete_volume_file_response(
+        &config.storage.volume_data_dir,
+        &id,
+        &query.path,
+        query.recursive,
+    )
+    .await
+}
+
+/// Delete a file, symlink or directory in a mounted volume. Directories must
+/// be empty unless `recursive` is set. Symlinks are removed, not followed.
+async fn delete_volume_file_response(
+    volume_data_dir: &Path,
+    id: &str,
+    path: &str,
+    recursive: bool,
+) -> Response {
+    let file = match mounted_root(volume_data_dir, id)
+        .and_then(|root| resolve_volume_entry(&root, path))
+    {
+        Ok(file) => file,
+        Err(error) => return error.into_response(),
+    };
+    let result = match fs::symlink_metadata(&file).await {
+        Ok(metadata) if metadata.is_dir() && recursive => fs::remove_dir_all(&file).await,
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir(&file).await,
+        Ok(_) => fs::remove_file(&file).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => (StatusCode::OK, format!("{path} deleted")).into_response(),
+        Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty => (
+            StatusCode::CONFLICT,
+            format!("{path} is not empty; delete it recursively"),
+        )
+            .into_response(),
+        Err(e) => file_error(path, e).into_response(),
+    }
+}
+
+pub async fn make_volume_dir_handler(
+    AxumPath(id): AxumPath<String>,
+    Query(query): Query<VolumeFileQuery>,
+) -> Response {
+    info!("Creating directory {} in volume {id}", query.path);
+    let config = Config::load().expect("Failed to load configuration");
+    make_volume_dir_response(&config.storage.volume_data_dir, &id, &query.path).await
+}
+
+/// Create a directory, and any missing parents, in a mounted volume.
+/// Directories that already exist are not an error.
+async fn make_volume_dir_response(volume_data_dir: &Path, id: &str, path: &str) -> Response {
+    let dir = match mounted_root(volume_data_dir, id)
+        .and_then(|root| resolve_volume_path(&root, path, true))
+    {
+        Ok(dir) => dir,
+        Err(error) => return error.into_response(),
+    };
+    match fs::create_dir_all(&dir).await {
+        Ok(()) => (StatusCode::OK, format!("{path} created")).into_response(),
+        Err(e) => file_error(path, e).into_response(),
+    }
+}
+
+pub async fn rename_volume_file_handler(
+    AxumPath(id): AxumPath<String>,
+    Json(payload): Json<RenameVolumeFileRequest>,
+) -> Response {
+    info!("Renaming {} to {} in volume {id}", payload.from, payload.to);
+    let config = Config::load().expect("Failed to load configuration");
+    rename_volume_file_response(
+        &config.storage.volume_data_dir,
+        &id,
+        &payload.from,
+        &payload.to,
+    )
+    .await
+}
+
+/// Move a file or directory within a mounted volume. An existing `to` is not
+/// replaced, since a rename should not silently delete data.
+async fn rename_volume_file_response(
+    volume_data_dir: &Path,
+    id: &str,
+    from: &str,
+    to: &str,
+) -> Response {
+    let (source, target) = match mounted_root(volume_data_dir, id).and_then(|root| {
+        Ok((
+            resolve_volume_entry(&root, from)?,
+            resolve_volume_entry(&root, to)?,
+        ))
+    }) {
+        Ok(paths) => paths,
+        Err(error) => return error.into_response(),
+    };
+    if let Err(e) = fs::symlink_metadata(&source).await {
+        return file_error(from, e).into_response();
+    }
+    if fs::symlink_metadata(&target).await.is_ok() {
+        return (StatusCode::CONFLICT, format!("{to} already exists")).into_response();
+    }
+    match fs::rename(&source, &target).await {
+        Ok(()) => (StatusCode::OK, format!("{from} renamed to {to}")).into_response(),
+        Err(e) if e.kind() == ErrorKind::InvalidInput => (
+            StatusCode::BAD_REQUEST,
+            format!("Cannot move {from} into itself"),
+        )
+            .into_response(),
+        Err(e) => file_error(to, e).into_response(),
+    }
+}
+
+/// The host directory a volume is mounted on. Its files can only be reached
+/// while it is mounted.
+pub(crate) fn mounted_root(
+    volume_data_dir: &Path,
+    id: &str,
+) -> Result<PathBuf, (StatusCode, String)> {
+    let volume_info = load_volume(volume_data_dir, id)?;
+    if !volume_info.mounted {
+        return Err((
+            StatusCode::CONFLICT,
+            "Volume is not mounted on the host".to_string(),
+        ));
+    }
+    Ok(PathBuf::from(volume_info.mount_path))
+}
+
+/// Like [`resolve_volume_path`], for operations that act on the entry itself:
+/// a final symlink is not followed, and the volume's root is refused.
+fn resolve_volume_entry(root: &Path, path: &str) -> Result<PathBuf, (StatusCode, String)> {
+    let resolved = resolve_volume_path(root, path, false)?;
+    if volume_path_names(path)?.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Path must name an entry inside the volume".to_string(),
+        ));
+    }
+    Ok(resolved)
+}
+
+/// Resolve `path`, relative to the volume mounted at `root`, to a host path
+/// that cannot leave the volume. `..` is refused outright, and symlinks
+/// along the way are resolved and must point back inside the volume. The
+/// final component is only resolved if `follow_last` is set.
+fn resolve_volume_path(
+    root: &Path,
+    path: &str,
+    follow_last: bool,
+) -> Result<PathBuf, (StatusCode, String)> {
+    let names = volume_path_names(path)?;
+    let root = std::fs::canonicalize(root).map_err(|e| file_error(path, e))?;
+
+    let mut resolved = root.clone();
+    let mut missing = false;
+    for (i, name) in names.iter().enumerate() {
+        let next = resolved.join(name);
+        let follow = follow_last || i + 1 < names.len();
+        if missing || !follow {
+            resolved = next;
+            continue;
+        }
+        match std::fs::canonicalize(&next) {
+            Ok(canonical) => resolved = canonical,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                // A dangling symlink would be followed once its target is
+                // created, wherever it points.
+                if std::fs::symlink_metadata(&next).is_ok() {
+                    return Err(escape_error(path));
+                }
+                missing = true;
+                resolved = next;
+            }
+            Err(e) => return Err(file_error(path, e)),
+        }
+    }
+    if resolved.starts_with(&root) {
+        Ok(resolved)
+    } else {
+        Err(escape_error(path))
+    }
+}
+
+/// The names in a volume path, with `.` and leading `/` dropped.
+fn volume_path_names(path: &str) -> Result<Vec<&std::ffi::OsStr>, (StatusCode, String)> {
+    let mut names = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::Normal(name) => names.push(name),
+            Component::ParentDir | Component::Prefix(_) => return Err(escape_error(path)),
+        }
+    }
+    Ok(names)
+}
+
+fn escape_error(path: &str) -> (StatusCode, String) {
+    (
+        StatusCode::BAD_REQUEST,
+        format!("Path {path} leaves the volume"),
+    )
+}
+
+fn file_error(path: &str, error: std::io::Error) -> (StatusCode, String) {
+    match error.kind() {
+        ErrorKind::NotFound => (StatusCode::NOT_FOUND, format!("{path} not found")),
+        ErrorKind::AlreadyExists => (StatusCode::CONFLICT, format!("{path} already exists")),
+        ErrorKind::NotADirectory => (
+            StatusCode::BAD_REQUEST,
+            format!("A parent of {path} is not a directory"),
+        ),
+        _ => {
+            error!("Volume file operation on {path} failed: {error}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to access {path}: {error}"),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume_db::mounted_test_volume;
+    use axum::body::to_bytes;
+    use tempfile::TempDir;
+
+    async fn body_string(resp: Response) -> String {
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    // ── path resolution ──────────────────────────────────────────────────────
+
+    #[test]
+    fn test_resolve_volume_path_stays_inside() {
+        let (_dir, mnt) = mounted_test_volume(true);
+        let root = std::fs::canonicalize(&mnt).unwrap();
+        std::fs::create_dir(mnt.join("a")).unwrap();
+
+        assert_eq!(resolve_volume_path(&mnt, "/", true).unwrap(), root);
+        assert_eq!(
+            resolve_volume_path(&mnt, "/a/./new/file", true).unwrap(),
+            root.join("a/new/file")
+        );
+        for path in ["../etc/passwd", "a/../../x", "/.."] {
+            let error = resolve_volume_path(&mnt, path, true).unwrap_err();
+            assert_eq!(error.0, StatusCode::BAD_REQUEST, "{path}");
+        }
+    }
+
+    #[test]
+    fn test_resolve_volume_path_checks_symlinks() {
+        let (dir, mnt) = mounted_test_volume(true);
+        let root = std::fs::canonicalize(&mnt).unwrap();
+        std::fs::create_dir(mnt.join("real")).unwrap();
+        std::os::unix::fs::symlink("real", mnt.join("inside")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), mnt.join("outside")).unwrap();
+        std::os::unix::fs::symlink("/nonexistent/dir", mnt.join("dangling")).unwrap();
+
+        assert_eq!(
+            resolve_volume_path(&mnt, "inside/f", true).unwrap(),
+            root.join("real/f")
+        );
+        for path in ["outside/f", "outside", "dangling/f"] {
+            let error = resolve_volume_path(&mnt, path, true).unwrap_err();
+            assert_eq!(error.0, StatusCode::BAD_REQUEST, "{path}");
+        }
+        // The link itself can still be deleted or renamed.
+        assert_eq!(
+            resolve_volume_entry(&mnt, "outside").unwrap(),
+            root.join("outside")
+        );
+        assert_eq!(
+            resolve_volume_entry(&mnt, "/").unwrap_err().0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    // ── listing ──────────────────────────────────────────────────────────────
+
+    fn read_all(dir: &Path, depth: u32) -> Vec<VolumeFileEntry> {
+        let mut files = Vec::new();
+        read_volume_files(dir, Path::new(""), depth, &mut |entry| files.push(entry)).unwrap();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+
+    fn list_query(path: &str) -> ListVolumeFilesQuery {
+        ListVolumeFilesQuery {
+            path: path.to_string(),
+            depth: 1,
+            sort: FileSort::Name,
+            order: SortOrder::Asc,
+            limit: None,
+            cursor: None,
+        }
+    }
+
+    async fn listing(resp: Response) -> serde_json::Value {
+        assert_eq!(resp.status(), StatusCode::OK);
+        serde_json::from_str(&body_string(resp).await).unwrap()
+    }
+
+    fn listed_paths(listing: &serde_json::Value) -> Vec<&str> {
+        listing["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["path"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_volume_files_empty_dir() {
+        let dir = TempDir::new().unwrap();
+        assert!(read_all(dir.path(), 1).is_empty());
+    }
+
+    #[test]
+    fn test_read_volume_files_returns_file_entry() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("hello.txt"), "world").unwrap();
+
+        let files = read_all(dir.path(), 1);
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "hello.txt");
+        assert_eq!(files[0].path, "hello.txt");
+        assert!(!files[0].is_dir);
+        assert_eq!(files[0].size_bytes, 5);
+        assert!(files[0].modified_secs > 0);
+        let owner = std::fs::metadata(dir.path().join("hello.txt")).unwrap();
+        assert_eq!((files[0].uid, files[0].gid), (owner.uid(), owner.gid()));
+    }
+
+    #[test]
+    fn test_read_volume_files_returns_dir_entry() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("subdir")).unwrap();
+
+        let files = read_all(dir.path(), 1);
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "subdir");
+        assert!(files[0].is_dir);
+    }
+
+    #[test]
+    fn test_read_volume_files_mixed_entries() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("file.txt"), "data").unwrap();
+        std::fs::create_dir(dir.path().join("subdir")).unwrap();
+
+        let files = read_all(dir.path(), 1);
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name, "file.txt");
+        assert!(!files[0].is_dir);
+        assert_eq!(files[1].name, "subdir");
+        assert!(files[1].is_dir);
+    }
+
+    #[test]
+    fn test_read_volume_files_nonexistent_path() {
+        let dir = TempDir::new().unwrap();
+        let nonexistent = dir.path().join("does-not-exist");
+        let result = read_volume_files(&nonexistent, Path::new(""), 1, &mut |_| {});
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read_volume_files_descends_to_depth() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        std::fs::write(dir.path().join("a/b/deep.txt"), "x").unwrap();
+
+        let paths = |depth| -> Vec<String> {
+            read_all(dir.path(), depth)
+                .into_iter()
+                .map(|f| f.path)
+                .collect()
+        };
+        // Depth 1 lists only the directory itself, not nested entries.
+        assert_eq!(paths(1), ["a"]);
+        assert_eq!(paths(2), ["a", "a/b"]);
+        assert_eq!(paths(3), ["a", "a/b", "a/b/deep.txt"]);
+    }
+
+    #[test]
+    fn test_read_volume_files_reports_symlinks_and_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("run.sh");
+        std::fs::write(&script, "#!/bin/sh").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o750)).unwrap();
+        std::fs::create_dir(dir.path().join("real")).unwrap();
+        std::fs::write(dir.path().join("real/f"), "x").unwrap();
+        std::os::unix::fs::symlink("real", dir.path().join("link")).unwrap();
+
+        let files = read_all(dir.path(), 3);
+
+        let paths: Vec<_> = files.iter().map(|f| f.path.as_str()).collect();
+        // The link is listed but not descended into.
+        assert_eq!(paths, ["link", "real", "real/f", "run.sh"]);
+        assert!(files[0].is_symlink);
+        assert!(!files[0].is_dir);
+        assert_eq!(files[0].symlink_target.as_deref(), Some("real"));
+        assert_eq!(files[3].mode, 0o750);
+        assert_eq!(files[3].symlink_target, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_volume_files_subdirectory() {
+        let (dir, mnt) = mounted_test_volume(true);
+        std::fs::create_dir_all(mnt.join("docs/old")).unwrap();
+        std::fs::write(mnt.join("docs/a.txt"), "x").unwrap();
+        std::fs::write(mnt.join("top.txt"), "x").unwrap();
+
+        let resp = list_volume_files_response(dir.path(), "vol-1", &list_query("/docs/"));
+
+        let listing = listing(resp).await;
+        assert_eq!(listing["path"], "docs");
+        assert_eq!(listed_paths(&listing), ["docs/a.txt", "docs/old"]);
+        assert!(listing.get("next_cursor").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_volume_files_rejects_bad_requests() {
+        let (dir, mnt) = mounted_test_volume(true);
+        std::fs::write(mnt.join("f"), "x").unwrap();
+
+        for (path, depth, cursor, status) in [
+            ("..", 1, None, StatusCode::BAD_REQUEST),
+            ("f", 1, None, StatusCode::BAD_REQUEST),
+            ("missing", 1, None, StatusCode::NOT_FOUND),
+            ("", 0, None, StatusCode::BAD_REQUEST),
+            ("", 1, Some("not-a-cursor"), StatusCode::BAD_REQUEST),
+        ] {
+            let query = ListVolumeFilesQuery {
+                depth,
+                cursor: cursor.map(str::to_string),
+                ..list_query(path)
+            };
+            let resp = list_volume_files_response(dir.path(), "vol-1", &query);
+            assert_eq!(resp.status(), status, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_volume_files_pages_through_everything() {
+        let (dir, mnt) = mounted_test_volume(true);
+        std::fs::create_dir(mnt.join("a")).unwrap();
+        std::fs::write(mnt.join("a/b"), "x").unwrap();
+        std::fs::write(mnt.join("a-c"), "x").unwrap();
+        for name in ["e", "d", "f"] {
+            std::fs::write(mnt.join(name), "x").unwrap();
+        }
+
+        let mut query = ListVolumeFilesQuery {
+            depth: 2,
+            limit: Some(2),
+            ..list_query("")
+        };
+        let mut pages = Vec::new();
+        loop {
+            let listing = listing(list_volume_files_response(dir.path(), "vol-1", &query)).await;
+            pages.push(
+                listed_paths(&listing)
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>(),
+            );
+            match listing["next_cursor"].as_str() {
+                Some(cursor) => query.cursor = Some(cursor.to_string()),
+                None => break,
+            }
+        }
+        // A directory's contents follow it, even past names like "a-c".
+        assert_eq!(pages, [["a", "a/b"], ["a-c", "d"], ["e", "f"]]);
+    }
+
+    #[tokio::test]
+    async fn test_list_volume_files_sorts_by_size_descending() {
+        let (dir, mnt) = mounted_test_volume(true);
+        std::fs::write(mnt.join("small"), "x").unwrap();
+        std::fs::write(mnt.join("large"), "xxxxxxxx").unwrap();
+        std::fs::write(mnt.join("medium"), "xxxx").unwrap();
+        std::fs::write(mnt.join("medium2"), "xxxx").unwrap();
+
+        let mut query = ListVolumeFilesQuery {
+            sort: FileSort::Size,
+            order: SortOrder::Desc,
+            limit: Some(2),
+            ..list_query("")
+        };
+        let first = listing(list_volume_files_response(dir.path(), "vol-1", &query)).await;
+        assert_eq!(listed_paths(&first), ["large", "medium2"]);
+
+        // Entries added before the cursor's position do not shift the pages.
+        std::fs::write(mnt.join("huge"), "x".repeat(64)).unwrap();
+        query.cursor = first["next_cursor"].as_str().map(str::to_string);
+        let second = listing(list_volume_files_response(dir.path(), "vol-1", &query)).await;
+        assert_eq!(listed_paths(&second), ["medium", "small"]);
+        assert!(second.get("next_cursor").is_none());
+    }
+
+    // ── read and write ───────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_write_then_read_volume_file() {
+        let (dir, mnt) = mounted_test_volume(true);
+
+        let resp =
+            write_volume_file_response(dir.path(), "vol-1", "/notes.txt", Body::from("hello"))
+                .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: WriteFileResponse = serde_json::from_str(&body_string(resp).await).unwrap();
+        assert_eq!(body.bytes_written, 5);
+        assert_eq!(
+            std::fs::read_to_string(mnt.join("notes.txt")).unwrap(),
+            "hello"
+        );
+        // Only the file itself is left behind.
+        assert_eq!(std::fs::read_dir(&mnt).unwrap().count(), 1);
+
+        let resp = read_volume_file_response(dir.path(), "vol-1", "notes.txt").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["content-length"], "5");
+        assert_eq!(body_string(resp).await, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_write_volume_file_needs_parent() {
+        let (dir, mnt) = mounted_test_volume(true);
+        let resp =
+            write_volume_file_response(dir.path(), "vol-1", "missing/f", Body::from("x")).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(std::fs::read_dir(&mnt).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_volume_files_need_mounted_volume() {
+        let (dir, _mnt) = mounted_test_volume(false);
+        let resp = read_volume_file_response(dir.path(), "vol-1", "f").await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let resp = read_volume_file_response(dir.path(), "vol-2", "f").await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_read_volume_file_errors() {
+        let (dir, mnt) = mounted_test_volume(true);
+        std::fs::create_dir(mnt.join("sub")).unwrap();
+
+        let resp = read_volume_file_response(dir.path(), "vol-1", "sub").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = read_volume_file_response(dir.path(), "vol-1", "nope").await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = read_volume_file_response(dir.path(), "vol-1", "../vol-1.json").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        nix::unistd::mkfifo(&mnt.join("pipe"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+        let resp = read_volume_file_response(dir.path(), "vol-1", "pipe").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    // ── delete, mkdir and rename ─────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_delete_volume_file() {
+        let (dir, mnt) = mounted_test_volume(true);
+        std::fs::create_dir_all(mnt.join("a/b")).unwrap();
+        std::fs::write(mnt.join("a/b/f"), "x").unwrap();
+
+        let resp = delete_volume_file_response(dir.path(), "vol-1", "a", false).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let resp = delete_volume_file_response(dir.path(), "vol-1", "a/b/f", false).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = delete_volume_file_response(dir.path(), "vol-1", "a", true).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!mnt.join("a").exists());
+        let resp = delete_volume_file_response(dir.path(), "vol-1", "a", true).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_symlink_leaves_target() {
+        let (dir, mnt) = mounted_test_volume(true);
+        let outside = dir.path().join("keep");
+        std::fs::create_dir(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, mnt.join("link")).unwrap();
+
+        let resp = delete_volume_file_response(dir.path(), "vol-1", "link", true).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(outside.exists());
+        assert!(std::fs::symlink_metadata(mnt.join("link")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_make_volume_dir() {
+        let (dir, mnt) = mounted_test_volume(true);
+        let resp = make_volume_dir_response(dir.path(), "vol-1", "a/b").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(mnt.join("a/b").is_dir());
+        let resp = make_volume_dir_response(dir.path(), "vol-1", "a/b").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        std::fs::write(mnt.join("f"), "x").unwrap();
+        let resp = make_volume_dir_response(dir.path(), "vol-1", "f").await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_rename_volume_file() {
+        let (dir, mnt) = mounted_test_volume(true);
+        std::fs::create_dir(mnt.join("dir")).unwrap();
+        std::fs::write(mnt.join("a"), "1").unwrap();
+        std::fs::write(mnt.join("b"), "2").unwrap();
+
+        let resp = rename_volume_file_response(dir.path(), "vol-1", "a", "b").await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let resp = rename_volume_file_response(dir.path(), "vol-1", "a", "dir/c").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(std::fs::read_to_string(mnt.join("dir/c")).unwrap(), "1");
+        let resp = rename_volume_file_response(dir.path(), "vol-1", "a", "d").await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = rename_volume_file_response(dir.path(), "vol-1", "b", "../b").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = rename_volume_file_response(dir.path(), "vol-1", "dir", "dir/sub").await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
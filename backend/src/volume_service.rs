@@ -1,9 +1,18 @@
 use crate::config::Config;
+use crate::qemu::GIB;
+use crate::volume_attach::ATTACH_LOCK;
 use crate::volume_db::{
-    delete_volume_by_id, get_volume_by_id, list_volumes, store_volume_info, VolumeInfo,
+    delete_volume_by_id, get_volume_by_id, list_volumes, store_volume_info, update_volume,
+    volume_snapshot_path, VolumeInfo,
 };
-use axum::{extract::Path as AxumPath, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use nix::sys::statvfs::statvfs;
 use serde::{Deserialize, Serialize};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::process::Command;
@@ -25,6 +34,12 @@ pub struct LaunchVolumeResponse {
     pub mount_path: Option<String>,
 }
 
+/// The raw ext4 image backing a volume, loop-mounted on the host or handed to
+/// a VM as a virtio disk.
+pub fn volume_image_path(volume_data_dir: &Path, id: &str) -> PathBuf {
+    volume_data_dir.join(format!("{id}.img"))
+}
+
 pub async fn launch_volume(
     Json(payload): Json<LaunchVolumeRequest>,
 ) -> (StatusCode, Json<LaunchVolumeResponse>) {
@@ -32,44 +47,33 @@ pub async fn launch_volume(
     let volume_data_dir = &config.storage.volume_data_dir;
     let id = Uuid::new_v4().to_string();
 
-    let img_path = volume_data_dir.join(format!("{id}.img"));
+    let img_path = volume_image_path(volume_data_dir, &id);
     let mount_path = volume_data_dir.join("volumes").join(&id);
 
-    if let Err(e) = fs::create_dir_all(&mount_path).await {
-        error!("Failed to create mount point {mount_path:?}: {e}");
-        return error_response(format!("Failed to create mount point: {e}"));
-    }
-
-    if let Err(e) = create_sparse_image(&img_path, payload.size_gb).await {
-        error!("Failed to create sparse image {img_path:?}: {e}");
-        return error_response(format!("Failed to create image file: {e}"));
-    }
-
-    if let Err(e) = format_ext4(&img_path).await {
-        error!("Failed to format image {img_path:?}: {e}");
-        let _ = fs::remove_file(&img_path).await;
-        return error_response(format!("Failed to format volume: {e}"));
-    }
-
-    if let Err(e) = mount_image(&img_path, &mount_path).await {
-        error!("Failed to mount image {img_path:?} at {mount_path:?}: {e}");
-        let _ = fs::remove_file(&img_path).await;
-        return error_response(format!("Failed to mount volume: {e}"));
+    if let Err(message) = provision_volume(&img_path, &mount_path, payload.size_gb).await {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, message);
     }
 
     let mount_path_str = mount_path.to_string_lossy().to_string();
     let volume_info = VolumeInfo {
         id: id.clone(),
         name: payload.name.clone(),
+        size_gb: payload.size_gb,
         mount_path: mount_path_str.clone(),
         loop_device: None,
+        mounted: true,
+        attached_to: None,
+        snapshots: Vec::new(),
     };
 
     if let Err(e) = store_volume_info(volume_data_dir, &volume_info) {
         error!("Failed to store volume metadata for {id}: {e}");
         let _ = unmount_image(&mount_path).await;
         let _ = fs::remove_file(&img_path).await;
-        return error_response(format!("Failed to store volume metadata: {e}"));
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to store volume metadata: {e}"),
+        );
     }
 
     info!(
@@ -89,11 +93,77 @@ pub async fn launch_volume(
     )
 }
 
+/// Create, format and mount the image of a new volume. On failure no image
+/// is left behind.
+pub(crate) async fn provision_volume(
+    img_path: &Path,
+    mount_path: &Path,
+    size_gb: u64,
+) -> Result<(), String> {
+    if let Err(e) = fs::create_dir_all(mount_path).await {
+        error!("Failed to create mount point {mount_path:?}: {e}");
+        return Err(format!("Failed to create mount point: {e}"));
+    }
+
+    if let Err(e) = create_sparse_image(img_path, size_gb).await {
+        error!("Failed to create sparse image {img_path:?}: {e}");
+        return Err(format!("Failed to create image file: {e}"));
+    }
+
+    if let Err(e) = format_ext4(img_path).await {
+        error!("Failed to format image {img_path:?}: {e}");
+        let _ = fs::remove_file(img_path).await;
+        return Err(format!("Failed to format volume: {e}"));
+    }
+
+    if let Err(e) = mount_image(img_path, mount_path).await {
+        error!("Failed to mount image {img_path:?} at {mount_path:?}: {e}");
+        let _ = fs::remove_file(img_path).await;
+        return Err(format!("Failed to mount volume: {e}"));
+    }
+    Ok(())
+}
+
+/// A volume as listed by `/list-volumes`: its metadata and how full it is.
+#[derive(Debug, Serialize)]
+pub struct VolumeListEntry {
+    #[serde(flatten)]
+    pub volume: VolumeInfo,
+    #[serde(flatten)]
+    pub usage: VolumeUsage,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct VolumeUsage {
+    /// Apparent size of the image file, i.e. the provisioned capacity.
+    pub size_bytes: u64,
+    /// Host disk space the sparse image occupies.
+    pub allocated_bytes: u64,
+    /// Space used and available in the ext4 filesystem, known while the
+    /// volume is mounted on the host.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fs_used_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fs_free_bytes: Option<u64>,
+}
+
 pub async fn list_volumes_handler() -> impl IntoResponse {
     let config = Config::load().expect("Failed to load configuration");
+    list_volumes_response(&config.storage.volume_data_dir)
+}
 
-    match list_volumes(&config.storage.volume_data_dir) {
-        Ok(volumes) => (StatusCode::OK, Json(volumes)).into_response(),
+fn list_volumes_response(volume_data_dir: &Path) -> Response {
+    match list_volumes(volume_data_dir) {
+        Ok(volumes) => {
+            let entries: Vec<VolumeListEntry> = volumes
+                .into_iter()
+                .map(|volume| VolumeListEntry {
+                    usage: volume_usage(volume_data_dir, &volume),
+                    volume,
+                })
+                .collect();
+            (StatusCode::OK, Json(entries)).into_response()
+        }
         Err(e) => {
             error!("Failed to list volumes: {e}");
             (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
@@ -112,24 +182,41 @@ pub async fn delete_volume_handler(Json(payload): Json<DeleteVolumeRequest>) ->
     let config = Config::load().expect("Failed to load configuration");
     let volume_data_dir = &config.storage.volume_data_dir;
 
+    let _guard = ATTACH_LOCK.lock().await;
     match get_volume_by_id(volume_data_dir, &payload.id) {
         Ok(Some(volume_info)) => {
-            let mount_path = PathBuf::from(&volume_info.mount_path);
-            if let Err(e) = unmount_image(&mount_path).await {
-                error!("Failed to unmount {}: {e}", volume_info.mount_path);
+            if let Some(vm_id) = &volume_info.attached_to {
                 return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to unmount volume: {e}"),
+                    StatusCode::CONFLICT,
+                    format!("Volume is attached to VM {vm_id}; detach it first"),
                 )
                     .into_response();
             }
 
-            let img_path = volume_data_dir.join(format!("{}.img", volume_info.id));
+            let mount_path = PathBuf::from(&volume_info.mount_path);
+            if volume_info.mounted {
+                if let Err(e) = unmount_image(&mount_path).await {
+                    error!("Failed to unmount {}: {e}", volume_info.mount_path);
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to unmount volume: {e}"),
+                    )
+                        .into_response();
+                }
+            }
+
+            let img_path = volume_image_path(volume_data_dir, &volume_info.id);
             if let Err(e) = fs::remove_file(&img_path).await {
                 warn!("Could not delete image file {img_path:?}: {e}");
             } else {
                 info!("Deleted image file: {img_path:?}");
             }
+            for snapshot in &volume_info.snapshots {
+                let path = volume_snapshot_path(volume_data_dir, &volume_info.id, &snapshot.name);
+                if let Err(e) = fs::remove_file(&path).await {
+                    warn!("Could not delete snapshot file {path:?}: {e}");
+                }
+            }
 
             if let Err(e) = delete_volume_by_id(volume_data_dir, &volume_info.id) {
                 error!(
@@ -157,6 +244,264 @@ pub async fn delete_volume_handler(Json(payload): Json<DeleteVolumeRequest>) ->
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MountVolumeRequest {
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnmountVolumeRequest {
+    pub id: String,
+}
+
+/// Loop-mount a volume on the host again after it was unmounted.
+pub async fn mount_volume_handler(Json(payload): Json<MountVolumeRequest>) -> Response {
+    info!("Mounting volume: {}", payload.id);
+    let config = Config::load().expect("Failed to load configuration");
+    mount_volume_response(&config.storage.volume_data_dir, &payload.id).await
+}
+
+pub async fn mount_volume_response(volume_data_dir: &Path, id: &str) -> Response {
+    // Keeps the volume from being attached to a VM while it is mounted here.
+    let _guard = ATTACH_LOCK.lock().await;
+    let volume_info = match get_volume_by_id(volume_data_dir, id) {
+        Ok(Some(v)) => v,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Volume not found").into_response(),
+        Err(e) => return volume_lookup_error(id, e),
+    };
+    if let Some(vm_id) = &volume_info.attached_to {
+        return (
+            StatusCode::CONFLICT,
+            format!("Volume is attached to VM {vm_id}; detach it first"),
+        )
+            .into_response();
+    }
+    if volume_info.mounted {
+        return (StatusCode::CONFLICT, "Volume is already mounted").into_response();
+    }
+
+    let mount_path = PathBuf::from(&volume_info.mount_path);
+    if let Err(e) = fs::create_dir_all(&mount_path).await {
+        error!("Failed to create mount point {mount_path:?}: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to create mount point: {e}"),
+        )
+            .into_response();
+    }
+    let img_path = volume_image_path(volume_data_dir, id);
+    if let Err(e) = mount_image(&img_path, &mount_path).await {
+        error!("Failed to mount image {img_path:?} at {mount_path:?}: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to mount volume: {e}"),
+        )
+            .into_response();
+    }
+
+    set_mounted(volume_data_dir, id, true)
+}
+
+/// Unmount a volume from the host so it can be attached to a VM.
+pub async fn unmount_volume_handler(Json(payload): Json<UnmountVolumeRequest>) -> Response {
+    info!("Unmounting volume: {}", payload.id);
+    let config = Config::load().expect("Failed to load configuration");
+    unmount_volume_response(&config.storage.volume_data_dir, &payload.id).await
+}
+
+pub async fn unmount_volume_response(volume_data_dir: &Path, id: &str) -> Response {
+    let _guard = ATTACH_LOCK.lock().await;
+    let volume_info = match get_volume_by_id(volume_data_dir, id) {
+        Ok(Some(v)) => v,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Volume not found").into_response(),
+        Err(e) => return volume_lookup_error(id, e),
+    };
+    if !volume_info.mounted {
+        return (StatusCode::CONFLICT, "Volume is not mounted").into_response();
+    }
+
+    let mount_path = PathBuf::from(&volume_info.mount_path);
+    if let Err(e) = unmount_image(&mount_path).await {
+        error!("Failed to unmount {}: {e}", volume_info.mount_path);
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to unmount volume: {e}"),
+        )
+            .into_response();
+    }
+
+    set_mounted(volume_data_dir, id, false)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResizeVolumeRequest {
+    pub id: String,
+    /// New size in GiB; must be larger than the current one.
+    pub size_gb: u64,
+}
+
+/// Grow a volume's image and the ext4 filesystem on it.
+pub async fn resize_volume_handler(Json(payload): Json<ResizeVolumeRequest>) -> Response {
+    info!("Resizing volume {} to {} GiB", payload.id, payload.size_gb);
+    let config = Config::load().expect("Failed to load configuration");
+    resize_volume_response(
+        &config.storage.volume_data_dir,
+        &payload.id,
+        payload.size_gb,
+    )
+    .await
+}
+
+pub async fn resize_volume_response(volume_data_dir: &Path, id: &str, size_gb: u64) -> Response {
+    // Keeps the volume from being attached to a VM halfway through.
+    let _guard = ATTACH_LOCK.lock().await;
+    let volume_info = match get_volume_by_id(volume_data_dir, id) {
+        Ok(Some(v)) => v,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Volume not found").into_response(),
+        Err(e) => return volume_lookup_error(id, e),
+    };
+    if let Some(vm_id) = &volume_info.attached_to {
+        return (
+            StatusCode::CONFLICT,
+            format!("Volume is attached to VM {vm_id}; detach it first"),
+        )
+            .into_response();
+    }
+
+    let img_path = volume_image_path(volume_data_dir, id);
+    let current = match fs::metadata(&img_path).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            error!("Failed to read image file {img_path:?}: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read image file: {e}"),
+            )
+                .into_response();
+        }
+    };
+    let size = size_gb.saturating_mul(GIB);
+    if size <= current {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "New size must be larger than the current {:.1} GiB",
+                current as f64 / GIB as f64
+            ),
+        )
+            .into_response();
+    }
+
+    if let Err(e) = set_image_len(&img_path, size).await {
+        error!("Failed to extend image {img_path:?}: {e}");
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to extend image file: {e}"),
+        )
+            .into_response();
+    }
+    let grown = if volume_info.mounted {
+        grow_mounted_ext4(&img_path).await
+    } else {
+        grow_unmounted_ext4(&img_path).await
+    };
+    if let Err(e) = grown {
+        error!("Failed to grow the filesystem of volume {id}: {e}");
+        // The filesystem still ends where it did, so the image can shrink back.
+        if let Err(e) = set_image_len(&img_path, current).await {
+            warn!("Could not restore the size of image {img_path:?}: {e}");
+        }
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to grow filesystem: {e}"),
+        )
+            .into_response();
+    }
+
+    match update_volume(volume_data_dir, id, |v| v.size_gb = size_gb) {
+        Ok(Some(volume_info)) => (StatusCode::OK, Json(volume_info)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Volume not found").into_response(),
+        Err(e) => {
+            error!("Failed to store volume metadata for {id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to store volume metadata: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+fn set_mounted(volume_data_dir: &Path, id: &str, mounted: bool) -> Response {
+    match update_volume(volume_data_dir, id, |v| v.mounted = mounted) {
+        Ok(Some(volume_info)) => (StatusCode::OK, Json(volume_info)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Volume not found").into_response(),
+        Err(e) => {
+            error!("Failed to store volume metadata for {id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to store volume metadata: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Measure a volume's image and, if it is mounted, its filesystem. Anything
+/// that cannot be read is left at zero or unknown rather than failing the
+/// listing.
+fn volume_usage(volume_data_dir: &Path, volume: &VolumeInfo) -> VolumeUsage {
+    let mut usage = VolumeUsage::default();
+    let img_path = volume_image_path(volume_data_dir, &volume.id);
+    match std::fs::metadata(&img_path) {
+        Ok(metadata) => {
+            usage.size_bytes = metadata.len();
+            // st_blocks counts 512-byte units whatever the filesystem's block size.
+            usage.allocated_bytes = metadata.blocks() * 512;
+        }
+        Err(e) => warn!("Could not read image file {img_path:?}: {e}"),
+    }
+
+    let mount_path = Path::new(&volume.mount_path);
+    if volume.mounted && is_mount_point(mount_path) {
+        match filesystem_usage(mount_path) {
+            Ok((used, free)) => {
+                usage.fs_used_bytes = Some(used);
+                usage.fs_free_bytes = Some(free);
+            }
+            Err(e) => warn!("Could not read filesystem usage of {mount_path:?}: {e}"),
+        }
+    }
+    usage
+}
+
+/// Bytes used and available to unprivileged users in the filesystem holding
+/// `path`, as `df` reports them.
+fn filesystem_usage(path: &Path) -> nix::Result<(u64, u64)> {
+    let stat = statvfs(path)?;
+    let fragment = stat.fragment_size() as u64;
+    let used = (stat.blocks() as u64).saturating_sub(stat.blocks_free() as u64) * fragment;
+    Ok((used, stat.blocks_available() as u64 * fragment))
+}
+
+/// Whether a filesystem is mounted at `path`, so statvfs does not report the
+/// host filesystem for a volume whose mount went away.
+fn is_mount_point(path: &Path) -> bool {
+    let (Some(parent), Ok(metadata)) = (path.parent(), std::fs::metadata(path)) else {
+        return false;
+    };
+    std::fs::metadata(parent).is_ok_and(|parent| parent.dev() != metadata.dev())
+}
+
+fn volume_lookup_error(id: &str, e: std::io::Error) -> Response {
+    error!("Error retrieving volume info for {id}: {e}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Error retrieving volume info: {e}"),
+    )
+        .into_response()
+}
+
 async fn create_sparse_image(img_path: &Path, size_gb: u64) -> std::io::Result<()> {
     let file = fs::OpenOptions::new()
         .write(true)
@@ -165,7 +510,79 @@ async fn create_sparse_image(img_path: &Path, size_gb: u64) -> std::io::Result<(
         .open(img_path)
         .await?;
     // set_len calls ftruncate, which creates a sparse file on Linux
-    file.set_len(size_gb * 1024 * 1024 * 1024).await
+    file.set_len(size_gb * GIB).await
+}
+
+/// Extend or truncate an image file; new space is sparse.
+async fn set_image_len(img_path: &Path, len: u64) -> std::io::Result<()> {
+    let file = fs::OpenOptions::new().write(true).open(img_path).await?;
+    file.set_len(len).await
+}
+
+/// Grow the ext4 filesystem of a loop-mounted image to fill the image. The
+/// loop device has to pick up the new image size first; ext4 then grows
+/// online, without unmounting.
+async fn grow_mounted_ext4(img_path: &Path) -> std::io::Result<()> {
+    let output = Command::new("losetup")
+        .args(["--noheadings", "--output", "NAME", "--associated"])
+        .arg(img_path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "losetup exited with status {}",
+            output.status
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let loop_device = first_loop_device(&stdout)
+        .ok_or_else(|| std::io::Error::other("image is not attached to a loop device"))?;
+
+    run_tool(
+        Command::new("losetup")
+            .arg("--set-capacity")
+            .arg(loop_device),
+    )
+    .await?;
+    run_tool(Command::new("resize2fs").arg(loop_device)).await
+}
+
+/// Grow the ext4 filesystem of an unmounted image to fill the image.
+/// `resize2fs` refuses to touch an unmounted filesystem that has not just
+/// been checked.
+async fn grow_unmounted_ext4(img_path: &Path) -> std::io::Result<()> {
+    let status = Command::new("e2fsck")
+        .args(["-f", "-p"])
+        .arg(img_path)
+        .status()
+        .await?;
+    // Exit code 1 means errors were found and fixed.
+    if !matches!(status.code(), Some(0 | 1)) {
+        return Err(std::io::Error::other(format!(
+            "e2fsck exited with status {status}"
+        )));
+    }
+    run_tool(Command::new("resize2fs").arg(img_path)).await
+}
+
+/// First loop device in `losetup --noheadings --output NAME` output.
+fn first_loop_device(stdout: &str) -> Option<&str> {
+    stdout.lines().map(str::trim).find(|line| !line.is_empty())
+}
+
+pub(crate) async fn run_tool(command: &mut Command) -> std::io::Result<()> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let status = command.status().await?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!(
+            "{program} exited with status {status}"
+        )));
+    }
+    Ok(())
 }
 
 async fn format_ext4(img_path: &Path) -> std::io::Result<()> {
@@ -179,9 +596,11 @@ async fn format_ext4(img_path: &Path) -> std::io::Result<()> {
     Ok(())
 }
 
-async fn mount_image(img_path: &Path, mount_point: &Path) -> std::io::Result<()> {
+/// Volume contents come from guests and imported archives, so device nodes
+/// and setuid bits on them are never honoured on the host.
+pub(crate) async fn mount_image(img_path: &Path, mount_point: &Path) -> std::io::Result<()> {
     let status = Command::new("mount")
-        .args(["-o", "loop"])
+        .args(["-o", "loop,nodev,nosuid"])
         .arg(img_path)
         .arg(mount_point)
         .status()
@@ -195,7 +614,7 @@ async fn mount_image(img_path: &Path, mount_point: &Path) -> std::io::Result<()>
     Ok(())
 }
 
-async fn unmount_image(mount_point: &Path) -> std::io::Result<()> {
+pub(crate) async fn unmount_image(mount_point: &Path) -> std::io::Result<()> {
     let status = Command::new("umount").arg(mount_point).status().await?;
 
     if !status.success() {
@@ -206,75 +625,12 @@ async fn unmount_image(mount_point: &Path) -> std::io::Result<()> {
     Ok(())
 }
 
-#[derive(Debug, Serialize)]
-pub struct VolumeFileEntry {
-    pub name: String,
-    pub is_dir: bool,
-    pub size_bytes: u64,
-    pub modified_secs: u64,
-}
-
-pub async fn list_volume_files_handler(AxumPath(id): AxumPath<String>) -> impl IntoResponse {
-    let config = Config::load().expect("Failed to load configuration");
-    let volume_data_dir = &config.storage.volume_data_dir;
-
-    let volume_info = match get_volume_by_id(volume_data_dir, &id) {
-        Ok(Some(v)) => v,
-        Ok(None) => return (StatusCode::NOT_FOUND, "Volume not found").into_response(),
-        Err(e) => {
-            error!("Error retrieving volume info for {id}: {e}");
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Error retrieving volume info: {e}"),
-            )
-                .into_response();
-        }
-    };
-
-    let mount_path = Path::new(&volume_info.mount_path);
-    match read_volume_files(mount_path) {
-        Ok(files) => (StatusCode::OK, Json(files)).into_response(),
-        Err(e) => {
-            error!("Failed to read volume directory {mount_path:?}: {e}");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to read volume: {e}"),
-            )
-                .into_response()
-        }
-    }
-}
-
-fn read_volume_files(mount_path: &Path) -> std::io::Result<Vec<VolumeFileEntry>> {
-    let mut files = Vec::new();
-    for entry in std::fs::read_dir(mount_path)? {
-        let entry = entry?;
-        let metadata = match entry.metadata() {
-            Ok(m) => m,
-            Err(e) => {
-                warn!("Could not read metadata for {:?}: {e}", entry.path());
-                continue;
-            }
-        };
-        let modified_secs = metadata
-            .modified()
-            .ok()
-            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-        files.push(VolumeFileEntry {
-            name: entry.file_name().to_string_lossy().into_owned(),
-            is_dir: metadata.is_dir(),
-            size_bytes: metadata.len(),
-            modified_secs,
-        });
-    }
-    Ok(files)
-}
-
-fn error_response(message: String) -> (StatusCode, Json<LaunchVolumeResponse>) {
+pub(crate) fn error_response(
+    status: StatusCode,
+    message: String,
+) -> (StatusCode, Json<LaunchVolumeResponse>) {
     (
-        StatusCode::INTERNAL_SERVER_ERROR,
+        status,
         Json(LaunchVolumeResponse {
             success: false,
             message,
@@ -288,78 +644,142 @@ fn error_response(message: String) -> (StatusCode, Json<LaunchVolumeResponse>) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::volume_db::store_test_volume;
     use std::fs;
     use tempfile::TempDir;
 
-    #[test]
-    fn test_read_volume_files_empty_dir() {
+    #[tokio::test]
+    async fn test_mount_rejects_attached_and_mounted_volumes() {
         let dir = TempDir::new().unwrap();
-        let files = read_volume_files(dir.path()).unwrap();
-        assert!(files.is_empty());
+        store_test_volume(dir.path(), "vol-1", false, Some("vm-1"));
+        store_test_volume(dir.path(), "vol-2", true, None);
+
+        let response = mount_volume_response(dir.path(), "vol-1").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = mount_volume_response(dir.path(), "vol-2").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = mount_volume_response(dir.path(), "missing").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
-    #[test]
-    fn test_read_volume_files_returns_file_entry() {
+    #[tokio::test]
+    async fn test_unmount_rejects_unmounted_volume() {
         let dir = TempDir::new().unwrap();
-        fs::write(dir.path().join("hello.txt"), "world").unwrap();
+        store_test_volume(dir.path(), "vol-1", false, None);
 
-        let files = read_volume_files(dir.path()).unwrap();
+        let response = unmount_volume_response(dir.path(), "vol-1").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = unmount_volume_response(dir.path(), "missing").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 
-        assert_eq!(files.len(), 1);
-        assert_eq!(files[0].name, "hello.txt");
-        assert!(!files[0].is_dir);
-        assert_eq!(files[0].size_bytes, 5);
-        assert!(files[0].modified_secs > 0);
+    /// Store a volume whose image is `size_gb` GiB of sparse file.
+    fn store_sized_volume(dir: &Path, id: &str, size_gb: u64, attached_to: Option<&str>) {
+        store_test_volume(dir, id, false, attached_to);
+        let file = fs::File::create(volume_image_path(dir, id)).unwrap();
+        file.set_len(size_gb * GIB).unwrap();
     }
 
-    #[test]
-    fn test_read_volume_files_returns_dir_entry() {
+    #[tokio::test]
+    async fn test_resize_rejects_attached_volume() {
         let dir = TempDir::new().unwrap();
-        fs::create_dir(dir.path().join("subdir")).unwrap();
+        store_sized_volume(dir.path(), "vol-1", 1, Some("vm-1"));
 
-        let files = read_volume_files(dir.path()).unwrap();
+        let response = resize_volume_response(dir.path(), "vol-1", 2).await;
 
-        assert_eq!(files.len(), 1);
-        assert_eq!(files[0].name, "subdir");
-        assert!(files[0].is_dir);
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let len = fs::metadata(volume_image_path(dir.path(), "vol-1"))
+            .unwrap()
+            .len();
+        assert_eq!(len, GIB);
+    }
+
+    #[tokio::test]
+    async fn test_resize_requires_larger_size() {
+        let dir = TempDir::new().unwrap();
+        store_sized_volume(dir.path(), "vol-1", 2, None);
+
+        for size_gb in [1, 2] {
+            let response = resize_volume_response(dir.path(), "vol-1", size_gb).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{size_gb}");
+        }
+        let response = resize_volume_response(dir.path(), "missing", 4).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_resize_restores_image_when_filesystem_cannot_grow() {
+        let dir = TempDir::new().unwrap();
+        // A sparse file without a filesystem, which e2fsck rejects.
+        store_sized_volume(dir.path(), "vol-1", 1, None);
+
+        let response = resize_volume_response(dir.path(), "vol-1", 2).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let len = fs::metadata(volume_image_path(dir.path(), "vol-1"))
+            .unwrap()
+            .len();
+        assert_eq!(len, GIB);
+        let stored = get_volume_by_id(dir.path(), "vol-1").unwrap().unwrap();
+        assert_eq!(stored.size_gb, 1);
     }
 
     #[test]
-    fn test_read_volume_files_mixed_entries() {
+    fn test_volume_usage_of_sparse_unmounted_image() {
         let dir = TempDir::new().unwrap();
-        fs::write(dir.path().join("file.txt"), "data").unwrap();
-        fs::create_dir(dir.path().join("subdir")).unwrap();
+        store_sized_volume(dir.path(), "vol-1", 1, None);
+        let volume = get_volume_by_id(dir.path(), "vol-1").unwrap().unwrap();
 
-        let mut files = read_volume_files(dir.path()).unwrap();
-        files.sort_by(|a, b| a.name.cmp(&b.name));
+        let usage = volume_usage(dir.path(), &volume);
 
-        assert_eq!(files.len(), 2);
-        assert_eq!(files[0].name, "file.txt");
-        assert!(!files[0].is_dir);
-        assert_eq!(files[1].name, "subdir");
-        assert!(files[1].is_dir);
+        assert_eq!(usage.size_bytes, GIB);
+        assert!(usage.allocated_bytes < GIB);
+        assert_eq!(usage.fs_used_bytes, None);
+        assert_eq!(usage.fs_free_bytes, None);
     }
 
     #[test]
-    fn test_read_volume_files_nonexistent_path() {
+    fn test_volume_usage_ignores_mount_dir_without_filesystem() {
         let dir = TempDir::new().unwrap();
-        let nonexistent = dir.path().join("does-not-exist");
-        let result = read_volume_files(&nonexistent);
-        assert!(result.is_err());
+        store_test_volume(dir.path(), "vol-1", true, None);
+        fs::create_dir_all(dir.path().join("volumes").join("vol-1")).unwrap();
+        let volume = get_volume_by_id(dir.path(), "vol-1").unwrap().unwrap();
+
+        // Neither the image nor a mount exist: the listing still works.
+        let usage = volume_usage(dir.path(), &volume);
+
+        assert_eq!(usage, VolumeUsage::default());
     }
 
     #[test]
-    fn test_read_volume_files_is_not_recursive() {
+    fn test_filesystem_usage() {
         let dir = TempDir::new().unwrap();
-        let subdir = dir.path().join("subdir");
-        fs::create_dir(&subdir).unwrap();
-        fs::write(subdir.join("nested.txt"), "hidden").unwrap();
+        let (used, free) = filesystem_usage(dir.path()).unwrap();
+        assert!(used + free > 0);
+    }
 
-        let files = read_volume_files(dir.path()).unwrap();
+    #[tokio::test]
+    async fn test_list_volumes_includes_usage() {
+        let dir = TempDir::new().unwrap();
+        store_sized_volume(dir.path(), "vol-1", 1, None);
+
+        let response = list_volumes_response(dir.path());
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(entries[0]["id"], "vol-1");
+        assert_eq!(entries[0]["size_gb"], 1);
+        assert_eq!(entries[0]["size_bytes"], GIB);
+        assert!(entries[0]["allocated_bytes"].is_u64());
+        assert!(entries[0].get("fs_used_bytes").is_none());
+    }
 
-        // Only the subdir itself should appear, not nested.txt
-        assert_eq!(files.len(), 1);
-        assert_eq!(files[0].name, "subdir");
-        assert!(files[0].is_dir);
+    #[test]
+    fn test_first_loop_device() {
+        assert_eq!(first_loop_device("/dev/loop3\n"), Some("/dev/loop3"));
+        assert_eq!(first_loop_device("\n"), None);
     }
 }
@@ -0,0 +1,551 @@
+use crate::config::Config;
+use crate::guest_agent::{guest_agent_socket_path, while_frozen};
+use crate::image_service::now_secs;
+use crate::qemu::GIB;
+use crate::vm_exec::agent_error;
+use crate::vm_snapshot::{check_snapshot_name, load_vm};
+use crate::vm_state::VmState;
+use crate::volume_attach::{load_volume, ATTACH_LOCK};
+use crate::volume_db::{
+    store_volume_info, update_volume, volume_snapshot_path, VolumeInfo, VolumeSnapshot,
+};
+use crate::volume_service::{
+    error_response, mount_image, run_tool, unmount_image, volume_image_path, LaunchVolumeResponse,
+};
+use axum::{
+    extract::Path as AxumPath,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use tokio::fs;
+use tokio::process::Command;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VolumeSnapshotRequest {
+    pub volume_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CloneVolumeRequest {
+    pub volume_id: String,
+    /// Snapshot of `volume_id` the new volume starts from.
+    pub snapshot: String,
+    /// Name of the new volume.
+    pub name: String,
+}
+
+pub async fn create_volume_snapshot_handler(
+    Json(payload): Json<VolumeSnapshotRequest>,
+) -> Response {
+    info!(
+        "Creating snapshot {} of volume {}",
+        payload.name, payload.volume_id
+    );
+    let config = Config::load().expect("Failed to load configuration");
+    create_volume_snapshot_response(
+        &config.storage.volume_data_dir,
+        &config.storage.metadata_dir,
+        &payload.volume_id,
+        &payload.name,
+    )
+    .await
+}
+
+/// Copy a volume's image while nothing writes to it. A volume mounted on the
+/// host is frozen with `fsfreeze`; one attached to a running VM through the
+/// VM's guest agent. The copy is reflinked where the host filesystem
+/// supports it, and sparse either way.
+async fn create_volume_snapshot_response(
+    volume_data_dir: &Path,
+    metadata_dir: &Path,
+    volume_id: &str,
+    name: &str,
+) -> Response {
+    if let Err(error) = check_snapshot_name(name) {
+        return error.into_response();
+    }
+    // Keeps the volume from being attached, detached or resized mid-copy.
+    let _guard = ATTACH_LOCK.lock().await;
+    let volume_info = match load_volume(volume_data_dir, volume_id) {
+        Ok(volume_info) => volume_info,
+        Err(error) => return error.into_response(),
+    };
+    if volume_info.snapshots.iter().any(|s| s.name == name) {
+        return (
+            StatusCode::CONFLICT,
+            format!("Volume {volume_id} already has a snapshot named {name}"),
+        )
+            .into_response();
+    }
+
+    let source = volume_image_path(volume_data_dir, volume_id);
+    let target = volume_snapshot_path(volume_data_dir, volume_id, name);
+    if let Some(parent) = target.parent() {
+        if let Err(e) = fs::create_dir_all(parent).await {
+            error!("Failed to create snapshot directory {parent:?}: {e}");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create snapshot directory: {e}"),
+            )
+                .into_response();
+        }
+    }
+
+    let copied = match &volume_info.attached_to {
+        Some(vm_id) => copy_attached(metadata_dir, vm_id, &source, &target).await,
+        None if volume_info.mounted => {
+            let mount_path = Path::new(&volume_info.mount_path);
+            while_host_frozen(mount_path, copy_sparse(&source, &target))
+                .await
+                .and_then(|copied| copied)
+                .map_err(copy_error)
+        }
+        None => copy_sparse(&source, &target).await.map_err(copy_error),
+    };
+    if let Err(error) = copied {
+        let _ = fs::remove_file(&target).await;
+        return error.into_response();
+    }
+
+    let metadata = match fs::metadata(&target).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            let _ = fs::remove_file(&target).await;
+            return copy_error(e).into_response();
+        }
+    };
+    let snapshot = VolumeSnapshot {
+        name: name.to_string(),
+        created_secs: now_secs(),
+        size_bytes: metadata.len(),
+        allocated_bytes: metadata.blocks() * 512,
+    };
+    let stored = snapshot.clone();
+    match update_volume(volume_data_dir, volume_id, |v| v.snapshots.push(stored)) {
+        Ok(Some(_)) => Json(snapshot).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Volume not found").into_response(),
+        Err(e) => {
+            error!("Failed to store volume metadata for {volume_id}: {e}");
+            let _ = fs::remove_file(&target).await;
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to store volume metadata: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Copy the image of a volume attached to `vm_id`: frozen through the guest
+/// agent while the VM runs, as it is while the VM is stopped.
+async fn copy_attached(
+    metadata_dir: &Path,
+    vm_id: &str,
+    source: &Path,
+    target: &Path,
+) -> Result<(), (StatusCode, String)> {
+    let vm_info = load_vm(metadata_dir, vm_id)?;
+    match vm_info.state {
+        VmState::Stopped | VmState::Crashed => {
+            copy_sparse(source, target).await.map_err(copy_error)
+        }
+        VmState::Running => {
+            let socket = guest_agent_socket_path(metadata_dir, vm_id);
+            while_frozen(&socket, copy_sparse(source, target))
+                .await
+                .map_err(|e| agent_error(vm_id, e))?
+                .map_err(copy_error)
+        }
+        state => Err((
+            StatusCode::CONFLICT,
+            format!("Cannot snapshot a volume while its VM is {state}"),
+        )),
+    }
+}
+
+/// Run `work` while the filesystem mounted at `mount_path` is frozen. It is
+/// thawed however `work` ends.
+async fn while_host_frozen<T>(
+    mount_path: &Path,
+    work: impl Future<Output = T>,
+) -> std::io::Result<T> {
+    run_tool(Command::new("fsfreeze").arg("--freeze").arg(mount_path)).await?;
+    let result = work.await;
+    if let Err(e) = run_tool(Command::new("fsfreeze").arg("--unfreeze").arg(mount_path)).await {
+        error!("Failed to thaw {mount_path:?}; writes to it will hang: {e}");
+    }
+    Ok(result)
+}
+
+/// Copy an image, sharing blocks with the source where the filesystem can
+/// reflink and skipping holes either way.
+async fn copy_sparse(source: &Path, target: &Path) -> std::io::Result<()> {
+    run_tool(
+        Command::new("cp")
+            .args(["--reflink=auto", "--sparse=always"])
+            .arg(source)
+            .arg(target),
+    )
+    .await
+}
+
+fn copy_error(e: std::io::Error) -> (StatusCode, String) {
+    error!("Failed to copy volume image: {e}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to copy volume image: {e}"),
+    )
+}
+
+pub async fn list_volume_snapshots_handler(AxumPath(id): AxumPath<String>) -> Response {
+    let config = Config::load().expect("Failed to load configuration");
+    match load_volume(&config.storage.volume_data_dir, &id) {
+        Ok(volume_info) => Json(volume_info.snapshots).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
+
+pub async fn delete_volume_snapshot_handler(
+    Json(payload): Json<VolumeSnapshotRequest>,
+) -> Response {
+    info!(
+        "Deleting snapshot {} of volume {}",
+        payload.name, payload.volume_id
+    );
+    let config = Config::load().expect("Failed to load configuration");
+    delete_volume_snapshot_response(
+        &config.storage.volume_data_dir,
+        &payload.volume_id,
+        &payload.name,
+    )
+    .await
+}
+
+/// Remove a snapshot's copy. The volume and its other snapshots are
+/// independent copies, so nothing else changes.
+async fn delete_volume_snapshot_response(
+    volume_data_dir: &Path,
+    volume_id: &str,
+    name: &str,
+) -> Response {
+    let _guard = ATTACH_LOCK.lock().await;
+    let volume_info = match load_volume(volume_data_dir, volume_id) {
+        Ok(volume_info) => volume_info,
+        Err(error) => return error.into_response(),
+    };
+    if !volume_info.snapshots.iter().any(|s| s.name == name) {
+        return (StatusCode::NOT_FOUND, "Snapshot not found").into_response();
+    }
+
+    let path = volume_snapshot_path(volume_data_dir, volume_id, name);
+    if let Err(e) = fs::remove_file(&path).await {
+        warn!("Could not delete snapshot file {path:?}: {e}");
+    }
+    match update_volume(volume_data_dir, volume_id, |v| {
+        v.snapshots.retain(|s| s.name != name)
+    }) {
+        Ok(_) => (StatusCode::OK, format!("Snapshot {name} deleted")).into_response(),
+        Err(e) => {
+            error!("Failed to store volume metadata for {volume_id}: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to store volume metadata: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn clone_volume_handler(
+    Json(payload): Json<CloneVolumeRequest>,
+) -> (StatusCode, Json<LaunchVolumeResponse>) {
+    info!(
+        "Cloning volume {} from snapshot {} of volume {}",
+        payload.name, payload.snapshot, payload.volume_id
+    );
+    let config = Config::load().expect("Failed to load configuration");
+    clone_volume_response(&config.storage.volume_data_dir, payload).await
+}
+
+/// Create a new volume, on this backend, from a copy of a snapshot. It is
+/// mounted like a freshly launched volume.
+async fn clone_volume_response(
+    volume_data_dir: &Path,
+    payload: CloneVolumeRequest,
+) -> (StatusCode, Json<LaunchVolumeResponse>) {
+    let (id, img_path) = {
+        // Keeps the snapshot from being deleted mid-copy.
+        let _guard = ATTACH_LOCK.lock().await;
+        let volume_info = match load_volume(volume_data_dir, &payload.volume_id) {
+            Ok(volume_info) => volume_info,
+            Err((status, message)) => return error_response(status, message),
+        };
+        if !volume_info
+            .snapshots
+            .iter()
+            .any(|s| s.name == payload.snapshot)
+        {
+            return error_response(StatusCode::NOT_FOUND, "Snapshot not found".to_string());
+        }
+        let source = volume_snapshot_path(volume_data_dir, &payload.volume_id, &payload.snapshot);
+
+        let id = Uuid::new_v4().to_string();
+        let img_path = volume_image_path(volume_data_dir, &id);
+        if let Err(e) = copy_sparse(&source, &img_path).await {
+            let _ = fs::remove_file(&img_path).await;
+            let (status, message) = copy_error(e);
+            return error_response(status, message);
+        }
+        (id, img_path)
+    };
+
+    let mount_path = volume_data_dir.join("volumes").join(&id);
+    if let Err(e) = mount_clone(&img_path, &mount_path).await {
+        error!("Failed to mount image {img_path:?} at {mount_path:?}: {e}");
+        let _ = fs::remove_file(&img_path).await;
+        let _ = fs::remove_dir(&mount_path).await;
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to mount volume: {e}"),
+        );
+    }
+
+    let size_bytes = fs::metadata(&img_path).await.map(|m| m.len()).unwrap_or(0);
+    let mount_path_str = mount_path.to_string_lossy().to_string();
+    let volume_info = VolumeInfo {
+        id: id.clone(),
+        name: payload.name.clone(),
+        size_gb: size_bytes.div_ceil(GIB),
+        mount_path: mount_path_str.clone(),
+        loop_device: None,
+        mounted: true,
+        attached_to: None,
+        snapshots: Vec::new(),
+    };
+    if let Err(e) = store_volume_info(volume_data_dir, &volume_info) {
+        error!("Failed to store volume metadata for {id}: {e}");
+        let _ = unmount_image(&mount_path).await;
+        let _ = fs::remove_file(&img_path).await;
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to store volume metadata: {e}"),
+        );
+    }
+
+    (
+        StatusCode::OK,
+        Json(LaunchVolumeResponse {
+            success: true,
+            message: format!(
+                "Volume {} created from snapshot {} and mounted",
+                payload.name, payload.snapshot
+            ),
+            id: Some(id),
+            name: Some(payload.name),
+            mount_path: Some(mount_path_str),
+        }),
+    )
+}
+
+async fn mount_clone(img_path: &Path, mount_path: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(mount_path).await?;
+    mount_image(img_path, mount_path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guest_agent::fake;
+    use crate::vm_db::store_test_vm;
+    use crate::vm_stop::test_process;
+    use crate::volume_db::{get_volume_by_id, store_test_volume};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use tempfile::TempDir;
+
+    const VOLUME_ID: &str = "vol-1";
+
+    struct Dirs {
+        volumes: TempDir,
+        meta: TempDir,
+    }
+
+    impl Dirs {
+        /// An unmounted volume whose image is the text "contents".
+        fn new(attached_to: Option<&str>) -> Self {
+            let dirs = Dirs {
+                volumes: TempDir::new().unwrap(),
+                meta: TempDir::new().unwrap(),
+            };
+            store_test_volume(dirs.volumes.path(), VOLUME_ID, false, attached_to);
+            std::fs::write(
+                volume_image_path(dirs.volumes.path(), VOLUME_ID),
+                "contents",
+            )
+            .unwrap();
+            dirs
+        }
+
+        fn store_vm(&self, state: VmState, pid: u32) {
+            store_test_vm(self.meta.path(), "vm-1", state, pid);
+        }
+
+        async fn snapshot(&self, name: &str) -> Response {
+            create_volume_snapshot_response(self.volumes.path(), self.meta.path(), VOLUME_ID, name)
+                .await
+        }
+
+        fn snapshots(&self) -> Vec<VolumeSnapshot> {
+            get_volume_by_id(self.volumes.path(), VOLUME_ID)
+                .unwrap()
+                .unwrap()
+                .snapshots
+        }
+
+        fn snapshot_file(&self, name: &str) -> std::path::PathBuf {
+            volume_snapshot_path(self.volumes.path(), VOLUME_ID, name)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_of_detached_volume_copies_image() {
+        let dirs = Dirs::new(None);
+
+        let resp = dirs.snapshot("before-migration").await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let snapshots = dirs.snapshots();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].name, "before-migration");
+        assert_eq!(snapshots[0].size_bytes, 8);
+        assert!(snapshots[0].created_secs > 0);
+        let copy = std::fs::read_to_string(dirs.snapshot_file("before-migration")).unwrap();
+        assert_eq!(copy, "contents");
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_rejects_bad_or_duplicate_names() {
+        let dirs = Dirs::new(None);
+
+        assert_eq!(
+            dirs.snapshot("../escape").await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(dirs.snapshot("s1").await.status(), StatusCode::OK);
+        assert_eq!(dirs.snapshot("s1").await.status(), StatusCode::CONFLICT);
+        assert_eq!(dirs.snapshots().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_of_unknown_volume_returns_404() {
+        let dirs = Dirs::new(None);
+        let resp =
+            create_volume_snapshot_response(dirs.volumes.path(), dirs.meta.path(), "missing", "s1")
+                .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_of_volume_on_paused_vm_returns_conflict() {
+        let dirs = Dirs::new(Some("vm-1"));
+        let pid = test_process::spawn(false);
+        dirs.store_vm(VmState::Paused, pid);
+
+        let resp = dirs.snapshot("s1").await;
+
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert!(!dirs.snapshot_file("s1").exists());
+        assert!(dirs.snapshots().is_empty());
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_of_volume_on_running_vm_freezes_guest() {
+        let dirs = Dirs::new(Some("vm-1"));
+        let pid = test_process::spawn(false);
+        dirs.store_vm(VmState::Running, pid);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let seen = received.clone();
+        fake::spawn(
+            &guest_agent_socket_path(dirs.meta.path(), "vm-1"),
+            move |command, _: &Value| {
+                seen.lock().unwrap().push(command.to_string());
+                json!({ "return": 1 })
+            },
+        );
+
+        let resp = dirs.snapshot("s1").await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            *received.lock().unwrap(),
+            ["guest-fsfreeze-freeze", "guest-fsfreeze-thaw"]
+        );
+        assert!(dirs.snapshot_file("s1").exists());
+        test_process::kill(pid);
+    }
+
+    #[tokio::test]
+    async fn test_delete_snapshot_removes_only_that_snapshot() {
+        let dirs = Dirs::new(None);
+        dirs.snapshot("s1").await;
+        dirs.snapshot("s2").await;
+
+        let resp = delete_volume_snapshot_response(dirs.volumes.path(), VOLUME_ID, "s1").await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!dirs.snapshot_file("s1").exists());
+        assert!(dirs.snapshot_file("s2").exists());
+        let names: Vec<String> = dirs.snapshots().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, ["s2"]);
+
+        let resp = delete_volume_snapshot_response(dirs.volumes.path(), VOLUME_ID, "s1").await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_clone_from_unknown_snapshot_returns_404() {
+        let dirs = Dirs::new(None);
+        let request = CloneVolumeRequest {
+            volume_id: VOLUME_ID.to_string(),
+            snapshot: "missing".to_string(),
+            name: "copy".to_string(),
+        };
+
+        let (status, Json(resp)) = clone_volume_response(dirs.volumes.path(), request).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(!resp.success);
+    }
+
+    #[tokio::test]
+    async fn test_clone_cleans_up_when_mount_fails() {
+        let dirs = Dirs::new(None);
+        dirs.snapshot("s1").await;
+        let request = CloneVolumeRequest {
+            volume_id: VOLUME_ID.to_string(),
+            snapshot: "s1".to_string(),
+            name: "copy".to_string(),
+        };
+
+        // The image holds no filesystem, so mounting the clone fails.
+        let (status, Json(resp)) = clone_volume_response(dirs.volumes.path(), request).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!resp.success);
+        let images: Vec<_> = std::fs::read_dir(dirs.volumes.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".img") || name.ends_with(".json"))
+            .collect();
+        assert_eq!(images.len(), 2, "{images:?}");
+    }
+}
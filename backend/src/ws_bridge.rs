@@ -0,0 +1,107 @@
+use axum::extract::ws::{Message, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+/// Relay between a WebSocket client and a VM's unix socket (serial console or
+/// VNC) until either side closes. Bytes from the socket are sent as binary
+/// messages; text and binary messages from the client are both written to the
+/// socket as raw bytes.
+pub async fn bridge(ws: WebSocket, stream: UnixStream) {
+    let (mut ws_tx, mut ws_rx) = ws.split();
+    let (mut reader, mut writer) = stream.into_split();
+
+    let to_socket = async {
+        while let Some(Ok(message)) = ws_rx.next().await {
+            let data = match message {
+                Message::Binary(data) => data,
+                Message::Text(text) => text.into_bytes(),
+                Message::Close(_) => break,
+                Message::Ping(_) | Message::Pong(_) => continue,
+            };
+            if writer.write_all(&data).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    let from_socket = async {
+        let mut buf = vec![0u8; 4096];
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if ws_tx
+                        .send(Message::Binary(buf[..n].to_vec()))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        }
+        let _ = ws_tx.send(Message::Close(None)).await;
+    };
+
+    tokio::select! {
+        _ = to_socket => {}
+        _ = from_socket => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::ws::WebSocketUpgrade;
+    use tokio::net::{TcpListener, UnixListener};
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    #[tokio::test]
+    async fn test_bridge_relays_both_ways() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let socket = dir.path().join("vm-1.console");
+
+        // Stand-in for QEMU: print a prompt, then echo back one line of input.
+        let qemu = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = qemu.accept().await.unwrap();
+            stream.write_all(b"login: ").await.unwrap();
+            let mut buf = [0u8; 64];
+            let n = stream.read(&mut buf).await.unwrap();
+            stream.write_all(&buf[..n]).await.unwrap();
+        });
+
+        let app = axum::Router::new().route(
+            "/console",
+            axum::routing::get(move |ws: WebSocketUpgrade| async move {
+                let console = UnixStream::connect(&socket).await.unwrap();
+                ws.on_upgrade(move |socket| bridge(socket, console))
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
+
+        let (mut client, _) =
+            tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{port}/console"))
+                .await
+                .unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            WsMessage::Binary(b"login: ".to_vec())
+        );
+
+        client.send(WsMessage::Text("root\n".into())).await.unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            WsMessage::Binary(b"root\n".to_vec())
+        );
+
+        // The stand-in hangs up after echoing, which closes the session.
+        assert!(matches!(
+            client.next().await,
+            Some(Ok(WsMessage::Close(_))) | None
+        ));
+    }
+}
@@ -33,6 +33,154 @@ impl Client {
             .map_err(|e| format!("Failed to parse response: {e}"))
     }
 
+    /// GET an endpoint that answers with a binary body, e.g. a PNG.
+    pub async fn get_bytes(&self, path: &str) -> Result<Vec<u8>, String> {
+        let url = format!("{}{}", self.base_url, path);
+        let resp = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(format!("Server returned {status}: {body}"));
+        }
+
+        resp.bytes()
+            .await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| format!("Failed to read response: {e}"))
+    }
+
+    /// GET a JSON body from an endpoint that takes query parameters, which
+    /// are URL-encoded here.
+    pub async fn get_with_query<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, String> {
+        let url = format!("{}{}", self.base_url, path);
+        let resp = self
+            .http
+            .get(&url)
+            .query(query)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(format!("Server returned {status}: {body}"));
+        }
+
+        resp.json::<T>()
+            .await
+            .map_err(|e| format!("Failed to parse response: {e}"))
+    }
+
+    /// GET a binary body into a local file as it arrives, so large files
+    /// never sit in memory. Returns the number of bytes written.
+    pub async fn download(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        destination: &std::path::Path,
+    ) -> Result<u64, String> {
+        use tokio::io::AsyncWriteExt;
+
+        let url = format!("{}{}", self.base_url, path);
+        let mut resp = self
+            .http
+            .get(&url)
+            .query(query)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(format!("Server returned {status}: {body}"));
+        }
+
+        let write_error = |e| format!("Failed to write {}: {e}", destination.display());
+        let mut file = tokio::fs::File::create(destination)
+            .await
+            .map_err(write_error)?;
+        let mut written = 0;
+        while let Some(chunk) = resp
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to read response: {e}"))?
+        {
+            file.write_all(&chunk).await.map_err(write_error)?;
+            written += chunk.len() as u64;
+        }
+        file.flush().await.map_err(write_error)?;
+        Ok(written)
+    }
+
+    /// PUT a local file to an endpoint that takes query parameters. The file
+    /// is streamed rather than read into memory first.
+    pub async fn put_file<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        source: &std::path::Path,
+    ) -> Result<T, String> {
+        self.send_file(reqwest::Method::PUT, path, query, source)
+            .await
+    }
+
+    /// POST a local file, streamed like `put_file`.
+    pub async fn post_file<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        source: &std::path::Path,
+    ) -> Result<T, String> {
+        self.send_file(reqwest::Method::POST, path, query, source)
+            .await
+    }
+
+    async fn send_file<T: DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, &str)],
+        source: &std::path::Path,
+    ) -> Result<T, String> {
+        let read_error = |e| format!("Failed to read {}: {e}", source.display());
+        let file = tokio::fs::File::open(source).await.map_err(read_error)?;
+        let len = file.metadata().await.map_err(read_error)?.len();
+
+        let url = format!("{}{}", self.base_url, path);
+        let resp = self
+            .http
+            .request(method, &url)
+            .query(query)
+            .header("content-type", "application/octet-stream")
+            .header("content-length", len)
+            .body(file)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(format!("Server returned {status}: {body}"));
+        }
+
+        resp.json::<T>()
+            .await
+            .map_err(|e| format!("Failed to parse response: {e}"))
+    }
+
     pub async fn post<B: Serialize, T: DeserializeOwned>(
         &self,
         path: &str,
@@ -58,16 +206,56 @@ impl Client {
             .map_err(|e| format!("Failed to parse response: {e}"))
     }
 
-    pub async fn delete<B: Serialize>(
+    /// POST to an endpoint that answers with a plain-text message.
+    pub async fn post_text<B: Serialize>(&self, path: &str, body: &B) -> Result<String, String> {
+        let url = format!("{}{}", self.base_url, path);
+        let resp = self
+            .http
+            .post(&url)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(format!("Server returned {status}: {text}"));
+        }
+
+        Ok(text)
+    }
+
+    pub async fn delete<B: Serialize>(&self, path: &str, body: &B) -> Result<String, String> {
+        let url = format!("{}{}", self.base_url, path);
+        let resp = self
+            .http
+            .delete(&url)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(format!("Server returned {status}: {text}"));
+        }
+
+        Ok(text)
+    }
+
+    /// DELETE an endpoint that names its target in query parameters.
+    pub async fn delete_with_query(
         &self,
         path: &str,
-        body: &B,
+        query: &[(&str, &str)],
     ) -> Result<String, String> {
         let url = format!("{}{}", self.base_url, path);
         let resp = self
             .http
             .delete(&url)
-            .json(body)
+            .query(query)
             .send()
             .await
             .map_err(|e| format!("Request failed: {e}"))?;
@@ -0,0 +1,227 @@
+use crate::client::Client;
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+#[derive(Subcommand)]
+pub enum ImageCommand {
+    /// Register a qcow2 base image in the catalog
+    Register {
+        /// Image name
+        #[arg(long)]
+        name: String,
+        /// Operating system (e.g. alpine, debian)
+        #[arg(long)]
+        os: String,
+        /// OS version (e.g. 3.19)
+        #[arg(long)]
+        version: String,
+        /// Path of the qcow2 file on the worker node, inside its import directory
+        #[arg(
+            long,
+            conflicts_with = "source_url",
+            required_unless_present = "source_url"
+        )]
+        source_path: Option<String>,
+        /// URL the worker downloads the qcow2 file from
+        #[arg(long)]
+        source_url: Option<String>,
+        /// Expected SHA-256 of the image file
+        #[arg(long)]
+        checksum: Option<String>,
+        /// What the image contains
+        #[arg(long)]
+        description: Option<String>,
+    },
+    /// Create an image from a VM's disk; a running VM's filesystems are
+    /// frozen for the copy through its guest agent
+    Create {
+        /// VM ID
+        #[arg(long)]
+        vm_id: String,
+        /// Image name
+        #[arg(long)]
+        name: String,
+        /// What the image contains
+        #[arg(long)]
+        description: Option<String>,
+        /// Operating system; defaults to that of the VM's image
+        #[arg(long)]
+        os: Option<String>,
+        /// OS version; defaults to that of the VM's image
+        #[arg(long)]
+        version: Option<String>,
+    },
+    /// List all images
+    List,
+    /// Delete an image
+    Delete {
+        /// Image ID
+        #[arg(long)]
+        id: String,
+    },
+}
+
+#[derive(Serialize)]
+struct RegisterImageRequest {
+    name: String,
+    os: String,
+    version: String,
+    source_path: Option<String>,
+    source_url: Option<String>,
+    checksum: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CreateImageRequest {
+    vm_id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RegisterImageResponse {
+    success: bool,
+    message: String,
+    id: Option<String>,
+    checksum: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ImageInfo {
+    id: String,
+    name: String,
+    os: String,
+    version: String,
+    #[serde(default)]
+    description: String,
+    checksum: String,
+    size_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct DeleteImageRequest {
+    id: String,
+}
+
+pub async fn run(cmd: ImageCommand, client: &Client, json: bool) -> Result<(), String> {
+    match cmd {
+        ImageCommand::Register {
+            name,
+            os,
+            version,
+            source_path,
+            source_url,
+            checksum,
+            description,
+        } => {
+            let resp: RegisterImageResponse = client
+                .post(
+                    "/register-image",
+                    &RegisterImageRequest {
+                        name,
+                        os,
+                        version,
+                        source_path,
+                        source_url,
+                        checksum,
+                        description,
+                    },
+                )
+                .await?;
+            print_registered(&resp, json, "Registered image")?;
+        }
+
+        ImageCommand::Create {
+            vm_id,
+            name,
+            description,
+            os,
+            version,
+        } => {
+            let resp: RegisterImageResponse = client
+                .post(
+                    "/create-image",
+                    &CreateImageRequest {
+                        vm_id,
+                        name,
+                        description,
+                        os,
+                        version,
+                    },
+                )
+                .await?;
+            print_registered(&resp, json, "Created image")?;
+        }
+
+        ImageCommand::List => {
+            let images: Vec<ImageInfo> = client.get("/list-images").await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&images).unwrap());
+            } else if images.is_empty() {
+                println!("No images.");
+            } else {
+                println!(
+                    "{:<38} {:<20} {:<10} {:<10} SIZE",
+                    "ID", "NAME", "OS", "VERSION"
+                );
+                println!("{}", "-".repeat(90));
+                for i in &images {
+                    println!(
+                        "{:<38} {:<20} {:<10} {:<10} {}",
+                        i.id, i.name, i.os, i.version, i.size_bytes
+                    );
+                    if !i.description.is_empty() {
+                        println!("  {}", i.description);
+                    }
+                }
+            }
+        }
+
+        ImageCommand::Delete { id } => {
+            let msg = client
+                .delete("/delete-image", &DeleteImageRequest { id })
+                .await?;
+            if json {
+                println!("{}", serde_json::json!({ "message": msg }));
+            } else {
+                println!("{msg}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_registered(resp: &RegisterImageResponse, json: bool, heading: &str) -> Result<(), String> {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "success": resp.success,
+                "message": resp.message,
+                "id": resp.id,
+                "checksum": resp.checksum,
+            }))
+            .unwrap()
+        );
+    } else if resp.success {
+        println!("{heading}");
+        if let Some(id) = &resp.id {
+            println!("  ID:       {id}");
+        }
+        if let Some(checksum) = &resp.checksum {
+            println!("  SHA-256:  {checksum}");
+        }
+    } else {
+        return Err(resp.message.clone());
+    }
+    Ok(())
+}
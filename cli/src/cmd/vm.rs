@@ -1,6 +1,7 @@
 use crate::client::Client;
 use clap::Subcommand;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Subcommand)]
 pub enum VmCommand {
@@ -15,9 +16,126 @@ pub enum VmCommand {
         /// Region
         #[arg(long, default_value = "us-east-1")]
         region: String,
+        /// Base image ID (see `image list`); defaults to the worker's alpine.qcow2
+        #[arg(long)]
+        image_id: Option<String>,
+        /// File containing cloud-init user-data for the guest
+        #[arg(long)]
+        user_data_file: Option<std::path::PathBuf>,
+        /// Guest hostname set via cloud-init
+        #[arg(long)]
+        hostname: Option<String>,
+        /// SSH public key to install in the guest; may be repeated
+        #[arg(long = "ssh-key")]
+        ssh_keys: Vec<String>,
+        /// Do not start the VM again when its backend restarts
+        #[arg(long)]
+        no_autostart: bool,
+        /// Export the VM's display over VNC
+        #[arg(long)]
+        graphics: bool,
+        /// Tag as KEY=VALUE, readable by the guest from the metadata service;
+        /// may be repeated
+        #[arg(long = "tag", value_parser = parse_tag)]
+        tags: Vec<(String, String)>,
+        /// Disk size in GiB (default: the instance type's disk size)
+        #[arg(long)]
+        disk_size_gb: Option<u64>,
     },
     /// List all VMs
     List,
+    /// List the instance types accepted by `vm launch`
+    InstanceTypes,
+    /// Stop a VM, forcing it off if the guest does not shut down in time
+    Stop {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// Seconds to wait for the guest to shut down (backend default: 30)
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
+    /// Reboot a VM, hard-resetting it if the guest does not shut down in time
+    Reboot {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// Seconds to wait for the guest to shut down (backend default: 30)
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
+    /// Pause a running VM's vCPUs
+    Pause {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+    },
+    /// Resume a paused VM
+    Resume {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+    },
+    /// Print a VM's serial console output
+    Logs {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// Only print the last N lines
+        #[arg(long)]
+        tail: Option<usize>,
+        /// Keep printing new output as it arrives
+        #[arg(long)]
+        follow: bool,
+    },
+    /// Save a PNG screenshot of a VM's display
+    Screenshot {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// File to write the PNG to
+        #[arg(long, default_value = "screenshot.png")]
+        output: std::path::PathBuf,
+    },
+    /// Run a command in a VM through its guest agent and print its output;
+    /// exits with the command's exit code
+    Exec {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// Seconds to wait for the command to exit (backend default: 30)
+        #[arg(long)]
+        timeout: Option<u64>,
+        /// Pass this command's stdin to the command in the VM
+        #[arg(long)]
+        stdin: bool,
+        /// Program and arguments, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Copy a file into or out of a VM through its guest agent; name the
+    /// VM side as VM_ID:/absolute/path
+    Cp {
+        /// Source: a local file or VM_ID:/path
+        source: String,
+        /// Destination: a local file or VM_ID:/path
+        destination: String,
+    },
+    /// Grow a VM's disk, live if it is running; the guest must then grow
+    /// its partition and filesystem
+    ResizeDisk {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// New disk size in GiB
+        #[arg(long)]
+        size_gb: u64,
+    },
+    /// Manage a VM's disk snapshots
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotCommand,
+    },
     /// Delete a VM
     Delete {
         /// VM ID
@@ -26,11 +144,89 @@ pub enum VmCommand {
     },
 }
 
+#[derive(Subcommand)]
+pub enum SnapshotCommand {
+    /// Snapshot a VM's disk; running VMs are snapshotted live
+    Create {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// Snapshot name
+        #[arg(long)]
+        name: String,
+    },
+    /// List a VM's snapshots
+    List {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+    },
+    /// Revert a stopped VM's disk to a snapshot, discarding later changes
+    Revert {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// Snapshot name
+        #[arg(long)]
+        name: String,
+    },
+    /// Delete a stopped VM's snapshot
+    Delete {
+        /// VM ID
+        #[arg(long)]
+        id: String,
+        /// Snapshot name
+        #[arg(long)]
+        name: String,
+    },
+}
+
 #[derive(Serialize)]
 struct LaunchVmRequest {
     name: String,
     instance_type: String,
     region: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ssh_authorized_keys: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    autostart: Option<bool>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    graphics: bool,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disk_size_gb: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct ExecRequest {
+    command: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ExecOutput {
+    exit_code: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signal: Option<i64>,
+    stdout: String,
+    stderr: String,
+    #[serde(default)]
+    truncated: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct WriteFileResponse {
+    pub(crate) bytes_written: u64,
 }
 
 #[derive(Deserialize)]
@@ -50,6 +246,94 @@ struct VmListEntry {
     ssh_host: String,
     ssh_port: u16,
     pid: u32,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    state_reason: String,
+    #[serde(default)]
+    interfaces: Vec<GuestInterface>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct GuestInterface {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mac_address: Option<String>,
+    #[serde(default)]
+    ipv4: Vec<String>,
+    #[serde(default)]
+    ipv6: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct InstanceType {
+    name: String,
+    vcpus: u32,
+    memory_mb: u32,
+    disk_gb: u64,
+}
+
+#[derive(Serialize)]
+struct StopVmRequest {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct StopVmResponse {
+    message: String,
+    stopped_by: String,
+}
+
+#[derive(Serialize)]
+struct RebootVmRequest {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct RebootVmResponse {
+    message: String,
+    rebooted_by: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ConsoleLogChunk {
+    offset: u64,
+    next_offset: u64,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct ResizeDiskRequest {
+    id: String,
+    size_gb: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ResizeDiskResponse {
+    message: String,
+    size_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct SnapshotRequest {
+    vm_id: String,
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct VmSnapshot {
+    name: String,
+    created_secs: u64,
+    kind: String,
+}
+
+#[derive(Serialize)]
+struct VmIdRequest {
+    id: String,
 }
 
 #[derive(Serialize)]
@@ -57,13 +341,57 @@ struct DeleteVmRequest {
     id: String,
 }
 
+/// Split `VM_ID:/path` into the VM ID and guest path. Local paths, which
+/// have no colon before their first slash, give `None`.
+fn parse_vm_path(arg: &str) -> Option<(&str, &str)> {
+    let (id, path) = arg.split_once(':')?;
+    (!id.is_empty() && !id.contains('/')).then_some((id, path))
+}
+
+/// How long ago `secs` (Unix time) was, e.g. "3h ago".
+pub(crate) fn format_age(secs: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let age = now.saturating_sub(secs);
+    match age {
+        0..=59 => format!("{age}s ago"),
+        60..=3599 => format!("{}m ago", age / 60),
+        3600..=86399 => format!("{}h ago", age / 3600),
+        _ => format!("{}d ago", age / 86400),
+    }
+}
+
+fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    match tag.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {tag:?}")),
+    }
+}
+
 pub async fn run(cmd: VmCommand, client: &Client, json: bool) -> Result<(), String> {
     match cmd {
         VmCommand::Launch {
             name,
             instance_type,
             region,
+            image_id,
+            user_data_file,
+            hostname,
+            ssh_keys,
+            no_autostart,
+            graphics,
+            tags,
+            disk_size_gb,
         } => {
+            let user_data = match user_data_file {
+                Some(path) => Some(
+                    std::fs::read_to_string(&path)
+                        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?,
+                ),
+                None => None,
+            };
             let resp: LaunchVmResponse = client
                 .post(
                     "/launch-vm",
@@ -71,6 +399,14 @@ pub async fn run(cmd: VmCommand, client: &Client, json: bool) -> Result<(), Stri
                         name,
                         instance_type,
                         region,
+                        image_id,
+                        user_data,
+                        hostname,
+                        ssh_authorized_keys: ssh_keys,
+                        autostart: no_autostart.then_some(false),
+                        graphics,
+                        tags: tags.into_iter().collect(),
+                        disk_size_gb,
                     },
                 )
                 .await?;
@@ -112,17 +448,247 @@ pub async fn run(cmd: VmCommand, client: &Client, json: bool) -> Result<(), Stri
             } else if vms.is_empty() {
                 println!("No VMs running.");
             } else {
-                println!("{:<38} {:<20} {:<16} {:<6} {:<8}", "ID", "NAME", "SSH HOST", "PORT", "PID");
-                println!("{}", "-".repeat(90));
+                println!(
+                    "{:<38} {:<20} {:<10} {:<16} {:<6} {:<8}",
+                    "ID", "NAME", "STATE", "SSH HOST", "PORT", "PID"
+                );
+                println!("{}", "-".repeat(100));
                 for vm in &vms {
                     println!(
-                        "{:<38} {:<20} {:<16} {:<6} {:<8}",
-                        vm.id, vm.name, vm.ssh_host, vm.ssh_port, vm.pid
+                        "{:<38} {:<20} {:<10} {:<16} {:<6} {:<8}",
+                        vm.id, vm.name, vm.state, vm.ssh_host, vm.ssh_port, vm.pid
                     );
+                    if vm.state == "crashed" && !vm.state_reason.is_empty() {
+                        println!("  {}", vm.state_reason);
+                    }
+                    for iface in vm.interfaces.iter().filter(|i| i.name != "lo") {
+                        let addrs: Vec<&str> = iface
+                            .ipv4
+                            .iter()
+                            .chain(&iface.ipv6)
+                            .map(String::as_str)
+                            .collect();
+                        if !addrs.is_empty() {
+                            println!("  {}: {}", iface.name, addrs.join(", "));
+                        }
+                    }
                 }
             }
         }
 
+        VmCommand::InstanceTypes => {
+            let types: Vec<InstanceType> = client.get("/instance-types").await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&types).unwrap());
+            } else {
+                println!(
+                    "{:<16} {:<6} {:<12} {:<8}",
+                    "NAME", "VCPUS", "MEMORY MB", "DISK GB"
+                );
+                println!("{}", "-".repeat(46));
+                for t in &types {
+                    println!(
+                        "{:<16} {:<6} {:<12} {:<8}",
+                        t.name, t.vcpus, t.memory_mb, t.disk_gb
+                    );
+                }
+            }
+        }
+
+        VmCommand::Stop { id, timeout } => {
+            let resp: StopVmResponse = client
+                .post(
+                    "/stop-vm",
+                    &StopVmRequest {
+                        id,
+                        timeout_secs: timeout,
+                    },
+                )
+                .await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&resp).unwrap());
+            } else {
+                println!("{}", resp.message);
+            }
+        }
+
+        VmCommand::Reboot { id, timeout } => {
+            let resp: RebootVmResponse = client
+                .post(
+                    "/reboot-vm",
+                    &RebootVmRequest {
+                        id,
+                        timeout_secs: timeout,
+                    },
+                )
+                .await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&resp).unwrap());
+            } else {
+                println!("{}", resp.message);
+            }
+        }
+
+        VmCommand::Pause { id } => {
+            let msg = client.post_text("/pause-vm", &VmIdRequest { id }).await?;
+            if json {
+                println!("{}", serde_json::json!({ "message": msg }));
+            } else {
+                println!("{msg}");
+            }
+        }
+
+        VmCommand::Resume { id } => {
+            let msg = client.post_text("/resume-vm", &VmIdRequest { id }).await?;
+            if json {
+                println!("{}", serde_json::json!({ "message": msg }));
+            } else {
+                println!("{msg}");
+            }
+        }
+
+        VmCommand::Logs { id, tail, follow } => {
+            let path = match tail {
+                Some(n) => format!("/vm-console-log/{id}?tail={n}"),
+                None => format!("/vm-console-log/{id}"),
+            };
+            let mut chunk: ConsoleLogChunk = client.get(&path).await?;
+            loop {
+                if json {
+                    // One object per line; skip polls that found nothing new.
+                    if !follow || !chunk.content.is_empty() {
+                        println!("{}", serde_json::to_string(&chunk).unwrap());
+                    }
+                } else {
+                    print!("{}", chunk.content);
+                    std::io::Write::flush(&mut std::io::stdout())
+                        .map_err(|e| format!("Failed to write output: {e}"))?;
+                }
+                if !follow {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                chunk = client
+                    .get(&format!(
+                        "/vm-console-log/{id}?offset={}",
+                        chunk.next_offset
+                    ))
+                    .await?;
+            }
+        }
+
+        VmCommand::Screenshot { id, output } => {
+            let png = client.get_bytes(&format!("/vm-screenshot/{id}")).await?;
+            std::fs::write(&output, &png)
+                .map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::json!({ "path": output, "size_bytes": png.len() })
+                );
+            } else {
+                println!("Saved screenshot to {}", output.display());
+            }
+        }
+
+        VmCommand::Exec {
+            id,
+            timeout,
+            stdin,
+            command,
+        } => {
+            let input = if stdin {
+                let mut input = String::new();
+                std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)
+                    .map_err(|e| format!("Failed to read stdin: {e}"))?;
+                Some(input)
+            } else {
+                None
+            };
+            let output: ExecOutput = client
+                .post(
+                    &format!("/vm-exec/{id}"),
+                    &ExecRequest {
+                        command,
+                        input,
+                        timeout_secs: timeout,
+                    },
+                )
+                .await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&output).unwrap());
+                return Ok(());
+            }
+            print!("{}", output.stdout);
+            eprint!("{}", output.stderr);
+            if output.truncated {
+                eprintln!("(output truncated by the guest agent)");
+            }
+            match (output.exit_code, output.signal) {
+                (Some(0), _) => {}
+                (Some(code), _) => std::process::exit(code as i32),
+                (None, signal) => {
+                    eprintln!("Command killed by signal {}", signal.unwrap_or_default());
+                    std::process::exit(128 + signal.unwrap_or_default() as i32);
+                }
+            }
+        }
+
+        VmCommand::Cp {
+            source,
+            destination,
+        } => match (parse_vm_path(&source), parse_vm_path(&destination)) {
+            (None, Some((id, path))) => {
+                let resp: WriteFileResponse = client
+                    .put_file(
+                        &format!("/vm-file/{id}"),
+                        &[("path", path)],
+                        std::path::Path::new(&source),
+                    )
+                    .await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&resp).unwrap());
+                } else {
+                    println!("Copied {} bytes to {id}:{path}", resp.bytes_written);
+                }
+            }
+            (Some((id, path)), None) => {
+                let size = client
+                    .download(
+                        &format!("/vm-file/{id}"),
+                        &[("path", path)],
+                        std::path::Path::new(&destination),
+                    )
+                    .await?;
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::json!({ "path": destination, "size_bytes": size })
+                    );
+                } else {
+                    println!("Copied {size} bytes to {destination}");
+                }
+            }
+            _ => {
+                return Err("Exactly one of source and destination must be VM_ID:/path".to_string());
+            }
+        },
+
+        VmCommand::ResizeDisk { id, size_gb } => {
+            let resp: ResizeDiskResponse = client
+                .post("/resize-vm-disk", &ResizeDiskRequest { id, size_gb })
+                .await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&resp).unwrap());
+            } else {
+                println!("{}", resp.message);
+            }
+        }
+
+        VmCommand::Snapshot { action } => run_snapshot(action, client, json).await?,
+
         VmCommand::Delete { id } => {
             let msg = client.delete("/delete-vm", &DeleteVmRequest { id }).await?;
             if json {
@@ -135,3 +701,55 @@ pub async fn run(cmd: VmCommand, client: &Client, json: bool) -> Result<(), Stri
 
     Ok(())
 }
+
+async fn run_snapshot(cmd: SnapshotCommand, client: &Client, json: bool) -> Result<(), String> {
+    let msg = match cmd {
+        SnapshotCommand::Create { id, name } => {
+            let snapshot: VmSnapshot = client
+                .post("/create-snapshot", &SnapshotRequest { vm_id: id, name })
+                .await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&snapshot).unwrap());
+            } else {
+                println!("Created {} snapshot {}", snapshot.kind, snapshot.name);
+            }
+            return Ok(());
+        }
+        SnapshotCommand::List { id } => {
+            let snapshots: Vec<VmSnapshot> = client.get(&format!("/list-snapshots/{id}")).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&snapshots).unwrap());
+            } else if snapshots.is_empty() {
+                println!("No snapshots.");
+            } else {
+                println!("{:<32} {:<10} {:<12}", "NAME", "KIND", "CREATED");
+                println!("{}", "-".repeat(56));
+                for s in &snapshots {
+                    println!(
+                        "{:<32} {:<10} {:<12}",
+                        s.name,
+                        s.kind,
+                        format_age(s.created_secs)
+                    );
+                }
+            }
+            return Ok(());
+        }
+        SnapshotCommand::Revert { id, name } => {
+            client
+                .post_text("/revert-snapshot", &SnapshotRequest { vm_id: id, name })
+                .await?
+        }
+        SnapshotCommand::Delete { id, name } => {
+            client
+                .delete("/delete-snapshot", &SnapshotRequest { vm_id: id, name })
+                .await?
+        }
+    };
+    if json {
+        println!("{}", serde_json::json!({ "message": msg }));
+    } else {
+        println!("{msg}");
+    }
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use super::vm::{format_age, WriteFileResponse};
 use crate::client::Client;
 use clap::Subcommand;
 use serde::{Deserialize, Serialize};
@@ -15,17 +16,182 @@ pub enum VolumeCommand {
     },
     /// List all volumes
     List,
+    /// Show volume capacity and usage per backend
+    Usage,
     /// Delete a volume
     Delete {
         /// Volume ID
         #[arg(long)]
         id: String,
     },
-    /// List files in a volume
+    /// List files in a volume, one page at a time
     Files {
         /// Volume ID
         #[arg(long)]
         id: String,
+        /// Directory to list, relative to the volume root
+        #[arg(long, default_value = "")]
+        path: String,
+        /// Levels to list; 1 lists only the directory's own entries
+        #[arg(long, default_value_t = 1)]
+        depth: u32,
+        /// Sort by name, size or modified
+        #[arg(long, default_value = "name", value_parser = ["name", "size", "modified"])]
+        sort: String,
+        /// Sort in descending order
+        #[arg(long)]
+        reverse: bool,
+        /// Entries per page
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Cursor printed after the previous page
+        #[arg(long)]
+        cursor: Option<String>,
+        /// Fetch every page instead of stopping after one
+        #[arg(long)]
+        all: bool,
+    },
+    /// Upload a local file into a mounted volume, replacing any file there
+    Put {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// Local file to upload
+        source: String,
+        /// Path in the volume; defaults to the file's name in the volume root
+        destination: Option<String>,
+    },
+    /// Download a file from a mounted volume
+    Get {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// Path of the file in the volume
+        path: String,
+        /// Local file to write; defaults to the file's name
+        destination: Option<String>,
+    },
+    /// Delete a file or directory in a mounted volume
+    Rm {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// Path in the volume
+        path: String,
+        /// Also delete a directory's contents
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Mount a volume on its worker node again
+    Mount {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+    },
+    /// Unmount a volume from its worker node so it can be attached to a VM
+    Unmount {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+    },
+    /// Grow a volume and its filesystem; mounted volumes grow online
+    Resize {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// New size in gigabytes
+        #[arg(long)]
+        size_gb: u64,
+    },
+    /// Manage a volume's snapshots
+    Snapshot {
+        #[command(subcommand)]
+        action: VolumeSnapshotCommand,
+    },
+    /// Create a new volume, on the same worker, from a snapshot
+    Clone {
+        /// ID of the volume the snapshot belongs to
+        #[arg(long)]
+        id: String,
+        /// Snapshot name
+        #[arg(long)]
+        snapshot: String,
+        /// Name of the new volume
+        #[arg(long)]
+        name: String,
+    },
+    /// Download a mounted volume's files as a tar archive
+    Export {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// Archive file to write
+        #[arg(long)]
+        output: String,
+        /// Compress the archive with zstd
+        #[arg(long)]
+        zstd: bool,
+    },
+    /// Create a volume holding the files of a tar archive
+    Import {
+        /// Name of the new volume
+        #[arg(long)]
+        name: String,
+        /// Size in gigabytes; the archive's contents must fit
+        #[arg(long)]
+        size_gb: u64,
+        /// Archive file to read
+        #[arg(long)]
+        input: String,
+        /// The archive is compressed with zstd
+        #[arg(long)]
+        zstd: bool,
+    },
+    /// Attach an unmounted volume to a VM on the same backend as a virtio disk
+    Attach {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// VM ID
+        #[arg(long)]
+        vm_id: String,
+    },
+    /// Detach a volume from a VM
+    Detach {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// VM ID
+        #[arg(long)]
+        vm_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum VolumeSnapshotCommand {
+    /// Snapshot a volume, freezing its filesystem for the copy
+    Create {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// Snapshot name
+        #[arg(long)]
+        name: String,
+    },
+    /// List a volume's snapshots
+    List {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+    },
+    /// Delete a volume's snapshot
+    Delete {
+        /// Volume ID
+        #[arg(long)]
+        id: String,
+        /// Snapshot name
+        #[arg(long)]
+        name: String,
     },
 }
 
@@ -48,7 +214,91 @@ struct LaunchVolumeResponse {
 struct VolumeListEntry {
     id: String,
     name: String,
+    #[serde(default)]
+    size_gb: u64,
     mount_path: String,
+    #[serde(default = "default_mounted")]
+    mounted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    attached_to: Option<String>,
+    #[serde(default)]
+    size_bytes: u64,
+    #[serde(default)]
+    allocated_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fs_used_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fs_free_bytes: Option<u64>,
+}
+
+fn default_mounted() -> bool {
+    true
+}
+
+impl VolumeListEntry {
+    fn status(&self) -> &str {
+        match (&self.attached_to, self.mounted) {
+            (Some(vm_id), _) => vm_id,
+            (None, true) => "mounted",
+            (None, false) => "unmounted",
+        }
+    }
+
+    fn size(&self) -> String {
+        match (self.size_bytes, self.size_gb) {
+            (0, 0) => "-".to_string(),
+            (0, gb) => format_bytes(gb << 30),
+            (bytes, _) => format_bytes(bytes),
+        }
+    }
+
+    /// Filesystem usage while mounted on the worker, e.g. "1.2 GiB (12%)".
+    fn used(&self) -> String {
+        match (self.fs_used_bytes, self.fs_free_bytes) {
+            (Some(used), Some(free)) if used + free > 0 => {
+                format!("{} ({}%)", format_bytes(used), used * 100 / (used + free))
+            }
+            _ => "-".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct VolumeUsageTotals {
+    volumes: u64,
+    size_bytes: u64,
+    allocated_bytes: u64,
+    fs_used_bytes: u64,
+    fs_free_bytes: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+struct BackendVolumeUsage {
+    backend: String,
+    #[serde(flatten)]
+    usage: VolumeUsageTotals,
+}
+
+#[derive(Deserialize, Serialize)]
+struct VolumeUsageReport {
+    backends: Vec<BackendVolumeUsage>,
+    total: VolumeUsageTotals,
+}
+
+/// Byte count in binary units, e.g. "1.5 GiB".
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
 }
 
 #[derive(Serialize)]
@@ -56,12 +306,90 @@ struct DeleteVolumeRequest {
     id: String,
 }
 
+#[derive(Serialize)]
+struct MountVolumeRequest {
+    id: String,
+}
+
+#[derive(Serialize)]
+struct ResizeVolumeRequest {
+    id: String,
+    size_gb: u64,
+}
+
+#[derive(Serialize)]
+struct VolumeSnapshotRequest {
+    volume_id: String,
+    name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct VolumeSnapshot {
+    name: String,
+    created_secs: u64,
+    size_bytes: u64,
+    allocated_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct CloneVolumeRequest {
+    volume_id: String,
+    snapshot: String,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct AttachVolumeRequest {
+    volume_id: String,
+    vm_id: String,
+}
+
 #[derive(Deserialize, Serialize)]
 struct VolumeFileEntry {
     name: String,
+    path: String,
     is_dir: bool,
+    #[serde(default)]
+    is_symlink: bool,
     size_bytes: u64,
     modified_secs: u64,
+    #[serde(default)]
+    mode: u32,
+    #[serde(default)]
+    uid: u32,
+    #[serde(default)]
+    gid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    symlink_target: Option<String>,
+}
+
+impl VolumeFileEntry {
+    /// Type and permissions as `ls -l` shows them, e.g. "drwxr-xr-x".
+    fn mode_string(&self) -> String {
+        let kind = if self.is_symlink {
+            'l'
+        } else if self.is_dir {
+            'd'
+        } else {
+            '-'
+        };
+        let mut mode = String::from(kind);
+        for shift in [6, 3, 0] {
+            let bits = self.mode >> shift;
+            mode.push(if bits & 4 != 0 { 'r' } else { '-' });
+            mode.push(if bits & 2 != 0 { 'w' } else { '-' });
+            mode.push(if bits & 1 != 0 { 'x' } else { '-' });
+        }
+        mode
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct VolumeFileListing {
+    path: String,
+    entries: Vec<VolumeFileEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
 }
 
 pub async fn run(cmd: VolumeCommand, client: &Client, json: bool) -> Result<(), String> {
@@ -70,33 +398,7 @@ pub async fn run(cmd: VolumeCommand, client: &Client, json: bool) -> Result<(),
             let resp: LaunchVolumeResponse = client
                 .post("/launch-volume", &LaunchVolumeRequest { name, size_gb })
                 .await?;
-
-            if json {
-                println!(
-                    "{}",
-                    serde_json::to_string_pretty(&serde_json::json!({
-                        "success": resp.success,
-                        "message": resp.message,
-                        "id": resp.id,
-                        "name": resp.name,
-                        "mount_path": resp.mount_path,
-                    }))
-                    .unwrap()
-                );
-            } else if resp.success {
-                println!("Launched volume");
-                if let Some(id) = &resp.id {
-                    println!("  ID:         {id}");
-                }
-                if let Some(name) = &resp.name {
-                    println!("  Name:       {name}");
-                }
-                if let Some(path) = &resp.mount_path {
-                    println!("  Mount path: {path}");
-                }
-            } else {
-                return Err(resp.message);
-            }
+            print_launched(resp, json, "Launched volume")?;
         }
 
         VolumeCommand::List => {
@@ -107,10 +409,51 @@ pub async fn run(cmd: VolumeCommand, client: &Client, json: bool) -> Result<(),
             } else if volumes.is_empty() {
                 println!("No volumes.");
             } else {
-                println!("{:<38} {:<20} {}", "ID", "NAME", "MOUNT PATH");
-                println!("{}", "-".repeat(90));
+                println!(
+                    "{:<38} {:<20} {:<10} {:<16} {:<38} MOUNT PATH",
+                    "ID", "NAME", "SIZE", "USED", "STATUS"
+                );
+                println!("{}", "-".repeat(158));
                 for v in &volumes {
-                    println!("{:<38} {:<20} {}", v.id, v.name, v.mount_path);
+                    println!(
+                        "{:<38} {:<20} {:<10} {:<16} {:<38} {}",
+                        v.id,
+                        v.name,
+                        v.size(),
+                        v.used(),
+                        v.status(),
+                        v.mount_path
+                    );
+                }
+            }
+        }
+
+        VolumeCommand::Usage => {
+            let report: VolumeUsageReport = client.get("/volume-usage").await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else {
+                println!(
+                    "{:<32} {:>8} {:>12} {:>12} {:>12} {:>12}",
+                    "BACKEND", "VOLUMES", "SIZE", "ALLOCATED", "FS USED", "FS FREE"
+                );
+                println!("{}", "-".repeat(93));
+                let rows = report
+                    .backends
+                    .iter()
+                    .map(|b| (b.backend.as_str(), &b.usage))
+                    .chain([("total", &report.total)]);
+                for (name, u) in rows {
+                    println!(
+                        "{:<32} {:>8} {:>12} {:>12} {:>12} {:>12}",
+                        name,
+                        u.volumes,
+                        format_bytes(u.size_bytes),
+                        format_bytes(u.allocated_bytes),
+                        format_bytes(u.fs_used_bytes),
+                        format_bytes(u.fs_free_bytes)
+                    );
                 }
             }
         }
@@ -126,24 +469,365 @@ pub async fn run(cmd: VolumeCommand, client: &Client, json: bool) -> Result<(),
             }
         }
 
-        VolumeCommand::Files { id } => {
-            let files: Vec<VolumeFileEntry> =
-                client.get(&format!("/volume-files/{id}")).await?;
+        VolumeCommand::Files {
+            id,
+            path,
+            depth,
+            sort,
+            reverse,
+            limit,
+            mut cursor,
+            all,
+        } => {
+            let mut query = vec![
+                ("path", path),
+                ("depth", depth.to_string()),
+                ("sort", sort),
+                ("order", if reverse { "desc" } else { "asc" }.to_string()),
+            ];
+            if let Some(limit) = limit {
+                query.push(("limit", limit.to_string()));
+            }
+
+            let mut printed_header = false;
+            loop {
+                let mut page_query = query.clone();
+                if let Some(cursor) = &cursor {
+                    page_query.push(("cursor", cursor.clone()));
+                }
+                let listing: VolumeFileListing = client
+                    .get_with_query(&format!("/volume-files/{id}"), &page_query)
+                    .await?;
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&listing).unwrap());
+                } else {
+                    if !printed_header && !listing.entries.is_empty() {
+                        println!(
+                            "{:<10} {:>6} {:>6} {:>10} {:<10} PATH",
+                            "MODE", "UID", "GID", "SIZE", "MODIFIED"
+                        );
+                        println!("{}", "-".repeat(70));
+                        printed_header = true;
+                    }
+                    for f in &listing.entries {
+                        let name = match &f.symlink_target {
+                            Some(target) => format!("{} -> {target}", f.path),
+                            None => f.path.clone(),
+                        };
+                        println!(
+                            "{:<10} {:>6} {:>6} {:>10} {:<10} {}",
+                            f.mode_string(),
+                            f.uid,
+                            f.gid,
+                            format_bytes(f.size_bytes),
+                            format_age(f.modified_secs),
+                            name
+                        );
+                    }
+                }
+
+                cursor = listing.next_cursor;
+                match &cursor {
+                    Some(_) if all => continue,
+                    Some(next) if !json => {
+                        println!("More entries follow; continue with --cursor {next}");
+                    }
+                    _ => {}
+                }
+                break;
+            }
+            if !json && !printed_header && cursor.is_none() {
+                println!("Directory is empty.");
+            }
+        }
+
+        VolumeCommand::Put {
+            id,
+            source,
+            destination,
+        } => {
+            let source = std::path::PathBuf::from(source);
+            let path = match destination {
+                Some(path) => path,
+                None => file_name(&source)?,
+            };
+            let resp: WriteFileResponse = client
+                .put_file(&format!("/volume-file/{id}"), &[("path", &path)], &source)
+                .await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&resp).unwrap());
+            } else {
+                println!("Copied {} bytes to {id}:{path}", resp.bytes_written);
+            }
+        }
 
+        VolumeCommand::Get {
+            id,
+            path,
+            destination,
+        } => {
+            let destination = match destination {
+                Some(destination) => std::path::PathBuf::from(destination),
+                None => std::path::PathBuf::from(file_name(std::path::Path::new(&path))?),
+            };
+            let size = client
+                .download(
+                    &format!("/volume-file/{id}"),
+                    &[("path", &path)],
+                    &destination,
+                )
+                .await?;
             if json {
-                println!("{}", serde_json::to_string_pretty(&files).unwrap());
-            } else if files.is_empty() {
-                println!("Volume is empty.");
+                println!(
+                    "{}",
+                    serde_json::json!({ "path": destination, "size_bytes": size })
+                );
             } else {
-                println!("{:<6} {:<12} {}", "TYPE", "SIZE", "NAME");
-                println!("{}", "-".repeat(50));
-                for f in &files {
-                    let kind = if f.is_dir { "dir" } else { "file" };
-                    println!("{:<6} {:<12} {}", kind, f.size_bytes, f.name);
+                println!("Copied {size} bytes to {}", destination.display());
+            }
+        }
+
+        VolumeCommand::Rm {
+            id,
+            path,
+            recursive,
+        } => {
+            let mut query = vec![("path", path.as_str())];
+            if recursive {
+                query.push(("recursive", "true"));
+            }
+            let msg = client
+                .delete_with_query(&format!("/volume-file/{id}"), &query)
+                .await?;
+            if json {
+                println!("{}", serde_json::json!({ "message": msg }));
+            } else {
+                println!("{msg}");
+            }
+        }
+
+        VolumeCommand::Mount { id } => {
+            let volume: VolumeListEntry = client
+                .post("/mount-volume", &MountVolumeRequest { id })
+                .await?;
+            print_volume(&volume, json, &format!("Mounted at {}", volume.mount_path));
+        }
+
+        VolumeCommand::Unmount { id } => {
+            let volume: VolumeListEntry = client
+                .post("/unmount-volume", &MountVolumeRequest { id })
+                .await?;
+            print_volume(&volume, json, "Unmounted");
+        }
+
+        VolumeCommand::Resize { id, size_gb } => {
+            let volume: VolumeListEntry = client
+                .post("/resize-volume", &ResizeVolumeRequest { id, size_gb })
+                .await?;
+            print_volume(&volume, json, &format!("Resized to {} GiB", volume.size_gb));
+        }
+
+        VolumeCommand::Snapshot { action } => run_snapshot(action, client, json).await?,
+
+        VolumeCommand::Clone { id, snapshot, name } => {
+            let resp: LaunchVolumeResponse = client
+                .post(
+                    "/clone-volume",
+                    &CloneVolumeRequest {
+                        volume_id: id,
+                        snapshot,
+                        name,
+                    },
+                )
+                .await?;
+            print_launched(resp, json, "Cloned volume")?;
+        }
+
+        VolumeCommand::Export { id, output, zstd } => {
+            let compression = if zstd { "zstd" } else { "none" };
+            let output = std::path::PathBuf::from(output);
+            let size = client
+                .download(
+                    &format!("/export-volume/{id}"),
+                    &[("compression", compression)],
+                    &output,
+                )
+                .await?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::json!({ "path": output, "size_bytes": size })
+                );
+            } else {
+                println!("Exported {} to {}", format_bytes(size), output.display());
+            }
+        }
+
+        VolumeCommand::Import {
+            name,
+            size_gb,
+            input,
+            zstd,
+        } => {
+            let size_gb = size_gb.to_string();
+            let query = [
+                ("name", name.as_str()),
+                ("size_gb", size_gb.as_str()),
+                ("compression", if zstd { "zstd" } else { "none" }),
+            ];
+            let resp: LaunchVolumeResponse = client
+                .post_file("/import-volume", &query, std::path::Path::new(&input))
+                .await?;
+            print_launched(resp, json, "Imported volume")?;
+        }
+
+        VolumeCommand::Attach { id, vm_id } => {
+            let volume: VolumeListEntry = client
+                .post(
+                    "/attach-volume",
+                    &AttachVolumeRequest {
+                        volume_id: id,
+                        vm_id,
+                    },
+                )
+                .await?;
+            print_volume(
+                &volume,
+                json,
+                &format!("Attached to VM {}", volume.status()),
+            );
+        }
+
+        VolumeCommand::Detach { id, vm_id } => {
+            let volume: VolumeListEntry = client
+                .post(
+                    "/detach-volume",
+                    &AttachVolumeRequest {
+                        volume_id: id,
+                        vm_id,
+                    },
+                )
+                .await?;
+            print_volume(&volume, json, "Detached");
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_snapshot(
+    cmd: VolumeSnapshotCommand,
+    client: &Client,
+    json: bool,
+) -> Result<(), String> {
+    match cmd {
+        VolumeSnapshotCommand::Create { id, name } => {
+            let snapshot: VolumeSnapshot = client
+                .post(
+                    "/create-volume-snapshot",
+                    &VolumeSnapshotRequest {
+                        volume_id: id,
+                        name,
+                    },
+                )
+                .await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&snapshot).unwrap());
+            } else {
+                println!(
+                    "Created snapshot {} ({})",
+                    snapshot.name,
+                    format_bytes(snapshot.size_bytes)
+                );
+            }
+        }
+        VolumeSnapshotCommand::List { id } => {
+            let snapshots: Vec<VolumeSnapshot> =
+                client.get(&format!("/list-volume-snapshots/{id}")).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&snapshots).unwrap());
+            } else if snapshots.is_empty() {
+                println!("No snapshots.");
+            } else {
+                println!(
+                    "{:<32} {:<12} {:>12} {:>12}",
+                    "NAME", "CREATED", "SIZE", "ALLOCATED"
+                );
+                println!("{}", "-".repeat(71));
+                for s in &snapshots {
+                    println!(
+                        "{:<32} {:<12} {:>12} {:>12}",
+                        s.name,
+                        format_age(s.created_secs),
+                        format_bytes(s.size_bytes),
+                        format_bytes(s.allocated_bytes)
+                    );
                 }
             }
         }
+        VolumeSnapshotCommand::Delete { id, name } => {
+            let msg = client
+                .delete(
+                    "/delete-volume-snapshot",
+                    &VolumeSnapshotRequest {
+                        volume_id: id,
+                        name,
+                    },
+                )
+                .await?;
+            if json {
+                println!("{}", serde_json::json!({ "message": msg }));
+            } else {
+                println!("{msg}");
+            }
+        }
     }
+    Ok(())
+}
+
+/// The last component of a path, used when `put` or `get` is not told where
+/// the copy goes.
+fn file_name(path: &std::path::Path) -> Result<String, String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("{} does not name a file", path.display()))
+}
 
+fn print_launched(resp: LaunchVolumeResponse, json: bool, heading: &str) -> Result<(), String> {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "success": resp.success,
+                "message": resp.message,
+                "id": resp.id,
+                "name": resp.name,
+                "mount_path": resp.mount_path,
+            }))
+            .unwrap()
+        );
+    } else if resp.success {
+        println!("{heading}");
+        if let Some(id) = &resp.id {
+            println!("  ID:         {id}");
+        }
+        if let Some(name) = &resp.name {
+            println!("  Name:       {name}");
+        }
+        if let Some(path) = &resp.mount_path {
+            println!("  Mount path: {path}");
+        }
+    } else {
+        return Err(resp.message);
+    }
     Ok(())
 }
+
+fn print_volume(volume: &VolumeListEntry, json: bool, message: &str) {
+    if json {
+        println!("{}", serde_json::to_string_pretty(volume).unwrap());
+    } else {
+        println!("Volume {} ({}): {message}", volume.name, volume.id);
+    }
+}
@@ -30,6 +30,11 @@ enum Command {
         #[command(subcommand)]
         action: cmd::vm::VmCommand,
     },
+    /// Manage base images
+    Image {
+        #[command(subcommand)]
+        action: cmd::image::ImageCommand,
+    },
     /// Manage volumes
     Volume {
         #[command(subcommand)]
@@ -44,6 +49,7 @@ async fn main() {
 
     let result = match cli.command {
         Command::Vm { action } => cmd::vm::run(action, &client, cli.json).await,
+        Command::Image { action } => cmd::image::run(action, &client, cli.json).await,
         Command::Volume { action } => cmd::volume::run(action, &client, cli.json).await,
     };
 
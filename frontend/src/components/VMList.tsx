@@ -1,11 +1,22 @@
 import React, { useState, useEffect } from 'react';
-import { VM } from '../types';
+import { VM, VMState } from '../types';
 import { getApiUrl } from '../config';
 
 interface VMListProps {
   refreshKey?: number;
 }
 
+const STATE_BADGE: Record<VMState, string> = {
+  pending: 'bg-info',
+  starting: 'bg-info',
+  running: 'bg-success',
+  paused: 'bg-warning',
+  stopping: 'bg-warning',
+  stopped: 'bg-secondary',
+  crashed: 'bg-danger',
+  deleting: 'bg-dark',
+};
+
 const VMList: React.FC<VMListProps> = ({ refreshKey = 0 }) => {
   const [vms, setVms] = useState<VM[]>([]);
   const [loading, setLoading] = useState(true);
@@ -181,7 +192,9 @@ const VMList: React.FC<VMListProps> = ({ refreshKey = 0 }) => {
                 </thead>
                 <tbody>
                   {vms.map((vm) => {
-                    const isRunning = vm.running !== false;
+                    const state = vm.state ?? (vm.running !== false ? 'running' : 'stopped');
+                    const isRunning = state === 'running';
+                    const canStart = state === 'stopped' || state === 'crashed';
                     return (
                       <tr key={vm.id}>
                         <td>
@@ -190,8 +203,8 @@ const VMList: React.FC<VMListProps> = ({ refreshKey = 0 }) => {
                           <small className="text-muted">ID: {vm.id}</small>
                         </td>
                         <td>
-                          <span className={`badge ${isRunning ? 'bg-success' : 'bg-secondary'}`}>
-                            {isRunning ? 'Running' : 'Stopped'}
+                          <span className={`badge ${STATE_BADGE[state]}`} title={vm.state_reason}>
+                            {state.charAt(0).toUpperCase() + state.slice(1)}
                           </span>
                         </td>
                         <td>
@@ -206,7 +219,7 @@ const VMList: React.FC<VMListProps> = ({ refreshKey = 0 }) => {
                               className="btn btn-outline-primary btn-sm"
                               onClick={() => handleConnect(vm.name, vm.ssh_host, vm.ssh_port)}
                               disabled={!vm.ssh_host || !isRunning}
-                              title={!isRunning ? 'VM is stopped' : vm.ssh_host ? `Open SSH terminal for ${vm.name} in new tab` : 'Waiting for IP address...'}
+                              title={!isRunning ? `VM is ${state}` : vm.ssh_host ? `Open SSH terminal for ${vm.name} in new tab` : 'Waiting for IP address...'}
                             >
                               <i className="bi bi-terminal"></i> Connect
                             </button>
@@ -229,7 +242,7 @@ const VMList: React.FC<VMListProps> = ({ refreshKey = 0 }) => {
                               <button
                                 className="btn btn-outline-success btn-sm"
                                 onClick={() => startVM(vm.id)}
-                                disabled={startingVM === vm.id}
+                                disabled={!canStart || startingVM === vm.id}
                               >
                                 {startingVM === vm.id ? (
                                   <>
@@ -1,3 +1,13 @@
+export type VMState =
+  | 'pending'
+  | 'starting'
+  | 'running'
+  | 'paused'
+  | 'stopping'
+  | 'stopped'
+  | 'crashed'
+  | 'deleting';
+
 export interface VM {
   id: string;
   name: string;
@@ -5,6 +15,8 @@ export interface VM {
   ssh_port: number;
   pid: number;
   running?: boolean;
+  state?: VMState;
+  state_reason?: string;
 }
 
 export interface VMListResponse {
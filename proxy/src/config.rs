@@ -12,6 +12,9 @@ pub struct Config {
     /// Path to the JSON file where volume_id → backend_url mappings are persisted
     /// across proxy restarts. Defaults to `./volume-backends.json`.
     pub volume_backends_file: PathBuf,
+    /// Path to the JSON file where image_id → backend_url mappings are persisted
+    /// across proxy restarts. Defaults to `./image-backends.json`.
+    pub image_backends_file: PathBuf,
     /// Path to the dnsmasq lease file used to resolve VM MAC addresses to IPs.
     pub lease_file: PathBuf,
 }
@@ -30,6 +33,9 @@ impl Config {
         let volume_backends_file = env::var("VOLUME_BACKENDS_FILE")
             .map(PathBuf::from)
             .unwrap_or_else(|_| PathBuf::from("./volume-backends.json"));
+        let image_backends_file = env::var("IMAGE_BACKENDS_FILE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from("./image-backends.json"));
         let lease_file = env::var("LEASE_FILE")
             .map(PathBuf::from)
             .unwrap_or_else(|_| PathBuf::from("/var/lib/misc/dnsmasq.leases"));
@@ -40,6 +46,7 @@ impl Config {
             log_level,
             vm_backends_file,
             volume_backends_file,
+            image_backends_file,
             lease_file,
         })
     }
@@ -165,4 +172,27 @@ mod tests {
             PathBuf::from("/tmp/my-volume-backends.json")
         );
     }
+
+    #[test]
+    fn test_default_image_backends_file() {
+        let _g = env_guard();
+        env::remove_var("IMAGE_BACKENDS_FILE");
+        let config = Config::load().unwrap();
+        assert_eq!(
+            config.image_backends_file,
+            PathBuf::from("./image-backends.json")
+        );
+    }
+
+    #[test]
+    fn test_image_backends_file_from_env() {
+        let _g = env_guard();
+        env::set_var("IMAGE_BACKENDS_FILE", "/tmp/my-image-backends.json");
+        let config = Config::load().unwrap();
+        env::remove_var("IMAGE_BACKENDS_FILE");
+        assert_eq!(
+            config.image_backends_file,
+            PathBuf::from("/tmp/my-image-backends.json")
+        );
+    }
 }
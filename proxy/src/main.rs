@@ -1,7 +1,7 @@
 use axum::{
     body::Body,
-    extract::State,
-    http::{Request, Response, StatusCode},
+    extract::{ws::WebSocketUpgrade, Query, State},
+    http::{Request, Response, StatusCode, Uri},
     response::IntoResponse,
     routing::{delete, get, post},
     Router,
@@ -19,6 +19,7 @@ mod config;
 mod ip_lookup;
 mod proxy_service;
 mod registry;
+mod ws_proxy;
 
 use config::Config;
 use proxy_service::ProxyService;
@@ -34,8 +35,36 @@ use registry::BackendRegistry;
 struct LaunchVmRequest {
     /// Human-readable name for the VM.
     name: String,
+    /// Name of an entry in the backend's instance-type catalog (see `/instance-types`).
     instance_type: String,
     region: String,
+    /// Catalog image to create the VM from. The VM is launched on the backend
+    /// storing the image. Omit to use the backend's default `alpine.qcow2`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_id: Option<String>,
+    /// cloud-init user-data passed to the guest unchanged, e.g. a `#cloud-config` document.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_data: Option<String>,
+    /// Guest hostname set via cloud-init. Defaults to `name` when any cloud-init option is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<String>,
+    /// SSH public keys cloud-init installs for the guest's default user.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ssh_authorized_keys: Vec<String>,
+    /// Whether the backend starts the VM again after it restarts. Defaults to true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    autostart: Option<bool>,
+    /// Export the VM's display over VNC, reachable through `/vm-vnc/{id}`. Defaults to false.
+    #[serde(default)]
+    graphics: bool,
+    /// Free-form labels. Guests read them from the metadata service under
+    /// `/latest/meta-data/tags/instance/`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    tags: HashMap<String, String>,
+    /// Size of the VM's disk in GiB. Defaults to the instance type's `disk_gb`,
+    /// or the image's own size if that is larger. Must not be smaller than the image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disk_size_gb: Option<u64>,
 }
 
 /// Response returned after a VM launch attempt.
@@ -56,31 +85,140 @@ struct LaunchVmResponse {
 struct VmListEntry {
     id: String,
     name: String,
-    /// SSH host to connect to. Resolved from the dnsmasq lease file by the proxy in bridge mode.
+    /// SSH host to connect to. In bridge mode the address reported by the guest agent, or
+    /// resolved from the dnsmasq lease file by the proxy when the guest has no agent.
     ssh_host: String,
     ssh_port: u16,
     pid: u32,
-    /// Whether the QEMU process is currently alive on the worker.
+    /// Whether a QEMU process exists for the VM on the worker (starting, running, paused or stopping).
     running: bool,
+    /// Lifecycle state: pending, starting, running, paused, stopping, stopped, crashed or deleting.
+    #[schema(example = "running")]
+    state: String,
+    /// Why the VM entered its current state, e.g. "QEMU exited unexpectedly with signal: 9".
+    state_reason: String,
     /// MAC address of the VM's network interface. Present in bridge mode only.
     #[serde(skip_serializing_if = "Option::is_none")]
     mac_address: Option<String>,
+    /// Instance type the VM was launched with. Empty for VMs created before instance types existed.
+    instance_type: String,
+    /// Catalog image the VM was created from. Absent for VMs created from the default image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_id: Option<String>,
+    /// Desired state: whether the backend starts the VM on startup. Cleared by
+    /// `/stop-vm`, set by `/start-vm`.
+    autostart: bool,
+    /// Whether the VM's display is exported over VNC (`/vm-vnc/{id}`).
+    graphics: bool,
+    /// UUIDs of the volumes attached to the VM as virtio disks.
+    volumes: Vec<String>,
+    /// Labels given at launch.
+    tags: HashMap<String, String>,
+    /// Guest network interfaces reported by qemu-guest-agent. Empty while the VM is not
+    /// running or has no agent.
+    interfaces: Vec<GuestInterface>,
+}
+
+/// A guest network interface with all its addresses, as reported by qemu-guest-agent.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct GuestInterface {
+    #[schema(example = "eth0")]
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mac_address: Option<String>,
+    ipv4: Vec<String>,
+    ipv6: Vec<String>,
+}
+
+/// A VM shape offered by the backend's instance-type catalog.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct InstanceType {
+    name: String,
+    vcpus: u32,
+    memory_mb: u32,
+    /// Default root disk size in gigabytes.
+    disk_gb: u64,
 }
 
 /// Request body for gracefully stopping a running VM.
-#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
 struct StopVmRequest {
     /// UUID of the VM to stop.
     id: String,
+    /// Seconds to wait for the guest to shut down before forcing QEMU to
+    /// exit. Zero skips the guest shutdown. Defaults to 30.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+}
+
+/// Result of a completed stop.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct StopVmResponse {
+    message: String,
+    /// Step of the stop sequence after which QEMU had exited:
+    /// `guest_shutdown`, `qmp_quit`, `sigterm` or `sigkill`.
+    #[schema(example = "guest_shutdown")]
+    stopped_by: String,
 }
 
-/// Request body for starting (resuming) a stopped VM.
+/// Request body for starting a stopped VM.
 #[derive(serde::Deserialize, utoipa::ToSchema)]
 struct StartVmRequest {
     /// UUID of the VM to start.
     id: String,
 }
 
+/// Request body for rebooting a running VM.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct RebootVmRequest {
+    /// UUID of the VM to reboot.
+    id: String,
+    /// Seconds to wait for the guest to shut down before hard-resetting the
+    /// machine. Zero resets immediately. Defaults to 30.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+}
+
+/// Result of a completed reboot.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct RebootVmResponse {
+    message: String,
+    /// How the reboot was done: `guest_reboot` or `hard_reset`.
+    #[schema(example = "guest_reboot")]
+    rebooted_by: String,
+}
+
+/// Request body for pausing a running VM.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct PauseVmRequest {
+    /// UUID of the VM to pause.
+    id: String,
+}
+
+/// Request body for growing a VM's disk.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct ResizeVmDiskRequest {
+    /// UUID of the VM.
+    id: String,
+    /// New disk size in GiB. Disks can only grow.
+    size_gb: u64,
+}
+
+/// Result of a completed disk resize.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct ResizeVmDiskResponse {
+    message: String,
+    /// New virtual size of the disk.
+    size_bytes: u64,
+}
+
+/// Request body for resuming a paused VM.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct ResumeVmRequest {
+    /// UUID of the VM to resume.
+    id: String,
+}
+
 /// Request body for creating a new volume.
 #[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
 struct LaunchVolumeRequest {
@@ -107,9 +245,95 @@ struct LaunchVolumeResponse {
 struct VolumeInfo {
     id: String,
     name: String,
+    /// Size of the volume in GiB; 0 for volumes created before sizes were recorded.
+    size_gb: u64,
     mount_path: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     loop_device: Option<String>,
+    /// Whether the volume is loop-mounted on its worker at `mount_path`.
+    mounted: bool,
+    /// UUID of the VM the volume is attached to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attached_to: Option<String>,
+    /// Provisioned capacity: the apparent size of the volume's image.
+    size_bytes: u64,
+    /// Worker disk space the sparse image occupies.
+    allocated_bytes: u64,
+    /// Space used in the volume's filesystem; present while it is mounted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fs_used_bytes: Option<u64>,
+    /// Space available in the volume's filesystem; present while it is mounted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fs_free_bytes: Option<u64>,
+    /// Snapshots of the volume, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    snapshots: Vec<VolumeSnapshot>,
+}
+
+/// Request body for mounting a volume on its worker node.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct MountVolumeRequest {
+    /// UUID of the volume to mount.
+    id: String,
+}
+
+/// Request body for growing a volume.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct ResizeVolumeRequest {
+    /// UUID of the volume to grow.
+    id: String,
+    /// New size in GiB; must be larger than the current size.
+    size_gb: u64,
+}
+
+/// Request body for creating or deleting a volume snapshot.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct VolumeSnapshotRequest {
+    /// UUID of the volume.
+    volume_id: String,
+    /// Snapshot name: letters, digits, '-', '_' or '.'; unique per volume.
+    #[schema(example = "before-migration")]
+    name: String,
+}
+
+/// A point-in-time copy of a volume's image.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct VolumeSnapshot {
+    name: String,
+    /// Unix time the snapshot was taken.
+    created_secs: u64,
+    /// Size of the volume when the snapshot was taken.
+    size_bytes: u64,
+    /// Worker disk space the copy occupied when it was taken; reflinked
+    /// copies share blocks with the volume.
+    allocated_bytes: u64,
+}
+
+/// Request body for creating a volume from a snapshot.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct CloneVolumeRequest {
+    /// UUID of the volume the snapshot belongs to.
+    volume_id: String,
+    /// Name of the snapshot to copy.
+    snapshot: String,
+    /// Name of the new volume.
+    name: String,
+}
+
+/// Request body for unmounting a volume from its worker node.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct UnmountVolumeRequest {
+    /// UUID of the volume to unmount.
+    id: String,
+}
+
+/// Request body for attaching a volume to a VM, or detaching it.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct AttachVolumeRequest {
+    /// UUID of the volume.
+    volume_id: String,
+    /// UUID of the VM. It must live on the same backend as the volume.
+    vm_id: String,
 }
 
 /// Request body for deleting a volume.
@@ -123,10 +347,120 @@ struct DeleteVolumeRequest {
 #[derive(serde::Serialize, utoipa::ToSchema)]
 struct VolumeFileEntry {
     name: String,
+    /// Path relative to the volume's root, usable as `path` in the other
+    /// volume file endpoints.
+    #[schema(example = "docs/report.txt")]
+    path: String,
     is_dir: bool,
+    /// Symlinks are listed as themselves and never descended into.
+    is_symlink: bool,
     size_bytes: u64,
     /// Last-modified time as seconds since the Unix epoch.
     modified_secs: u64,
+    /// Permission bits, e.g. 420 for 0o644.
+    #[schema(example = 420)]
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    /// Where a symlink points, as stored in the link.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symlink_target: Option<String>,
+}
+
+/// One page of a volume directory listing.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct VolumeFileListing {
+    /// The listed directory, relative to the volume's root.
+    path: String,
+    entries: Vec<VolumeFileEntry>,
+    /// Pass as `cursor` to get the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+/// Move a file or directory within a volume. Both paths are relative to the
+/// volume's root; `to` must not exist yet.
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+struct RenameVolumeFileRequest {
+    #[schema(example = "reports/draft.txt")]
+    from: String,
+    #[schema(example = "reports/final.txt")]
+    to: String,
+}
+
+/// A slice of a VM's serial console output. Offsets count bytes since the
+/// VM's first boot; poll with `offset = next_offset` to follow the console.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct ConsoleLogChunk {
+    /// Offset of the first byte of `content`.
+    offset: u64,
+    /// Offset to request next to continue after `content`.
+    next_offset: u64,
+    content: String,
+}
+
+/// A base image stored in a backend's image catalog.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct ImageInfo {
+    id: String,
+    name: String,
+    os: String,
+    version: String,
+    description: String,
+    /// Hex-encoded SHA-256 of the qcow2 file.
+    checksum: String,
+    size_bytes: u64,
+    /// Registration time as seconds since the Unix epoch.
+    created_secs: u64,
+}
+
+/// Request body for registering a base image. Exactly one of `source_path`
+/// and `source_url` must be given.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct RegisterImageRequest {
+    name: String,
+    os: String,
+    version: String,
+    description: Option<String>,
+    /// Path of a qcow2 file on the worker node to copy into the catalog; it
+    /// must be inside the worker's import directory.
+    source_path: Option<String>,
+    /// URL the worker downloads the qcow2 file from.
+    source_url: Option<String>,
+    /// Expected SHA-256; registration fails with 422 on mismatch.
+    checksum: Option<String>,
+}
+
+/// Request body for creating an image from a VM's disk.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct CreateImageRequest {
+    /// UUID of the VM. It must be stopped, crashed or running; a running VM
+    /// needs qemu-guest-agent to freeze its filesystems for the copy.
+    vm_id: String,
+    name: String,
+    description: Option<String>,
+    /// Defaults to the OS of the image the VM was launched from.
+    os: Option<String>,
+    /// Defaults to the version of the image the VM was launched from.
+    version: Option<String>,
+}
+
+/// Response returned after an image registration attempt.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct RegisterImageResponse {
+    success: bool,
+    message: String,
+    /// UUID of the newly registered image. Present on success.
+    id: Option<String>,
+    /// SHA-256 computed by the backend. Present on success.
+    checksum: Option<String>,
+}
+
+/// Request body for deleting an image.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct DeleteImageRequest {
+    /// UUID of the image to delete.
+    id: String,
 }
 
 #[derive(Clone)]
@@ -137,6 +471,8 @@ pub struct AppState {
     pub vm_backends_file: PathBuf,
     /// Path to the JSON file used to persist volume_id → backend_url across restarts.
     pub volume_backends_file: PathBuf,
+    /// Path to the JSON file used to persist image_id → backend_url across restarts.
+    pub image_backends_file: PathBuf,
 }
 
 /// Load a vm_id → backend_url map from a JSON file. Returns an empty map if
@@ -183,18 +519,43 @@ async fn save_vm_backends(path: &Path, backends: &HashMap<String, String>) {
     }
 }
 
+/// Load an image_id → backend_url map from a JSON file. Returns an empty map if
+/// the file is absent or unreadable — this is normal on first startup.
+async fn load_image_backends(path: &Path) -> HashMap<String, String> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Persist the current image_id → backend_url map to disk. Logs a warning on
+/// failure rather than propagating an error — a failed write is non-fatal.
+async fn save_image_backends(path: &Path, backends: &HashMap<String, String>) {
+    match serde_json::to_string(backends) {
+        Ok(content) => {
+            if let Err(e) = tokio::fs::write(path, content).await {
+                tracing::warn!("Failed to persist image_backends to {path:?}: {e}");
+            }
+        }
+        Err(e) => tracing::error!("Failed to serialize image_backends: {e}"),
+    }
+}
+
 #[utoipa::path(
     post,
-    path = "/launch-volume",
-    request_body = LaunchVolumeRequest,
+    path = "/register-image",
+    request_body = RegisterImageRequest,
     responses(
-        (status = 200, description = "Volume created and mounted", body = LaunchVolumeResponse),
-        (status = 500, description = "Failed to create or mount volume", body = LaunchVolumeResponse),
+        (status = 200, description = "Image copied or downloaded into the catalog", body = RegisterImageResponse),
+        (status = 400, description = "Neither or both of source_path and source_url given, or source_path outside the import directory", body = RegisterImageResponse),
+        (status = 422, description = "Source file missing, not a standalone qcow2 image, or checksum mismatch", body = RegisterImageResponse),
         (status = 503, description = "No backend worker is registered"),
     ),
-    tag = "volumes"
+    tag = "images"
 )]
-async fn launch_volume_handler(
+/// Round-robin /register-image: selects the next backend, forwards the request,
+/// and if the backend accepts it records the image_id → backend mapping.
+async fn register_image_handler(
     State(state): State<AppState>,
     request: Request<Body>,
 ) -> impl IntoResponse {
@@ -218,21 +579,30 @@ async fn launch_volume_handler(
         .proxy_service
         .proxy_request_to(backend_url.clone(), method, uri, headers, body, None)
         .await;
+    record_image_backend(&state, response, backend_url).await
+}
 
+/// If the backend added an image to its catalog, record the image_id →
+/// backend mapping from the response's `id`.
+async fn record_image_backend(
+    state: &AppState,
+    response: Response<Body>,
+    backend_url: String,
+) -> axum::response::Response {
     if response.status().is_success() {
         let (parts, resp_body) = response.into_parts();
         let bytes = axum::body::to_bytes(resp_body, usize::MAX)
             .await
             .unwrap_or_default();
         if let Ok(val) = serde_json::from_slice::<serde_json::Value>(&bytes) {
-            if let Some(volume_id) = val.get("id").and_then(|v| v.as_str()) {
+            if let Some(image_id) = val.get("id").and_then(|v| v.as_str()) {
                 state
                     .registry
                     .write()
                     .await
-                    .register_volume(volume_id.to_string(), backend_url);
-                let backends = state.registry.read().await.all_volume_backends();
-                save_volume_backends(&state.volume_backends_file, &backends).await;
+                    .register_image(image_id.to_string(), backend_url);
+                let backends = state.registry.read().await.all_image_backends();
+                save_image_backends(&state.image_backends_file, &backends).await;
             }
         }
         return Response::from_parts(parts, Body::from(bytes)).into_response();
@@ -242,35 +612,24 @@ async fn launch_volume_handler(
 }
 
 #[utoipa::path(
-    get,
-    path = "/list-volumes",
-    responses(
-        (status = 200, description = "Aggregated list of all volumes across all backends", body = Vec<VolumeInfo>),
-        (status = 503, description = "No backend worker is registered"),
-    ),
-    tag = "volumes"
-)]
-async fn list_volumes_handler(
-    State(state): State<AppState>,
-    request: Request<Body>,
-) -> impl IntoResponse {
-    let headers = request.headers().clone();
-    let uri = request.uri().clone();
-    state.proxy_service.list_all(uri.path(), headers).await
-}
-
-#[utoipa::path(
-    delete,
-    path = "/delete-volume",
-    request_body = DeleteVolumeRequest,
+    post,
+    path = "/create-image",
+    request_body = CreateImageRequest,
     responses(
-        (status = 200, description = "Volume unmounted and removed"),
+        (status = 200, description = "VM disk copied into the catalog of the VM's backend", body = RegisterImageResponse),
         (status = 400, description = "Invalid request body"),
-        (status = 404, description = "Volume ID not known to this proxy"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is paused or between states"),
+        (status = 422, description = "The guest agent refused to freeze the filesystems", body = RegisterImageResponse),
+        (status = 503, description = "Running VM has no guest agent channel", body = RegisterImageResponse),
+        (status = 504, description = "Running VM's guest agent did not answer", body = RegisterImageResponse),
     ),
-    tag = "volumes"
+    tag = "images"
 )]
-async fn delete_volume_handler(
+/// Route /create-image to the backend that owns the VM, which flattens the
+/// VM's disk into a new image in its catalog, and record the image_id →
+/// backend mapping so the image can be launched.
+async fn create_image_handler(
     State(state): State<AppState>,
     request: Request<Body>,
 ) -> impl IntoResponse {
@@ -279,20 +638,19 @@ async fn delete_volume_handler(
         .await
         .unwrap_or_default();
 
-    let volume_id = match serde_json::from_slice::<DeleteVolumeRequest>(&bytes) {
-        Ok(req) => req.id,
+    let vm_id = match serde_json::from_slice::<CreateImageRequest>(&bytes) {
+        Ok(req) => req.vm_id,
         Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
     };
-
-    let backend_url = match state.registry.read().await.backend_for_volume(&volume_id) {
+    let backend_url = match state.registry.read().await.backend_for_vm(&vm_id) {
         Some(u) => u,
-        None => return (StatusCode::NOT_FOUND, "Unknown volume ID").into_response(),
+        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
     };
 
     let response = state
         .proxy_service
         .proxy_request_to(
-            backend_url,
+            backend_url.clone(),
             parts.method,
             parts.uri,
             parts.headers,
@@ -300,64 +658,79 @@ async fn delete_volume_handler(
             None,
         )
         .await;
-
-    if response.status().is_success() {
-        state.registry.write().await.remove_volume(&volume_id);
-        let backends = state.registry.read().await.all_volume_backends();
-        save_volume_backends(&state.volume_backends_file, &backends).await;
-    }
-
-    response.into_response()
+    record_image_backend(&state, response, backend_url).await
 }
 
 #[utoipa::path(
     get,
-    path = "/volume-files/{id}",
+    path = "/image-file/{id}",
     params(
-        ("id" = String, Path, description = "Volume UUID")
+        ("id" = String, Path, description = "Image UUID")
     ),
     responses(
-        (status = 200, description = "List of files in the volume root", body = Vec<VolumeFileEntry>),
-        (status = 404, description = "Volume ID not known to this proxy"),
+        (status = 200, description = "The image's qcow2 file", content_type = "application/octet-stream"),
+        (status = 404, description = "Image ID not known to this proxy"),
     ),
-    tag = "volumes"
+    tag = "images"
 )]
-async fn list_volume_files_handler(
+/// Route /image-file to the backend storing the image. Pass this URL as
+/// `source_url` to /register-image to copy the image to another backend.
+async fn image_file_handler(
     State(state): State<AppState>,
     axum::extract::Path(id): axum::extract::Path<String>,
     request: Request<Body>,
 ) -> impl IntoResponse {
-    let backend_url = match state.registry.read().await.backend_for_volume(&id) {
+    let backend_url = match state.registry.read().await.backend_for_image(&id) {
         Some(u) => u,
-        None => return (StatusCode::NOT_FOUND, "Unknown volume ID").into_response(),
+        None => return (StatusCode::NOT_FOUND, "Unknown image ID").into_response(),
     };
 
-    let method = request.method().clone();
-    let uri = request.uri().clone();
-    let headers = request.headers().clone();
-
+    let (parts, _) = request.into_parts();
     state
         .proxy_service
-        .proxy_request_to(backend_url, method, uri, headers, None, None)
+        .proxy_request_to(
+            backend_url,
+            parts.method,
+            parts.uri,
+            parts.headers,
+            None,
+            None,
+        )
         .await
         .into_response()
 }
 
 #[utoipa::path(
-    post,
-    path = "/stop-vm",
-    request_body = StopVmRequest,
+    get,
+    path = "/list-images",
+    responses(
+        (status = 200, description = "Aggregated list of all images across all backends", body = Vec<ImageInfo>),
+        (status = 503, description = "No backend worker is registered"),
+    ),
+    tag = "images"
+)]
+async fn list_images_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let headers = request.headers().clone();
+    let uri = request.uri().clone();
+    state.proxy_service.list_all(uri.path(), headers).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/delete-image",
+    request_body = DeleteImageRequest,
     responses(
-        (status = 200, description = "ACPI shutdown signal sent; VM is powering off"),
+        (status = 200, description = "Image removed from the catalog"),
+        (status = 409, description = "Image still backs one or more VM disks"),
         (status = 400, description = "Invalid request body"),
-        (status = 404, description = "VM ID not known to this proxy"),
-        (status = 409, description = "VM is not currently running"),
+        (status = 404, description = "Image ID not known to this proxy"),
     ),
-    tag = "vms"
+    tag = "images"
 )]
-/// Route /stop-vm to the backend that owns the VM. The backend sends
-/// `system_powerdown` via the QEMU monitor for a graceful ACPI shutdown.
-async fn stop_vm_handler(
+async fn delete_image_handler(
     State(state): State<AppState>,
     request: Request<Body>,
 ) -> impl IntoResponse {
@@ -366,17 +739,17 @@ async fn stop_vm_handler(
         .await
         .unwrap_or_default();
 
-    let vm_id = match serde_json::from_slice::<StopVmRequest>(&bytes) {
+    let image_id = match serde_json::from_slice::<DeleteImageRequest>(&bytes) {
         Ok(req) => req.id,
         Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
     };
 
-    let backend_url = match state.registry.read().await.backend_for_vm(&vm_id) {
+    let backend_url = match state.registry.read().await.backend_for_image(&image_id) {
         Some(u) => u,
-        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
+        None => return (StatusCode::NOT_FOUND, "Unknown image ID").into_response(),
     };
 
-    state
+    let response = state
         .proxy_service
         .proxy_request_to(
             backend_url,
@@ -386,201 +759,29 @@ async fn stop_vm_handler(
             Some(Body::from(bytes)),
             None,
         )
-        .await
-        .into_response()
+        .await;
+
+    if response.status().is_success() {
+        state.registry.write().await.remove_image(&image_id);
+        let backends = state.registry.read().await.all_image_backends();
+        save_image_backends(&state.image_backends_file, &backends).await;
+    }
+
+    response.into_response()
 }
 
 #[utoipa::path(
     post,
-    path = "/start-vm",
-    request_body = StartVmRequest,
+    path = "/launch-volume",
+    request_body = LaunchVolumeRequest,
     responses(
-        (status = 200, description = "VM re-launched from its existing disk image"),
-        (status = 400, description = "Invalid request body"),
-        (status = 404, description = "VM ID not known to this proxy"),
-        (status = 409, description = "VM is already running"),
+        (status = 200, description = "Volume created and mounted", body = LaunchVolumeResponse),
+        (status = 500, description = "Failed to create or mount volume", body = LaunchVolumeResponse),
+        (status = 503, description = "No backend worker is registered"),
     ),
-    tag = "vms"
+    tag = "volumes"
 )]
-/// Route /start-vm to the backend that owns the VM. The backend re-launches
-/// the QEMU process from the existing qcow2 image.
-async fn start_vm_handler(
-    State(state): State<AppState>,
-    request: Request<Body>,
-) -> impl IntoResponse {
-    let (parts, body) = request.into_parts();
-    let bytes = axum::body::to_bytes(body, usize::MAX)
-        .await
-        .unwrap_or_default();
-
-    let vm_id = match serde_json::from_slice::<StartVmRequest>(&bytes) {
-        Ok(req) => req.id,
-        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
-    };
-
-    let backend_url = match state.registry.read().await.backend_for_vm(&vm_id) {
-        Some(u) => u,
-        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
-    };
-
-    state
-        .proxy_service
-        .proxy_request_to(
-            backend_url,
-            parts.method,
-            parts.uri,
-            parts.headers,
-            Some(Body::from(bytes)),
-            None,
-        )
-        .await
-        .into_response()
-}
-
-#[derive(OpenApi)]
-#[openapi(
-    paths(
-        registry::register_handler,
-        launch_vm_handler,
-        list_vms_handler,
-        delete_vm_handler,
-        stop_vm_handler,
-        start_vm_handler,
-        launch_volume_handler,
-        list_volumes_handler,
-        delete_volume_handler,
-        list_volume_files_handler,
-    ),
-    components(schemas(
-        registry::RegisterRequest,
-        registry::RegisterResponse,
-        LaunchVmRequest,
-        LaunchVmResponse,
-        VmListEntry,
-        DeleteVmRequest,
-        StopVmRequest,
-        StartVmRequest,
-        LaunchVolumeRequest,
-        LaunchVolumeResponse,
-        VolumeInfo,
-        DeleteVolumeRequest,
-        VolumeFileEntry,
-    )),
-    tags(
-        (name = "vms", description = "VM lifecycle management"),
-        (name = "volumes", description = "Volume lifecycle management"),
-        (name = "internal", description = "Backend registration — called by worker nodes on startup, not by end users"),
-    ),
-    info(title = "Andy's Web Services API", version = "0.1.0")
-)]
-struct ApiDoc;
-
-#[tokio::main]
-async fn main() {
-    let config = Config::load().expect("Failed to load configuration");
-
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(&config.log_level))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
-    tracing::info!("Starting proxy server with config: {:?}", config);
-
-    let registry: Arc<RwLock<BackendRegistry>> = Arc::new(RwLock::new(BackendRegistry::new()));
-
-    // Restore vm_id → backend_url mappings saved before the last proxy restart.
-    let saved_vms = load_vm_backends(&config.vm_backends_file).await;
-    let vm_count = saved_vms.len();
-    {
-        let mut reg = registry.write().await;
-        for (vm_id, backend_url) in saved_vms {
-            reg.register_vm(vm_id, backend_url);
-        }
-    }
-    if vm_count > 0 {
-        tracing::info!(
-            "Restored {vm_count} VM-backend mapping(s) from {:?}",
-            config.vm_backends_file
-        );
-    }
-
-    // Restore volume_id → backend_url mappings saved before the last proxy restart.
-    let saved_volumes = load_volume_backends(&config.volume_backends_file).await;
-    let volume_count = saved_volumes.len();
-    {
-        let mut reg = registry.write().await;
-        for (volume_id, backend_url) in saved_volumes {
-            reg.register_volume(volume_id, backend_url);
-        }
-    }
-    if volume_count > 0 {
-        tracing::info!(
-            "Restored {volume_count} volume-backend mapping(s) from {:?}",
-            config.volume_backends_file
-        );
-    }
-
-    let proxy_service = Arc::new(ProxyService::new(
-        Arc::clone(&registry),
-        config.lease_file.clone(),
-    ));
-    let state = AppState {
-        proxy_service,
-        registry,
-        vm_backends_file: config.vm_backends_file.clone(),
-        volume_backends_file: config.volume_backends_file.clone(),
-    };
-
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
-    let api_router = Router::new()
-        .route("/register", post(registry::register_handler))
-        .route("/launch-vm", post(launch_vm_handler))
-        .route("/list-vms", get(list_vms_handler))
-        .route("/delete-vm", delete(delete_vm_handler))
-        .route("/stop-vm", post(stop_vm_handler))
-        .route("/start-vm", post(start_vm_handler))
-        .route("/launch-volume", post(launch_volume_handler))
-        .route("/list-volumes", get(list_volumes_handler))
-        .route("/delete-volume", delete(delete_volume_handler))
-        .route("/volume-files/:id", get(list_volume_files_handler))
-        .fallback(proxy_handler)
-        .with_state(state);
-
-    let app = api_router
-        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
-        .layer(cors);
-
-    let listener =
-        tokio::net::TcpListener::bind(format!("{}:{}", config.listen_ip, config.proxy_port))
-            .await
-            .unwrap();
-
-    tracing::info!(
-        "Proxy server listening on {}",
-        listener.local_addr().unwrap()
-    );
-    tracing::info!("Waiting for backend to register via POST /register");
-
-    axum::serve(listener, app).await.unwrap();
-}
-
-#[utoipa::path(
-    post,
-    path = "/launch-vm",
-    request_body = LaunchVmRequest,
-    responses(
-        (status = 200, description = "VM launched successfully", body = LaunchVmResponse),
-        (status = 503, description = "No backend worker is registered"),
-    ),
-    tag = "vms"
-)]
-/// Round-robin /launch-vm: selects the next backend, forwards the request,
-/// and if the backend accepts it records the vm_id → backend mapping.
-async fn launch_vm_handler(
+async fn launch_volume_handler(
     State(state): State<AppState>,
     request: Request<Body>,
 ) -> impl IntoResponse {
@@ -605,21 +806,30 @@ async fn launch_vm_handler(
         .proxy_request_to(backend_url.clone(), method, uri, headers, body, None)
         .await;
 
-    // Only record the VM→backend mapping if the launch succeeded.
+    record_volume_backend(&state, response, backend_url).await
+}
+
+/// Remember that the volume whose `id` a successful backend response carries
+/// lives on `backend_url`, then pass the response on.
+async fn record_volume_backend(
+    state: &AppState,
+    response: Response<Body>,
+    backend_url: String,
+) -> axum::response::Response {
     if response.status().is_success() {
         let (parts, resp_body) = response.into_parts();
         let bytes = axum::body::to_bytes(resp_body, usize::MAX)
             .await
             .unwrap_or_default();
         if let Ok(val) = serde_json::from_slice::<serde_json::Value>(&bytes) {
-            if let Some(vm_id) = val.get("instance_id").and_then(|v| v.as_str()) {
+            if let Some(volume_id) = val.get("id").and_then(|v| v.as_str()) {
                 state
                     .registry
                     .write()
                     .await
-                    .register_vm(vm_id.to_string(), backend_url);
-                let backends = state.registry.read().await.all_vm_backends();
-                save_vm_backends(&state.vm_backends_file, &backends).await;
+                    .register_volume(volume_id.to_string(), backend_url);
+                let backends = state.registry.read().await.all_volume_backends();
+                save_volume_backends(&state.volume_backends_file, &backends).await;
             }
         }
         return Response::from_parts(parts, Body::from(bytes)).into_response();
@@ -630,15 +840,14 @@ async fn launch_vm_handler(
 
 #[utoipa::path(
     get,
-    path = "/list-vms",
+    path = "/list-volumes",
     responses(
-        (status = 200, description = "Aggregated list of all running VMs across all backends", body = Vec<VmListEntry>),
+        (status = 200, description = "Aggregated list of all volumes across all backends", body = Vec<VolumeInfo>),
         (status = 503, description = "No backend worker is registered"),
     ),
-    tag = "vms"
+    tag = "volumes"
 )]
-/// Fan-out /list-vms to all backends and return the merged JSON array.
-async fn list_vms_handler(
+async fn list_volumes_handler(
     State(state): State<AppState>,
     request: Request<Body>,
 ) -> impl IntoResponse {
@@ -647,27 +856,117 @@ async fn list_vms_handler(
     state.proxy_service.list_all(uri.path(), headers).await
 }
 
-#[derive(serde::Deserialize, utoipa::ToSchema)]
-struct DeleteVmRequest {
-    /// UUID of the VM to delete.
-    id: String,
+/// Volume usage summed over a set of volumes.
+#[derive(Debug, Default, PartialEq, serde::Serialize, utoipa::ToSchema)]
+struct VolumeUsageTotals {
+    volumes: u64,
+    size_bytes: u64,
+    allocated_bytes: u64,
+    /// Summed over the volumes that are mounted on their workers.
+    fs_used_bytes: u64,
+    /// Summed over the volumes that are mounted on their workers.
+    fs_free_bytes: u64,
+}
+
+impl VolumeUsageTotals {
+    /// Add one `/list-volumes` entry; missing fields count as zero.
+    fn add(&mut self, volume: &serde_json::Value) {
+        let field = |name: &str| volume.get(name).and_then(|v| v.as_u64()).unwrap_or(0);
+        self.volumes += 1;
+        self.size_bytes += field("size_bytes");
+        self.allocated_bytes += field("allocated_bytes");
+        self.fs_used_bytes += field("fs_used_bytes");
+        self.fs_free_bytes += field("fs_free_bytes");
+    }
+
+    fn merge(&mut self, other: &VolumeUsageTotals) {
+        self.volumes += other.volumes;
+        self.size_bytes += other.size_bytes;
+        self.allocated_bytes += other.allocated_bytes;
+        self.fs_used_bytes += other.fs_used_bytes;
+        self.fs_free_bytes += other.fs_free_bytes;
+    }
+}
+
+/// Volume usage of one backend worker.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct BackendVolumeUsage {
+    /// Base URL of the backend.
+    backend: String,
+    #[serde(flatten)]
+    usage: VolumeUsageTotals,
+}
+
+/// Volume usage per backend and across all of them.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct VolumeUsageReport {
+    /// One entry per backend that answered, sorted by URL.
+    backends: Vec<BackendVolumeUsage>,
+    total: VolumeUsageTotals,
+}
+
+#[utoipa::path(
+    get,
+    path = "/volume-usage",
+    responses(
+        (status = 200, description = "Volume usage per backend and in total", body = VolumeUsageReport),
+        (status = 503, description = "No backend worker is registered"),
+    ),
+    tag = "volumes"
+)]
+/// Fan out `/list-volumes` to every backend and total the capacity and usage
+/// of each backend's volumes. Backends that do not answer are left out.
+async fn volume_usage_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let headers = request.headers().clone();
+    let Some(results) = state
+        .proxy_service
+        .fetch_all("/list-volumes", headers)
+        .await
+    else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Backend not yet registered",
+        )
+            .into_response();
+    };
+    axum::Json(volume_usage_report(results)).into_response()
+}
+
+fn volume_usage_report(results: Vec<(String, Vec<serde_json::Value>)>) -> VolumeUsageReport {
+    let mut backends: Vec<BackendVolumeUsage> = results
+        .into_iter()
+        .map(|(backend, volumes)| {
+            let mut usage = VolumeUsageTotals::default();
+            for volume in &volumes {
+                usage.add(volume);
+            }
+            BackendVolumeUsage { backend, usage }
+        })
+        .collect();
+    backends.sort_by(|a, b| a.backend.cmp(&b.backend));
+
+    let mut total = VolumeUsageTotals::default();
+    for backend in &backends {
+        total.merge(&backend.usage);
+    }
+    VolumeUsageReport { backends, total }
 }
 
 #[utoipa::path(
     delete,
-    path = "/delete-vm",
-    request_body = DeleteVmRequest,
+    path = "/delete-volume",
+    request_body = DeleteVolumeRequest,
     responses(
-        (status = 200, description = "VM terminated and removed"),
+        (status = 200, description = "Volume unmounted and removed"),
         (status = 400, description = "Invalid request body"),
-        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 404, description = "Volume ID not known to this proxy"),
     ),
-    tag = "vms"
+    tag = "volumes"
 )]
-/// Route /delete-vm to the backend that owns the VM, identified by the ID in
-/// the request body. Returns 400 if the body is invalid JSON, 404 if the VM
-/// is not known to the registry.
-async fn delete_vm_handler(
+async fn delete_volume_handler(
     State(state): State<AppState>,
     request: Request<Body>,
 ) -> impl IntoResponse {
@@ -676,14 +975,14 @@ async fn delete_vm_handler(
         .await
         .unwrap_or_default();
 
-    let vm_id = match serde_json::from_slice::<DeleteVmRequest>(&bytes) {
+    let volume_id = match serde_json::from_slice::<DeleteVolumeRequest>(&bytes) {
         Ok(req) => req.id,
         Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
     };
 
-    let backend_url = match state.registry.read().await.backend_for_vm(&vm_id) {
+    let backend_url = match state.registry.read().await.backend_for_volume(&volume_id) {
         Some(u) => u,
-        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
+        None => return (StatusCode::NOT_FOUND, "Unknown volume ID").into_response(),
     };
 
     let response = state
@@ -699,963 +998,3888 @@ async fn delete_vm_handler(
         .await;
 
     if response.status().is_success() {
-        state.registry.write().await.remove_vm(&vm_id);
-        let backends = state.registry.read().await.all_vm_backends();
-        save_vm_backends(&state.vm_backends_file, &backends).await;
-    }
+        state.registry.write().await.remove_volume(&volume_id);
+        let backends = state.registry.read().await.all_volume_backends();
+        save_volume_backends(&state.volume_backends_file, &backends).await;
+    }
 
     response.into_response()
 }
 
-async fn proxy_handler(State(state): State<AppState>, request: Request<Body>) -> impl IntoResponse {
-    let method = request.method().clone();
-    let uri = request.uri().clone();
-    let headers = request.headers().clone();
-    let body = Some(request.into_body());
+#[utoipa::path(
+    get,
+    path = "/volume-files/{id}",
+    params(
+        ("id" = String, Path, description = "Volume UUID"),
+        ("path" = Option<String>, Query, description = "Directory to list, relative to the volume's root; defaults to the root"),
+        ("depth" = Option<u32>, Query, description = "Levels to list: 1 (the default) lists the directory's own entries, 2 adds its subdirectories' and so on, up to 64"),
+        ("sort" = Option<String>, Query, description = "`name` (the default, by path), `size` or `modified`"),
+        ("order" = Option<String>, Query, description = "`asc` (the default) or `desc`"),
+        ("limit" = Option<usize>, Query, description = "Entries per page; defaults to 1000, at most 10000"),
+        ("cursor" = Option<String>, Query, description = "`next_cursor` of the previous page; keep the other parameters the same"),
+    ),
+    responses(
+        (status = 200, description = "One page of the directory's entries", body = VolumeFileListing),
+        (status = 400, description = "Path leaves the volume or is not a directory, or an invalid parameter"),
+        (status = 404, description = "Volume ID not known to this proxy, or no such directory"),
+        (status = 409, description = "Volume is not mounted on the host"),
+    ),
+    tag = "volumes"
+)]
+/// Route /volume-files to the backend that owns the volume, keeping the
+/// query string that selects the page.
+async fn list_volume_files_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_file(&state, &id, query, request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/volume-file/{id}",
+    params(
+        ("id" = String, Path, description = "Volume UUID"),
+        ("path" = String, Query, description = "Path of the file, relative to the volume's root"),
+    ),
+    responses(
+        (status = 200, description = "File contents", content_type = "application/octet-stream"),
+        (status = 400, description = "Path leaves the volume or names a directory"),
+        (status = 404, description = "Volume ID not known to this proxy, or no such file"),
+        (status = 409, description = "Volume is not mounted on the host"),
+    ),
+    tag = "volumes"
+)]
+/// Route GET /volume-file to the backend that owns the volume, which streams
+/// the file at `path` back.
+async fn volume_file_get_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_file(&state, &id, query, request).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/volume-file/{id}",
+    params(
+        ("id" = String, Path, description = "Volume UUID"),
+        ("path" = String, Query, description = "Path of the file, relative to the volume's root; created or replaced"),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "File written", body = WriteFileResponse),
+        (status = 400, description = "Path leaves the volume"),
+        (status = 404, description = "Volume ID not known to this proxy, or the parent directory is missing"),
+        (status = 409, description = "Volume is not mounted, or the path is a directory"),
+    ),
+    tag = "volumes"
+)]
+/// Route PUT /volume-file to the backend that owns the volume. The body is
+/// streamed through to the backend, which writes it to `path`.
+async fn volume_file_put_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_file(&state, &id, query, request).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/volume-file/{id}",
+    params(
+        ("id" = String, Path, description = "Volume UUID"),
+        ("path" = String, Query, description = "Path of the file or directory, relative to the volume's root"),
+        ("recursive" = Option<bool>, Query, description = "Also delete a directory's contents"),
+    ),
+    responses(
+        (status = 200, description = "Deleted"),
+        (status = 400, description = "Path leaves the volume or names its root"),
+        (status = 404, description = "Volume ID not known to this proxy, or no such file"),
+        (status = 409, description = "Volume is not mounted, or the directory is not empty"),
+    ),
+    tag = "volumes"
+)]
+/// Route DELETE /volume-file to the backend that owns the volume.
+async fn volume_file_delete_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_file(&state, &id, query, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/volume-mkdir/{id}",
+    params(
+        ("id" = String, Path, description = "Volume UUID"),
+        ("path" = String, Query, description = "Directory to create, with any missing parents"),
+    ),
+    responses(
+        (status = 200, description = "Directory exists"),
+        (status = 400, description = "Path leaves the volume"),
+        (status = 404, description = "Volume ID not known to this proxy"),
+        (status = 409, description = "Volume is not mounted, or a file is in the way"),
+    ),
+    tag = "volumes"
+)]
+/// Route /volume-mkdir to the backend that owns the volume.
+async fn volume_mkdir_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_file(&state, &id, query, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/volume-rename/{id}",
+    params(
+        ("id" = String, Path, description = "Volume UUID")
+    ),
+    request_body = RenameVolumeFileRequest,
+    responses(
+        (status = 200, description = "Renamed"),
+        (status = 400, description = "A path leaves the volume, or a directory would move into itself"),
+        (status = 404, description = "Volume ID not known to this proxy, or no such file"),
+        (status = 409, description = "Volume is not mounted, or `to` already exists"),
+    ),
+    tag = "volumes"
+)]
+/// Route /volume-rename to the backend that owns the volume.
+async fn volume_rename_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_file(&state, &id, query, request).await
+}
+
+/// Forward a volume file request to the volume's backend with its query
+/// string, streaming the body in both directions.
+async fn forward_volume_file(
+    state: &AppState,
+    volume_id: &str,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let backend_url = match state.registry.read().await.backend_for_volume(volume_id) {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "Unknown volume ID").into_response(),
+    };
+
+    let (parts, body) = request.into_parts();
+    state
+        .proxy_service
+        .proxy_request_to(
+            backend_url,
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(body),
+            Some(query),
+        )
+        .await
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/mount-volume",
+    request_body = MountVolumeRequest,
+    responses(
+        (status = 200, description = "Volume mounted", body = VolumeInfo),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "Volume ID not known to this proxy"),
+        (status = 409, description = "Volume is already mounted or is attached to a VM"),
+    ),
+    tag = "volumes"
+)]
+/// Route /mount-volume to the backend that owns the volume.
+async fn mount_volume_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let volume_id = match serde_json::from_slice::<MountVolumeRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_volume_backend(&state, parts, bytes, &volume_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/resize-volume",
+    request_body = ResizeVolumeRequest,
+    responses(
+        (status = 200, description = "Volume and its filesystem grown", body = VolumeInfo),
+        (status = 400, description = "Invalid request body, or the size is not larger"),
+        (status = 404, description = "Volume ID not known to this proxy"),
+        (status = 409, description = "Volume is attached to a VM"),
+    ),
+    tag = "volumes"
+)]
+/// Route /resize-volume to the backend that owns the volume. The backend
+/// extends the image file and grows its ext4 filesystem, online if the volume
+/// is mounted.
+async fn resize_volume_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let volume_id = match serde_json::from_slice::<ResizeVolumeRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_volume_backend(&state, parts, bytes, &volume_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/create-volume-snapshot",
+    request_body = VolumeSnapshotRequest,
+    responses(
+        (status = 200, description = "Snapshot taken", body = VolumeSnapshot),
+        (status = 400, description = "Invalid request body or snapshot name"),
+        (status = 404, description = "Volume ID not known to this proxy"),
+        (status = 409, description = "Name already in use, or the volume's VM is between states"),
+    ),
+    tag = "volumes"
+)]
+/// Route /create-volume-snapshot to the backend that owns the volume. The
+/// volume's filesystem is frozen for the copy: with `fsfreeze` when it is
+/// mounted on the worker, through the guest agent when its VM is running.
+async fn create_volume_snapshot_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_snapshot_request(&state, request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/list-volume-snapshots/{id}",
+    params(
+        ("id" = String, Path, description = "Volume UUID")
+    ),
+    responses(
+        (status = 200, description = "The volume's snapshots, oldest first", body = Vec<VolumeSnapshot>),
+        (status = 404, description = "Volume ID not known to this proxy"),
+    ),
+    tag = "volumes"
+)]
+/// Route /list-volume-snapshots to the backend that owns the volume.
+async fn list_volume_snapshots_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, _) = request.into_parts();
+    forward_to_volume_backend(&state, parts, axum::body::Bytes::new(), &id).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/delete-volume-snapshot",
+    request_body = VolumeSnapshotRequest,
+    responses(
+        (status = 200, description = "Snapshot deleted"),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "Volume or snapshot not found"),
+    ),
+    tag = "volumes"
+)]
+/// Route /delete-volume-snapshot to the backend that owns the volume. The
+/// volume and its other snapshots are unaffected.
+async fn delete_volume_snapshot_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_snapshot_request(&state, request).await
+}
+
+/// Forward a volume snapshot request to the backend that owns the
+/// `volume_id` in its body.
+async fn forward_volume_snapshot_request(
+    state: &AppState,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let volume_id = match serde_json::from_slice::<VolumeSnapshotRequest>(&bytes) {
+        Ok(req) => req.volume_id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_volume_backend(state, parts, bytes, &volume_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/clone-volume",
+    request_body = CloneVolumeRequest,
+    responses(
+        (status = 200, description = "Volume created from the snapshot and mounted", body = LaunchVolumeResponse),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "Volume or snapshot not found"),
+        (status = 500, description = "Failed to copy or mount the new volume", body = LaunchVolumeResponse),
+    ),
+    tag = "volumes"
+)]
+/// Route /clone-volume to the backend that owns the source volume, which
+/// creates the new volume, and record that it lives there.
+async fn clone_volume_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let volume_id = match serde_json::from_slice::<CloneVolumeRequest>(&bytes) {
+        Ok(req) => req.volume_id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+    let backend_url = match state.registry.read().await.backend_for_volume(&volume_id) {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "Unknown volume ID").into_response(),
+    };
+
+    let response = state
+        .proxy_service
+        .proxy_request_to(
+            backend_url.clone(),
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(Body::from(bytes)),
+            None,
+        )
+        .await;
+    record_volume_backend(&state, response, backend_url).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/export-volume/{id}",
+    params(
+        ("id" = String, Path, description = "Volume UUID"),
+        ("compression" = Option<String>, Query, description = "`none` (the default) or `zstd`"),
+    ),
+    responses(
+        (status = 200, description = "Tar archive of the volume's files, streamed; a body cut short means tar failed part way", content_type = "application/x-tar"),
+        (status = 404, description = "Volume ID not known to this proxy"),
+        (status = 409, description = "Volume is not mounted on the host"),
+    ),
+    tag = "volumes"
+)]
+/// Route /export-volume to the backend that owns the volume. The archive is
+/// streamed through, never held by the proxy.
+async fn export_volume_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_file(&state, &id, query, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/import-volume",
+    params(
+        ("name" = String, Query, description = "Name of the new volume"),
+        ("size_gb" = u64, Query, description = "Size of the new volume in GiB; the archive's contents must fit"),
+        ("compression" = Option<String>, Query, description = "`none` (the default) or `zstd`"),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/x-tar"),
+    responses(
+        (status = 200, description = "Volume created from the archive and mounted", body = LaunchVolumeResponse),
+        (status = 400, description = "Missing or invalid query parameters"),
+        (status = 422, description = "The archive could not be unpacked, e.g. it is corrupt, does not fit or has members outside the volume", body = LaunchVolumeResponse),
+        (status = 500, description = "Failed to create or mount the volume", body = LaunchVolumeResponse),
+        (status = 503, description = "No backend worker is registered"),
+    ),
+    tag = "volumes"
+)]
+/// Route /import-volume to a backend picked like /launch-volume's, streaming
+/// the archive through, and record that the new volume lives there.
+async fn import_volume_handler(
+    State(state): State<AppState>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let backend_url = match state.registry.write().await.round_robin_url() {
+        Some(u) => u,
+        None => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Backend not yet registered",
+            )
+                .into_response();
+        }
+    };
+
+    let (parts, body) = request.into_parts();
+    let response = state
+        .proxy_service
+        .proxy_request_to(
+            backend_url.clone(),
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(body),
+            Some(query),
+        )
+        .await;
+    record_volume_backend(&state, response, backend_url).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/unmount-volume",
+    request_body = UnmountVolumeRequest,
+    responses(
+        (status = 200, description = "Volume unmounted", body = VolumeInfo),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "Volume ID not known to this proxy"),
+        (status = 409, description = "Volume is not mounted"),
+    ),
+    tag = "volumes"
+)]
+/// Route /unmount-volume to the backend that owns the volume. A volume has to
+/// be unmounted before it can be attached to a VM.
+async fn unmount_volume_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let volume_id = match serde_json::from_slice::<UnmountVolumeRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_volume_backend(&state, parts, bytes, &volume_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/attach-volume",
+    request_body = AttachVolumeRequest,
+    responses(
+        (status = 200, description = "Volume attached", body = VolumeInfo),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM or volume ID not known to this proxy"),
+        (status = 409, description = "VM and volume are on different backends, or the volume is mounted or attached elsewhere"),
+    ),
+    tag = "volumes"
+)]
+/// Route /attach-volume to the backend that owns both the VM and the volume.
+/// The backend hot-plugs the disk into a running VM.
+async fn attach_volume_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_attachment(&state, request).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/detach-volume",
+    request_body = AttachVolumeRequest,
+    responses(
+        (status = 200, description = "Volume detached", body = VolumeInfo),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM or volume ID not known to this proxy"),
+        (status = 409, description = "Volume is not attached to the VM, or the VM is paused"),
+        (status = 504, description = "The guest did not release the disk"),
+    ),
+    tag = "volumes"
+)]
+/// Route /detach-volume to the backend that owns both the VM and the volume.
+async fn detach_volume_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_volume_attachment(&state, request).await
+}
+
+/// A volume's image is a file on its worker, so it can only be attached to a
+/// VM on that same worker. Anything else is refused here with a 409.
+async fn forward_volume_attachment(
+    state: &AppState,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let req = match serde_json::from_slice::<AttachVolumeRequest>(&bytes) {
+        Ok(req) => req,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    let registry = state.registry.read().await;
+    let Some(vm_backend) = registry.backend_for_vm(&req.vm_id) else {
+        return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response();
+    };
+    let Some(volume_backend) = registry.backend_for_volume(&req.volume_id) else {
+        return (StatusCode::NOT_FOUND, "Unknown volume ID").into_response();
+    };
+    drop(registry);
+    if vm_backend != volume_backend {
+        return (
+            StatusCode::CONFLICT,
+            "VM and volume are on different backends",
+        )
+            .into_response();
+    }
+
+    state
+        .proxy_service
+        .proxy_request_to(
+            vm_backend,
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(Body::from(bytes)),
+            None,
+        )
+        .await
+        .into_response()
+}
+
+/// Send a buffered request on to the backend that owns `volume_id`, or 404 if
+/// the volume is not in the registry.
+async fn forward_to_volume_backend(
+    state: &AppState,
+    parts: axum::http::request::Parts,
+    bytes: axum::body::Bytes,
+    volume_id: &str,
+) -> axum::response::Response {
+    let backend_url = match state.registry.read().await.backend_for_volume(volume_id) {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "Unknown volume ID").into_response(),
+    };
+
+    state
+        .proxy_service
+        .proxy_request_to(
+            backend_url,
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(Body::from(bytes)),
+            None,
+        )
+        .await
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/stop-vm",
+    request_body = StopVmRequest,
+    responses(
+        (status = 200, description = "VM stopped", body = StopVmResponse),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not in the running state"),
+        (status = 500, description = "QEMU survived every stop step"),
+    ),
+    tag = "vms"
+)]
+/// Route /stop-vm to the backend that owns the VM. The backend sends
+/// `system_powerdown` over QMP and waits up to `timeout_secs` for the guest
+/// to shut down, then escalates through QMP `quit`, SIGTERM and SIGKILL.
+async fn stop_vm_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let vm_id = match serde_json::from_slice::<StopVmRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_vm_backend(&state, parts, bytes, &vm_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/start-vm",
+    request_body = StartVmRequest,
+    responses(
+        (status = 200, description = "VM re-launched from its existing disk image"),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not stopped or crashed"),
+    ),
+    tag = "vms"
+)]
+/// Route /start-vm to the backend that owns the VM. The backend re-launches
+/// the QEMU process from the existing qcow2 image.
+async fn start_vm_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let vm_id = match serde_json::from_slice::<StartVmRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_vm_backend(&state, parts, bytes, &vm_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/reboot-vm",
+    request_body = RebootVmRequest,
+    responses(
+        (status = 200, description = "VM rebooted", body = RebootVmResponse),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not running"),
+    ),
+    tag = "vms"
+)]
+/// Route /reboot-vm to the backend that owns the VM. The backend asks the
+/// guest to shut down with QEMU set to pause rather than exit, then resets the
+/// machine; if the guest does not shut down within `timeout_secs` it is
+/// hard-reset.
+async fn reboot_vm_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let vm_id = match serde_json::from_slice::<RebootVmRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_vm_backend(&state, parts, bytes, &vm_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/pause-vm",
+    request_body = PauseVmRequest,
+    responses(
+        (status = 200, description = "VM paused"),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not running"),
+    ),
+    tag = "vms"
+)]
+/// Route /pause-vm to the backend that owns the VM. The backend halts the
+/// vCPUs with QMP `stop`; memory and devices are left as they are.
+async fn pause_vm_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let vm_id = match serde_json::from_slice::<PauseVmRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_vm_backend(&state, parts, bytes, &vm_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/resume-vm",
+    request_body = ResumeVmRequest,
+    responses(
+        (status = 200, description = "VM resumed"),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not paused"),
+    ),
+    tag = "vms"
+)]
+/// Route /resume-vm to the backend that owns the VM. The backend restarts the
+/// vCPUs with QMP `cont`.
+async fn resume_vm_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let vm_id = match serde_json::from_slice::<ResumeVmRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_vm_backend(&state, parts, bytes, &vm_id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/vm-console-log/{id}",
+    params(
+        ("id" = String, Path, description = "VM UUID"),
+        ("offset" = Option<u64>, Query, description = "Return output from this byte offset (default: oldest retained)"),
+        ("tail" = Option<usize>, Query, description = "Return only the last N lines"),
+    ),
+    responses(
+        (status = 200, description = "Serial console output", body = ConsoleLogChunk),
+        (status = 404, description = "VM ID not known to this proxy"),
+    ),
+    tag = "vms"
+)]
+/// Route /vm-console-log to the backend that owns the VM, keeping the query
+/// string so `offset` and `tail` reach it.
+async fn console_log_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let backend_url = match state.registry.read().await.backend_for_vm(&id) {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
+    };
+
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let headers = request.headers().clone();
+
+    state
+        .proxy_service
+        .proxy_request_to(backend_url, method, uri, headers, None, Some(query))
+        .await
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/vm-console/{id}",
+    params(
+        ("id" = String, Path, description = "VM UUID")
+    ),
+    responses(
+        (status = 101, description = "WebSocket attached to the VM's serial console"),
+        (status = 404, description = "VM ID not known to this proxy or its backend"),
+        (status = 409, description = "VM is not running"),
+        (status = 502, description = "Owning backend could not be reached"),
+    ),
+    tag = "vms"
+)]
+/// Upgrade to a WebSocket relayed to the serial console on the backend that
+/// owns the VM. Console output arrives as binary messages; text or binary
+/// messages sent by the client are typed into the console.
+async fn vm_console_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    ws: WebSocketUpgrade,
+    uri: Uri,
+) -> axum::response::Response {
+    relay_to_vm_backend(&state, &id, ws, &uri).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/vm-vnc/{id}",
+    params(
+        ("id" = String, Path, description = "VM UUID")
+    ),
+    responses(
+        (status = 101, description = "WebSocket carrying the VM's VNC (RFB) stream"),
+        (status = 404, description = "VM ID not known to this proxy or its backend"),
+        (status = 409, description = "VM is not running or was launched without graphics"),
+        (status = 502, description = "Owning backend could not be reached"),
+    ),
+    tag = "vms"
+)]
+/// Upgrade to a WebSocket relayed to the VNC server of a VM launched with
+/// `graphics`, for noVNC-style clients. The "binary" subprotocol is accepted.
+async fn vm_vnc_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    ws: WebSocketUpgrade,
+    uri: Uri,
+) -> axum::response::Response {
+    relay_to_vm_backend(&state, &id, ws.protocols(["binary"]), &uri).await
+}
+
+/// Open the same WebSocket path on the backend that owns `vm_id` and relay
+/// between it and the client, or 404 if the VM is not in the registry.
+async fn relay_to_vm_backend(
+    state: &AppState,
+    vm_id: &str,
+    ws: WebSocketUpgrade,
+    uri: &Uri,
+) -> axum::response::Response {
+    let backend_url = match state.registry.read().await.backend_for_vm(vm_id) {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
+    };
+
+    let url = ws_proxy::backend_ws_url(&backend_url, uri.path());
+    match ws_proxy::connect(&url).await {
+        Ok(backend) => ws.on_upgrade(move |socket| ws_proxy::relay(socket, backend)),
+        Err(response) => response,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/vm-screenshot/{id}",
+    params(
+        ("id" = String, Path, description = "VM UUID")
+    ),
+    responses(
+        (status = 200, description = "PNG of the VM's display", content_type = "image/png"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not running"),
+    ),
+    tag = "vms"
+)]
+/// Route /vm-screenshot to the backend that owns the VM. The backend captures
+/// the display with QMP `screendump`.
+async fn vm_screenshot_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, _) = request.into_parts();
+    forward_to_vm_backend(&state, parts, axum::body::Bytes::new(), &id).await
+}
+
+/// Request body for running a command in a VM through its guest agent.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct ExecRequest {
+    /// Program to run, looked up on the guest's PATH, followed by its arguments. No shell is
+    /// involved; pass `["sh", "-c", "..."]` for one.
+    #[schema(example = json!(["uname", "-a"]))]
+    command: Vec<String>,
+    /// Written to the command's stdin.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input: Option<String>,
+    /// Seconds to wait for the command to exit (default 30).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+}
+
+/// Result of a command that exited.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct ExecOutput {
+    /// Exit code; absent when the command was killed by a signal.
+    exit_code: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signal: Option<i64>,
+    stdout: String,
+    stderr: String,
+    /// Whether output beyond the guest agent's 16 MiB capture limit was dropped.
+    truncated: bool,
+}
+
+/// Request body for creating, reverting to or deleting a VM snapshot.
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+struct SnapshotRequest {
+    /// UUID of the VM.
+    vm_id: String,
+    /// Snapshot name: letters, digits, '-', '_' or '.'; unique per VM.
+    #[schema(example = "before-upgrade")]
+    name: String,
+}
+
+/// A point-in-time copy of a VM's disk.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct VmSnapshot {
+    name: String,
+    /// Unix time the snapshot was taken.
+    created_secs: u64,
+    /// "internal" when taken while the VM was stopped, stored inside the disk
+    /// file; "external" when taken live, freezing the disk file under a new
+    /// overlay.
+    #[schema(example = "internal")]
+    kind: String,
+    /// Disk file on the worker node that holds the snapshot.
+    file: String,
+}
+
+/// Result of copying a file into a VM or volume.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+struct WriteFileResponse {
+    bytes_written: u64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/vm-exec/{id}",
+    params(
+        ("id" = String, Path, description = "VM UUID")
+    ),
+    request_body = ExecRequest,
+    responses(
+        (status = 200, description = "Command exited", body = ExecOutput),
+        (status = 400, description = "Empty command"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not running"),
+        (status = 422, description = "The guest agent refused the command, e.g. program not found"),
+        (status = 503, description = "VM has no guest agent channel; restart it"),
+        (status = 504, description = "No guest agent answered, or the command outlived its timeout"),
+    ),
+    tag = "vms"
+)]
+/// Route /vm-exec to the backend that owns the VM. The backend runs the
+/// command with the guest agent's `guest-exec` and waits for it to exit.
+async fn vm_exec_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    forward_to_vm_backend(&state, parts, bytes, &id).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/vm-file/{id}",
+    params(
+        ("id" = String, Path, description = "VM UUID"),
+        ("path" = String, Query, description = "Absolute path of the file in the guest"),
+    ),
+    responses(
+        (status = 200, description = "File contents", content_type = "application/octet-stream"),
+        (status = 400, description = "Path is not absolute"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not running"),
+        (status = 422, description = "The guest agent could not read the file"),
+        (status = 504, description = "No guest agent answered"),
+    ),
+    tag = "vms"
+)]
+/// Route GET /vm-file to the backend that owns the VM, which copies the file
+/// at `path` out of the guest with the guest agent's `guest-file-*` commands.
+async fn vm_file_get_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_vm_file(&state, &id, query, request).await
+}
+
+#[utoipa::path(
+    put,
+    path = "/vm-file/{id}",
+    params(
+        ("id" = String, Path, description = "VM UUID"),
+        ("path" = String, Query, description = "Absolute path of the file in the guest; created or truncated"),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "File written", body = WriteFileResponse),
+        (status = 400, description = "Path is not absolute"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is not running"),
+        (status = 422, description = "The guest agent could not write the file"),
+        (status = 504, description = "No guest agent answered"),
+    ),
+    tag = "vms"
+)]
+/// Route PUT /vm-file to the backend that owns the VM, which copies the
+/// request body into the file at `path` in the guest.
+async fn vm_file_put_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_vm_file(&state, &id, query, request).await
+}
+
+/// Forward a /vm-file request with its body, keeping the query string so
+/// `path` reaches the backend.
+async fn forward_vm_file(
+    state: &AppState,
+    vm_id: &str,
+    query: Query<HashMap<String, String>>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let backend_url = match state.registry.read().await.backend_for_vm(vm_id) {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
+    };
+
+    let (parts, body) = request.into_parts();
+    state
+        .proxy_service
+        .proxy_request_to(
+            backend_url,
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(body),
+            Some(query),
+        )
+        .await
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/resize-vm-disk",
+    request_body = ResizeVmDiskRequest,
+    responses(
+        (status = 200, description = "Disk grown, or already that size", body = ResizeVmDiskResponse),
+        (status = 400, description = "Invalid request body, or the disk is larger than `size_gb`"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is between states"),
+    ),
+    tag = "vms"
+)]
+/// Route /resize-vm-disk to the backend that owns the VM. A stopped VM's disk
+/// is grown with `qemu-img resize`; a running or paused one's with QMP
+/// `block_resize`. The guest must grow its partition and filesystem itself.
+async fn resize_vm_disk_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let vm_id = match serde_json::from_slice::<ResizeVmDiskRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_vm_backend(&state, parts, bytes, &vm_id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/create-snapshot",
+    request_body = SnapshotRequest,
+    responses(
+        (status = 200, description = "Snapshot taken", body = VmSnapshot),
+        (status = 400, description = "Invalid request body or snapshot name"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "Name already in use, or the VM is between states"),
+    ),
+    tag = "vms"
+)]
+/// Route /create-snapshot to the backend that owns the VM. A stopped VM is
+/// snapshotted inside its disk file with `qemu-img`; a running one with QMP
+/// `blockdev-snapshot-sync`, without pausing the guest.
+async fn create_snapshot_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_snapshot_request(&state, request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/list-snapshots/{id}",
+    params(
+        ("id" = String, Path, description = "VM UUID")
+    ),
+    responses(
+        (status = 200, description = "The VM's snapshots, oldest first", body = Vec<VmSnapshot>),
+        (status = 404, description = "VM ID not known to this proxy"),
+    ),
+    tag = "vms"
+)]
+/// Route /list-snapshots to the backend that owns the VM.
+async fn list_snapshots_handler(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, _) = request.into_parts();
+    forward_to_vm_backend(&state, parts, axum::body::Bytes::new(), &id).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/revert-snapshot",
+    request_body = SnapshotRequest,
+    responses(
+        (status = 200, description = "VM disk reverted"),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM or snapshot not found"),
+        (status = 409, description = "VM is not stopped or crashed"),
+    ),
+    tag = "vms"
+)]
+/// Route /revert-snapshot to the backend that owns the VM. Everything the VM
+/// wrote since the snapshot is discarded, with any snapshots taken after it.
+async fn revert_snapshot_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_snapshot_request(&state, request).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/delete-snapshot",
+    request_body = SnapshotRequest,
+    responses(
+        (status = 200, description = "Snapshot deleted"),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM or snapshot not found"),
+        (status = 409, description = "VM is not stopped or crashed, or a live snapshot still holds offline ones"),
+    ),
+    tag = "vms"
+)]
+/// Route /delete-snapshot to the backend that owns the VM. The VM's current
+/// disk contents are unaffected.
+async fn delete_snapshot_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    forward_snapshot_request(&state, request).await
+}
+
+/// Forward a snapshot request to the backend that owns the `vm_id` in its body.
+async fn forward_snapshot_request(
+    state: &AppState,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let vm_id = match serde_json::from_slice::<SnapshotRequest>(&bytes) {
+        Ok(req) => req.vm_id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    forward_to_vm_backend(state, parts, bytes, &vm_id).await
+}
+
+/// Send a buffered request on to the backend that owns `vm_id`, or 404 if the
+/// VM is not in the registry.
+async fn forward_to_vm_backend(
+    state: &AppState,
+    parts: axum::http::request::Parts,
+    bytes: axum::body::Bytes,
+    vm_id: &str,
+) -> axum::response::Response {
+    let backend_url = match state.registry.read().await.backend_for_vm(vm_id) {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
+    };
+
+    state
+        .proxy_service
+        .proxy_request_to(
+            backend_url,
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(Body::from(bytes)),
+            None,
+        )
+        .await
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/instance-types",
+    responses(
+        (status = 200, description = "Instance types accepted by /launch-vm", body = Vec<InstanceType>),
+        (status = 503, description = "No backend worker is registered"),
+    ),
+    tag = "vms"
+)]
+/// Forward /instance-types to any backend; all workers share the same catalog.
+async fn list_instance_types_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let headers = request.headers().clone();
+
+    state
+        .proxy_service
+        .proxy_request(method, uri, headers, None, None)
+        .await
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        registry::register_handler,
+        launch_vm_handler,
+        list_vms_handler,
+        delete_vm_handler,
+        stop_vm_handler,
+        start_vm_handler,
+        reboot_vm_handler,
+        pause_vm_handler,
+        resume_vm_handler,
+        console_log_handler,
+        vm_console_handler,
+        vm_vnc_handler,
+        vm_screenshot_handler,
+        vm_exec_handler,
+        vm_file_get_handler,
+        vm_file_put_handler,
+        resize_vm_disk_handler,
+        create_snapshot_handler,
+        list_snapshots_handler,
+        revert_snapshot_handler,
+        delete_snapshot_handler,
+        list_instance_types_handler,
+        register_image_handler,
+        list_images_handler,
+        delete_image_handler,
+        create_image_handler,
+        image_file_handler,
+        launch_volume_handler,
+        list_volumes_handler,
+        volume_usage_handler,
+        delete_volume_handler,
+        list_volume_files_handler,
+        volume_file_get_handler,
+        volume_file_put_handler,
+        volume_file_delete_handler,
+        volume_mkdir_handler,
+        volume_rename_handler,
+        mount_volume_handler,
+        unmount_volume_handler,
+        resize_volume_handler,
+        create_volume_snapshot_handler,
+        list_volume_snapshots_handler,
+        delete_volume_snapshot_handler,
+        clone_volume_handler,
+        export_volume_handler,
+        import_volume_handler,
+        attach_volume_handler,
+        detach_volume_handler,
+    ),
+    components(schemas(
+        registry::RegisterRequest,
+        registry::RegisterResponse,
+        LaunchVmRequest,
+        LaunchVmResponse,
+        VmListEntry,
+        GuestInterface,
+        DeleteVmRequest,
+        StopVmRequest,
+        StopVmResponse,
+        StartVmRequest,
+        RebootVmRequest,
+        RebootVmResponse,
+        PauseVmRequest,
+        ResumeVmRequest,
+        ConsoleLogChunk,
+        ExecRequest,
+        ExecOutput,
+        WriteFileResponse,
+        ResizeVmDiskRequest,
+        ResizeVmDiskResponse,
+        SnapshotRequest,
+        VmSnapshot,
+        InstanceType,
+        ImageInfo,
+        RegisterImageRequest,
+        RegisterImageResponse,
+        DeleteImageRequest,
+        CreateImageRequest,
+        LaunchVolumeRequest,
+        LaunchVolumeResponse,
+        VolumeInfo,
+        DeleteVolumeRequest,
+        MountVolumeRequest,
+        UnmountVolumeRequest,
+        VolumeUsageTotals,
+        BackendVolumeUsage,
+        VolumeUsageReport,
+        ResizeVolumeRequest,
+        VolumeSnapshotRequest,
+        VolumeSnapshot,
+        CloneVolumeRequest,
+        AttachVolumeRequest,
+        VolumeFileEntry,
+        VolumeFileListing,
+        RenameVolumeFileRequest,
+    )),
+    tags(
+        (name = "vms", description = "VM lifecycle management"),
+        (name = "images", description = "Base image catalog"),
+        (name = "volumes", description = "Volume lifecycle management"),
+        (name = "internal", description = "Backend registration — called by worker nodes on startup, not by end users"),
+    ),
+    info(title = "Andy's Web Services API", version = "0.1.0")
+)]
+struct ApiDoc;
+
+#[tokio::main]
+async fn main() {
+    let config = Config::load().expect("Failed to load configuration");
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(&config.log_level))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    tracing::info!("Starting proxy server with config: {:?}", config);
+
+    let registry: Arc<RwLock<BackendRegistry>> = Arc::new(RwLock::new(BackendRegistry::new()));
+
+    // Restore vm_id → backend_url mappings saved before the last proxy restart.
+    let saved_vms = load_vm_backends(&config.vm_backends_file).await;
+    let vm_count = saved_vms.len();
+    {
+        let mut reg = registry.write().await;
+        for (vm_id, backend_url) in saved_vms {
+            reg.register_vm(vm_id, backend_url);
+        }
+    }
+    if vm_count > 0 {
+        tracing::info!(
+            "Restored {vm_count} VM-backend mapping(s) from {:?}",
+            config.vm_backends_file
+        );
+    }
+
+    // Restore volume_id → backend_url mappings saved before the last proxy restart.
+    let saved_volumes = load_volume_backends(&config.volume_backends_file).await;
+    let volume_count = saved_volumes.len();
+    {
+        let mut reg = registry.write().await;
+        for (volume_id, backend_url) in saved_volumes {
+            reg.register_volume(volume_id, backend_url);
+        }
+    }
+    if volume_count > 0 {
+        tracing::info!(
+            "Restored {volume_count} volume-backend mapping(s) from {:?}",
+            config.volume_backends_file
+        );
+    }
+
+    // Restore image_id → backend_url mappings saved before the last proxy restart.
+    let saved_images = load_image_backends(&config.image_backends_file).await;
+    let image_count = saved_images.len();
+    {
+        let mut reg = registry.write().await;
+        for (image_id, backend_url) in saved_images {
+            reg.register_image(image_id, backend_url);
+        }
+    }
+    if image_count > 0 {
+        tracing::info!(
+            "Restored {image_count} image-backend mapping(s) from {:?}",
+            config.image_backends_file
+        );
+    }
+
+    let proxy_service = Arc::new(ProxyService::new(
+        Arc::clone(&registry),
+        config.lease_file.clone(),
+    ));
+    let state = AppState {
+        proxy_service,
+        registry,
+        vm_backends_file: config.vm_backends_file.clone(),
+        volume_backends_file: config.volume_backends_file.clone(),
+        image_backends_file: config.image_backends_file.clone(),
+    };
+
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any);
+
+    let api_router = Router::new()
+        .route("/register", post(registry::register_handler))
+        .route("/launch-vm", post(launch_vm_handler))
+        .route("/list-vms", get(list_vms_handler))
+        .route("/delete-vm", delete(delete_vm_handler))
+        .route("/stop-vm", post(stop_vm_handler))
+        .route("/start-vm", post(start_vm_handler))
+        .route("/reboot-vm", post(reboot_vm_handler))
+        .route("/pause-vm", post(pause_vm_handler))
+        .route("/resume-vm", post(resume_vm_handler))
+        .route("/vm-console/:id", get(vm_console_handler))
+        .route("/vm-vnc/:id", get(vm_vnc_handler))
+        .route("/vm-screenshot/:id", get(vm_screenshot_handler))
+        .route("/vm-exec/:id", post(vm_exec_handler))
+        .route(
+            "/vm-file/:id",
+            get(vm_file_get_handler).put(vm_file_put_handler),
+        )
+        .route("/vm-console-log/:id", get(console_log_handler))
+        .route("/resize-vm-disk", post(resize_vm_disk_handler))
+        .route("/create-snapshot", post(create_snapshot_handler))
+        .route("/list-snapshots/:id", get(list_snapshots_handler))
+        .route("/revert-snapshot", post(revert_snapshot_handler))
+        .route("/delete-snapshot", delete(delete_snapshot_handler))
+        .route("/instance-types", get(list_instance_types_handler))
+        .route("/register-image", post(register_image_handler))
+        .route("/list-images", get(list_images_handler))
+        .route("/delete-image", delete(delete_image_handler))
+        .route("/create-image", post(create_image_handler))
+        .route("/image-file/:id", get(image_file_handler))
+        .route("/launch-volume", post(launch_volume_handler))
+        .route("/list-volumes", get(list_volumes_handler))
+        .route("/volume-usage", get(volume_usage_handler))
+        .route("/delete-volume", delete(delete_volume_handler))
+        .route("/volume-files/:id", get(list_volume_files_handler))
+        .route(
+            "/volume-file/:id",
+            get(volume_file_get_handler)
+                .put(volume_file_put_handler)
+                .delete(volume_file_delete_handler),
+        )
+        .route("/volume-mkdir/:id", post(volume_mkdir_handler))
+        .route("/volume-rename/:id", post(volume_rename_handler))
+        .route("/mount-volume", post(mount_volume_handler))
+        .route("/unmount-volume", post(unmount_volume_handler))
+        .route("/resize-volume", post(resize_volume_handler))
+        .route(
+            "/create-volume-snapshot",
+            post(create_volume_snapshot_handler),
+        )
+        .route(
+            "/list-volume-snapshots/:id",
+            get(list_volume_snapshots_handler),
+        )
+        .route(
+            "/delete-volume-snapshot",
+            delete(delete_volume_snapshot_handler),
+        )
+        .route("/clone-volume", post(clone_volume_handler))
+        .route("/export-volume/:id", get(export_volume_handler))
+        .route("/import-volume", post(import_volume_handler))
+        .route("/attach-volume", post(attach_volume_handler))
+        .route("/detach-volume", post(detach_volume_handler))
+        .fallback(proxy_handler)
+        .with_state(state);
+
+    let app = api_router
+        .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
+        .layer(cors);
+
+    let listener =
+        tokio::net::TcpListener::bind(format!("{}:{}", config.listen_ip, config.proxy_port))
+            .await
+            .unwrap();
+
+    tracing::info!(
+        "Proxy server listening on {}",
+        listener.local_addr().unwrap()
+    );
+    tracing::info!("Waiting for backend to register via POST /register");
+
+    axum::serve(listener, app).await.unwrap();
+}
+
+#[utoipa::path(
+    post,
+    path = "/launch-vm",
+    request_body = LaunchVmRequest,
+    responses(
+        (status = 200, description = "VM launched successfully", body = LaunchVmResponse),
+        (status = 400, description = "Unknown instance type", body = LaunchVmResponse),
+        (status = 404, description = "Image ID not known to this proxy or backend"),
+        (status = 422, description = "The image's disk file is missing on the backend", body = LaunchVmResponse),
+        (status = 503, description = "No backend worker is registered"),
+    ),
+    tag = "vms"
+)]
+/// Route /launch-vm to the backend storing the requested image, or the next
+/// backend round-robin when no image is named. If the backend accepts the
+/// launch, records the vm_id → backend mapping.
+async fn launch_vm_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let image_id = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|v| v.get("image_id").and_then(|i| i.as_str()).map(String::from));
+
+    let backend_url = match image_id {
+        Some(image_id) => match state.registry.read().await.backend_for_image(&image_id) {
+            Some(u) => u,
+            None => return (StatusCode::NOT_FOUND, "Unknown image ID").into_response(),
+        },
+        None => match state.registry.write().await.round_robin_url() {
+            Some(u) => u,
+            None => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Backend not yet registered",
+                )
+                    .into_response();
+            }
+        },
+    };
+
+    let response = state
+        .proxy_service
+        .proxy_request_to(
+            backend_url.clone(),
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(Body::from(bytes)),
+            None,
+        )
+        .await;
+
+    // Only record the VM→backend mapping if the launch succeeded.
+    if response.status().is_success() {
+        let (parts, resp_body) = response.into_parts();
+        let bytes = axum::body::to_bytes(resp_body, usize::MAX)
+            .await
+            .unwrap_or_default();
+        if let Ok(val) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+            if let Some(vm_id) = val.get("instance_id").and_then(|v| v.as_str()) {
+                state
+                    .registry
+                    .write()
+                    .await
+                    .register_vm(vm_id.to_string(), backend_url);
+                let backends = state.registry.read().await.all_vm_backends();
+                save_vm_backends(&state.vm_backends_file, &backends).await;
+            }
+        }
+        return Response::from_parts(parts, Body::from(bytes)).into_response();
+    }
+
+    response.into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/list-vms",
+    responses(
+        (status = 200, description = "Aggregated list of all running VMs across all backends", body = Vec<VmListEntry>),
+        (status = 503, description = "No backend worker is registered"),
+    ),
+    tag = "vms"
+)]
+/// Fan-out /list-vms to all backends and return the merged JSON array.
+async fn list_vms_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let headers = request.headers().clone();
+    let uri = request.uri().clone();
+    state.proxy_service.list_all(uri.path(), headers).await
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+struct DeleteVmRequest {
+    /// UUID of the VM to delete.
+    id: String,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/delete-vm",
+    request_body = DeleteVmRequest,
+    responses(
+        (status = 200, description = "VM terminated and removed"),
+        (status = 400, description = "Invalid request body"),
+        (status = 404, description = "VM ID not known to this proxy"),
+        (status = 409, description = "VM is already being deleted"),
+    ),
+    tag = "vms"
+)]
+/// Route /delete-vm to the backend that owns the VM, identified by the ID in
+/// the request body. Returns 400 if the body is invalid JSON, 404 if the VM
+/// is not known to the registry.
+async fn delete_vm_handler(
+    State(state): State<AppState>,
+    request: Request<Body>,
+) -> impl IntoResponse {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+
+    let vm_id = match serde_json::from_slice::<DeleteVmRequest>(&bytes) {
+        Ok(req) => req.id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid request body").into_response(),
+    };
+
+    let backend_url = match state.registry.read().await.backend_for_vm(&vm_id) {
+        Some(u) => u,
+        None => return (StatusCode::NOT_FOUND, "Unknown VM ID").into_response(),
+    };
+
+    let response = state
+        .proxy_service
+        .proxy_request_to(
+            backend_url,
+            parts.method,
+            parts.uri,
+            parts.headers,
+            Some(Body::from(bytes)),
+            None,
+        )
+        .await;
+
+    if response.status().is_success() {
+        state.registry.write().await.remove_vm(&vm_id);
+        let backends = state.registry.read().await.all_vm_backends();
+        save_vm_backends(&state.vm_backends_file, &backends).await;
+    }
+
+    response.into_response()
+}
+
+async fn proxy_handler(State(state): State<AppState>, request: Request<Body>) -> impl IntoResponse {
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let headers = request.headers().clone();
+    let body = Some(request.into_body());
+
+    state
+        .proxy_service
+        .proxy_request(method, uri, headers, body, None)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+    use tower::ServiceExt;
+
+    // ── helpers ───────────────────────────────────────────────────────────────
+
+    async fn start_mock_backend(status: u16, body: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = Router::new().fallback(move || async move {
+            (
+                axum::http::StatusCode::from_u16(status).unwrap_or(axum::http::StatusCode::OK),
+                body,
+            )
+        });
+        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
+        tokio::task::yield_now().await;
+        port
+    }
+
+    /// Backend that echoes the path and query string it was asked for.
+    async fn start_echo_uri_backend() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = Router::new().fallback(|uri: axum::http::Uri| async move { uri.to_string() });
+        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
+        tokio::task::yield_now().await;
+        port
+    }
+
+    /// Backend that counts requests and returns a fixed body.
+    async fn start_counting_backend(body: &'static str) -> (u16, Arc<Mutex<usize>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let count = Arc::new(Mutex::new(0usize));
+        let c = count.clone();
+        let app = Router::new().fallback(move || {
+            let c = c.clone();
+            async move {
+                *c.lock().await += 1;
+                (axum::http::StatusCode::OK, body)
+            }
+        });
+        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
+        tokio::task::yield_now().await;
+        (port, count)
+    }
+
+    fn build_test_app() -> (Router, Arc<RwLock<BackendRegistry>>) {
+        // Use unique temp paths per call so parallel tests never share files.
+        let vm_backends_file =
+            std::env::temp_dir().join(format!("test-vm-backends-{}.json", uuid::Uuid::new_v4()));
+        let volume_backends_file = std::env::temp_dir().join(format!(
+            "test-volume-backends-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+        let image_backends_file =
+            std::env::temp_dir().join(format!("test-image-backends-{}.json", uuid::Uuid::new_v4()));
+        build_test_app_with_files(vm_backends_file, volume_backends_file, image_backends_file)
+    }
+
+    fn build_test_app_with_files(
+        vm_backends_file: PathBuf,
+        volume_backends_file: PathBuf,
+        image_backends_file: PathBuf,
+    ) -> (Router, Arc<RwLock<BackendRegistry>>) {
+        let registry: Arc<RwLock<BackendRegistry>> = Arc::new(RwLock::new(BackendRegistry::new()));
+        let proxy_service = Arc::new(ProxyService::new(
+            Arc::clone(&registry),
+            PathBuf::from("/nonexistent/leases"),
+        ));
+        let state = AppState {
+            proxy_service,
+            registry: Arc::clone(&registry),
+            vm_backends_file,
+            volume_backends_file,
+            image_backends_file,
+        };
+
+        let cors = tower_http::cors::CorsLayer::new()
+            .allow_origin(tower_http::cors::Any)
+            .allow_methods(tower_http::cors::Any)
+            .allow_headers(tower_http::cors::Any);
+
+        let app = Router::new()
+            .route("/register", post(registry::register_handler))
+            .route("/launch-vm", post(launch_vm_handler))
+            .route("/list-vms", get(list_vms_handler))
+            .route("/delete-vm", delete(delete_vm_handler))
+            .route("/stop-vm", post(stop_vm_handler))
+            .route("/start-vm", post(start_vm_handler))
+            .route("/reboot-vm", post(reboot_vm_handler))
+            .route("/pause-vm", post(pause_vm_handler))
+            .route("/resume-vm", post(resume_vm_handler))
+            .route("/vm-console/:id", get(vm_console_handler))
+            .route("/vm-vnc/:id", get(vm_vnc_handler))
+            .route("/vm-screenshot/:id", get(vm_screenshot_handler))
+            .route("/vm-exec/:id", post(vm_exec_handler))
+            .route(
+                "/vm-file/:id",
+                get(vm_file_get_handler).put(vm_file_put_handler),
+            )
+            .route("/vm-console-log/:id", get(console_log_handler))
+            .route("/resize-vm-disk", post(resize_vm_disk_handler))
+            .route("/create-snapshot", post(create_snapshot_handler))
+            .route("/list-snapshots/:id", get(list_snapshots_handler))
+            .route("/revert-snapshot", post(revert_snapshot_handler))
+            .route("/delete-snapshot", delete(delete_snapshot_handler))
+            .route("/instance-types", get(list_instance_types_handler))
+            .route("/register-image", post(register_image_handler))
+            .route("/list-images", get(list_images_handler))
+            .route("/delete-image", delete(delete_image_handler))
+            .route("/create-image", post(create_image_handler))
+            .route("/image-file/:id", get(image_file_handler))
+            .route("/launch-volume", post(launch_volume_handler))
+            .route("/list-volumes", get(list_volumes_handler))
+            .route("/volume-usage", get(volume_usage_handler))
+            .route("/delete-volume", delete(delete_volume_handler))
+            .route("/volume-files/:id", get(list_volume_files_handler))
+            .route(
+                "/volume-file/:id",
+                get(volume_file_get_handler)
+                    .put(volume_file_put_handler)
+                    .delete(volume_file_delete_handler),
+            )
+            .route("/volume-mkdir/:id", post(volume_mkdir_handler))
+            .route("/volume-rename/:id", post(volume_rename_handler))
+            .route("/mount-volume", post(mount_volume_handler))
+            .route("/unmount-volume", post(unmount_volume_handler))
+            .route("/resize-volume", post(resize_volume_handler))
+            .route(
+                "/create-volume-snapshot",
+                post(create_volume_snapshot_handler),
+            )
+            .route(
+                "/list-volume-snapshots/:id",
+                get(list_volume_snapshots_handler),
+            )
+            .route(
+                "/delete-volume-snapshot",
+                delete(delete_volume_snapshot_handler),
+            )
+            .route("/clone-volume", post(clone_volume_handler))
+            .route("/export-volume/:id", get(export_volume_handler))
+            .route("/import-volume", post(import_volume_handler))
+            .route("/attach-volume", post(attach_volume_handler))
+            .route("/detach-volume", post(detach_volume_handler))
+            .fallback(proxy_handler)
+            .layer(cors)
+            .with_state(state);
+
+        (app, registry)
+    }
+
+    async fn body_string(resp: axum::http::Response<Body>) -> String {
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    // ── register handler ──────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_register_sets_backend_url() {
+        let (app, registry) = build_test_app();
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("/register")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"ip":"127.0.0.1","port":8081}"#))
+            .unwrap();
+
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+
+        let body = body_string(resp).await;
+        let val: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert!(val["id"].is_string());
+
+        let reg = registry.read().await;
+        assert_eq!(reg.any_url().as_deref(), Some("http://127.0.0.1:8081"));
+    }
+
+    // ── proxy routing ─────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_proxy_before_registration_returns_503() {
+        let (app, _) = build_test_app();
+        let req = Request::builder()
+            .method("GET")
+            .uri("/list-vms")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_full_proxy_flow_register_then_request() {
+        // list-vms fan-out aggregates JSON arrays; mock must return a valid array.
+        let port = start_mock_backend(200, r#"[{"id":"vm1"}]"#).await;
+        let (app, _) = build_test_app();
+
+        let register_req = Request::builder()
+            .method("POST")
+            .uri("/register")
+            .header("content-type", "application/json")
+            .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+            .unwrap();
+        app.clone().oneshot(register_req).await.unwrap();
+
+        let proxy_req = Request::builder()
+            .method("GET")
+            .uri("/list-vms")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.clone().oneshot(proxy_req).await.unwrap();
+        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+
+        let body = body_string(resp).await;
+        let val: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert!(val.is_array());
+        assert!(!val.as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_launch_vm_route_accepts_post() {
+        // Backend returns a 201 with a non-JSON body; the handler still proxies it.
+        let port = start_mock_backend(201, "launched").await;
+        let (app, _) = build_test_app();
+
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/launch-vm")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"name":"test-vm"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), axum::http::StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_delete_vm_unknown_id_returns_404() {
+        let port = start_mock_backend(200, "deleted").await;
+        let (app, _) = build_test_app();
+
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/delete-vm")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"id":"unknown-vm-id"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_route_proxies_arbitrary_path() {
+        let port = start_mock_backend(200, "arbitrary").await;
+        let (app, _) = build_test_app();
+
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/some/unknown/path")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+        assert_eq!(body_string(resp).await, "arbitrary");
+    }
+
+    #[tokio::test]
+    async fn test_cors_headers_present_on_response() {
+        let (app, _) = build_test_app();
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("OPTIONS")
+                    .uri("/list-vms")
+                    .header("origin", "http://localhost:3000")
+                    .header("access-control-request-method", "GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(
+            resp.headers().contains_key("access-control-allow-origin"),
+            "CORS allow-origin header must be present"
+        );
+    }
+
+    // ── multi-backend routing ─────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_launch_vm_round_robins_across_backends() {
+        let (port_a, count_a) = start_counting_backend(r#"{"instance_id":"vm-a"}"#).await;
+        let (port_b, count_b) = start_counting_backend(r#"{"instance_id":"vm-b"}"#).await;
+        let (app, _) = build_test_app();
+
+        // Register both backends.
+        for port in [port_a, port_b] {
+            app.clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/register")
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Two launches should alternate across the two backends.
+        for _ in 0..2 {
+            app.clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/launch-vm")
+                        .header("content-type", "application/json")
+                        .body(Body::from(r#"{"name":"test"}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            *count_a.lock().await,
+            1,
+            "backend A should receive exactly 1 launch"
+        );
+        assert_eq!(
+            *count_b.lock().await,
+            1,
+            "backend B should receive exactly 1 launch"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_vms_aggregates_all_backends() {
+        let port_a = start_mock_backend(200, r#"[{"id":"vm-a"}]"#).await;
+        let port_b = start_mock_backend(200, r#"[{"id":"vm-b"}]"#).await;
+        let (app, _) = build_test_app();
+
+        for port in [port_a, port_b] {
+            app.clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/register")
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/list-vms")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+        let body = body_string(resp).await;
+        let val: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let arr = val.as_array().unwrap();
+        assert_eq!(
+            arr.len(),
+            2,
+            "merged list should contain VMs from both backends"
+        );
+        let ids: Vec<&str> = arr.iter().filter_map(|v| v["id"].as_str()).collect();
+        assert!(ids.contains(&"vm-a"));
+        assert!(ids.contains(&"vm-b"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_vm_routes_to_correct_backend() {
+        // Backend A records whether it received a DELETE; backend B does too.
+        let delete_count_a = Arc::new(Mutex::new(0usize));
+        let delete_count_b = Arc::new(Mutex::new(0usize));
+
+        // Each backend counts every request (launch + delete).
+        let dca = delete_count_a.clone();
+        let listener_da = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port_da = listener_da.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let app = Router::new().fallback(move || {
+                let dca = dca.clone();
+                async move {
+                    *dca.lock().await += 1;
+                    (axum::http::StatusCode::OK, r#"{"instance_id":"vm-a"}"#)
+                }
+            });
+            axum::serve(listener_da, app).await.ok()
+        });
+
+        let dcb = delete_count_b.clone();
+        let listener_db = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port_db = listener_db.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let app = Router::new().fallback(move || {
+                let dcb = dcb.clone();
+                async move {
+                    *dcb.lock().await += 1;
+                    (axum::http::StatusCode::OK, r#"{"instance_id":"vm-b"}"#)
+                }
+            });
+            axum::serve(listener_db, app).await.ok()
+        });
+        tokio::task::yield_now().await;
+
+        let (app, _) = build_test_app();
+
+        // Register the delete-tracking backends (they also handle launch).
+        for port in [port_da, port_db] {
+            app.clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/register")
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Launch twice so vm-a maps to backend A and vm-b maps to backend B.
+        for _ in 0..2 {
+            app.clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/launch-vm")
+                        .header("content-type", "application/json")
+                        .body(Body::from(r#"{"name":"test"}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Delete vm-b — should route only to backend B (port_db).
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/delete-vm")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"id":"vm-b"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+
+        // Backend A must not have received an extra request for the delete.
+        assert_eq!(
+            *delete_count_a.lock().await,
+            1,
+            "backend A should have 1 hit (launch only)"
+        );
+        // Backend B received the launch AND the delete.
+        assert_eq!(
+            *delete_count_b.lock().await,
+            2,
+            "backend B should have 2 hits (launch + delete)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_instance_types_forwarded_to_backend() {
+        let port = start_mock_backend(
+            200,
+            r#"[{"name":"t2.micro","vcpus":1,"memory_mb":1024,"disk_gb":10}]"#,
+        )
+        .await;
+        let (app, registry) = build_test_app();
+        registry.write().await.register("127.0.0.1", port);
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/instance-types")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = body_string(resp).await;
+        let arr: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(arr[0]["name"], "t2.micro");
+    }
+
+    // ── persistence ───────────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_load_vm_backends_missing_file_returns_empty() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("nonexistent.json");
+        let map = load_vm_backends(&path).await;
+        assert!(map.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_vm_backends_round_trips() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("backends.json");
+
+        let mut map = HashMap::new();
+        map.insert("vm-a".to_string(), "http://10.0.0.1:8081".to_string());
+        map.insert("vm-b".to_string(), "http://10.0.0.2:8082".to_string());
+
+        save_vm_backends(&path, &map).await;
+
+        let loaded = load_vm_backends(&path).await;
+        assert_eq!(loaded, map);
+    }
+
+    #[tokio::test]
+    async fn test_load_vm_backends_invalid_json_returns_empty() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("bad.json");
+        tokio::fs::write(&path, b"not valid json").await.unwrap();
+        let map = load_vm_backends(&path).await;
+        assert!(map.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_launch_vm_persists_mapping_to_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let backends_file = tmp.path().join("vm-backends.json");
+        let (app, _) = build_test_app_with_files(
+            backends_file.clone(),
+            tmp.path().join("vol.json"),
+            tmp.path().join("img.json"),
+        );
+
+        // Start a mock backend that returns a successful launch response.
+        let port = start_mock_backend(200, r#"{"success":true,"instance_id":"vm-xyz"}"#).await;
+
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/launch-vm")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"name":"test"}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let persisted = load_vm_backends(&backends_file).await;
+        assert!(
+            persisted.contains_key("vm-xyz"),
+            "vm-xyz should be in the persisted file"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_vm_removes_mapping_from_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let backends_file = tmp.path().join("vm-backends.json");
+        let (app, registry) = build_test_app_with_files(
+            backends_file.clone(),
+            tmp.path().join("vol.json"),
+            tmp.path().join("img.json"),
+        );
+
+        // Pre-populate the registry and the file with a known mapping.
+        let port = start_mock_backend(200, "deleted").await;
+        registry.write().await.register_vm(
+            "vm-to-delete".to_string(),
+            format!("http://127.0.0.1:{port}"),
+        );
+
+        let mut initial = HashMap::new();
+        initial.insert(
+            "vm-to-delete".to_string(),
+            format!("http://127.0.0.1:{port}"),
+        );
+        save_vm_backends(&backends_file, &initial).await;
+
+        // Register the backend so the proxy can route to it.
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/delete-vm")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"id":"vm-to-delete"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+
+        let persisted = load_vm_backends(&backends_file).await;
+        assert!(
+            !persisted.contains_key("vm-to-delete"),
+            "vm-to-delete should be removed from the persisted file"
+        );
+    }
+
+    // ── volume handlers ───────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_launch_volume_records_backend_mapping() {
+        let port = start_mock_backend(200, r#"{"success":true,"id":"vol-abc"}"#).await;
+        let (app, registry) = build_test_app();
+
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/launch-volume")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"name":"data","size_gb":10}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert!(
+            registry
+                .read()
+                .await
+                .backend_for_volume("vol-abc")
+                .is_some(),
+            "volume-backend mapping should be recorded after launch"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_volume_streams_archive_and_records_backend() {
+        let port = start_echo_request_backend().await;
+        let (app, registry) = build_test_app();
+
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/import-volume?name=data&size_gb=1")
+                    .body(Body::from("archive"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/import-volume?name=data&size_gb=1")
+                    .body(Body::from("archive"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let echoed = body_string(resp).await;
+        assert!(echoed.starts_with("POST /import-volume?"), "{echoed}");
+        assert!(echoed.contains("size_gb=1"), "{echoed}");
+        assert!(echoed.ends_with(" archive"), "{echoed}");
+        // The echo is not a launch response, so nothing is recorded.
+        assert!(registry.read().await.all_volume_backends().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_import_volume_records_backend_mapping() {
+        let port = start_mock_backend(200, r#"{"success":true,"id":"vol-imported"}"#).await;
+        let (app, registry) = build_test_app();
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/import-volume?name=data&size_gb=1&compression=zstd")
+                    .body(Body::from("archive"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            registry.read().await.backend_for_volume("vol-imported"),
+            Some(format!("http://127.0.0.1:{port}"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_volume_routes_to_owning_backend() {
+        let port = start_echo_request_backend().await;
+        let (app, registry) = build_test_app();
+        let export = |app: Router| {
+            app.oneshot(
+                Request::builder()
+                    .uri("/export-volume/vol-1?compression=zstd")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let resp = export(app.clone()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        registry
+            .write()
+            .await
+            .register_volume("vol-1".to_string(), format!("http://127.0.0.1:{port}"));
+        let resp = export(app).await.unwrap();
+        assert_eq!(
+            body_string(resp).await,
+            "GET /export-volume/vol-1?compression=zstd "
+        );
+    }
+
+    #[tokio::test]
+    async fn test_launch_volume_persists_mapping_to_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let vol_file = tmp.path().join("volume-backends.json");
+        let (app, _) = build_test_app_with_files(
+            tmp.path().join("vm.json"),
+            vol_file.clone(),
+            tmp.path().join("img.json"),
+        );
+
+        let port = start_mock_backend(200, r#"{"success":true,"id":"vol-xyz"}"#).await;
+
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/launch-volume")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"name":"data","size_gb":5}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let persisted = load_volume_backends(&vol_file).await;
+        assert!(
+            persisted.contains_key("vol-xyz"),
+            "vol-xyz should be in the persisted volume-backends file"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_volumes_aggregates_all_backends() {
+        let port_a =
+            start_mock_backend(200, r#"[{"id":"vol-a","name":"a","mount_path":"/mnt/a"}]"#).await;
+        let port_b =
+            start_mock_backend(200, r#"[{"id":"vol-b","name":"b","mount_path":"/mnt/b"}]"#).await;
+        let (app, _) = build_test_app();
+
+        for port in [port_a, port_b] {
+            app.clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/register")
+                        .header("content-type", "application/json")
+                        .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/list-volumes")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = body_string(resp).await;
+        let arr: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(arr.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_volume_usage_totals_each_backend() {
+        let port_a = start_mock_backend(
+            200,
+            r#"[{"id":"vol-a","size_bytes":100,"allocated_bytes":10,"fs_used_bytes":5,"fs_free_bytes":90},
+                {"id":"vol-b","size_bytes":200,"allocated_bytes":20}]"#,
+        )
+        .await;
+        let port_b = start_mock_backend(200, r#"[{"id":"vol-c","size_bytes":300}]"#).await;
+        let (app, _) = build_test_app();
+        for port in [port_a, port_b] {
+            post_json(
+                app.clone(),
+                "/register",
+                &format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#),
+            )
+            .await;
+        }
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .uri("/volume-usage")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let report: serde_json::Value = serde_json::from_str(&body_string(resp).await).unwrap();
+        let backend_a = report["backends"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|b| b["backend"] == format!("http://127.0.0.1:{port_a}"))
+            .unwrap();
+        assert_eq!(backend_a["volumes"], 2);
+        assert_eq!(backend_a["size_bytes"], 300);
+        assert_eq!(backend_a["fs_used_bytes"], 5);
+        assert_eq!(
+            report["total"],
+            serde_json::json!({
+                "volumes": 3,
+                "size_bytes": 600,
+                "allocated_bytes": 30,
+                "fs_used_bytes": 5,
+                "fs_free_bytes": 90,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_volume_usage_without_backends_returns_503() {
+        let (app, _) = build_test_app();
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .uri("/volume-usage")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_volume_snapshot_requests_route_to_owning_backend() {
+        let port = start_echo_request_backend().await;
+        let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_volume("vol-1".to_string(), format!("http://127.0.0.1:{port}"));
+
+        let body = r#"{"volume_id":"vol-1","name":"s1"}"#;
+        let resp = post_json(app.clone(), "/create-volume-snapshot", body).await;
+        assert_eq!(
+            body_string(resp).await,
+            format!("POST /create-volume-snapshot {body}")
+        );
+
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/list-volume-snapshots/vol-1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(body_string(resp).await, "GET /list-volume-snapshots/vol-1 ");
+
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/delete-volume-snapshot")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            body_string(resp).await,
+            format!("DELETE /delete-volume-snapshot {body}")
+        );
+
+        let body = r#"{"volume_id":"no-such-volume","name":"s1"}"#;
+        let resp = post_json(app, "/create-volume-snapshot", body).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_clone_volume_records_new_volume_on_source_backend() {
+        let port = start_mock_backend(200, r#"{"success":true,"id":"vol-2"}"#).await;
+        let (app, registry) = build_test_app();
+        let backend_url = format!("http://127.0.0.1:{port}");
+        registry
+            .write()
+            .await
+            .register_volume("vol-1".to_string(), backend_url.clone());
+
+        let body = r#"{"volume_id":"vol-1","snapshot":"s1","name":"copy"}"#;
+        let resp = post_json(app.clone(), "/clone-volume", body).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            registry.read().await.backend_for_volume("vol-2"),
+            Some(backend_url)
+        );
+
+        let body = r#"{"volume_id":"no-such-volume","snapshot":"s1","name":"copy"}"#;
+        let resp = post_json(app, "/clone-volume", body).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_volume_unknown_id_returns_404() {
+        let (app, _) = build_test_app();
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/delete-volume")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"id":"no-such-volume"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_volume_routes_to_owning_backend_and_removes_mapping() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let vol_file = tmp.path().join("volume-backends.json");
+        let (app, registry) = build_test_app_with_files(
+            tmp.path().join("vm.json"),
+            vol_file.clone(),
+            tmp.path().join("img.json"),
+        );
 
-    state
-        .proxy_service
-        .proxy_request(method, uri, headers, body, None)
-        .await
-}
+        let port = start_mock_backend(200, "deleted").await;
+        registry.write().await.register_volume(
+            "vol-to-delete".to_string(),
+            format!("http://127.0.0.1:{port}"),
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use axum::http::Request;
-    use tokio::net::TcpListener;
-    use tokio::sync::Mutex;
-    use tower::ServiceExt;
+        let mut initial = HashMap::new();
+        initial.insert(
+            "vol-to-delete".to_string(),
+            format!("http://127.0.0.1:{port}"),
+        );
+        save_volume_backends(&vol_file, &initial).await;
 
-    // ── helpers ───────────────────────────────────────────────────────────────
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-    async fn start_mock_backend(status: u16, body: &'static str) -> u16 {
-        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let port = listener.local_addr().unwrap().port();
-        let app = Router::new().fallback(move || async move {
-            (
-                axum::http::StatusCode::from_u16(status).unwrap_or(axum::http::StatusCode::OK),
-                body,
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri("/delete-volume")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"id":"vol-to-delete"}"#))
+                    .unwrap(),
             )
-        });
-        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
-        tokio::task::yield_now().await;
-        port
-    }
+            .await
+            .unwrap();
 
-    /// Backend that counts requests and returns a fixed body.
-    async fn start_counting_backend(body: &'static str) -> (u16, Arc<Mutex<usize>>) {
-        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let port = listener.local_addr().unwrap().port();
-        let count = Arc::new(Mutex::new(0usize));
-        let c = count.clone();
-        let app = Router::new().fallback(move || {
-            let c = c.clone();
-            async move {
-                *c.lock().await += 1;
-                (axum::http::StatusCode::OK, body)
-            }
-        });
-        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
-        tokio::task::yield_now().await;
-        (port, count)
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let persisted = load_volume_backends(&vol_file).await;
+        assert!(
+            !persisted.contains_key("vol-to-delete"),
+            "vol-to-delete should be removed from the persisted file"
+        );
     }
 
-    fn build_test_app() -> (Router, Arc<RwLock<BackendRegistry>>) {
-        // Use unique temp paths per call so parallel tests never share files.
-        let vm_backends_file =
-            std::env::temp_dir().join(format!("test-vm-backends-{}.json", uuid::Uuid::new_v4()));
-        let volume_backends_file = std::env::temp_dir().join(format!(
-            "test-volume-backends-{}.json",
-            uuid::Uuid::new_v4()
-        ));
-        build_test_app_with_files(vm_backends_file, volume_backends_file)
+    #[tokio::test]
+    async fn test_list_volume_files_unknown_id_returns_404() {
+        let (app, _) = build_test_app();
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/volume-files/no-such-id")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
-    fn build_test_app_with_files(
-        vm_backends_file: PathBuf,
-        volume_backends_file: PathBuf,
-    ) -> (Router, Arc<RwLock<BackendRegistry>>) {
-        let registry: Arc<RwLock<BackendRegistry>> = Arc::new(RwLock::new(BackendRegistry::new()));
-        let proxy_service = Arc::new(ProxyService::new(
-            Arc::clone(&registry),
-            PathBuf::from("/nonexistent/leases"),
-        ));
-        let state = AppState {
-            proxy_service,
-            registry: Arc::clone(&registry),
-            vm_backends_file,
-            volume_backends_file,
-        };
+    #[tokio::test]
+    async fn test_list_volume_files_routes_to_owning_backend() {
+        let port = start_mock_backend(
+            200,
+            r#"{"path":"","entries":[{"name":"file.txt","path":"file.txt","is_dir":false,"is_symlink":false,"size_bytes":5,"modified_secs":0,"mode":420,"uid":0,"gid":0}]}"#,
+        )
+        .await;
+        let (app, registry) = build_test_app();
 
-        let cors = tower_http::cors::CorsLayer::new()
-            .allow_origin(tower_http::cors::Any)
-            .allow_methods(tower_http::cors::Any)
-            .allow_headers(tower_http::cors::Any);
+        registry
+            .write()
+            .await
+            .register_volume("my-vol".to_string(), format!("http://127.0.0.1:{port}"));
 
-        let app = Router::new()
-            .route("/register", post(registry::register_handler))
-            .route("/launch-vm", post(launch_vm_handler))
-            .route("/list-vms", get(list_vms_handler))
-            .route("/delete-vm", delete(delete_vm_handler))
-            .route("/stop-vm", post(stop_vm_handler))
-            .route("/start-vm", post(start_vm_handler))
-            .route("/launch-volume", post(launch_volume_handler))
-            .route("/list-volumes", get(list_volumes_handler))
-            .route("/delete-volume", delete(delete_volume_handler))
-            .route("/volume-files/:id", get(list_volume_files_handler))
-            .fallback(proxy_handler)
-            .layer(cors)
-            .with_state(state);
+        app.clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/register")
+                    .header("content-type", "application/json")
+                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-        (app, registry)
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/volume-files/my-vol")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = body_string(resp).await;
+        let listing: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(listing["entries"].as_array().unwrap().len(), 1);
     }
 
-    async fn body_string(resp: axum::http::Response<Body>) -> String {
-        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+    #[tokio::test]
+    async fn test_list_volume_files_forwards_query() {
+        let port = start_echo_request_backend().await;
+        let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_volume("vol-1".to_string(), format!("http://127.0.0.1:{port}"));
+
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .uri("/volume-files/vol-1?path=docs&cursor=abc")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
             .await
             .unwrap();
-        String::from_utf8_lossy(&bytes).into_owned()
+
+        let echoed = body_string(resp).await;
+        assert!(echoed.starts_with("GET /volume-files/vol-1?"), "{echoed}");
+        assert!(echoed.contains("path=docs"), "{echoed}");
+        assert!(echoed.contains("cursor=abc"), "{echoed}");
     }
 
-    // ── register handler ──────────────────────────────────────────────────────
+    async fn post_json(app: Router, uri: &str, body: &str) -> axum::http::Response<Body> {
+        app.oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+    }
 
     #[tokio::test]
-    async fn test_register_sets_backend_url() {
+    async fn test_attach_volume_unknown_ids_return_404() {
         let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_vm("vm-1".to_string(), "http://127.0.0.1:1".to_string());
 
-        let req = Request::builder()
-            .method("POST")
-            .uri("/register")
-            .header("content-type", "application/json")
-            .body(Body::from(r#"{"ip":"127.0.0.1","port":8081}"#))
-            .unwrap();
+        let body = r#"{"volume_id":"vol-1","vm_id":"no-such-vm"}"#;
+        let resp = post_json(app.clone(), "/attach-volume", body).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_string(resp).await, "Unknown VM ID");
 
-        let resp = app.oneshot(req).await.unwrap();
-        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+        let body = r#"{"volume_id":"no-such-volume","vm_id":"vm-1"}"#;
+        let resp = post_json(app, "/attach-volume", body).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_string(resp).await, "Unknown volume ID");
+    }
 
-        let body = body_string(resp).await;
-        let val: serde_json::Value = serde_json::from_str(&body).unwrap();
-        assert!(val["id"].is_string());
+    #[tokio::test]
+    async fn test_attach_volume_on_other_backend_returns_409() {
+        let (port, count) = start_counting_backend("attached").await;
+        let (app, registry) = build_test_app();
+        {
+            let mut reg = registry.write().await;
+            reg.register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
+            reg.register_volume("vol-1".to_string(), "http://10.0.0.9:8081".to_string());
+        }
 
-        let reg = registry.read().await;
-        assert_eq!(reg.any_url().as_deref(), Some("http://127.0.0.1:8081"));
+        for uri in ["/attach-volume", "/detach-volume"] {
+            let body = r#"{"volume_id":"vol-1","vm_id":"vm-1"}"#;
+            let resp = post_json(app.clone(), uri, body).await;
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+        }
+        assert_eq!(*count.lock().await, 0);
     }
 
-    // ── proxy routing ─────────────────────────────────────────────────────────
+    #[tokio::test]
+    async fn test_attach_volume_routes_to_shared_backend() {
+        let port = start_echo_uri_backend().await;
+        let (app, registry) = build_test_app();
+        {
+            let mut reg = registry.write().await;
+            reg.register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
+            reg.register_volume("vol-1".to_string(), format!("http://127.0.0.1:{port}"));
+        }
+        post_json(
+            app.clone(),
+            "/register",
+            &format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#),
+        )
+        .await;
+
+        let body = r#"{"volume_id":"vol-1","vm_id":"vm-1"}"#;
+        let resp = post_json(app.clone(), "/attach-volume", body).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_string(resp).await, "/attach-volume");
+
+        let resp = post_json(app, "/detach-volume", body).await;
+        assert_eq!(body_string(resp).await, "/detach-volume");
+    }
 
     #[tokio::test]
-    async fn test_proxy_before_registration_returns_503() {
-        let (app, _) = build_test_app();
-        let req = Request::builder()
-            .method("GET")
-            .uri("/list-vms")
-            .body(Body::empty())
-            .unwrap();
-        let resp = app.oneshot(req).await.unwrap();
-        assert_eq!(resp.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
+    async fn test_mount_volume_routes_to_owning_backend() {
+        let port = start_echo_uri_backend().await;
+        let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_volume("vol-1".to_string(), format!("http://127.0.0.1:{port}"));
+        post_json(
+            app.clone(),
+            "/register",
+            &format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#),
+        )
+        .await;
+
+        let resp = post_json(app.clone(), "/unmount-volume", r#"{"id":"vol-1"}"#).await;
+        assert_eq!(body_string(resp).await, "/unmount-volume");
+        let resp = post_json(app.clone(), "/mount-volume", r#"{"id":"vol-1"}"#).await;
+        assert_eq!(body_string(resp).await, "/mount-volume");
+
+        let resp = post_json(app, "/mount-volume", r#"{"id":"no-such-volume"}"#).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_full_proxy_flow_register_then_request() {
-        // list-vms fan-out aggregates JSON arrays; mock must return a valid array.
-        let port = start_mock_backend(200, r#"[{"id":"vm1"}]"#).await;
-        let (app, _) = build_test_app();
+    async fn test_resize_volume_routes_to_owning_backend() {
+        let port = start_echo_request_backend().await;
+        let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_volume("vol-1".to_string(), format!("http://127.0.0.1:{port}"));
 
-        let register_req = Request::builder()
-            .method("POST")
-            .uri("/register")
-            .header("content-type", "application/json")
-            .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-            .unwrap();
-        app.clone().oneshot(register_req).await.unwrap();
+        let body = r#"{"id":"vol-1","size_gb":20}"#;
+        let resp = post_json(app.clone(), "/resize-volume", body).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(resp).await,
+            format!("POST /resize-volume {body}")
+        );
 
-        let proxy_req = Request::builder()
-            .method("GET")
-            .uri("/list-vms")
-            .body(Body::empty())
-            .unwrap();
-        let resp = app.clone().oneshot(proxy_req).await.unwrap();
-        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+        let resp = post_json(app.clone(), "/resize-volume", r#"{"id":"vol-1"}"#).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
 
-        let body = body_string(resp).await;
-        let val: serde_json::Value = serde_json::from_str(&body).unwrap();
-        assert!(val.is_array());
-        assert!(!val.as_array().unwrap().is_empty());
+        let body = r#"{"id":"no-such-volume","size_gb":20}"#;
+        let resp = post_json(app, "/resize-volume", body).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
+    // ── power handlers ────────────────────────────────────────────────────────
+
     #[tokio::test]
-    async fn test_launch_vm_route_accepts_post() {
-        // Backend returns a 201 with a non-JSON body; the handler still proxies it.
-        let port = start_mock_backend(201, "launched").await;
+    async fn test_stop_vm_unknown_id_returns_404() {
         let (app, _) = build_test_app();
 
-        app.clone()
-            .oneshot(
-                Request::builder()
-                    .method("POST")
-                    .uri("/register")
-                    .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-
         let resp = app
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri("/launch-vm")
+                    .uri("/stop-vm")
                     .header("content-type", "application/json")
-                    .body(Body::from(r#"{"name":"test-vm"}"#))
+                    .body(Body::from(r#"{"id":"no-such-vm"}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
-        assert_eq!(resp.status(), axum::http::StatusCode::CREATED);
+
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_delete_vm_unknown_id_returns_404() {
-        let port = start_mock_backend(200, "deleted").await;
-        let (app, _) = build_test_app();
+    async fn test_stop_vm_routes_to_owning_backend() {
+        let port = start_mock_backend(200, "stopped").await;
+        let (app, registry) = build_test_app();
 
-        app.clone()
-            .oneshot(
-                Request::builder()
-                    .method("POST")
-                    .uri("/register")
-                    .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-                    .unwrap(),
-            )
+        registry
+            .write()
             .await
-            .unwrap();
+            .register_vm("vm-to-stop".to_string(), format!("http://127.0.0.1:{port}"));
 
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("DELETE")
-                    .uri("/delete-vm")
+                    .method("POST")
+                    .uri("/stop-vm")
                     .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"unknown-vm-id"}"#))
+                    .body(Body::from(r#"{"id":"vm-to-stop"}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
-        assert_eq!(resp.status(), axum::http::StatusCode::NOT_FOUND);
+
+        assert_eq!(resp.status(), StatusCode::OK);
     }
 
     #[tokio::test]
-    async fn test_fallback_route_proxies_arbitrary_path() {
-        let port = start_mock_backend(200, "arbitrary").await;
+    async fn test_start_vm_unknown_id_returns_404() {
         let (app, _) = build_test_app();
 
-        app.clone()
+        let resp = app
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri("/register")
+                    .uri("/start-vm")
                     .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-
-        let resp = app
-            .oneshot(
-                Request::builder()
-                    .method("GET")
-                    .uri("/some/unknown/path")
-                    .body(Body::empty())
+                    .body(Body::from(r#"{"id":"no-such-vm"}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
-        assert_eq!(resp.status(), axum::http::StatusCode::OK);
-        assert_eq!(body_string(resp).await, "arbitrary");
-    }
 
-    #[tokio::test]
-    async fn test_cors_headers_present_on_response() {
-        let (app, _) = build_test_app();
-        let resp = app
-            .oneshot(
-                Request::builder()
-                    .method("OPTIONS")
-                    .uri("/list-vms")
-                    .header("origin", "http://localhost:3000")
-                    .header("access-control-request-method", "GET")
-                    .body(Body::empty())
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-        assert!(
-            resp.headers().contains_key("access-control-allow-origin"),
-            "CORS allow-origin header must be present"
-        );
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
-    // ── multi-backend routing ─────────────────────────────────────────────────
-
     #[tokio::test]
-    async fn test_launch_vm_round_robins_across_backends() {
-        let (port_a, count_a) = start_counting_backend(r#"{"instance_id":"vm-a"}"#).await;
-        let (port_b, count_b) = start_counting_backend(r#"{"instance_id":"vm-b"}"#).await;
-        let (app, _) = build_test_app();
-
-        // Register both backends.
-        for port in [port_a, port_b] {
-            app.clone()
-                .oneshot(
-                    Request::builder()
-                        .method("POST")
-                        .uri("/register")
-                        .header("content-type", "application/json")
-                        .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-                        .unwrap(),
-                )
-                .await
-                .unwrap();
-        }
-
-        // Two launches should alternate across the two backends.
-        for _ in 0..2 {
-            app.clone()
-                .oneshot(
-                    Request::builder()
-                        .method("POST")
-                        .uri("/launch-vm")
-                        .header("content-type", "application/json")
-                        .body(Body::from(r#"{"name":"test"}"#))
-                        .unwrap(),
-                )
-                .await
-                .unwrap();
-        }
+    async fn test_start_vm_routes_to_owning_backend() {
+        let port = start_mock_backend(200, "started").await;
+        let (app, registry) = build_test_app();
 
-        assert_eq!(
-            *count_a.lock().await,
-            1,
-            "backend A should receive exactly 1 launch"
-        );
-        assert_eq!(
-            *count_b.lock().await,
-            1,
-            "backend B should receive exactly 1 launch"
+        registry.write().await.register_vm(
+            "vm-to-start".to_string(),
+            format!("http://127.0.0.1:{port}"),
         );
-    }
-
-    #[tokio::test]
-    async fn test_list_vms_aggregates_all_backends() {
-        let port_a = start_mock_backend(200, r#"[{"id":"vm-a"}]"#).await;
-        let port_b = start_mock_backend(200, r#"[{"id":"vm-b"}]"#).await;
-        let (app, _) = build_test_app();
-
-        for port in [port_a, port_b] {
-            app.clone()
-                .oneshot(
-                    Request::builder()
-                        .method("POST")
-                        .uri("/register")
-                        .header("content-type", "application/json")
-                        .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-                        .unwrap(),
-                )
-                .await
-                .unwrap();
-        }
 
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("GET")
-                    .uri("/list-vms")
-                    .body(Body::empty())
+                    .method("POST")
+                    .uri("/start-vm")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"id":"vm-to-start"}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
 
-        assert_eq!(resp.status(), axum::http::StatusCode::OK);
-        let body = body_string(resp).await;
-        let val: serde_json::Value = serde_json::from_str(&body).unwrap();
-        let arr = val.as_array().unwrap();
-        assert_eq!(
-            arr.len(),
-            2,
-            "merged list should contain VMs from both backends"
-        );
-        let ids: Vec<&str> = arr.iter().filter_map(|v| v["id"].as_str()).collect();
-        assert!(ids.contains(&"vm-a"));
-        assert!(ids.contains(&"vm-b"));
+        assert_eq!(resp.status(), StatusCode::OK);
     }
 
     #[tokio::test]
-    async fn test_delete_vm_routes_to_correct_backend() {
-        // Backend A records whether it received a DELETE; backend B does too.
-        let delete_count_a = Arc::new(Mutex::new(0usize));
-        let delete_count_b = Arc::new(Mutex::new(0usize));
-
-        // Each backend counts every request (launch + delete).
-        let dca = delete_count_a.clone();
-        let listener_da = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let port_da = listener_da.local_addr().unwrap().port();
-        tokio::spawn(async move {
-            let app = Router::new().fallback(move || {
-                let dca = dca.clone();
-                async move {
-                    *dca.lock().await += 1;
-                    (axum::http::StatusCode::OK, r#"{"instance_id":"vm-a"}"#)
-                }
-            });
-            axum::serve(listener_da, app).await.ok()
-        });
-
-        let dcb = delete_count_b.clone();
-        let listener_db = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let port_db = listener_db.local_addr().unwrap().port();
-        tokio::spawn(async move {
-            let app = Router::new().fallback(move || {
-                let dcb = dcb.clone();
-                async move {
-                    *dcb.lock().await += 1;
-                    (axum::http::StatusCode::OK, r#"{"instance_id":"vm-b"}"#)
-                }
-            });
-            axum::serve(listener_db, app).await.ok()
-        });
-        tokio::task::yield_now().await;
+    async fn test_reboot_pause_resume_route_to_owning_backend() {
+        let port = start_mock_backend(200, "ok").await;
+        let (app, registry) = build_test_app();
 
-        let (app, _) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
 
-        // Register the delete-tracking backends (they also handle launch).
-        for port in [port_da, port_db] {
-            app.clone()
+        for uri in ["/reboot-vm", "/pause-vm", "/resume-vm"] {
+            let resp = app
+                .clone()
                 .oneshot(
                     Request::builder()
                         .method("POST")
-                        .uri("/register")
+                        .uri(uri)
                         .header("content-type", "application/json")
-                        .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                        .body(Body::from(r#"{"id":"vm-1"}"#))
                         .unwrap(),
                 )
                 .await
                 .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK, "{uri}");
         }
+    }
 
-        // Launch twice so vm-a maps to backend A and vm-b maps to backend B.
-        for _ in 0..2 {
-            app.clone()
+    #[tokio::test]
+    async fn test_reboot_pause_resume_unknown_id_returns_404() {
+        let (app, _) = build_test_app();
+
+        for uri in ["/reboot-vm", "/pause-vm", "/resume-vm"] {
+            let resp = app
+                .clone()
                 .oneshot(
                     Request::builder()
                         .method("POST")
-                        .uri("/launch-vm")
+                        .uri(uri)
                         .header("content-type", "application/json")
-                        .body(Body::from(r#"{"name":"test"}"#))
+                        .body(Body::from(r#"{"id":"no-such-vm"}"#))
                         .unwrap(),
                 )
                 .await
                 .unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND, "{uri}");
         }
+    }
+
+    #[tokio::test]
+    async fn test_resize_vm_disk_routes_to_owning_backend() {
+        let port = start_echo_request_backend().await;
+        let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
+
+        let body = r#"{"id":"vm-1","size_gb":20}"#;
+        let resp = post_json(app.clone(), "/resize-vm-disk", body).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(resp).await,
+            format!("POST /resize-vm-disk {body}")
+        );
+
+        let resp = post_json(app.clone(), "/resize-vm-disk", r#"{"id":"vm-1"}"#).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body = r#"{"id":"no-such-vm","size_gb":20}"#;
+        let resp = post_json(app, "/resize-vm-disk", body).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    // ── console handlers ──────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn test_console_log_unknown_id_returns_404() {
+        let (app, _) = build_test_app();
 
-        // Delete vm-b — should route only to backend B (port_db).
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("DELETE")
-                    .uri("/delete-vm")
-                    .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"vm-b"}"#))
+                    .method("GET")
+                    .uri("/vm-console-log/no-such-vm")
+                    .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-        assert_eq!(resp.status(), axum::http::StatusCode::OK);
 
-        // Backend A must not have received an extra request for the delete.
-        assert_eq!(
-            *delete_count_a.lock().await,
-            1,
-            "backend A should have 1 hit (launch only)"
-        );
-        // Backend B received the launch AND the delete.
-        assert_eq!(
-            *delete_count_b.lock().await,
-            2,
-            "backend B should have 2 hits (launch + delete)"
-        );
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
-    // ── persistence ───────────────────────────────────────────────────────────
-
     #[tokio::test]
-    async fn test_load_vm_backends_missing_file_returns_empty() {
-        let tmp = tempfile::TempDir::new().unwrap();
-        let path = tmp.path().join("nonexistent.json");
-        let map = load_vm_backends(&path).await;
-        assert!(map.is_empty());
-    }
+    async fn test_console_log_forwards_query_to_owning_backend() {
+        let port = start_echo_uri_backend().await;
+        let (app, registry) = build_test_app();
 
-    #[tokio::test]
-    async fn test_save_and_load_vm_backends_round_trips() {
-        let tmp = tempfile::TempDir::new().unwrap();
-        let path = tmp.path().join("backends.json");
+        registry
+            .write()
+            .await
+            .register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
 
-        let mut map = HashMap::new();
-        map.insert("vm-a".to_string(), "http://10.0.0.1:8081".to_string());
-        map.insert("vm-b".to_string(), "http://10.0.0.2:8082".to_string());
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/vm-console-log/vm-1?tail=20")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-        save_vm_backends(&path, &map).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_string(resp).await, "/vm-console-log/vm-1?tail=20");
+    }
 
-        let loaded = load_vm_backends(&path).await;
-        assert_eq!(loaded, map);
+    /// Backend whose WebSockets, on any path, echo every message back.
+    async fn start_ws_echo_backend() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = Router::new().fallback(|ws: WebSocketUpgrade| async move {
+            ws.on_upgrade(|mut socket| async move {
+                while let Some(Ok(message)) = socket.recv().await {
+                    if socket.send(message).await.is_err() {
+                        break;
+                    }
+                }
+            })
+        });
+        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
+        tokio::task::yield_now().await;
+        port
     }
 
-    #[tokio::test]
-    async fn test_load_vm_backends_invalid_json_returns_empty() {
-        let tmp = tempfile::TempDir::new().unwrap();
-        let path = tmp.path().join("bad.json");
-        tokio::fs::write(&path, b"not valid json").await.unwrap();
-        let map = load_vm_backends(&path).await;
-        assert!(map.is_empty());
+    /// Serve `app` on a real socket; WebSocket upgrades need a live connection.
+    async fn serve(app: Router) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
+        tokio::task::yield_now().await;
+        port
+    }
+
+    fn handshake_status(result: Result<impl Sized, tokio_tungstenite::tungstenite::Error>) -> u16 {
+        match result {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                response.status().as_u16()
+            }
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("handshake unexpectedly succeeded"),
+        }
     }
 
     #[tokio::test]
-    async fn test_launch_vm_persists_mapping_to_file() {
-        let tmp = tempfile::TempDir::new().unwrap();
-        let backends_file = tmp.path().join("vm-backends.json");
-        let (app, _) =
-            build_test_app_with_files(backends_file.clone(), tmp.path().join("vol.json"));
+    async fn test_vm_console_relays_messages_to_owning_backend() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
 
-        // Start a mock backend that returns a successful launch response.
-        let port = start_mock_backend(200, r#"{"success":true,"instance_id":"vm-xyz"}"#).await;
+        let backend_port = start_ws_echo_backend().await;
+        let (app, registry) = build_test_app();
+        registry.write().await.register_vm(
+            "vm-1".to_string(),
+            format!("http://127.0.0.1:{backend_port}"),
+        );
+        let port = serve(app).await;
 
-        app.clone()
-            .oneshot(
-                Request::builder()
-                    .method("POST")
-                    .uri("/register")
-                    .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-                    .unwrap(),
-            )
+        let (mut socket, _) =
+            tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{port}/vm-console/vm-1"))
+                .await
+                .unwrap();
+        socket
+            .send(Message::Text("uname -a\n".into()))
             .await
             .unwrap();
+        assert_eq!(
+            socket.next().await.unwrap().unwrap(),
+            Message::Text("uname -a\n".into())
+        );
+        socket.send(Message::Binary(vec![3])).await.unwrap();
+        assert_eq!(
+            socket.next().await.unwrap().unwrap(),
+            Message::Binary(vec![3])
+        );
+    }
 
-        app.oneshot(
-            Request::builder()
-                .method("POST")
-                .uri("/launch-vm")
-                .header("content-type", "application/json")
-                .body(Body::from(r#"{"name":"test"}"#))
-                .unwrap(),
-        )
-        .await
-        .unwrap();
+    #[tokio::test]
+    async fn test_vm_console_unknown_id_returns_404() {
+        let (app, _) = build_test_app();
+        let port = serve(app).await;
 
-        let persisted = load_vm_backends(&backends_file).await;
-        assert!(
-            persisted.contains_key("vm-xyz"),
-            "vm-xyz should be in the persisted file"
+        let result =
+            tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{port}/vm-console/nope"))
+                .await;
+        assert_eq!(handshake_status(result), 404);
+    }
+
+    #[tokio::test]
+    async fn test_vm_console_passes_backend_refusal_through() {
+        let backend_port =
+            start_mock_backend(409, "Cannot open the console while the VM is stopped").await;
+        let (app, registry) = build_test_app();
+        registry.write().await.register_vm(
+            "vm-1".to_string(),
+            format!("http://127.0.0.1:{backend_port}"),
         );
+        let port = serve(app).await;
+
+        let result =
+            tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{port}/vm-console/vm-1"))
+                .await;
+        assert_eq!(handshake_status(result), 409);
     }
 
     #[tokio::test]
-    async fn test_delete_vm_removes_mapping_from_file() {
-        let tmp = tempfile::TempDir::new().unwrap();
-        let backends_file = tmp.path().join("vm-backends.json");
-        let (app, registry) =
-            build_test_app_with_files(backends_file.clone(), tmp.path().join("vol.json"));
+    async fn test_vm_vnc_relays_and_accepts_binary_subprotocol() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::Message;
 
-        // Pre-populate the registry and the file with a known mapping.
-        let port = start_mock_backend(200, "deleted").await;
+        let backend_port = start_ws_echo_backend().await;
+        let (app, registry) = build_test_app();
         registry.write().await.register_vm(
-            "vm-to-delete".to_string(),
-            format!("http://127.0.0.1:{port}"),
+            "vm-1".to_string(),
+            format!("http://127.0.0.1:{backend_port}"),
         );
+        let port = serve(app).await;
 
-        let mut initial = HashMap::new();
-        initial.insert(
-            "vm-to-delete".to_string(),
-            format!("http://127.0.0.1:{port}"),
+        let mut request = format!("ws://127.0.0.1:{port}/vm-vnc/vm-1")
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("sec-websocket-protocol", "binary".parse().unwrap());
+        let (mut socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+        assert_eq!(response.headers()["sec-websocket-protocol"], "binary");
+
+        socket
+            .send(Message::Binary(b"RFB 003.008\n".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(
+            socket.next().await.unwrap().unwrap(),
+            Message::Binary(b"RFB 003.008\n".to_vec())
         );
-        save_vm_backends(&backends_file, &initial).await;
+    }
 
-        // Register the backend so the proxy can route to it.
-        app.clone()
+    #[tokio::test]
+    async fn test_vm_screenshot_routes_to_owning_backend() {
+        let port = start_echo_uri_backend().await;
+        let (app, registry) = build_test_app();
+
+        let resp = app
+            .clone()
             .oneshot(
                 Request::builder()
-                    .method("POST")
-                    .uri("/register")
-                    .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .uri("/vm-screenshot/vm-1")
+                    .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 
+        registry
+            .write()
+            .await
+            .register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("DELETE")
-                    .uri("/delete-vm")
-                    .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"vm-to-delete"}"#))
+                    .uri("/vm-screenshot/vm-1")
+                    .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_string(resp).await, "/vm-screenshot/vm-1");
+    }
 
-        assert_eq!(resp.status(), axum::http::StatusCode::OK);
+    // ── guest agent handlers ──────────────────────────────────────────────────
 
-        let persisted = load_vm_backends(&backends_file).await;
-        assert!(
-            !persisted.contains_key("vm-to-delete"),
-            "vm-to-delete should be removed from the persisted file"
+    /// Backend that answers every request with its method, URI and body.
+    async fn start_echo_request_backend() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let app = Router::new().fallback(
+            |method: axum::http::Method, uri: axum::http::Uri, body: String| async move {
+                format!("{method} {uri} {body}")
+            },
         );
+        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
+        tokio::task::yield_now().await;
+        port
     }
 
-    // ── volume handlers ───────────────────────────────────────────────────────
-
     #[tokio::test]
-    async fn test_launch_volume_records_backend_mapping() {
-        let port = start_mock_backend(200, r#"{"success":true,"id":"vol-abc"}"#).await;
+    async fn test_vm_exec_routes_body_to_owning_backend() {
+        let port = start_echo_request_backend().await;
         let (app, registry) = build_test_app();
+        let body = r#"{"command":["uname","-a"]}"#;
 
-        app.clone()
-            .oneshot(
-                Request::builder()
-                    .method("POST")
-                    .uri("/register")
-                    .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-
-        app.oneshot(
-            Request::builder()
-                .method("POST")
-                .uri("/launch-volume")
-                .header("content-type", "application/json")
-                .body(Body::from(r#"{"name":"data","size_gb":10}"#))
-                .unwrap(),
-        )
-        .await
-        .unwrap();
+        let resp = post_json(app.clone(), "/vm-exec/vm-1", body).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 
-        assert!(
-            registry
-                .read()
-                .await
-                .backend_for_volume("vol-abc")
-                .is_some(),
-            "volume-backend mapping should be recorded after launch"
+        registry
+            .write()
+            .await
+            .register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
+        let resp = post_json(app, "/vm-exec/vm-1", body).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(resp).await,
+            format!("POST /vm-exec/vm-1 {body}")
         );
     }
 
-    #[tokio::test]
-    async fn test_launch_volume_persists_mapping_to_file() {
-        let tmp = tempfile::TempDir::new().unwrap();
-        let vol_file = tmp.path().join("volume-backends.json");
-        let (app, _) = build_test_app_with_files(tmp.path().join("vm.json"), vol_file.clone());
-
-        let port = start_mock_backend(200, r#"{"success":true,"id":"vol-xyz"}"#).await;
+    #[tokio::test]
+    async fn test_vm_file_forwards_path_and_body() {
+        let port = start_echo_request_backend().await;
+        let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
 
-        app.clone()
+        let resp = app
+            .clone()
             .oneshot(
                 Request::builder()
-                    .method("POST")
-                    .uri("/register")
-                    .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .method("PUT")
+                    .uri("/vm-file/vm-1?path=%2Froot%2Fsetup.sh")
+                    .body(Body::from("echo ready"))
                     .unwrap(),
             )
             .await
             .unwrap();
-
-        app.oneshot(
-            Request::builder()
-                .method("POST")
-                .uri("/launch-volume")
-                .header("content-type", "application/json")
-                .body(Body::from(r#"{"name":"data","size_gb":5}"#))
-                .unwrap(),
-        )
-        .await
-        .unwrap();
-
-        let persisted = load_volume_backends(&vol_file).await;
-        assert!(
-            persisted.contains_key("vol-xyz"),
-            "vol-xyz should be in the persisted volume-backends file"
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(resp).await,
+            "PUT /vm-file/vm-1?path=%2Froot%2Fsetup.sh echo ready"
         );
-    }
-
-    #[tokio::test]
-    async fn test_list_volumes_aggregates_all_backends() {
-        let port_a =
-            start_mock_backend(200, r#"[{"id":"vol-a","name":"a","mount_path":"/mnt/a"}]"#).await;
-        let port_b =
-            start_mock_backend(200, r#"[{"id":"vol-b","name":"b","mount_path":"/mnt/b"}]"#).await;
-        let (app, _) = build_test_app();
-
-        for port in [port_a, port_b] {
-            app.clone()
-                .oneshot(
-                    Request::builder()
-                        .method("POST")
-                        .uri("/register")
-                        .header("content-type", "application/json")
-                        .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
-                        .unwrap(),
-                )
-                .await
-                .unwrap();
-        }
 
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("GET")
-                    .uri("/list-volumes")
+                    .uri("/vm-file/vm-1?path=/etc/hostname")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-
-        assert_eq!(resp.status(), StatusCode::OK);
-        let body = body_string(resp).await;
-        let arr: serde_json::Value = serde_json::from_str(&body).unwrap();
-        assert_eq!(arr.as_array().unwrap().len(), 2);
+        assert_eq!(
+            body_string(resp).await,
+            "GET /vm-file/vm-1?path=%2Fetc%2Fhostname "
+        );
     }
 
     #[tokio::test]
-    async fn test_delete_volume_unknown_id_returns_404() {
+    async fn test_vm_file_unknown_id_returns_404() {
         let (app, _) = build_test_app();
-
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("DELETE")
-                    .uri("/delete-volume")
-                    .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"no-such-volume"}"#))
+                    .uri("/vm-file/no-such-vm?path=/etc/hostname")
+                    .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-
         assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_delete_volume_routes_to_owning_backend_and_removes_mapping() {
-        let tmp = tempfile::TempDir::new().unwrap();
-        let vol_file = tmp.path().join("volume-backends.json");
-        let (app, registry) =
-            build_test_app_with_files(tmp.path().join("vm.json"), vol_file.clone());
-
-        let port = start_mock_backend(200, "deleted").await;
-        registry.write().await.register_volume(
-            "vol-to-delete".to_string(),
-            format!("http://127.0.0.1:{port}"),
-        );
-
-        let mut initial = HashMap::new();
-        initial.insert(
-            "vol-to-delete".to_string(),
-            format!("http://127.0.0.1:{port}"),
-        );
-        save_volume_backends(&vol_file, &initial).await;
+    async fn test_volume_file_requests_route_to_owning_backend() {
+        let port = start_echo_request_backend().await;
+        let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_volume("vol-1".to_string(), format!("http://127.0.0.1:{port}"));
 
-        app.clone()
+        // A body arriving in chunks is passed on as it arrives.
+        let chunks = futures_util::stream::iter(["hello ", "world"].map(|chunk| {
+            Ok::<_, std::io::Error>(axum::body::Bytes::from_static(chunk.as_bytes()))
+        }));
+        let resp = app
+            .clone()
             .oneshot(
                 Request::builder()
-                    .method("POST")
-                    .uri("/register")
-                    .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .method("PUT")
+                    .uri("/volume-file/vol-1?path=docs%2Fa.txt")
+                    .body(Body::from_stream(chunks))
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(resp).await,
+            "PUT /volume-file/vol-1?path=docs%2Fa.txt hello world"
+        );
 
         let resp = app
+            .clone()
             .oneshot(
                 Request::builder()
                     .method("DELETE")
-                    .uri("/delete-volume")
-                    .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"vol-to-delete"}"#))
+                    .uri("/volume-file/vol-1?path=docs&recursive=true")
+                    .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        let echoed = body_string(resp).await;
+        assert!(echoed.starts_with("DELETE /volume-file/vol-1?"), "{echoed}");
+        assert!(echoed.contains("recursive=true"), "{echoed}");
 
-        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/volume-mkdir/vol-1?path=docs")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            body_string(resp).await,
+            "POST /volume-mkdir/vol-1?path=docs "
+        );
 
-        let persisted = load_volume_backends(&vol_file).await;
-        assert!(
-            !persisted.contains_key("vol-to-delete"),
-            "vol-to-delete should be removed from the persisted file"
+        let body = r#"{"from":"a.txt","to":"docs/a.txt"}"#;
+        let resp = post_json(app, "/volume-rename/vol-1", body).await;
+        assert_eq!(
+            body_string(resp).await,
+            format!("POST /volume-rename/vol-1 {body}")
         );
     }
 
     #[tokio::test]
-    async fn test_list_volume_files_unknown_id_returns_404() {
+    async fn test_volume_file_unknown_id_returns_404() {
         let (app, _) = build_test_app();
-
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("GET")
-                    .uri("/volume-files/no-such-id")
+                    .uri("/volume-file/no-such-volume?path=a.txt")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-
         assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
+    // ── snapshot handlers ─────────────────────────────────────────────────────
+
     #[tokio::test]
-    async fn test_list_volume_files_routes_to_owning_backend() {
-        let port = start_mock_backend(
-            200,
-            r#"[{"name":"file.txt","is_dir":false,"size_bytes":5,"modified_secs":0}]"#,
-        )
-        .await;
+    async fn test_snapshot_requests_route_to_owning_backend() {
+        let port = start_echo_request_backend().await;
         let (app, registry) = build_test_app();
+        let body = r#"{"vm_id":"vm-1","name":"before-upgrade"}"#;
+
+        let resp = post_json(app.clone(), "/create-snapshot", body).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 
         registry
             .write()
             .await
-            .register_volume("my-vol".to_string(), format!("http://127.0.0.1:{port}"));
+            .register_vm("vm-1".to_string(), format!("http://127.0.0.1:{port}"));
+        for uri in ["/create-snapshot", "/revert-snapshot"] {
+            let resp = post_json(app.clone(), uri, body).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(body_string(resp).await, format!("POST {uri} {body}"));
+        }
 
-        app.clone()
+        let resp = app
+            .clone()
             .oneshot(
                 Request::builder()
-                    .method("POST")
-                    .uri("/register")
+                    .method("DELETE")
+                    .uri("/delete-snapshot")
                     .header("content-type", "application/json")
-                    .body(Body::from(format!(r#"{{"ip":"127.0.0.1","port":{port}}}"#)))
+                    .body(Body::from(body))
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(
+            body_string(resp).await,
+            format!("DELETE /delete-snapshot {body}")
+        );
 
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("GET")
-                    .uri("/volume-files/my-vol")
+                    .uri("/list-snapshots/vm-1")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(body_string(resp).await, "GET /list-snapshots/vm-1 ");
+    }
 
-        assert_eq!(resp.status(), StatusCode::OK);
-        let body = body_string(resp).await;
-        let arr: serde_json::Value = serde_json::from_str(&body).unwrap();
-        assert_eq!(arr.as_array().unwrap().len(), 1);
+    #[tokio::test]
+    async fn test_snapshot_request_without_vm_id_returns_400() {
+        let (app, _) = build_test_app();
+
+        let resp = post_json(app, "/create-snapshot", r#"{"name":"s1"}"#).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
 
-    // ── stop/start vm handlers ────────────────────────────────────────────────
+    // ── image handlers ────────────────────────────────────────────────────────
 
     #[tokio::test]
-    async fn test_stop_vm_unknown_id_returns_404() {
-        let (app, _) = build_test_app();
+    async fn test_register_image_records_and_persists_mapping() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let img_file = tmp.path().join("image-backends.json");
+        let (app, registry) = build_test_app_with_files(
+            tmp.path().join("vm.json"),
+            tmp.path().join("vol.json"),
+            img_file.clone(),
+        );
+
+        let port = start_mock_backend(200, r#"{"success":true,"id":"img-abc"}"#).await;
+        registry.write().await.register("127.0.0.1", port);
 
         let resp = app
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri("/stop-vm")
+                    .uri("/register-image")
                     .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"no-such-vm"}"#))
+                    .body(Body::from(
+                        r#"{"name":"alpine","os":"alpine","version":"3.19","source_path":"/tmp/a.qcow2"}"#,
+                    ))
                     .unwrap(),
             )
             .await
             .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
 
+        assert_eq!(
+            registry.read().await.backend_for_image("img-abc"),
+            Some(format!("http://127.0.0.1:{port}"))
+        );
+        let persisted = load_image_backends(&img_file).await;
+        assert!(persisted.contains_key("img-abc"));
+    }
+
+    #[tokio::test]
+    async fn test_create_image_routes_to_vm_backend_and_records_mapping() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let img_file = tmp.path().join("image-backends.json");
+        let (app, registry) = build_test_app_with_files(
+            tmp.path().join("vm.json"),
+            tmp.path().join("vol.json"),
+            img_file.clone(),
+        );
+        let body = r#"{"vm_id":"vm-1","name":"web-golden"}"#;
+
+        let resp = post_json(app.clone(), "/create-image", body).await;
         assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let port = start_mock_backend(200, r#"{"success":true,"id":"img-new"}"#).await;
+        let backend_url = format!("http://127.0.0.1:{port}");
+        registry
+            .write()
+            .await
+            .register_vm("vm-1".to_string(), backend_url.clone());
+        let resp = post_json(app, "/create-image", body).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        assert_eq!(
+            registry.read().await.backend_for_image("img-new"),
+            Some(backend_url)
+        );
+        assert!(load_image_backends(&img_file).await.contains_key("img-new"));
     }
 
     #[tokio::test]
-    async fn test_stop_vm_routes_to_owning_backend() {
-        let port = start_mock_backend(200, "stopped").await;
+    async fn test_image_file_routes_to_image_backend() {
+        let port = start_echo_uri_backend().await;
         let (app, registry) = build_test_app();
 
+        let request = || {
+            Request::builder()
+                .uri("/image-file/img-1")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let resp = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
         registry
             .write()
             .await
-            .register_vm("vm-to-stop".to_string(), format!("http://127.0.0.1:{port}"));
+            .register_image("img-1".to_string(), format!("http://127.0.0.1:{port}"));
+        let resp = app.oneshot(request()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_string(resp).await, "/image-file/img-1");
+    }
+
+    #[tokio::test]
+    async fn test_delete_image_unknown_id_returns_404() {
+        let (app, _) = build_test_app();
 
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("POST")
-                    .uri("/stop-vm")
+                    .method("DELETE")
+                    .uri("/delete-image")
                     .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"vm-to-stop"}"#))
+                    .body(Body::from(r#"{"id":"no-such-image"}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
 
-        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_start_vm_unknown_id_returns_404() {
-        let (app, _) = build_test_app();
+    async fn test_delete_image_routes_to_owning_backend_and_removes_mapping() {
+        let port = start_mock_backend(200, "deleted").await;
+        let (app, registry) = build_test_app();
+        registry
+            .write()
+            .await
+            .register_image("img-1".to_string(), format!("http://127.0.0.1:{port}"));
 
         let resp = app
             .oneshot(
                 Request::builder()
-                    .method("POST")
-                    .uri("/start-vm")
+                    .method("DELETE")
+                    .uri("/delete-image")
                     .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"no-such-vm"}"#))
+                    .body(Body::from(r#"{"id":"img-1"}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
 
-        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(registry.read().await.backend_for_image("img-1").is_none());
     }
 
     #[tokio::test]
-    async fn test_start_vm_routes_to_owning_backend() {
-        let port = start_mock_backend(200, "started").await;
+    async fn test_list_images_aggregates_all_backends() {
+        let port_a = start_mock_backend(200, r#"[{"id":"img-a"}]"#).await;
+        let port_b = start_mock_backend(200, r#"[{"id":"img-b"}]"#).await;
         let (app, registry) = build_test_app();
+        for port in [port_a, port_b] {
+            registry.write().await.register("127.0.0.1", port);
+        }
 
-        registry.write().await.register_vm(
-            "vm-to-start".to_string(),
-            format!("http://127.0.0.1:{port}"),
-        );
+        let resp = app
+            .oneshot(
+                Request::builder()
+                    .method("GET")
+                    .uri("/list-images")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let arr: serde_json::Value = serde_json::from_str(&body_string(resp).await).unwrap();
+        assert_eq!(arr.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_launch_vm_with_image_routes_to_image_backend() {
+        let (port_a, count_a) = start_counting_backend(r#"{"instance_id":"vm-a"}"#).await;
+        let (port_b, count_b) = start_counting_backend(r#"{"instance_id":"vm-b"}"#).await;
+        let (app, registry) = build_test_app();
+        {
+            let mut reg = registry.write().await;
+            reg.register("127.0.0.1", port_a);
+            reg.register("127.0.0.1", port_b);
+            reg.register_image("img-b".to_string(), format!("http://127.0.0.1:{port_b}"));
+        }
+
+        // Both launches name the image, so neither may round-robin onto backend A.
+        for _ in 0..2 {
+            let resp = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri("/launch-vm")
+                        .header("content-type", "application/json")
+                        .body(Body::from(r#"{"name":"test","image_id":"img-b"}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+
+        assert_eq!(*count_a.lock().await, 0);
+        assert_eq!(*count_b.lock().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_launch_vm_with_unknown_image_returns_404() {
+        let port = start_mock_backend(200, r#"{"instance_id":"vm-a"}"#).await;
+        let (app, registry) = build_test_app();
+        registry.write().await.register("127.0.0.1", port);
 
         let resp = app
             .oneshot(
                 Request::builder()
                     .method("POST")
-                    .uri("/start-vm")
+                    .uri("/launch-vm")
                     .header("content-type", "application/json")
-                    .body(Body::from(r#"{"id":"vm-to-start"}"#))
+                    .body(Body::from(r#"{"name":"test","image_id":"nope"}"#))
                     .unwrap(),
             )
             .await
             .unwrap();
 
-        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 }
@@ -73,13 +73,54 @@ impl ProxyService {
     /// Fan-out GET to all registered backends and merge the JSON array results.
     /// Backends that fail or return non-JSON-array responses are skipped with a warning.
     pub async fn list_all(&self, path: &str, headers: HeaderMap) -> impl IntoResponse {
-        let urls = self.registry.read().await.all_urls();
-        if urls.is_empty() {
+        let Some(results) = self.fetch_all(path, headers).await else {
             return (
                 StatusCode::SERVICE_UNAVAILABLE,
                 "Backend not yet registered",
             )
                 .into_response();
+        };
+        let mut merged: Vec<serde_json::Value> =
+            results.into_iter().flat_map(|(_, items)| items).collect();
+
+        // Resolve mac_address → ssh_host for VMs in bridge mode whose guest
+        // agent has not reported an address. The MAC is set by the backend;
+        // the proxy (which runs on the controller alongside dnsmasq) is the
+        // only node that can read the lease file reliably.
+        for vm in &mut merged {
+            let has_host = vm
+                .get("ssh_host")
+                .and_then(|v| v.as_str())
+                .is_some_and(|h| !h.is_empty());
+            if has_host {
+                continue;
+            }
+            if let Some(mac) = vm.get("mac_address").and_then(|v| v.as_str()) {
+                let mac = mac.to_string();
+                if !mac.is_empty() {
+                    if let Some(ip) = ip_lookup::lookup_ip_by_mac(&mac, &self.lease_file).await {
+                        if let Some(obj) = vm.as_object_mut() {
+                            obj.insert("ssh_host".to_string(), serde_json::Value::String(ip));
+                        }
+                    }
+                }
+            }
+        }
+
+        Json(merged).into_response()
+    }
+
+    /// Fan-out GET to all registered backends, returning each backend's URL
+    /// with its JSON array. Backends that fail or return non-JSON-array
+    /// responses are skipped with a warning. `None` if no backend is registered.
+    pub async fn fetch_all(
+        &self,
+        path: &str,
+        headers: HeaderMap,
+    ) -> Option<Vec<(String, Vec<serde_json::Value>)>> {
+        let urls = self.registry.read().await.all_urls();
+        if urls.is_empty() {
+            return None;
         }
 
         let mut tasks = tokio::task::JoinSet::new();
@@ -99,42 +140,25 @@ impl ProxyService {
                         }
                     }
                 }
-                req_builder.send().await
+                (url, req_builder.send().await)
             });
         }
 
-        let mut merged: Vec<serde_json::Value> = Vec::new();
+        let mut results = Vec::new();
         while let Some(result) = tasks.join_next().await {
             match result {
-                Ok(Ok(resp)) => {
+                Ok((url, Ok(resp))) => {
                     if let Ok(items) = resp.json::<Vec<serde_json::Value>>().await {
-                        merged.extend(items);
+                        results.push((url, items));
                     } else {
                         warn!("Backend returned non-JSON-array response for list request");
                     }
                 }
-                Ok(Err(e)) => warn!("Backend request failed: {}", e),
+                Ok((_, Err(e))) => warn!("Backend request failed: {}", e),
                 Err(e) => warn!("Fan-out task panicked: {}", e),
             }
         }
-
-        // Resolve mac_address → ssh_host for VMs in bridge mode. The MAC is
-        // set by the backend; the proxy (which runs on the controller alongside
-        // dnsmasq) is the only node that can read the lease file reliably.
-        for vm in &mut merged {
-            if let Some(mac) = vm.get("mac_address").and_then(|v| v.as_str()) {
-                let mac = mac.to_string();
-                if !mac.is_empty() {
-                    if let Some(ip) = ip_lookup::lookup_ip_by_mac(&mac, &self.lease_file).await {
-                        if let Some(obj) = vm.as_object_mut() {
-                            obj.insert("ssh_host".to_string(), serde_json::Value::String(ip));
-                        }
-                    }
-                }
-            }
-        }
-
-        Json(merged).into_response()
+        Some(results)
     }
 
     /// Shared forwarding logic used by both `proxy_request` and `proxy_request_to`.
@@ -181,17 +205,11 @@ impl ProxyService {
             request_builder = request_builder.query(&query.0);
         }
 
+        // Streamed like responses, so uploads such as volume files do not
+        // have to fit in memory.
         let request = if let Some(body) = body {
-            let body_bytes = match axum::body::to_bytes(body, usize::MAX).await {
-                Ok(bytes) => bytes,
-                Err(e) => {
-                    error!("Failed to read body: {}", e);
-                    return (StatusCode::BAD_REQUEST, "Failed to read request body")
-                        .into_response();
-                }
-            };
             request_builder
-                .body(reqwest::Body::from(body_bytes))
+                .body(reqwest::Body::wrap_stream(body.into_data_stream()))
                 .build()
                 .unwrap()
         } else {
@@ -202,7 +220,6 @@ impl ProxyService {
             Ok(response) => {
                 let status = response.status();
                 let resp_headers = response.headers().clone();
-                let body_bytes = response.bytes().await.unwrap_or_default();
                 info!("Backend response: {}", status);
 
                 let axum_status = StatusCode::from_u16(status.as_u16())
@@ -222,7 +239,11 @@ impl ProxyService {
                     }
                 }
 
-                response_builder.body(Body::from(body_bytes)).unwrap()
+                // Streamed rather than buffered, so large downloads such as
+                // image files do not have to fit in memory.
+                response_builder
+                    .body(Body::from_stream(response.bytes_stream()))
+                    .unwrap()
             }
             Err(e) => {
                 error!("Proxy request failed: {}", e);
@@ -836,6 +857,42 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_list_all_keeps_guest_agent_address_over_lease_file() {
+        use std::io::Write;
+
+        // The lease is stale: the guest agent knows the current address.
+        let mut lease_file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            lease_file,
+            "1234567890 52:54:00:ab:cd:ef 10.0.0.188 alpine-vm *"
+        )
+        .unwrap();
+
+        let port = start_mock_backend(
+            200,
+            r#"[{"id":"vm-1","name":"test","ssh_host":"10.0.0.42","ssh_port":22,"pid":42,"mac_address":"52:54:00:ab:cd:ef"}]"#,
+        )
+        .await;
+
+        let registry = BackendRegistry::with_url(format!("http://127.0.0.1:{port}"));
+        let svc = ProxyService::new(
+            Arc::new(RwLock::new(registry)),
+            lease_file.path().to_path_buf(),
+        );
+
+        let resp = svc
+            .list_all("/list-vms", HeaderMap::new())
+            .await
+            .into_response();
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let vms: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(vms[0]["ssh_host"].as_str(), Some("10.0.0.42"));
+    }
+
     #[tokio::test]
     async fn test_list_all_leaves_ssh_host_empty_when_mac_not_in_lease_file() {
         let lease_file = tempfile::NamedTempFile::new().unwrap(); // empty lease file
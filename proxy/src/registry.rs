@@ -16,6 +16,8 @@ pub struct BackendRegistry {
     vm_backends: HashMap<String, String>,
     /// Maps volume_id → backend_url so volume operations route to the owning backend.
     volume_backends: HashMap<String, String>,
+    /// Maps image_id → backend_url so launches from an image go to the backend storing it.
+    image_backends: HashMap<String, String>,
 }
 
 impl BackendRegistry {
@@ -110,6 +112,26 @@ impl BackendRegistry {
         self.volume_backends.clone()
     }
 
+    /// Record which backend an image was registered on.
+    pub fn register_image(&mut self, image_id: String, backend_url: String) {
+        self.image_backends.insert(image_id, backend_url);
+    }
+
+    /// Look up the backend URL that stores a given image.
+    pub fn backend_for_image(&self, image_id: &str) -> Option<String> {
+        self.image_backends.get(image_id).cloned()
+    }
+
+    /// Remove the backend mapping for an image (called after a successful delete).
+    pub fn remove_image(&mut self, image_id: &str) {
+        self.image_backends.remove(image_id);
+    }
+
+    /// Return a snapshot of all image_id → backend_url mappings, for persistence.
+    pub fn all_image_backends(&self) -> HashMap<String, String> {
+        self.image_backends.clone()
+    }
+
     /// Test helper: create a registry pre-populated with a single known URL.
     #[cfg(test)]
     pub fn with_url(url: String) -> Self {
@@ -384,6 +406,33 @@ mod tests {
         );
     }
 
+    // ── image mapping ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_register_image_and_lookup() {
+        let mut reg = BackendRegistry::new();
+        reg.register_image("img-1".to_string(), "http://10.0.0.2:8081".to_string());
+        assert_eq!(
+            reg.backend_for_image("img-1").as_deref(),
+            Some("http://10.0.0.2:8081")
+        );
+    }
+
+    #[test]
+    fn test_backend_for_image_unknown_returns_none() {
+        let reg = BackendRegistry::new();
+        assert!(reg.backend_for_image("no-such-image").is_none());
+    }
+
+    #[test]
+    fn test_remove_image_removes_mapping() {
+        let mut reg = BackendRegistry::new();
+        reg.register_image("img-1".to_string(), "http://10.0.0.2:8081".to_string());
+        reg.remove_image("img-1");
+        assert!(reg.backend_for_image("img-1").is_none());
+        assert!(reg.all_image_backends().is_empty());
+    }
+
     #[test]
     fn test_vm_and_volume_backends_are_independent() {
         let mut reg = BackendRegistry::new();
@@ -0,0 +1,147 @@
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::warn;
+
+pub type BackendSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// WebSocket URL for `path_and_query` on a backend registered by its HTTP URL.
+pub fn backend_ws_url(backend_url: &str, path_and_query: &str) -> String {
+    let base = if let Some(rest) = backend_url.strip_prefix("https://") {
+        format!("wss://{rest}")
+    } else if let Some(rest) = backend_url.strip_prefix("http://") {
+        format!("ws://{rest}")
+    } else {
+        backend_url.to_string()
+    };
+    format!("{base}{path_and_query}")
+}
+
+/// Open a WebSocket to the backend before the client's connection is upgraded,
+/// so a refused handshake (e.g. 404 for an unknown VM, 409 for a stopped one)
+/// reaches the client as the backend's own status and message.
+pub async fn connect(url: &str) -> Result<BackendSocket, Response> {
+    match tokio_tungstenite::connect_async(url).await {
+        Ok((socket, _)) => Ok(socket),
+        Err(tungstenite::Error::Http(response)) => {
+            let status = response.status();
+            let body = response.into_body().unwrap_or_default();
+            Err((status, body).into_response())
+        }
+        Err(e) => {
+            warn!("WebSocket connection to {url} failed: {e}");
+            Err((
+                StatusCode::BAD_GATEWAY,
+                format!("Failed to reach backend: {e}"),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Relay messages between an upgraded client connection and the backend until
+/// either side closes. Pings are not relayed: each hop answers its own.
+pub async fn relay(client: WebSocket, backend: BackendSocket) {
+    let (mut client_tx, mut client_rx) = client.split();
+    let (mut backend_tx, mut backend_rx) = backend.split();
+
+    let upstream = async {
+        while let Some(Ok(message)) = client_rx.next().await {
+            let Some(message) = to_backend(message) else {
+                continue;
+            };
+            let closing = message.is_close();
+            if backend_tx.send(message).await.is_err() || closing {
+                break;
+            }
+        }
+    };
+
+    let downstream = async {
+        while let Some(Ok(message)) = backend_rx.next().await {
+            let Some(message) = to_client(message) else {
+                continue;
+            };
+            let closing = matches!(message, Message::Close(_));
+            if client_tx.send(message).await.is_err() || closing {
+                break;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = upstream => {}
+        _ = downstream => {}
+    }
+}
+
+fn to_backend(message: Message) -> Option<tungstenite::Message> {
+    match message {
+        Message::Text(text) => Some(tungstenite::Message::Text(text)),
+        Message::Binary(data) => Some(tungstenite::Message::Binary(data)),
+        Message::Close(frame) => Some(tungstenite::Message::Close(frame.map(|frame| {
+            tungstenite::protocol::CloseFrame {
+                code: frame.code.into(),
+                reason: frame.reason,
+            }
+        }))),
+        Message::Ping(_) | Message::Pong(_) => None,
+    }
+}
+
+fn to_client(message: tungstenite::Message) -> Option<Message> {
+    match message {
+        tungstenite::Message::Text(text) => Some(Message::Text(text)),
+        tungstenite::Message::Binary(data) => Some(Message::Binary(data)),
+        tungstenite::Message::Close(frame) => Some(Message::Close(frame.map(|frame| CloseFrame {
+            code: frame.code.into(),
+            reason: frame.reason,
+        }))),
+        tungstenite::Message::Ping(_)
+        | tungstenite::Message::Pong(_)
+        | tungstenite::Message::Frame(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_ws_url_swaps_scheme() {
+        assert_eq!(
+            backend_ws_url("http://10.0.0.2:8081", "/vm-console/vm-1"),
+            "ws://10.0.0.2:8081/vm-console/vm-1"
+        );
+        assert_eq!(
+            backend_ws_url("https://worker.example", "/vm-console/vm-1?x=1"),
+            "wss://worker.example/vm-console/vm-1?x=1"
+        );
+    }
+
+    #[test]
+    fn test_close_frames_keep_code_and_reason() {
+        let frame = CloseFrame {
+            code: 4000,
+            reason: "VM stopped".into(),
+        };
+        let Some(tungstenite::Message::Close(Some(upstream))) =
+            to_backend(Message::Close(Some(frame)))
+        else {
+            panic!("close frame was not converted");
+        };
+        assert_eq!(u16::from(upstream.code), 4000);
+
+        let Some(Message::Close(Some(back))) =
+            to_client(tungstenite::Message::Close(Some(upstream)))
+        else {
+            panic!("close frame was not converted");
+        };
+        assert_eq!(back.code, 4000);
+        assert_eq!(back.reason, "VM stopped");
+    }
+}